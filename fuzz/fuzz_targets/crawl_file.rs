@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use search_engine::{
+    inverted_index::disk_inverted_index::{parse_page, CrawlFile},
+    tokenizer::Tokenizer,
+};
+
+// Crawl files as the indexer reads them: JSON, then the HTML inside
+fuzz_target!(|data: &[u8]| {
+    let Ok(page) = serde_json::from_slice::<CrawlFile>(data) else {
+        return;
+    };
+    let Ok(tokenizer) = Tokenizer::new() else {
+        return;
+    };
+
+    let _ = parse_page(&page.url, &page.content, &tokenizer);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use search_engine::{
+    inverted_index::disk_inverted_index::TermIndex, kv_database::database::MemoryKVDatabase,
+};
+use std::collections::HashMap;
+
+// An arbitrary posting list behind a seek map that covers all of it
+fuzz_target!(|data: &[u8]| {
+    let seek_pos_map = HashMap::from([("term".to_string(), (0_u64, data.len() as u64))]);
+    let Ok(seek) = bincode::serialize(&seek_pos_map) else {
+        return;
+    };
+
+    if let Ok(db) = MemoryKVDatabase::<String, Vec<TermIndex>>::from_bytes(data.to_vec(), &seek) {
+        let _ = db.get(&"term".to_string());
+    }
+});
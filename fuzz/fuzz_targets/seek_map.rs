@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use search_engine::{
+    inverted_index::disk_inverted_index::TermIndex, kv_database::database::MemoryKVDatabase,
+};
+
+// Arbitrary seek files, with and without a header, over an empty db
+fuzz_target!(|data: &[u8]| {
+    if let Ok(db) = MemoryKVDatabase::<String, Vec<TermIndex>>::from_bytes(Vec::new(), data) {
+        db.iter().for_each(drop);
+        let _ = db.verify();
+    }
+});
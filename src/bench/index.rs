@@ -0,0 +1,87 @@
+use super::{peak_rss, per_second};
+use crate::{
+    error::Result,
+    inverted_index::disk_inverted_index::{BuildStats, DiskInvertedIndex},
+};
+use std::{
+    fmt::{self, Display},
+    fs::create_dir_all,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct IndexBenchReport {
+    pub stats: BuildStats,
+    pub elapsed: Duration,
+    pub peak_rss: Option<u64>,
+}
+
+impl IndexBenchReport {
+    #[must_use]
+    pub fn docs_per_sec(&self) -> f64 {
+        per_second(self.stats.num_docs, self.elapsed)
+    }
+
+    #[must_use]
+    pub fn tokens_per_sec(&self) -> f64 {
+        per_second(self.stats.num_tokens, self.elapsed)
+    }
+}
+
+impl Display for IndexBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Indexed {} documents ({} tokens) in {:?}",
+            self.stats.num_docs, self.stats.num_tokens, self.elapsed
+        )?;
+        writeln!(f, "  docs/sec:   {:.1}", self.docs_per_sec())?;
+        writeln!(f, "  tokens/sec: {:.1}", self.tokens_per_sec())?;
+        match self.peak_rss {
+            Some(bytes) => writeln!(f, "  peak RSS:   {:.1} MiB", bytes as f64 / 1024.0 / 1024.0)?,
+            None => writeln!(f, "  peak RSS:   unavailable")?,
+        }
+        writeln!(f, "Phases:")?;
+        writeln!(f, "  parse: {:?}", self.stats.parse_time)?;
+        writeln!(f, "  flush: {:?}", self.stats.flush_time)?;
+        write!(f, "  score: {:?}", self.stats.score_time)
+    }
+}
+
+/// Builds a throwaway index of `corpus` inside `out_dir` and times it.
+pub fn bench_index(corpus: &Path, out_dir: &Path) -> Result<IndexBenchReport> {
+    create_dir_all(out_dir)?;
+
+    let start_time = Instant::now();
+    let (_, stats) = DiskInvertedIndex::build(
+        out_dir.join("database.db"),
+        out_dir.join("database.seek"),
+        out_dir.join("url_map.db"),
+        out_dir.join("url_map.seek"),
+        corpus.to_path_buf(),
+    )?;
+
+    Ok(IndexBenchReport {
+        stats,
+        elapsed: start_time.elapsed(),
+        peak_rss: peak_rss(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_index() {
+        let out_dir = std::env::temp_dir().join("search_engine_bench_index");
+
+        let report = bench_index(Path::new("tests/test-data/corpus"), &out_dir)
+            .expect("Failed to benchmark indexing");
+
+        assert_eq!(report.stats.num_docs, 4);
+        assert!(report.stats.num_tokens > 0);
+        assert!(report.docs_per_sec() > 0.0);
+    }
+}
@@ -0,0 +1,175 @@
+use super::per_second;
+use crate::{error::Result, search::engine::SearchEngine};
+use clap::ValueEnum;
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CacheMode {
+    /// Run every query once untimed before measuring
+    Warm,
+    /// Reopen the index before every timed query
+    Cold,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchBenchOptions {
+    pub mode: CacheMode,
+    pub iterations: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchBenchReport {
+    pub mode: CacheMode,
+    pub latencies: Vec<Duration>,
+}
+
+impl SearchBenchReport {
+    #[must_use]
+    pub fn percentile(&self, p: f64) -> Duration {
+        percentile(&self.latencies, p)
+    }
+
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.latencies.iter().sum()
+    }
+
+    #[must_use]
+    pub fn queries_per_sec(&self) -> f64 {
+        per_second(self.latencies.len() as u64, self.total())
+    }
+}
+
+impl Display for SearchBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Ran {} queries ({:?} cache) in {:?}",
+            self.latencies.len(),
+            self.mode,
+            self.total()
+        )?;
+        writeln!(f, "  throughput: {:.1} queries/sec", self.queries_per_sec())?;
+        writeln!(f, "  p50: {:?}", self.percentile(50.0))?;
+        writeln!(f, "  p95: {:?}", self.percentile(95.0))?;
+        write!(f, "  p99: {:?}", self.percentile(99.0))
+    }
+}
+
+/// Runs every query once, resolving the top `limit` results.
+///
+/// This is the unit of work measured by [`bench_search`] and is meant to be
+/// called directly from criterion benchmarks. Returns the number of results
+/// resolved.
+pub fn run_queries(
+    search_engine: &SearchEngine,
+    queries: &[String],
+    limit: usize,
+) -> Result<usize> {
+    let mut num_results = 0;
+
+    for query in queries {
+        search_engine.search_streaming(query, limit, |_| {
+            num_results += 1;
+            true
+        })?;
+    }
+
+    Ok(num_results)
+}
+
+/// Times each query individually. `open` is called once in warm mode and
+/// before every query in cold mode, and is never included in the latencies.
+pub fn bench_search<F>(
+    mut open: F,
+    queries: &[String],
+    options: SearchBenchOptions,
+) -> Result<SearchBenchReport>
+where
+    F: FnMut() -> Result<SearchEngine>,
+{
+    let mut latencies = Vec::with_capacity(queries.len() * options.iterations);
+    let mut search_engine = open()?;
+
+    if options.mode == CacheMode::Warm {
+        run_queries(&search_engine, queries, options.limit)?;
+    }
+
+    for _ in 0..options.iterations {
+        for query in queries {
+            if options.mode == CacheMode::Cold {
+                search_engine = open()?;
+            }
+
+            let start_time = Instant::now();
+            search_engine.search_streaming(query, options.limit, |_| true)?;
+            latencies.push(start_time.elapsed());
+        }
+    }
+
+    Ok(SearchBenchReport {
+        mode: options.mode,
+        latencies,
+    })
+}
+
+/// Nearest-rank percentile, `p` in `0.0..=100.0`.
+fn percentile(latencies: &[Duration], p: f64) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let mut sorted = latencies.to_vec();
+    sorted.sort_unstable();
+
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::DiskInvertedIndex;
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&latencies, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 95.0), Duration::from_millis(95));
+        assert_eq!(percentile(&latencies, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_bench_search() {
+        let open = || {
+            SearchEngine::new(DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )?)
+        };
+        let queries = vec!["eric".to_string(), "not_in_index".to_string()];
+
+        for mode in [CacheMode::Warm, CacheMode::Cold] {
+            let report = bench_search(
+                open,
+                &queries,
+                SearchBenchOptions {
+                    mode,
+                    iterations: 3,
+                    limit: 10,
+                },
+            )
+            .expect("Failed to benchmark search");
+
+            assert_eq!(report.latencies.len(), 6);
+        }
+    }
+}
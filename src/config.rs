@@ -0,0 +1,521 @@
+use crate::error::{Error, Result};
+use crate::{
+    inverted_index::{disk_inverted_index::FlushPolicy, json_documents::JsonMapping},
+    search::{engine::QueryLimits, ranking::RankingConfig},
+    tokenizer::Analyzer,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, net::SocketAddr, path::Path, path::PathBuf};
+use toml::{map::Map, Value};
+
+pub const ENV_PREFIX: &str = "SEARCH_ENGINE_";
+
+/// Offset basis and prime of 64-bit FNV-1a.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub crawler: CrawlerConfig,
+    pub daemon: DaemonConfig,
+    pub indexing: IndexConfig,
+    /// Named indexes hosted side by side in server mode
+    pub indexes: BTreeMap<String, HostedIndexConfig>,
+    pub paths: PathsConfig,
+    /// Caps on the work of every query, of the server and the CLI alike
+    pub query_limits: QueryLimits,
+    pub query_log: QueryLogConfig,
+    /// Ranking of queries that don't pick a named one, of the server and the
+    /// CLI alike
+    pub ranking: RankingConfig,
+    pub repl: ReplConfig,
+    pub safe_search: SafeSearchConfig,
+    pub server: ServerConfig,
+    pub slow_query_log: SlowQueryLogConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrawlerConfig {
+    /// Pages fetched before the crawl stops
+    pub max_pages: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Sent with every request, its product token selects the robots.txt group
+    pub user_agent: String,
+    /// Minimum time between two requests to the same host, raised by a
+    /// robots.txt `Crawl-delay`
+    pub delay_ms: u64,
+    /// Media types saved as crawl files, `text/*` matches a whole type
+    pub content_types: Vec<String>,
+    /// Responses larger than this are dropped
+    pub max_page_bytes: usize,
+    /// Domains links are followed on, subdomains included. Empty means the
+    /// hosts of the seeds
+    pub domains: Vec<String>,
+    /// Path prefixes links must start with, empty allows any path
+    pub path_prefixes: Vec<String>,
+    /// Links followed away from the seeds
+    pub max_depth: usize,
+    /// URLs that may share a shape, numbers and query values aside, before
+    /// more are treated as a crawl trap
+    pub max_url_variants: usize,
+    /// Fetch history used to schedule re-crawls, kept outside the crawled
+    /// data so the indexer doesn't read it
+    pub state: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub socket: PathBuf,
+    /// Most frequent terms whose postings are loaded into memory at startup
+    pub preload_terms: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    pub crawled_data: PathBuf,
+    /// Directories or glob patterns of crawls indexed together as one
+    /// corpus, each page tagged with its directory. Empty indexes
+    /// `crawled_data` alone
+    pub crawled_sources: Vec<PathBuf>,
+    /// JSON files, directories or glob patterns of structured documents
+    /// indexed along with the crawled data, as `indexing.json` maps them
+    pub json_documents: Vec<PathBuf>,
+    pub db: PathBuf,
+    pub db_seek: PathBuf,
+    pub url_map: PathBuf,
+    pub url_map_seek: PathBuf,
+    /// CSV file of `url,boost` lines boosting or demoting pages at build
+    /// time, empty boosts none
+    pub boosts: PathBuf,
+    /// Scratch directory for the temp files of index rewrites, empty writes
+    /// them next to the files they replace
+    pub temp_dir: PathBuf,
+}
+
+/// How builds index documents.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Words indexed at the positions of the key word wherever documents
+    /// contain it. Unlike ranking expansions they cost queries nothing, but
+    /// changes only apply to indexes built after them
+    pub synonyms: BTreeMap<String, Vec<String>>,
+    /// CSS selectors of custom fields by name, like `author = ".byline"`.
+    /// Their text is kept with documents and matched by `author:` clauses
+    pub fields: BTreeMap<String, String>,
+    /// Where the url, title, body and metadata of `paths.json_documents` sit
+    pub json: JsonMapping,
+    pub flush: FlushPolicy,
+    /// Threads parsing documents, 0 for one per core
+    pub threads: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostedIndexConfig {
+    #[serde(flatten)]
+    pub paths: PathsConfig,
+    pub analyzer: Analyzer,
+    /// Most frequent terms whose postings are loaded into memory at startup
+    /// and on every reload
+    pub preload_terms: usize,
+}
+
+/// Queries of the REPL and server, the source of query suggestions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryLogConfig {
+    pub path: PathBuf,
+    /// Suggestions returned when a request doesn't ask for a number
+    pub max_suggestions: usize,
+}
+
+/// Queries of the REPL and server slower than a threshold, logged with their
+/// trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlowQueryLogConfig {
+    pub path: PathBuf,
+    /// Latency from which queries are logged, 0 disables the log
+    pub threshold_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplConfig {
+    pub history: PathBuf,
+}
+
+/// Documents flagged at index time, which queries with safe search exclude.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeSearchConfig {
+    /// Words flagging the pages containing them, whatever their case
+    pub flagged_words: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    /// Index answering the top-level `/search` routes
+    pub default_index: String,
+    /// Queries run at once across all clients, others wait for a slot
+    pub max_concurrent_queries: usize,
+    /// Queries per second allowed per client address, 0 disables the limit
+    pub rate_limit: u32,
+    /// Queries a client may send in a burst above `rate_limit`
+    pub rate_limit_burst: u32,
+    /// Keys clients must send as a bearer token or `X-API-Key` header, empty
+    /// leaves the server open. Health checks never need one
+    pub api_keys: Vec<String>,
+    /// Named rankings a query picks with `ranking=<name>`. Queries without
+    /// one use `default`, the index's own ranking unless configured here.
+    /// Changes to the config file apply while the server runs
+    pub rankings: BTreeMap<String, RankingConfig>,
+    /// Recent query results kept in memory, 0 disables the cache. Reloads
+    /// and ranking changes invalidate them
+    pub query_cache_size: usize,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            max_pages: 1000,
+            concurrency: 8,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            delay_ms: 1000,
+            content_types: vec!["text/html".to_string(), "text/plain".to_string()],
+            max_page_bytes: 10 * 1024 * 1024,
+            domains: Vec::new(),
+            path_prefixes: Vec::new(),
+            max_depth: 10,
+            max_url_variants: 100,
+            state: "crawl_state.json".into(),
+        }
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            socket: "search-engine.sock".into(),
+            preload_terms: 0,
+        }
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            crawled_data: "data".into(),
+            crawled_sources: Vec::new(),
+            json_documents: Vec::new(),
+            db: "database.db".into(),
+            db_seek: "database.seek".into(),
+            url_map: "url_map.db".into(),
+            url_map_seek: "url_map.seek".into(),
+            boosts: PathBuf::new(),
+            temp_dir: PathBuf::new(),
+        }
+    }
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            path: "queries.log".into(),
+            max_suggestions: 5,
+        }
+    }
+}
+
+impl Default for SlowQueryLogConfig {
+    fn default() -> Self {
+        Self {
+            path: "slow_queries.log".into(),
+            threshold_ms: 0,
+        }
+    }
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        Self {
+            history: ".search_engine_history".into(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            default_index: "default".to_string(),
+            max_concurrent_queries: 8,
+            rate_limit: 0,
+            rate_limit_burst: 20,
+            api_keys: Vec::new(),
+            rankings: BTreeMap::new(),
+            query_cache_size: 1024,
+        }
+    }
+}
+
+impl Config {
+    /// Layers the defaults, the optional TOML file and `SEARCH_ENGINE_*`
+    /// environment variables, each overriding the previous one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut value = Value::try_from(Self::default())?;
+
+        if let Some(path) = path {
+            let file: Value = toml::from_str(&fs::read_to_string(path)?)?;
+            merge(&mut value, file);
+        }
+
+        apply_env(&mut value, std::env::vars())?;
+
+        Ok(value.try_into()?)
+    }
+
+    /// Hex digest of the settings that change what a build indexes, telling
+    /// apart builds run with different ones. Digests are kept in build
+    /// reports, so they hash the settings as JSON with sorted keys, which no
+    /// Rust release changes.
+    pub fn fingerprint(&self) -> Result<String> {
+        let settings = BuildSettings {
+            synonyms: &self.indexing.synonyms,
+            fields: &self.indexing.fields,
+            json: &self.indexing.json,
+            flagged_words: &self.safe_search.flagged_words,
+        };
+        let canonical = serde_json::to_value(settings)?.to_string();
+
+        Ok(format!("{:016x}", fnv1a(canonical.as_bytes())))
+    }
+}
+
+/// What [`Config::fingerprint`] hashes. The flush policy and threads of
+/// builds only change how fast they run, and no other section reaches them,
+/// keys and other secrets included.
+#[derive(Serialize)]
+struct BuildSettings<'a> {
+    synonyms: &'a BTreeMap<String, Vec<String>>,
+    fields: &'a BTreeMap<String, String>,
+    json: &'a JsonMapping,
+    flagged_words: &'a [String],
+}
+
+/// 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Maps `SEARCH_ENGINE_<SECTION>_<KEY>` variables onto the keys of `value`.
+///
+/// `SEARCH_ENGINE_PATHS_DB_SEEK` sets `paths.db_seek`. Keys are resolved
+/// against the existing tree, so every option known to the config structs is
+/// reachable without listing it here.
+pub fn apply_env<I>(value: &mut Value, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, raw) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        if let Some(target) = resolve_env_key(value, path) {
+            *target = parse_env_value(&name, target, &raw)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_env_key<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    let Value::Table(table) = value else {
+        return None;
+    };
+
+    let key = find_key(table, path)?;
+    let rest = &path[key.len()..];
+    let child = table.get_mut(&key)?;
+
+    if rest.is_empty() {
+        Some(child)
+    } else {
+        resolve_env_key(child, &rest[1..])
+    }
+}
+
+fn find_key(table: &Map<String, Value>, path: &str) -> Option<String> {
+    table
+        .keys()
+        .filter(|key| {
+            let upper = key.to_uppercase();
+            path == upper || path.starts_with(&format!("{upper}_"))
+        })
+        .max_by_key(|key| key.len())
+        .cloned()
+}
+
+fn parse_env_value(name: &str, current: &Value, raw: &str) -> Result<Value> {
+    if current.is_str() {
+        return Ok(Value::String(raw.to_string()));
+    }
+
+    toml::from_str::<Map<String, Value>>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .ok_or_else(|| Error::Generic(format!("Invalid value for {name}: {raw}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::engine::MatchMode;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn env_overrides_nested_keys() {
+        let mut value = Value::try_from(Config::default()).expect("Failed to serialize config");
+
+        apply_env(
+            &mut value,
+            env(&[
+                ("SEARCH_ENGINE_PATHS_DB_SEEK", "custom.seek"),
+                ("SEARCH_ENGINE_PATHS_DB", "custom.db"),
+                ("SEARCH_ENGINE_SERVER_ADDR", "0.0.0.0:9000"),
+                ("UNRELATED_VAR", "ignored"),
+            ]),
+        )
+        .expect("Failed to apply env");
+
+        let config: Config = value.try_into().expect("Failed to deserialize config");
+        assert_eq!(config.paths.db, PathBuf::from("custom.db"));
+        assert_eq!(config.paths.db_seek, PathBuf::from("custom.seek"));
+        assert_eq!(config.paths.url_map, PathBuf::from("url_map.db"));
+        assert_eq!(config.server.addr.port(), 9000);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let from_file = |file: &str| {
+            let mut value = Value::try_from(Config::default()).expect("Failed to serialize config");
+            merge(
+                &mut value,
+                toml::from_str(file).expect("Failed to parse config file"),
+            );
+            let config: Config = value.try_into().expect("Failed to deserialize config");
+            config.fingerprint().expect("Failed to fingerprint config")
+        };
+        let fingerprint = from_file("");
+
+        // Settings written in another order are the same settings
+        assert_eq!(
+            from_file("[indexing.fields]\nauthor = \".byline\"\ndate = \"time\"\n"),
+            from_file("[indexing.fields]\ndate = \"time\"\nauthor = \".byline\"\n")
+        );
+        assert_ne!(
+            from_file("[indexing.synonyms]\ncar = [\"automobile\"]\n"),
+            fingerprint
+        );
+        assert_ne!(
+            from_file("[safe_search]\nflagged_words = [\"spam\"]\n"),
+            fingerprint
+        );
+        assert_eq!(
+            from_file(concat!(
+                "[server]\napi_keys = [\"secret\"]\nrate_limit = 5\n\n",
+                "[crawler]\nuser_agent = \"other\"\n\n",
+                "[indexing.flush]\nbatch_docs = 7\n",
+            )),
+            fingerprint
+        );
+
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn hosted_indexes_from_file() {
+        let mut value = Value::try_from(Config::default()).expect("Failed to serialize config");
+        let file: Value = toml::from_str(
+            "[indexes.news]\ndb = \"news.db\"\nanalyzer = \"simple\"\n\n[indexes.blog]\n",
+        )
+        .expect("Failed to parse config file");
+
+        merge(&mut value, file);
+
+        let config: Config = value.try_into().expect("Failed to deserialize config");
+        assert_eq!(config.indexes.len(), 2);
+        assert_eq!(config.indexes["news"].paths.db, PathBuf::from("news.db"));
+        assert_eq!(config.indexes["news"].analyzer, Analyzer::Simple);
+        assert_eq!(config.indexes["blog"].analyzer, Analyzer::English);
+    }
+
+    #[test]
+    fn file_values_merge_over_defaults() {
+        let mut value = Value::try_from(Config::default()).expect("Failed to serialize config");
+        let file: Value = toml::from_str("[paths]\nurl_map = \"other.db\"\n")
+            .expect("Failed to parse config file");
+
+        merge(&mut value, file);
+
+        let config: Config = value.try_into().expect("Failed to deserialize config");
+        assert_eq!(config.paths.url_map, PathBuf::from("other.db"));
+        assert_eq!(config.paths.db, PathBuf::from("database.db"));
+    }
+
+    #[test]
+    fn rankings_from_file() {
+        let mut value = Value::try_from(Config::default()).expect("Failed to serialize config");
+        let file: Value = toml::from_str(
+            "[server.rankings.strict]\nmatch_mode = \"all\"\n\n[server.rankings.boosted.term_weights]\nrust = 2.0\n",
+        )
+        .expect("Failed to parse config file");
+
+        merge(&mut value, file);
+
+        let config: Config = value.try_into().expect("Failed to deserialize config");
+        let rankings = &config.server.rankings;
+        assert_eq!(rankings["strict"].match_mode, MatchMode::All);
+        assert_eq!(
+            rankings["boosted"].term_weights["rust"].to_bits(),
+            2.0_f64.to_bits()
+        );
+        assert_eq!(
+            rankings["boosted"].expansion_weight.to_bits(),
+            0.5_f64.to_bits()
+        );
+    }
+}
@@ -0,0 +1,61 @@
+/// Which fetched responses are worth saving as crawl files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentFilter {
+    content_types: Vec<String>,
+    max_bytes: usize,
+}
+
+impl ContentFilter {
+    /// Content types are media types like `text/html`, or `text/*` for a
+    /// whole type.
+    #[must_use]
+    pub fn new(content_types: &[String], max_bytes: usize) -> Self {
+        Self {
+            content_types: content_types
+                .iter()
+                .map(|content_type| content_type.trim().to_lowercase())
+                .collect(),
+            max_bytes,
+        }
+    }
+
+    /// Matches the media type of a `Content-Type` header, ignoring its
+    /// parameters. Responses without one are rejected.
+    #[must_use]
+    pub fn accepts_type(&self, content_type: Option<&str>) -> bool {
+        let Some(media_type) = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|media_type| media_type.trim().to_lowercase())
+        else {
+            return false;
+        };
+
+        self.content_types.iter().any(|allowed| {
+            allowed.strip_suffix("/*").map_or_else(
+                || *allowed == media_type,
+                |prefix| media_type.split('/').next() == Some(prefix),
+            )
+        })
+    }
+
+    #[must_use]
+    pub const fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_type() {
+        let filter = ContentFilter::new(&["text/html".to_string(), "Text/*".to_string()], 0);
+
+        assert!(filter.accepts_type(Some("text/html; charset=utf-8")));
+        assert!(filter.accepts_type(Some("TEXT/PLAIN")));
+        assert!(!filter.accepts_type(Some("image/png")));
+        assert!(!filter.accepts_type(Some("application/xhtml+xml")));
+        assert!(!filter.accepts_type(None));
+    }
+}
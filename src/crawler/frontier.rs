@@ -0,0 +1,130 @@
+use super::{robots::Robots, scope::Scope, traps::TrapDetector, CrawlStats};
+use reqwest::Client;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+use url::{Position, Url};
+
+/// What the crawler knows about one origin.
+struct Host {
+    robots: Robots,
+    next_fetch: Instant,
+}
+
+/// URLs waiting to be fetched, breadth first, with their link depth from the
+/// seeds and the politeness state of their hosts.
+pub struct Frontier {
+    queue: VecDeque<(Url, usize)>,
+    seen: HashSet<String>,
+    hosts: HashMap<String, Host>,
+    scope: Scope,
+    traps: TrapDetector,
+    client: Client,
+    user_agent: String,
+    delay: Duration,
+}
+
+impl Frontier {
+    /// Seeds are queued even when outside the scope.
+    pub fn new(
+        seeds: Vec<Url>,
+        scope: Scope,
+        traps: TrapDetector,
+        client: Client,
+        user_agent: String,
+        delay: Duration,
+    ) -> Self {
+        let mut seen = HashSet::new();
+        let queue = seeds
+            .into_iter()
+            .filter(|seed| seen.insert(seed.to_string()))
+            .map(|seed| (seed, 0))
+            .collect();
+
+        Self {
+            queue,
+            seen,
+            hosts: HashMap::new(),
+            scope,
+            traps,
+            client,
+            user_agent,
+            delay,
+        }
+    }
+
+    /// Queues a link unless it is out of scope, was already seen or looks
+    /// like a crawl trap.
+    pub fn push(&mut self, url: Url, depth: usize, stats: &mut CrawlStats) {
+        if !self.scope.contains(&url) || !self.seen.insert(url.to_string()) {
+            return;
+        }
+
+        if self.traps.is_trap(&url) {
+            stats.traps += 1;
+        } else {
+            self.queue.push_back((url, depth));
+        }
+    }
+
+    /// Keeps a URL from being queued, for pages reached through a redirect.
+    pub fn mark_seen(&mut self, url: &Url) {
+        self.seen.insert(url.to_string());
+    }
+
+    /// Pops the next URL robots.txt allows with its depth and the time it may
+    /// be fetched.
+    ///
+    /// Each host's robots.txt is fetched before its first page and requests
+    /// to a host are spaced by the larger of the delay and its `Crawl-delay`.
+    /// The slot is reserved right away so concurrent fetches stay spaced.
+    pub async fn next(&mut self, stats: &mut CrawlStats) -> Option<(Url, usize, Instant)> {
+        while let Some((url, depth)) = self.queue.pop_front() {
+            let host = match self.hosts.entry(url.origin().ascii_serialization()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(Host {
+                    robots: fetch_robots(&self.client, &url, &self.user_agent).await,
+                    next_fetch: Instant::now(),
+                }),
+            };
+
+            if !host
+                .robots
+                .allowed(&url[Position::BeforePath..Position::AfterQuery])
+            {
+                stats.disallowed += 1;
+                continue;
+            }
+
+            let fetch_at = host.next_fetch.max(Instant::now());
+            host.next_fetch = fetch_at
+                + host
+                    .robots
+                    .crawl_delay()
+                    .unwrap_or_default()
+                    .max(self.delay);
+
+            return Some((url, depth, fetch_at));
+        }
+
+        None
+    }
+}
+
+/// A missing robots.txt allows everything, one that can't be fetched
+/// disallows everything until the next crawl.
+async fn fetch_robots(client: &Client, url: &Url, user_agent: &str) -> Robots {
+    let Ok(robots_url) = url.join("/robots.txt") else {
+        return Robots::allow_all();
+    };
+
+    match client.get(robots_url).send().await {
+        Ok(response) if response.status().is_success() => response.text().await.map_or_else(
+            |_| Robots::disallow_all(),
+            |text| Robots::parse(&text, user_agent),
+        ),
+        Ok(response) if response.status().is_client_error() => Robots::allow_all(),
+        _ => Robots::disallow_all(),
+    }
+}
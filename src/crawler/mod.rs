@@ -0,0 +1,446 @@
+pub mod filter;
+mod frontier;
+pub mod robots;
+pub mod scope;
+pub mod state;
+pub mod traps;
+
+use crate::{
+    error::{Error, Result},
+    inverted_index::{charset::decode_body, disk_inverted_index::CrawlFile},
+    links::extract_links,
+    shutdown,
+};
+use filter::ContentFilter;
+use frontier::Frontier;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use scope::Scope;
+use state::CrawlState;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc::SyncSender, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{task::JoinSet, time};
+use traps::TrapDetector;
+use url::Url;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const PROGRESS_INTERVAL: u64 = 100;
+
+/// Where fetched pages go.
+#[derive(Debug, Clone)]
+pub enum CrawlOutput {
+    /// Directory the `CrawlFile` JSON documents are written to
+    Dir(PathBuf),
+    /// Handed straight to a consumer such as the indexer, which applies
+    /// backpressure once its buffer is full
+    Channel(SyncSender<CrawlFile>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CrawlOptions {
+    pub seeds: Vec<Url>,
+    pub output: CrawlOutput,
+    pub max_pages: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    pub user_agent: String,
+    /// Minimum time between two requests to the same host
+    pub delay: Duration,
+    pub filter: ContentFilter,
+    pub scope: Scope,
+    /// Links followed away from the seeds, 0 fetches only the seeds
+    pub max_depth: usize,
+    pub traps: TrapDetector,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CrawlStats {
+    pub fetched: u64,
+    /// Fetched pages that were new or changed, the only ones written out
+    pub changed: u64,
+    pub failed: u64,
+    /// Pages crawled before that no longer exist, forgotten along with their
+    /// saved copy
+    pub gone: u64,
+    /// Links skipped because robots.txt disallows them
+    pub disallowed: u64,
+    /// Responses not saved because of their content type or size
+    pub filtered: u64,
+    /// Pages not saved because another URL served the same content
+    pub duplicates: u64,
+    /// Links dropped because they look like an endless URL space
+    pub traps: u64,
+    pub elapsed: Duration,
+}
+
+enum Fetched {
+    /// The URL the page was finally served from, after redirects, and its body
+    /// decoded to UTF-8
+    Page(Url, String),
+    Filtered(String),
+}
+
+impl Display for CrawlStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Fetched {} pages ({} new or changed, {} duplicates, {} failed, {} gone, {} disallowed by robots.txt, {} filtered, {} trap links) in {:.2?}",
+            self.fetched,
+            self.changed,
+            self.duplicates,
+            self.failed,
+            self.gone,
+            self.disallowed,
+            self.filtered,
+            self.traps,
+            self.elapsed
+        )
+    }
+}
+
+/// Fetches the seeds and the links they lead to within scope, breadth first,
+/// handing every page to the output for the indexer.
+///
+/// Fetches are recorded in `history`, and pages unchanged since the last
+/// crawl are not written again. Neither are pages whose content was already
+/// fetched from another URL, like variants with tracking parameters. Pages of
+/// `history` the server reports as not found or gone are dropped from it.
+pub async fn crawl(options: CrawlOptions, history: &mut CrawlState) -> Result<CrawlStats> {
+    if let CrawlOutput::Dir(out_dir) = &options.output {
+        fs::create_dir_all(out_dir)?;
+    }
+
+    let client = Client::builder()
+        .user_agent(&options.user_agent)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let filter = Arc::new(options.filter);
+    let mut frontier = Frontier::new(
+        options.seeds,
+        options.scope,
+        options.traps,
+        client.clone(),
+        options.user_agent,
+        options.delay,
+    );
+
+    let mut content_owners = history.content_owners();
+    let mut in_flight = JoinSet::new();
+    let mut scheduled = 0;
+    let mut stats = CrawlStats::default();
+    let start_time = Instant::now();
+
+    loop {
+        while !shutdown::requested()
+            && in_flight.len() < options.concurrency.max(1)
+            && scheduled < options.max_pages
+        {
+            let Some((url, depth, fetch_at)) = frontier.next(&mut stats).await else {
+                break;
+            };
+            scheduled += 1;
+
+            let client = client.clone();
+            let filter = Arc::clone(&filter);
+            in_flight.spawn(async move {
+                time::sleep_until(fetch_at.into()).await;
+                let page = fetch(&client, &url, &filter).await;
+                (url, depth, page)
+            });
+        }
+
+        let Some(joined) = in_flight.join_next().await else {
+            break;
+        };
+        let (url, depth, page) = joined.map_err(Error::task_failed("Crawl"))?;
+
+        let (url, content) = match page {
+            Ok(Fetched::Page(url, content)) => (url, content),
+            Ok(Fetched::Filtered(reason)) => {
+                eprintln!("Skipped {url}: {reason}");
+                stats.filtered += 1;
+                continue;
+            }
+            Err(e) => {
+                record_failure(&e, &url, &options.output, history, &mut stats)?;
+                continue;
+            }
+        };
+
+        frontier.mark_seen(&url);
+        stats.fetched += 1;
+
+        let hash = content_hash(&content);
+        let owner = content_owners
+            .entry(hash)
+            .or_insert_with(|| url.to_string());
+        if owner != url.as_str() {
+            eprintln!("Skipped {url}: duplicate of {owner}");
+            stats.duplicates += 1;
+            continue;
+        }
+
+        if depth < options.max_depth {
+            for link in extract_links(&url, &content)? {
+                frontier.push(link, depth + 1, &mut stats);
+            }
+        }
+
+        if history.record(url.as_str(), hash, state::now()) {
+            emit(
+                &options.output,
+                CrawlFile {
+                    url: url.into(),
+                    content,
+                    encoding: "utf-8".to_string(),
+                    crawled_at: Some(state::now()),
+                    boost: None,
+                    source: None,
+                    fields: BTreeMap::new(),
+                },
+            )?;
+            stats.changed += 1;
+        }
+
+        if stats.fetched % PROGRESS_INTERVAL == 0 {
+            println!("Fetched {} pages", stats.fetched);
+        }
+    }
+
+    stats.elapsed = start_time.elapsed();
+
+    if shutdown::requested() {
+        return Err(Error::Interrupted);
+    }
+
+    Ok(stats)
+}
+
+/// Stops reading the body as soon as it exceeds the size limit, so large
+/// downloads are cut short even without a `Content-Length`.
+async fn fetch(client: &Client, url: &Url, filter: &ContentFilter) -> Result<Fetched> {
+    let mut response = client.get(url.clone()).send().await?.error_for_status()?;
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(str::to_string);
+    let content_type = content_type.as_deref();
+    if !filter.accepts_type(content_type) {
+        return Ok(Fetched::Filtered(format!(
+            "content type {}",
+            content_type.unwrap_or("missing")
+        )));
+    }
+
+    let too_large = || Fetched::Filtered(format!("larger than {} bytes", filter.max_bytes()));
+    if response
+        .content_length()
+        .is_some_and(|length| length > filter.max_bytes() as u64)
+    {
+        return Ok(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > filter.max_bytes() {
+            return Ok(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(Fetched::Page(
+        response.url().clone(),
+        decode_body(&body, content_type),
+    ))
+}
+
+/// Records a failed fetch of `url`, forgetting the page and its saved copy
+/// when the server says it no longer exists.
+fn record_failure(
+    e: &Error,
+    url: &Url,
+    output: &CrawlOutput,
+    history: &mut CrawlState,
+    stats: &mut CrawlStats,
+) -> Result<()> {
+    if is_gone(e) && history.forget(url.as_str()) {
+        eprintln!("Removed {url}: {e}");
+        stats.gone += 1;
+        if let CrawlOutput::Dir(out_dir) = output {
+            remove(out_dir, url.as_str())?;
+        }
+        return Ok(());
+    }
+
+    eprintln!("Failed to fetch {url}: {e}");
+    history.record_failure(url.as_str(), state::now());
+    stats.failed += 1;
+    Ok(())
+}
+
+fn emit(output: &CrawlOutput, page: CrawlFile) -> Result<()> {
+    match output {
+        CrawlOutput::Dir(out_dir) => save(out_dir, &page),
+        CrawlOutput::Channel(sender) => sender
+            .send(page)
+            .map_err(|_| Error::Generic("Page consumer stopped receiving".to_string())),
+    }
+}
+
+fn save(out_dir: &Path, page: &CrawlFile) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(page_path(out_dir, &page.url))?);
+    serde_json::to_writer(&mut writer, page)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Removes the saved copy of `url`, if there is one.
+fn remove(out_dir: &Path, url: &str) -> Result<()> {
+    match fs::remove_file(page_path(out_dir, url)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Where the page at `url` is saved in `out_dir`. Stable per URL, so
+/// crawling a page again replaces its previous copy.
+#[must_use]
+pub fn page_path(out_dir: &Path, url: &str) -> PathBuf {
+    out_dir.join(format!("{:016x}.json", content_hash(url)))
+}
+
+/// Whether fetching failed because the page no longer exists.
+fn is_gone(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Http(e) if matches!(e.status(), Some(StatusCode::NOT_FOUND | StatusCode::GONE))
+    )
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use axum::{http::StatusCode, response::Html, routing::get, Router};
+    use tokio::net::TcpListener;
+
+    async fn serve_site() -> Url {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    Html(concat!(
+                        r#"<a href="/a">A</a> <a href="/missing#x">Missing</a> "#,
+                        r#"<a href="/logo.png">Logo</a> <a href="/large">Large</a> "#,
+                        r#"<a href="https://elsewhere.example/">Out of scope</a>"#,
+                    ))
+                }),
+            )
+            .route(
+                "/a",
+                get(|| async {
+                    Html(concat!(
+                        r#"<a href="/">Home</a> <a href="/private">Private</a> "#,
+                        r#"<a href="/a?utm_source=feed">Same page</a>"#,
+                    ))
+                }),
+            )
+            .route(
+                "/logo.png",
+                get(|| async { ([(CONTENT_TYPE, "image/png")], vec![0_u8; 16]) }),
+            )
+            .route("/large", get(|| async { Html("x".repeat(4096)) }))
+            .route(
+                "/robots.txt",
+                get(|| async { "User-agent: *\nDisallow: /private\n" }),
+            )
+            .fallback(|| async { StatusCode::NOT_FOUND });
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind test server");
+        let addr = listener
+            .local_addr()
+            .expect("Failed to get test server address");
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Url::parse(&format!("http://{addr}/")).expect("Invalid test server URL")
+    }
+
+    #[tokio::test]
+    async fn crawls_within_scope() {
+        let dir = TestDir::new();
+        let seed = serve_site().await;
+        let out_dir = dir.path("crawl_within_scope");
+
+        let options = CrawlOptions {
+            seeds: vec![seed.clone()],
+            output: CrawlOutput::Dir(out_dir.clone()),
+            max_pages: 10,
+            concurrency: 2,
+            user_agent: "search-engine-test".to_string(),
+            delay: Duration::ZERO,
+            filter: ContentFilter::new(&["text/html".to_string()], 1024),
+            scope: Scope::new(&[], &[], std::slice::from_ref(&seed)),
+            max_depth: 10,
+            traps: TrapDetector::new(100),
+        };
+        let mut history = CrawlState::default();
+
+        let stats = crawl(options.clone(), &mut history)
+            .await
+            .expect("Failed to crawl");
+        let recrawl = crawl(options.clone(), &mut history)
+            .await
+            .expect("Failed to crawl again");
+        let seeds_only = crawl(
+            CrawlOptions {
+                max_depth: 0,
+                ..options
+            },
+            &mut CrawlState::default(),
+        )
+        .await
+        .expect("Failed to crawl the seeds");
+
+        let mut urls = fs::read_dir(&out_dir)
+            .expect("Failed to list crawl output")
+            .map(|entry| {
+                let path = entry.expect("Failed to read crawl output").path();
+                let page: CrawlFile =
+                    serde_json::from_reader(File::open(path).expect("Failed to open crawl file"))
+                        .expect("Failed to parse crawl file");
+                page.url
+            })
+            .collect::<Vec<_>>();
+        urls.sort();
+
+        assert_eq!(stats.fetched, 3);
+        assert_eq!(stats.changed, 2);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(recrawl.fetched, 3);
+        assert_eq!(recrawl.changed, 0);
+        assert_eq!(recrawl.duplicates, 1);
+        assert_eq!(seeds_only.fetched, 1);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.disallowed, 1);
+        assert_eq!(stats.filtered, 2);
+        assert_eq!(urls, vec![seed.to_string(), format!("{seed}a")]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    robots: Robots,
+}
+
+impl Robots {
+    /// Robots for a site without a robots.txt.
+    #[must_use]
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Robots for a site whose robots.txt could not be read, which RFC 9309
+    /// asks crawlers to treat as a full disallow.
+    #[must_use]
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    /// Picks the group naming the product token of `user_agent`, falling back
+    /// to the `*` group.
+    #[must_use]
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let token = user_agent
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+
+            if key == "user-agent" {
+                if !in_agents {
+                    groups.push(Group::default());
+                    in_agents = true;
+                }
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_lowercase());
+                }
+                continue;
+            }
+
+            in_agents = false;
+            let Some(group) = groups.last_mut() else {
+                continue;
+            };
+
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => group.robots.rules.push(Rule {
+                    allow: key == "allow",
+                    pattern: value.to_string(),
+                }),
+                "crawl-delay" => {
+                    group.robots.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        let find = |agent: &str| {
+            groups
+                .iter()
+                .position(|group| group.agents.iter().any(|name| name == agent))
+        };
+
+        find(&token)
+            .or_else(|| find("*"))
+            .map(|i| groups.swap_remove(i).robots)
+            .unwrap_or_default()
+    }
+
+    /// Whether `path`, including its query, may be fetched. The longest
+    /// matching rule wins, with `Allow` winning ties.
+    #[must_use]
+    pub fn allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    #[must_use]
+    pub const fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` is any sequence of characters
+/// and a trailing `$` anchors the end of the path.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = pattern
+        .strip_suffix('$')
+        .map_or((pattern, false), |pattern| (pattern, true));
+
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = parts.next().and_then(|prefix| path.strip_prefix(prefix)) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    if anchored {
+        rest.ends_with(last)
+    } else {
+        rest.contains(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+# Comments are ignored
+User-agent: *
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+
+User-agent: other-bot
+User-agent: search-engine
+Disallow: /
+Allow: /docs/
+Crawl-delay: 2.5
+";
+
+    #[test]
+    fn wildcard_group() {
+        let robots = Robots::parse(ROBOTS, "unknown-bot/1.0");
+
+        assert!(robots.allowed("/"));
+        assert!(!robots.allowed("/private/notes"));
+        assert!(robots.allowed("/private/public/notes"));
+        assert!(!robots.allowed("/files/report.pdf"));
+        assert!(robots.allowed("/files/report.pdf?download=1"));
+        assert_eq!(robots.crawl_delay(), None);
+    }
+
+    #[test]
+    fn named_group() {
+        let robots = Robots::parse(ROBOTS, "Search-Engine/0.1.0");
+
+        assert!(!robots.allowed("/"));
+        assert!(robots.allowed("/docs/index.html"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn missing_and_unreadable() {
+        assert!(Robots::allow_all().allowed("/anything"));
+        assert!(!Robots::disallow_all().allowed("/anything"));
+        assert!(Robots::parse("", "search-engine").allowed("/anything"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use url::Url;
+
+/// Which discovered links the crawler follows.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    domains: HashSet<String>,
+    path_prefixes: Vec<String>,
+}
+
+impl Scope {
+    /// Links must be on one of `domains` or their subdomains, or on the hosts
+    /// of the seeds when no domain is given. With path prefixes, the path
+    /// must also start with one of them.
+    #[must_use]
+    pub fn new(domains: &[String], path_prefixes: &[String], seeds: &[Url]) -> Self {
+        let domains = if domains.is_empty() {
+            seeds
+                .iter()
+                .filter_map(Url::host_str)
+                .map(str::to_string)
+                .collect()
+        } else {
+            domains
+                .iter()
+                .map(|domain| domain.trim_start_matches('.').to_lowercase())
+                .collect()
+        };
+
+        Self {
+            domains,
+            path_prefixes: path_prefixes.to_vec(),
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+
+        let on_domain = self.domains.contains(host)
+            || self.domains.iter().any(|domain| {
+                host.strip_suffix(domain.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+            });
+
+        on_domain
+            && (self.path_prefixes.is_empty()
+                || self
+                    .path_prefixes
+                    .iter()
+                    .any(|prefix| url.path().starts_with(prefix.as_str())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).expect("Invalid test URL")
+    }
+
+    #[test]
+    fn seed_hosts_by_default() {
+        let scope = Scope::new(&[], &[], &[url("https://example.com/start")]);
+
+        assert!(scope.contains(&url("https://example.com/other")));
+        assert!(!scope.contains(&url("https://other.com/")));
+        assert!(!scope.contains(&url("https://notexample.com/")));
+    }
+
+    #[test]
+    fn domains_and_prefixes() {
+        let scope = Scope::new(
+            &["example.com".to_string()],
+            &["/docs/".to_string(), "/blog/".to_string()],
+            &[url("https://seed.org/")],
+        );
+
+        assert!(scope.contains(&url("https://example.com/docs/intro")));
+        assert!(scope.contains(&url("https://www.example.com/blog/post")));
+        assert!(!scope.contains(&url("https://example.com/shop/")));
+        assert!(!scope.contains(&url("https://notexample.com/docs/")));
+        assert!(!scope.contains(&url("https://seed.org/docs/")));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HOUR: u64 = 60 * 60;
+const MIN_INTERVAL: u64 = HOUR;
+const INITIAL_INTERVAL: u64 = 24 * HOUR;
+const MAX_INTERVAL: u64 = 30 * 24 * HOUR;
+
+/// When a page was last fetched and how often it seems to change. Times are
+/// seconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageState {
+    pub last_fetched: u64,
+    pub last_changed: u64,
+    pub content_hash: u64,
+    /// Time to wait before fetching again, halved when the page changed and
+    /// doubled when it didn't
+    pub interval: u64,
+}
+
+/// Fetch history of every crawled page, used to schedule re-crawls.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlState {
+    pages: BTreeMap<String, PageState>,
+}
+
+impl CrawlState {
+    /// A missing file is an empty state, as before the first crawl.
+    pub fn load(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Written to a temporary file first so an interrupted save keeps the old
+    /// state intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let temp_path = path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn get(&self, url: &str) -> Option<&PageState> {
+        self.pages.get(url)
+    }
+
+    /// Records a successful fetch and returns whether the content is new or
+    /// changed since the previous one.
+    pub fn record(&mut self, url: &str, content_hash: u64, now: u64) -> bool {
+        let Some(page) = self.pages.get_mut(url) else {
+            self.pages.insert(
+                url.to_string(),
+                PageState {
+                    last_fetched: now,
+                    last_changed: now,
+                    content_hash,
+                    interval: INITIAL_INTERVAL,
+                },
+            );
+            return true;
+        };
+
+        let changed = page.content_hash != content_hash;
+        page.last_fetched = now;
+        if changed {
+            page.last_changed = now;
+            page.content_hash = content_hash;
+            page.interval = (page.interval / 2).max(MIN_INTERVAL);
+        } else {
+            page.interval = (page.interval * 2).min(MAX_INTERVAL);
+        }
+
+        changed
+    }
+
+    /// Which page each known content hash was first recorded for.
+    #[must_use]
+    pub fn content_owners(&self) -> HashMap<u64, String> {
+        let mut owners = HashMap::new();
+        for (url, page) in &self.pages {
+            owners
+                .entry(page.content_hash)
+                .or_insert_with(|| url.clone());
+        }
+
+        owners
+    }
+
+    /// Pushes back the next attempt of a page that failed to fetch.
+    pub fn record_failure(&mut self, url: &str, now: u64) {
+        if let Some(page) = self.pages.get_mut(url) {
+            page.last_fetched = now;
+        }
+    }
+
+    /// Drops a page that no longer exists, returning whether it was known.
+    pub fn forget(&mut self, url: &str) -> bool {
+        self.pages.remove(url).is_some()
+    }
+
+    /// Every known URL and its state.
+    pub fn pages(&self) -> impl Iterator<Item = (&String, &PageState)> {
+        self.pages.iter()
+    }
+
+    /// URLs whose interval has elapsed, most overdue first.
+    #[must_use]
+    pub fn due(&self, now: u64) -> Vec<String> {
+        let mut due = self
+            .pages
+            .iter()
+            .filter_map(|(url, page)| {
+                let next_fetch = page.last_fetched + page.interval;
+                (next_fetch <= now).then_some((next_fetch, url.clone()))
+            })
+            .collect::<Vec<_>>();
+        due.sort();
+
+        due.into_iter().map(|(_, url)| url).collect()
+    }
+}
+
+/// Seconds since the Unix epoch.
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapts_interval_to_changes() {
+        let mut state = CrawlState::default();
+
+        assert!(state.record("https://example.com/", 1, 0));
+        assert_eq!(
+            state.get("https://example.com/").map(|page| page.interval),
+            Some(INITIAL_INTERVAL)
+        );
+
+        assert!(!state.record("https://example.com/", 1, INITIAL_INTERVAL));
+        assert_eq!(
+            state.get("https://example.com/").map(|page| page.interval),
+            Some(2 * INITIAL_INTERVAL)
+        );
+
+        assert!(state.record("https://example.com/", 2, 3 * INITIAL_INTERVAL));
+        let page = state
+            .get("https://example.com/")
+            .expect("Page should be tracked");
+        assert_eq!(page.interval, INITIAL_INTERVAL);
+        assert_eq!(page.last_changed, 3 * INITIAL_INTERVAL);
+    }
+
+    #[test]
+    fn due_most_overdue_first() {
+        let mut state = CrawlState::default();
+        state.record("https://example.com/old", 1, 0);
+        state.record("https://example.com/older", 1, 0);
+        state.record("https://example.com/fresh", 1, INITIAL_INTERVAL);
+        state.record_failure("https://example.com/old", HOUR);
+
+        assert!(state.forget("https://example.com/fresh"));
+        assert!(!state.forget("https://example.com/fresh"));
+
+        assert!(state.due(INITIAL_INTERVAL - 1).is_empty());
+        assert_eq!(
+            state.due(INITIAL_INTERVAL + HOUR),
+            vec!["https://example.com/older", "https://example.com/old"]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use url::Url;
+
+const MAX_PATH_SEGMENTS: usize = 16;
+const MAX_SEGMENT_REPEATS: usize = 2;
+const MAX_QUERY_PARAMS: usize = 8;
+
+/// Spots URLs that a site can generate without end, like calendars that
+/// always link to the next month or faceted navigation combining filters.
+#[derive(Debug, Clone)]
+pub struct TrapDetector {
+    max_variants: usize,
+    variants: HashMap<String, usize>,
+}
+
+impl TrapDetector {
+    /// `max_variants` is how many URLs may share a shape, which is the URL
+    /// with its numbers and query values left out.
+    #[must_use]
+    pub fn new(max_variants: usize) -> Self {
+        Self {
+            max_variants,
+            variants: HashMap::new(),
+        }
+    }
+
+    /// Counts `url` towards its shape, so call it once per distinct URL.
+    pub fn is_trap(&mut self, url: &Url) -> bool {
+        let segments = url.path_segments().map_or_else(Vec::new, |segments| {
+            segments.filter(|segment| !segment.is_empty()).collect()
+        });
+
+        if segments.len() > MAX_PATH_SEGMENTS
+            || has_repeated_segments(&segments)
+            || url.query_pairs().count() > MAX_QUERY_PARAMS
+        {
+            return true;
+        }
+
+        let variants = self.variants.entry(shape(url)).or_insert(0);
+        *variants += 1;
+        *variants > self.max_variants
+    }
+}
+
+/// Relative links resolved against the wrong base pile up segments like
+/// `/a/b/a/b/a/b`.
+fn has_repeated_segments(segments: &[&str]) -> bool {
+    let mut counts = HashMap::new();
+    if segments.iter().any(|segment| {
+        let count = counts.entry(segment).or_insert(0);
+        *count += 1;
+        *count > MAX_SEGMENT_REPEATS
+    }) {
+        return true;
+    }
+
+    (1..=segments.len() / 2).any(|len| {
+        segments
+            .windows(2 * len)
+            .any(|window| window[..len] == window[len..])
+    })
+}
+
+/// The host, the path with digit runs replaced by `#` and the sorted query
+/// keys.
+fn shape(url: &Url) -> String {
+    let mut path = String::with_capacity(url.path().len());
+    for c in url.path().chars() {
+        if !c.is_ascii_digit() {
+            path.push(c);
+        } else if !path.ends_with('#') {
+            path.push('#');
+        }
+    }
+
+    let mut keys = url
+        .query_pairs()
+        .map(|(key, _)| key.into_owned())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    format!(
+        "{}{path}?{}",
+        url.host_str().unwrap_or_default(),
+        keys.join("&")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).expect("Invalid test URL")
+    }
+
+    #[test]
+    fn repeated_segments() {
+        let mut detector = TrapDetector::new(100);
+
+        assert!(!detector.is_trap(&url("https://example.com/docs/guide/intro")));
+        assert!(!detector.is_trap(&url("https://example.com/a/b/c/a/d")));
+        assert!(detector.is_trap(&url("https://example.com/a/b/a/b")));
+        assert!(detector.is_trap(&url("https://example.com/x/x")));
+        assert!(detector.is_trap(&url("https://example.com/a/b/a/c/a")));
+    }
+
+    #[test]
+    fn exploding_variants() {
+        let mut detector = TrapDetector::new(3);
+
+        for month in 1..=3 {
+            assert!(!detector.is_trap(&url(&format!("https://example.com/calendar/2024/{month}"))));
+        }
+        assert!(detector.is_trap(&url("https://example.com/calendar/2024/4")));
+
+        for color in ["red", "blue", "green"] {
+            assert!(!detector.is_trap(&url(&format!(
+                "https://example.com/shop?color={color}&size=m"
+            ))));
+        }
+        assert!(detector.is_trap(&url("https://example.com/shop?size=l&color=red")));
+        assert!(!detector.is_trap(&url("https://example.com/shop?color=red")));
+
+        let facets = (0..=MAX_QUERY_PARAMS)
+            .map(|i| format!("f{i}=1"))
+            .collect::<Vec<_>>()
+            .join("&");
+        assert!(detector.is_trap(&url(&format!("https://example.com/search?{facets}"))));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    error::{Error, Result},
+    search::{batch::run_query, engine::SearchEngine},
+    shutdown,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs::remove_file,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+const DEFAULT_LIMIT: usize = 10;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonRequest {
+    pub query: String,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+const fn default_limit() -> usize {
+    DEFAULT_LIMIT
+}
+
+/// Answers newline-delimited JSON requests on `socket_path` until a shutdown
+/// is requested.
+///
+/// Every connection gets its own thread, all searching the same engine
+/// concurrently, and in-flight connections are drained before the socket file
+/// is removed.
+pub fn serve(search_engine: &SearchEngine, socket_path: &Path) -> Result<()> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(Error::Generic(format!(
+                "A daemon is already listening on {}",
+                socket_path.display()
+            )));
+        }
+        remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    listener.set_nonblocking(true)?;
+    println!("Listening on {}", socket_path.display());
+
+    let outcome = thread::scope(|scope| loop {
+        if shutdown::requested() {
+            return Ok(());
+        }
+
+        match listener.accept() {
+            Ok((stream, _)) => {
+                scope.spawn(move || {
+                    if let Err(e) = handle_connection(stream, search_engine) {
+                        eprintln!("Daemon connection failed: {e}");
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+            Err(e) => return Err(Error::from(e)),
+        }
+    });
+
+    remove_file(socket_path)?;
+    println!("Daemon shut down");
+
+    outcome
+}
+
+fn handle_connection(stream: UnixStream, search_engine: &SearchEngine) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = serde_json::from_str::<DaemonRequest>(&line)
+            .map_err(Error::from)
+            .and_then(|request| run_query(search_engine, &request.query, request.limit));
+
+        match response {
+            Ok(results) => serde_json::to_writer(&mut writer, &results)?,
+            Err(e) => serde_json::to_writer(&mut writer, &json!({ "error": e.to_string() }))?,
+        }
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Sends one request to the daemon and returns its raw JSON response line.
+pub fn query(socket_path: &Path, request: &DaemonRequest) -> Result<String> {
+    let mut stream = UnixStream::connect(socket_path)?;
+
+    serde_json::to_writer(&mut stream, request)?;
+    writeln!(stream)?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+
+    Ok(response.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::DiskInvertedIndex;
+
+    #[test]
+    fn test_handle_connection() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+
+        let (client, server) = UnixStream::pair().expect("Failed to create socket pair");
+
+        let mut writer = client.try_clone().expect("Failed to clone socket");
+        writer
+            .write_all(b"{\"query\": \"eric\", \"limit\": 1}\nnot json\n")
+            .expect("Failed to write request");
+        writer
+            .shutdown(std::net::Shutdown::Write)
+            .expect("Failed to close socket");
+
+        handle_connection(server, &search_engine).expect("Failed to handle connection");
+
+        let responses: Vec<String> = BufReader::new(client)
+            .lines()
+            .collect::<io::Result<_>>()
+            .expect("Failed to read responses");
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[0],
+            r#"{"query":"eric","total":3,"results":[{"url":"https://www.ericminassian.com/","score":9.1}]}"#
+        );
+        assert!(responses[1].starts_with(r#"{"error":"#));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::search::search_result::SearchResult;
+use std::{env, io::IsTerminal};
+use terminal_size::{terminal_size, Width};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+
+const DEFAULT_WIDTH: usize = 80;
+const MIN_URL_WIDTH: usize = 16;
+const ELLIPSIS: char = '…';
+
+/// Renders search results as an aligned table for the terminal.
+pub struct ResultTable {
+    color: bool,
+    width: usize,
+}
+
+impl ResultTable {
+    #[must_use]
+    pub const fn new(color: bool, width: usize) -> Self {
+        Self { color, width }
+    }
+
+    /// Fits the table to the terminal, coloring only when stdout is a terminal
+    /// and `NO_COLOR` is unset.
+    #[must_use]
+    pub fn for_stdout() -> Self {
+        let color = std::io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+        let width = terminal_size().map_or_else(
+            || {
+                env::var("COLUMNS")
+                    .ok()
+                    .and_then(|columns| columns.parse().ok())
+                    .unwrap_or(DEFAULT_WIDTH)
+            },
+            |(Width(width), _)| usize::from(width),
+        );
+
+        Self::new(color, width)
+    }
+
+    /// One line per result: rank, score colored relative to the top hit, and
+    /// the URL truncated to the width with the query terms highlighted.
+    #[must_use]
+    pub fn render(&self, results: &[SearchResult], query: &str) -> String {
+        let terms = query_terms(query);
+        let top = results
+            .iter()
+            .map(|result| result.score)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let scores = results
+            .iter()
+            .map(|result| format!("{:.3}", result.score))
+            .collect::<Vec<_>>();
+        let rank_width = results.len().to_string().len();
+        let score_width = scores.iter().map(String::len).max().unwrap_or_default();
+        let url_width = self
+            .width
+            .saturating_sub(rank_width + score_width + 4)
+            .max(MIN_URL_WIDTH);
+
+        results
+            .iter()
+            .zip(scores)
+            .enumerate()
+            .map(|(i, (result, score))| {
+                let score = format!("{score:>score_width$}");
+                let url = truncate(&result.url, url_width);
+
+                if self.color {
+                    format!(
+                        "{:>rank_width$}. {}{score}{RESET}  {}",
+                        i + 1,
+                        score_color(result.score, top),
+                        highlight(&url, &terms)
+                    )
+                } else {
+                    format!("{:>rank_width$}. {score}  {url}", i + 1)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn score_color(score: f64, top: f64) -> &'static str {
+    let relative = if top > 0.0 { score / top } else { 0.0 };
+
+    if relative >= 2.0 / 3.0 {
+        GREEN
+    } else if relative >= 1.0 / 3.0 {
+        YELLOW
+    } else {
+        DIM
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+
+    let mut truncated = text.chars().take(width - 1).collect::<String>();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// Wraps every case-insensitive occurrence of the terms in bold.
+fn highlight(text: &str, terms: &[String]) -> String {
+    // ASCII lowercasing keeps byte offsets valid for `text`
+    let lower = text.to_ascii_lowercase();
+
+    let mut matches = terms
+        .iter()
+        .flat_map(|term| {
+            lower
+                .match_indices(term.as_str())
+                .map(|(start, term)| (start, start + term.len()))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_unstable();
+
+    let mut highlighted = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end) in matches {
+        if end <= cursor {
+            continue;
+        }
+        let start = start.max(cursor);
+
+        highlighted.push_str(&text[cursor..start]);
+        highlighted.push_str(BOLD);
+        highlighted.push_str(&text[start..end]);
+        highlighted.push_str(RESET);
+        cursor = end;
+    }
+    highlighted.push_str(&text[cursor..]);
+
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> Vec<SearchResult> {
+        vec![
+            SearchResult::new("https://example.com/rust".to_string(), 12.5),
+            SearchResult::new("https://example.com/rust-and-cooking".to_string(), 1.25),
+        ]
+    }
+
+    #[test]
+    fn render_aligned() {
+        let table = ResultTable::new(false, 80);
+
+        assert_eq!(
+            table.render(&results(), "rust"),
+            "1. 12.500  https://example.com/rust\n2.  1.250  https://example.com/rust-and-cooking"
+        );
+    }
+
+    #[test]
+    fn render_truncates_to_width() {
+        let table = ResultTable::new(false, 30);
+
+        let rendered = table.render(&results(), "rust");
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "2.  1.250  https://example.co…");
+        assert!(lines.iter().all(|line| line.chars().count() <= 30));
+    }
+
+    #[test]
+    fn render_colored() {
+        let table = ResultTable::new(true, 80);
+
+        let rendered = table.render(&results(), "Rust cooking");
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            format!("1. {GREEN}12.500{RESET}  https://example.com/{BOLD}rust{RESET}")
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "2. {DIM} 1.250{RESET}  https://example.com/{BOLD}rust{RESET}-and-{BOLD}cooking{RESET}"
+            )
+        );
+    }
+
+    #[test]
+    fn highlight_unicode() {
+        let terms = vec!["straße".to_string(), "café".to_string()];
+
+        assert_eq!(
+            highlight("https://CAFÉ.example/Straße-und-café", &terms),
+            format!("https://CAFÉ.example/{BOLD}Straße{RESET}-und-{BOLD}café{RESET}")
+        );
+        assert_eq!(truncate("日本語のページ", 4), "日本語…");
+    }
+}
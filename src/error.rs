@@ -1,8 +1,131 @@
+use crate::tokenizer::Analyzer;
+use std::path::PathBuf;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Generic {0}")]
     Generic(String),
 
+    #[error("Interrupted by a shutdown signal")]
+    Interrupted,
+
+    /// A seek file whose keys and positions couldn't be decoded
+    #[error("Failed to decode the keys of {}: {source}", path.display())]
+    KeyDecode {
+        path: PathBuf,
+        source: bincode::Error,
+    },
+
+    /// Contents of an index file that contradict the rest of the index
+    #[error("{} is corrupt at offset {offset}", path.display())]
+    Corrupt { path: PathBuf, offset: u64 },
+
+    /// A posting whose document is not in the url map
+    #[error("Document {doc_id} is not in the url map")]
+    MissingDoc { doc_id: u64 },
+
+    #[error("Failed to initialize the tokenizer: {0}")]
+    TokenizerInit(#[source] regex::Error),
+
+    /// A crawled page that is not a valid crawl file
+    #[error("Invalid crawled page {}: {source}", path.display())]
+    InvalidPage {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    /// A feed or sitemap of a crawl that is not well-formed
+    #[error("Invalid feed {}: {message}", path.display())]
+    InvalidFeed { path: PathBuf, message: String },
+
+    /// A pattern of input files that matches nothing
+    #[error("No {kind} matches {}", pattern.display())]
+    NoMatch {
+        kind: &'static str,
+        pattern: PathBuf,
+    },
+
+    /// A document of a JSON dataset its mapping can't make a page of
+    #[error("Invalid document {index} of {}: {message}", path.display())]
+    InvalidDocument {
+        path: PathBuf,
+        index: u64,
+        message: String,
+    },
+
+    /// A line of an index export that is not a valid record
+    #[error("Invalid export record on line {line}: {source}")]
+    InvalidRecord {
+        line: u64,
+        source: serde_json::Error,
+    },
+
+    /// A line of a boosts file that is not a url and a boost of at least 0
+    #[error("Invalid boost on line {line} of {}", path.display())]
+    InvalidBoost { path: PathBuf, line: u64 },
+
+    /// An index whose build is still in progress
+    #[error("{} is being rebuilt", path.display())]
+    Rebuilding { path: PathBuf },
+
+    /// An index another process is writing
+    #[error("{} is held by another process", path.display())]
+    Locked { path: PathBuf },
+
+    /// An index queried with another analyzer than it was built with
+    #[error("{} was built with the {built:?} analyzer, not {requested:?}", path.display())]
+    AnalyzerMismatch {
+        path: PathBuf,
+        built: Analyzer,
+        requested: Analyzer,
+    },
+
+    /// A companion file of an index built before it was kept
+    #[error("{} is missing, rebuild the index to use it", path.display())]
+    MissingCompanion { path: PathBuf },
+
+    /// Index files written by different builds
+    #[error("{} was written by a different build than the rest of the index", path.display())]
+    MixedBuild { path: PathBuf },
+
+    /// Index files in a format this version can't read
+    #[error("Unsupported index format version {found}, expected {expected}")]
+    UnsupportedVersion { found: u32, expected: u32 },
+
+    /// A custom field name queries couldn't tell from other prefixes
+    #[error("Invalid field name `{name}`")]
+    InvalidFieldName { name: String },
+
+    /// A CSS selector that doesn't parse
+    #[error("Invalid selector `{selector}`: {message}")]
+    InvalidSelector { selector: String, message: String },
+
+    /// A background task or thread pool that panicked, was cancelled or
+    /// couldn't start
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("{task} task failed: {source}")]
+    TaskFailed {
+        task: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// Postings that couldn't be archived or read back in place
+    #[cfg(feature = "rkyv")]
+    #[error("Invalid postings archive: {0}")]
+    Archive(#[source] rkyv::rancor::Error),
+
+    /// A value that couldn't be read or decoded from a database
+    #[error("Bad record for key {key} at offset {offset}: {source}")]
+    Record {
+        key: String,
+        offset: u64,
+        source: Box<Self>,
+    },
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
     #[error(transparent)]
     IO(#[from] std::io::Error),
 
@@ -11,6 +134,31 @@ pub enum Error {
 
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error(transparent)]
+    Readline(#[from] rustyline::error::ReadlineError),
+
+    #[error(transparent)]
+    TomlDeserialize(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlSerialize(#[from] toml::ser::Error),
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Error {
+    /// Maps the failure of the task named `task` for `map_err`.
+    pub fn task_failed(task: &'static str) -> impl FnOnce(tokio::task::JoinError) -> Self {
+        move |source| Self::TaskFailed {
+            task,
+            source: source.into(),
+        }
+    }
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
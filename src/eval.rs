@@ -0,0 +1,266 @@
+use crate::{
+    error::{Error, Result},
+    search::engine::SearchEngine,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io::BufRead,
+};
+
+pub const NDCG_CUTOFF: usize = 10;
+
+/// Relevance grades keyed by query id, then by document URL.
+pub type Qrels = HashMap<String, HashMap<String, u32>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalQuery {
+    pub id: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryEval {
+    pub id: String,
+    pub average_precision: f64,
+    pub ndcg_at_10: f64,
+    pub reciprocal_rank: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub queries: Vec<QueryEval>,
+}
+
+impl EvalReport {
+    #[must_use]
+    pub fn map(&self) -> f64 {
+        self.mean(|query| query.average_precision)
+    }
+
+    #[must_use]
+    pub fn ndcg_at_10(&self) -> f64 {
+        self.mean(|query| query.ndcg_at_10)
+    }
+
+    #[must_use]
+    pub fn mrr(&self) -> f64 {
+        self.mean(|query| query.reciprocal_rank)
+    }
+
+    fn mean(&self, metric: impl Fn(&QueryEval) -> f64) -> f64 {
+        if self.queries.is_empty() {
+            return 0.0;
+        }
+
+        self.queries.iter().map(metric).sum::<f64>() / self.queries.len() as f64
+    }
+}
+
+impl Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Evaluated {} queries", self.queries.len())?;
+        writeln!(f, "  MAP:     {:.4}", self.map())?;
+        writeln!(f, "  nDCG@10: {:.4}", self.ndcg_at_10())?;
+        write!(f, "  MRR:     {:.4}", self.mrr())
+    }
+}
+
+/// Parses `<query id>\t<query text>` lines.
+pub fn parse_queries<R: BufRead>(reader: R) -> Result<Vec<EvalQuery>> {
+    let mut queries = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (id, text) = line.split_once('\t').ok_or_else(|| {
+            Error::Generic(format!(
+                "Query line {} is not `<id>\\t<text>`",
+                line_number + 1
+            ))
+        })?;
+
+        queries.push(EvalQuery {
+            id: id.trim().to_string(),
+            text: text.trim().to_string(),
+        });
+    }
+
+    Ok(queries)
+}
+
+/// Parses TREC qrels lines, `<query id> <iteration> <document url> <grade>`.
+pub fn parse_qrels<R: BufRead>(reader: R) -> Result<Qrels> {
+    let mut qrels = Qrels::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<_> = line.split_whitespace().collect();
+
+        match fields[..] {
+            [] => {}
+            [query_id, _, url, grade] => {
+                let grade = grade.parse().map_err(|e| {
+                    Error::Generic(format!(
+                        "Invalid grade on qrels line {}: {e}",
+                        line_number + 1
+                    ))
+                })?;
+
+                qrels
+                    .entry(query_id.to_string())
+                    .or_default()
+                    .insert(url.to_string(), grade);
+            }
+            _ => {
+                return Err(Error::Generic(format!(
+                    "Qrels line {} does not have 4 fields",
+                    line_number + 1
+                )))
+            }
+        }
+    }
+
+    Ok(qrels)
+}
+
+/// Runs every judged query and scores the top `depth` results against the
+/// qrels. Queries without judgments are skipped, as `trec_eval` does.
+pub fn evaluate(
+    search_engine: &SearchEngine,
+    queries: &[EvalQuery],
+    qrels: &Qrels,
+    depth: usize,
+) -> Result<EvalReport> {
+    let mut evaluated = Vec::new();
+
+    for query in queries {
+        let Some(judgments) = qrels.get(&query.id) else {
+            continue;
+        };
+
+        let mut ranking = Vec::new();
+        search_engine.search_streaming(&query.text, depth, |result| {
+            ranking.push(result.url);
+            true
+        })?;
+
+        evaluated.push(QueryEval {
+            id: query.id.clone(),
+            average_precision: average_precision(&ranking, judgments),
+            ndcg_at_10: ndcg(&ranking, judgments, NDCG_CUTOFF),
+            reciprocal_rank: reciprocal_rank(&ranking, judgments),
+        });
+    }
+
+    Ok(EvalReport { queries: evaluated })
+}
+
+fn is_relevant(judgments: &HashMap<String, u32>, url: &str) -> bool {
+    judgments.get(url).is_some_and(|&grade| grade > 0)
+}
+
+fn average_precision(ranking: &[String], judgments: &HashMap<String, u32>) -> f64 {
+    let num_relevant = judgments.values().filter(|&&grade| grade > 0).count();
+    if num_relevant == 0 {
+        return 0.0;
+    }
+
+    let mut hits = 0;
+    let mut precision_sum = 0.0;
+    for (i, url) in ranking.iter().enumerate() {
+        if is_relevant(judgments, url) {
+            hits += 1;
+            precision_sum += f64::from(hits) / (i + 1) as f64;
+        }
+    }
+
+    precision_sum / num_relevant as f64
+}
+
+fn reciprocal_rank(ranking: &[String], judgments: &HashMap<String, u32>) -> f64 {
+    ranking
+        .iter()
+        .position(|url| is_relevant(judgments, url))
+        .map_or(0.0, |i| 1.0 / (i + 1) as f64)
+}
+
+fn ndcg(ranking: &[String], judgments: &HashMap<String, u32>, cutoff: usize) -> f64 {
+    let gains = ranking
+        .iter()
+        .take(cutoff)
+        .map(|url| judgments.get(url).copied().unwrap_or(0));
+
+    let mut ideal: Vec<u32> = judgments.values().copied().collect();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+
+    let ideal_dcg = dcg(ideal.into_iter().take(cutoff));
+    if ideal_dcg == 0.0 {
+        return 0.0;
+    }
+
+    dcg(gains) / ideal_dcg
+}
+
+fn dcg(grades: impl Iterator<Item = u32>) -> f64 {
+    grades
+        .enumerate()
+        .map(|(i, grade)| (f64::from(grade).exp2() - 1.0) / ((i + 2) as f64).log2())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| (*url).to_string()).collect()
+    }
+
+    fn judgments(grades: &[(&str, u32)]) -> HashMap<String, u32> {
+        grades
+            .iter()
+            .map(|(url, grade)| ((*url).to_string(), *grade))
+            .collect()
+    }
+
+    #[test]
+    fn test_average_precision() {
+        let judgments = judgments(&[("a", 1), ("c", 1), ("x", 1)]);
+        let ap = average_precision(&ranking(&["a", "b", "c"]), &judgments);
+
+        assert!((ap - (1.0 + 2.0 / 3.0) / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_reciprocal_rank() {
+        let judgments = judgments(&[("c", 2), ("b", 0)]);
+
+        assert!((reciprocal_rank(&ranking(&["a", "b", "c"]), &judgments) - 1.0 / 3.0).abs() < 1e-9);
+        assert!(reciprocal_rank(&ranking(&["a", "b"]), &judgments).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ndcg() {
+        let judgments = judgments(&[("a", 2), ("b", 1)]);
+
+        assert!((ndcg(&ranking(&["a", "b"]), &judgments, 10) - 1.0).abs() < 1e-9);
+
+        let swapped = ndcg(&ranking(&["b", "a"]), &judgments, 10);
+        let expected = (1.0 + 3.0 / 3f64.log2()) / (3.0 + 1.0 / 3f64.log2());
+        assert!((swapped - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_qrels() {
+        let qrels = parse_qrels(&b"1 0 https://a.com 2\n\n1 0 https://b.com 0\n"[..])
+            .expect("Failed to parse qrels");
+
+        assert_eq!(qrels["1"]["https://a.com"], 2);
+        assert_eq!(qrels["1"]["https://b.com"], 0);
+        assert!(parse_qrels(&b"1 0 https://a.com\n"[..]).is_err());
+    }
+}
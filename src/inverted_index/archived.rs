@@ -0,0 +1,203 @@
+use super::{
+    disk_inverted_index::{ArchivedTermIndex, TermIndex},
+    doc_map::{DocID, TFIDF},
+};
+use crate::{
+    error::{Error, Result},
+    kv_database::{
+        codec,
+        database::{replace_file, KVDatabase},
+        read_at::ReadAt,
+        scratch,
+        seek_pos_map::{SeekPos, SeekPosMap},
+    },
+};
+use rkyv::{rancor, util::AlignedVec, vec::ArchivedVec};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// Size of the postings database the archive was written from
+    db_len: u64,
+    seek_pos_map: SeekPosMap<String>,
+}
+
+/// Postings encoded with rkyv next to the bincode database, so queries can
+/// walk a posting list in place instead of decoding it into a `Vec` first.
+pub struct ArchivedPostings<R = File> {
+    seek_pos_map: SeekPosMap<String>,
+    data: R,
+    data_path: PathBuf,
+    data_len: u64,
+}
+
+impl ArchivedPostings {
+    /// Archive file next to the postings database at `db_path`.
+    #[must_use]
+    pub fn data_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("rkyv")
+    }
+
+    #[must_use]
+    pub fn seek_path(db_seek_path: &Path) -> PathBuf {
+        db_seek_path.with_extension("rkyv.seek")
+    }
+
+    /// Writes every posting list of `db` to the archive files.
+    pub fn write<R: ReadAt>(
+        db: &KVDatabase<String, Vec<TermIndex>, R>,
+        data_path: &Path,
+        seek_path: &Path,
+    ) -> Result<()> {
+        let temp_data_path = scratch::temp_path(data_path);
+        let mut writer = BufWriter::new(File::create(&temp_data_path)?);
+        let mut seek_pos_map = SeekPosMap::new();
+        let mut pos = 0;
+
+        for entry in db {
+            let (term, postings) = entry?;
+            let bytes = rkyv::to_bytes::<rancor::Error>(&postings).map_err(Error::Archive)?;
+
+            writer.write_all(&bytes)?;
+            seek_pos_map.insert(term, SeekPos::new(pos, bytes.len() as u64));
+            pos += bytes.len() as u64;
+        }
+        writer.flush()?;
+        scratch::persist(&temp_data_path, data_path)?;
+
+        let header = Header {
+            db_len: db.database.size()?,
+            seek_pos_map,
+        };
+        replace_file(seek_path, &codec::serialize(&header)?)?;
+
+        Ok(())
+    }
+
+    /// Opens the archive written from a database of `db_len` bytes. Missing
+    /// archives and ones left over from an earlier build are `None`.
+    pub fn open(data_path: &Path, seek_path: &Path, db_len: u64) -> Result<Option<Self>> {
+        let mut buffer = Vec::new();
+        match File::open(seek_path) {
+            Ok(mut file) => file.read_to_end(&mut buffer)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let header: Header = codec::deserialize(&buffer)?;
+        if header.db_len != db_len {
+            return Ok(None);
+        }
+
+        let data = File::open(data_path)?;
+        Ok(Some(Self {
+            seek_pos_map: header.seek_pos_map,
+            data_len: data.size()?,
+            data,
+            data_path: data_path.to_path_buf(),
+        }))
+    }
+}
+
+impl<R> ArchivedPostings<R> {
+    pub(super) const fn seek_pos_map(&self) -> &SeekPosMap<String> {
+        &self.seek_pos_map
+    }
+}
+
+impl<R: ReadAt> ArchivedPostings<R> {
+    /// Calls `f` with the doc ID and score of every posting of `term`, reading
+    /// the archived list into `bytes`. Returns `false` when the term is not in
+    /// the archive.
+    pub fn for_each<F>(&self, term: &str, bytes: &mut AlignedVec, mut f: F) -> Result<bool>
+    where
+        F: FnMut(DocID, TFIDF),
+    {
+        let Some(seek_pos) = self.seek_pos_map.get(term) else {
+            return Ok(false);
+        };
+
+        let end = seek_pos.pos.checked_add(seek_pos.len);
+        if end.is_none_or(|end| end > self.data_len) {
+            return Err(Error::Corrupt {
+                path: self.data_path.clone(),
+                offset: self.data_len,
+            });
+        }
+
+        // Archived values must be aligned, which a plain `Vec<u8>` doesn't promise
+        bytes.clear();
+        bytes.resize(seek_pos.len as usize, 0);
+        self.data.read_exact_at(bytes, seek_pos.pos)?;
+
+        let postings = rkyv::access::<ArchivedVec<ArchivedTermIndex>, rancor::Error>(bytes)
+            .map_err(Error::Archive)?;
+        for posting in postings.iter() {
+            f(posting.doc_id.to_native(), posting.tf_idf.to_native());
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn matches_bincode_postings() {
+        let dir = TestDir::new();
+        let db: KVDatabase<String, Vec<TermIndex>> = KVDatabase::from(
+            "tests/test-data/search_test_db.test".into(),
+            "tests/test-data/search_test_seek.test".into(),
+        )
+        .expect("Failed to open test index");
+        let data_path = dir.path("archived_postings.db");
+        let seek_path = dir.path("archived_postings.seek");
+
+        ArchivedPostings::write(&db, &data_path, &seek_path).expect("Failed to write archive");
+        let db_len = db.database.size().expect("Failed to read db size");
+        let archived = ArchivedPostings::open(&data_path, &seek_path, db_len)
+            .expect("Failed to open archive")
+            .expect("Archive should match the database");
+        let stale = ArchivedPostings::open(&data_path, &seek_path, db_len + 1)
+            .expect("Failed to open archive");
+
+        let mut bytes = AlignedVec::new();
+        let mut postings = Vec::new();
+        let found = archived
+            .for_each("eric", &mut bytes, |doc_id, tf_idf| {
+                postings.push(TermIndex { doc_id, tf_idf });
+            })
+            .expect("Failed to read archive");
+
+        assert!(found);
+        assert_eq!(
+            Some(postings),
+            db.get(&"eric".to_string())
+                .expect("Failed to read postings")
+        );
+        assert!(!archived
+            .for_each("not_in_index", &mut bytes, |_, _| {})
+            .expect("Failed to read archive"));
+        assert!(stale.is_none());
+
+        let len = std::fs::metadata(&data_path)
+            .expect("Failed to stat archive")
+            .len();
+        std::fs::write(&data_path, vec![0xff; len as usize]).expect("Failed to corrupt archive");
+        let corrupt = ArchivedPostings::open(&data_path, &seek_path, db_len)
+            .expect("Failed to open archive")
+            .expect("Archive should match the database");
+        assert!(matches!(
+            corrupt.for_each("eric", &mut bytes, |_, _| {}),
+            Err(Error::Archive(_))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use super::{disk_inverted_index::CrawlFile, doc_map::DocID};
+use crate::{
+    error::{Error, Result},
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Static boosts of the documents a build boosted or demoted, written next to
+/// the postings database so the ranker can weigh them without reading the url
+/// map.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocBoosts {
+    pub boosts: Vec<(DocID, f32)>,
+}
+
+impl DocBoosts {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.boosts", db_path.display()))
+    }
+
+    /// Keeps the `boost` of `doc_id`, unless it leaves scores as they are.
+    pub fn add(&mut self, doc_id: DocID, boost: Option<f32>) {
+        if let Some(boost) = boost.filter(|boost| (boost - 1.0).abs() > f32::EPSILON) {
+            self.boosts.push((doc_id, boost));
+        }
+    }
+
+    /// The boosts at `path`, `None` for indexes built before they were kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
+
+/// Boosts operators assign to urls in a CSV file of `url,boost` lines, to pin
+/// pages above their rank or demote them below it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UrlBoosts {
+    boosts: HashMap<String, f32>,
+}
+
+impl UrlBoosts {
+    /// Reads the CSV file at `path`, no boosts for an empty path. Blank lines
+    /// and `#` comments are skipped, and so is a header line.
+    pub fn read(path: &Path) -> Result<Self> {
+        if path.as_os_str().is_empty() {
+            return Ok(Self::default());
+        }
+
+        let mut boosts = HashMap::new();
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || Error::InvalidBoost {
+                path: path.to_path_buf(),
+                line: i as u64 + 1,
+            };
+            let (url, boost) = line.rsplit_once(',').ok_or_else(invalid)?;
+            let boost = match boost.trim().parse::<f32>() {
+                Ok(boost) if boost.is_finite() && boost >= 0.0 => boost,
+                Err(_) if i == 0 => continue,
+                _ => return Err(invalid()),
+            };
+            boosts.insert(url.trim().to_string(), boost);
+        }
+
+        Ok(Self { boosts })
+    }
+
+    /// Sets the boost of the `documents` the file lists, over any their crawl
+    /// files carry.
+    pub fn apply<I>(self, documents: I) -> impl Iterator<Item = Result<CrawlFile>>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
+    {
+        documents.into_iter().map(move |document| {
+            document.map(|mut document| {
+                if let Some(&boost) = self.boosts.get(&document.url) {
+                    document.boost = Some(boost);
+                }
+                document
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{page, TestDir};
+
+    #[test]
+    fn url_boosts() {
+        let dir = TestDir::new();
+        let path = dir.path("url_boosts.csv");
+        fs::write(
+            &path,
+            "url,boost\n# Pinned\nhttps://example.com/a, 2.5\n\nhttps://example.com/b,0\n",
+        )
+        .expect("Failed to write boosts");
+        let page = |name: &str| CrawlFile {
+            boost: Some(3.0),
+            ..page(name, "")
+        };
+
+        let boosts = UrlBoosts::read(&path).expect("Failed to read boosts");
+        let documents: Vec<_> = boosts
+            .apply([Ok(page("a")), Ok(page("b")), Ok(page("c"))])
+            .map(|document| document.expect("Failed to apply boosts").boost)
+            .collect();
+        assert_eq!(documents, [Some(2.5), Some(0.0), Some(3.0)]);
+
+        fs::write(&path, "https://example.com/a,1\nhttps://example.com/b,-1\n")
+            .expect("Failed to write boosts");
+        assert!(matches!(
+            UrlBoosts::read(&path),
+            Err(Error::InvalidBoost { line: 2, .. })
+        ));
+        assert_eq!(
+            UrlBoosts::read(Path::new("")).expect("Failed to read boosts"),
+            UrlBoosts::default()
+        );
+
+        let mut doc_boosts = DocBoosts::default();
+        doc_boosts.add(0, None);
+        doc_boosts.add(1, Some(1.0));
+        doc_boosts.add(2, Some(0.5));
+        assert_eq!(doc_boosts.boosts, [(2, 0.5)]);
+    }
+}
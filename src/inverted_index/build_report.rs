@@ -0,0 +1,133 @@
+use super::disk_inverted_index::BuildStats;
+use crate::{error::Result, kv_database::database::replace_file, tokenizer::Analyzer};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// What a build did, written next to the postings database as JSON so builds
+/// can be audited without opening the index.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildReport {
+    /// Seconds since the Unix epoch when the build finished
+    pub finished_at: u64,
+    pub num_docs: u64,
+    /// Documents left out, like second copies of a url
+    pub skipped_docs: u64,
+    pub num_tokens: u64,
+    /// Distinct terms, url and site terms included
+    pub num_terms: u64,
+    pub parse_ms: u64,
+    pub flush_ms: u64,
+    pub score_ms: u64,
+    /// Fingerprint of the config the build ran with, `None` for builds that
+    /// didn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    /// Analyzer the documents went through, which queries have to use too.
+    /// `None` for builds before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analyzer: Option<Analyzer>,
+}
+
+impl BuildReport {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.report.json", db_path.display()))
+    }
+
+    /// The report of a build that just finished with `stats`.
+    #[must_use]
+    pub fn new(stats: &BuildStats, config_hash: Option<&str>) -> Self {
+        let millis = |duration: std::time::Duration| duration.as_millis() as u64;
+
+        Self {
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            num_docs: stats.num_docs,
+            skipped_docs: stats.skipped_docs,
+            num_tokens: stats.num_tokens,
+            num_terms: stats.num_terms,
+            parse_ms: millis(stats.parse_time),
+            flush_ms: millis(stats.flush_time),
+            score_ms: millis(stats.score_time),
+            config_hash: config_hash.map(str::to_string),
+            analyzer: None,
+        }
+    }
+
+    /// The report at `path`, `None` for indexes built before reports were
+    /// written.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+impl Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Built {} documents ({} skipped) and {} terms at {}: parse {} ms, flush {} ms, score {} ms",
+            self.num_docs,
+            self.skipped_docs,
+            self.num_terms,
+            self.finished_at,
+            self.parse_ms,
+            self.flush_ms,
+            self.score_ms
+        )?;
+        if let Some(config_hash) = &self.config_hash {
+            write!(f, ", config {config_hash}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use std::time::Duration;
+
+    #[test]
+    fn build_report() {
+        let dir = TestDir::new();
+        let path = dir.path("build_report.report.json");
+        let stats = BuildStats {
+            num_docs: 3,
+            skipped_docs: 1,
+            num_terms: 7,
+            parse_time: Duration::from_millis(12),
+            ..BuildStats::default()
+        };
+
+        let report = BuildReport::new(&stats, Some("abc"));
+        assert_eq!((report.num_docs, report.skipped_docs), (3, 1));
+        assert_eq!(report.parse_ms, 12);
+        report.write(&path).expect("Failed to write report");
+        assert_eq!(
+            BuildReport::read(&path).expect("Failed to read report"),
+            Some(report)
+        );
+
+        fs::remove_file(&path).expect("Failed to remove report");
+        assert_eq!(
+            BuildReport::read(&path).expect("Failed to read report"),
+            None
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Character encodings of fetched pages, so pages served in legacy
+//! encodings like Latin-1 or Shift-JIS are indexed as text rather than
+//! mojibake.
+//!
+//! Encodings are picked the way browsers pick them: a byte order mark first,
+//! then the charset of the `Content-Type` header, then a `<meta>` charset
+//! near the top of the page. Pages declaring nothing are UTF-8 when they
+//! decode as such and windows-1252 otherwise.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+
+/// How far into a page `<meta>` charsets are looked for, as browsers do.
+const PRESCAN_BYTES: usize = 1024;
+
+/// `body` as served with `content_type`, decoded to UTF-8.
+#[must_use]
+pub fn decode_body(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(content_type_charset))
+        .or_else(|| sniff_meta(body))
+        .unwrap_or_else(|| {
+            if std::str::from_utf8(body).is_ok() {
+                UTF_8
+            } else {
+                WINDOWS_1252
+            }
+        });
+
+    // Strips the BOM, which takes precedence over the encoding if there is one
+    encoding.decode(body).0.into_owned()
+}
+
+/// `content` of a crawl file whose `encoding` it was read in, as UTF-8.
+///
+/// Crawlers that do not decode pages store them a byte per character, which
+/// is undone here before decoding. Content with characters beyond a byte was
+/// decoded already and is left alone, as is content of unknown encodings
+/// without a `<meta>` charset.
+#[must_use]
+pub fn decode_content<'a>(content: &'a str, encoding: &str) -> Cow<'a, str> {
+    let Some(encoding) =
+        Encoding::for_label(encoding.trim().as_bytes()).or_else(|| sniff_meta(content.as_bytes()))
+    else {
+        return Cow::Borrowed(content);
+    };
+    if encoding == UTF_8 {
+        return Cow::Borrowed(content);
+    }
+
+    let Some(bytes) = content
+        .chars()
+        .map(|c| u8::try_from(c).ok())
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Cow::Borrowed(content);
+    };
+
+    Cow::Owned(encoding.decode_without_bom_handling(&bytes).0.into_owned())
+}
+
+/// The encoding of a `Content-Type` like `text/html; charset=Shift_JIS`.
+fn content_type_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+    })
+}
+
+/// The encoding a `<meta>` tag at the top of `page` declares, either
+/// `<meta charset="...">` or the `http-equiv` form with a content type.
+fn sniff_meta(page: &[u8]) -> Option<&'static Encoding> {
+    let head = page[..page.len().min(PRESCAN_BYTES)].to_ascii_lowercase();
+
+    let mut rest = head.as_slice();
+    while let Some(start) = find(rest, b"<meta") {
+        let tag = &rest[start..];
+        let tag = &tag[..find(tag, b">").unwrap_or(tag.len())];
+        if let Some(encoding) = find(tag, b"charset")
+            .and_then(|at| charset_value(&tag[at + b"charset".len()..]))
+            .and_then(Encoding::for_label)
+        {
+            // A page can only be in UTF-16 if it has a BOM, see the HTML
+            // prescan algorithm
+            return Some(if encoding.output_encoding() == UTF_8 {
+                UTF_8
+            } else {
+                encoding
+            });
+        }
+        rest = &rest[start + b"<meta".len()..];
+    }
+
+    None
+}
+
+/// The label after `charset` in a tag, as in `="utf-8"` or `=utf-8;`.
+fn charset_value(after: &[u8]) -> Option<&[u8]> {
+    let value = after
+        .trim_ascii_start()
+        .strip_prefix(b"=")?
+        .trim_ascii_start();
+    let value = value
+        .strip_prefix(b"\"")
+        .or_else(|| value.strip_prefix(b"'"))
+        .unwrap_or(value);
+    let end = value
+        .iter()
+        .position(|&b| matches!(b, b'"' | b'\'' | b';' | b'/' | b'>') || b.is_ascii_whitespace())
+        .unwrap_or(value.len());
+
+    (end > 0).then_some(&value[..end])
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies() {
+        let (shift_jis, _, _) = encoding_rs::SHIFT_JIS.encode("<p>日本語</p>");
+        assert_eq!(
+            decode_body(&shift_jis, Some("text/html; charset=Shift_JIS")),
+            "<p>日本語</p>"
+        );
+
+        let mut page = b"<html><head><meta charset='iso-8859-1'></head>".to_vec();
+        page.extend_from_slice(b"<p>caf\xe9</p></html>");
+        assert_eq!(
+            decode_body(&page, Some("text/html")),
+            "<html><head><meta charset='iso-8859-1'></head><p>café</p></html>"
+        );
+
+        let page =
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=EUC-JP\">\xc6\xfc";
+        assert!(decode_body(page, None).ends_with('日'));
+
+        assert_eq!(
+            decode_body(
+                b"\xef\xbb\xbfcaf\xc3\xa9",
+                Some("text/html; charset=latin1")
+            ),
+            "café"
+        );
+        assert_eq!(decode_body("café".as_bytes(), None), "café");
+        assert_eq!(decode_body(b"caf\xe9", None), "café");
+    }
+
+    #[test]
+    fn contents() {
+        assert_eq!(decode_content("caf\u{e9}", "ISO-8859-1"), "café");
+        assert_eq!(
+            decode_content("\u{93}quoted\u{94}", "windows-1252"),
+            "“quoted”"
+        );
+
+        let (shift_jis, _, _) = encoding_rs::SHIFT_JIS.encode("日本語");
+        let stored: String = shift_jis.iter().map(|&b| char::from(b)).collect();
+        assert_eq!(decode_content(&stored, "shift_jis"), "日本語");
+        assert_eq!(decode_content("日本語", "shift_jis"), "日本語");
+
+        let sniffed = "<meta charset=\"windows-1252\">\u{80}";
+        assert_eq!(
+            decode_content(sniffed, ""),
+            "<meta charset=\"windows-1252\">€"
+        );
+        assert!(matches!(
+            decode_content("caf\u{e9}", "utf-8"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            decode_content("caf\u{e9}", "unknown"),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -1,5 +1,4 @@
 pub const MAX_ITERATIONS: u64 = 20_000;
-pub const TEMP_FILE_SUFFIX: &str = "tmp";
 pub const BOLD_WEIGHT: f32 = 2.0;
 pub const HEADER_WEIGHT: f32 = 4.0;
 pub const TITLE_WEIGHT: f32 = 9.0;
@@ -0,0 +1,45 @@
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Corpus totals a build writes next to the postings database, for figures
+/// the seek maps can't give without reading every document.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusStats {
+    pub num_docs: u64,
+    /// Tokens in the text of all documents
+    pub num_tokens: u64,
+}
+
+impl CorpusStats {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.stats", db_path.display()))
+    }
+
+    /// The stats at `path`, `None` for indexes built before they were kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+
+    /// Mean number of tokens per document, `None` for an empty corpus.
+    #[must_use]
+    pub fn average_doc_length(&self) -> Option<f64> {
+        (self.num_docs > 0).then(|| self.num_tokens as f64 / self.num_docs as f64)
+    }
+}
@@ -0,0 +1,203 @@
+use super::disk_inverted_index::{read_crawled_data, CrawlFile};
+use crate::error::{Error, Result};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The pages of every directory `patterns` match, one corpus after another.
+///
+/// Corpora of more than one directory tag their pages with the directory
+/// they were read from, unless the crawl file names a source of its own. A
+/// directory matched twice is read once, and a pattern matching nothing is an
+/// error rather than an empty corpus.
+pub fn read_crawl_sources(patterns: &[PathBuf]) -> Result<impl Iterator<Item = Result<CrawlFile>>> {
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        let matched = expand(pattern);
+        if matched.is_empty() {
+            return Err(Error::NoMatch {
+                kind: "crawled data",
+                pattern: pattern.clone(),
+            });
+        }
+        for source in matched {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+
+    let tagged = sources.len() > 1;
+    Ok(sources.into_iter().flat_map(move |source| {
+        let tag = tagged.then(|| source.display().to_string());
+        read_crawled_data(source).map(move |page| {
+            page.map(|mut page| {
+                if page.source.is_none() {
+                    page.source.clone_from(&tag);
+                }
+                page
+            })
+        })
+    }))
+}
+
+/// The paths `pattern` matches, sorted within each wildcard component.
+///
+/// Components may hold `*` for any run of characters and `?` for any one,
+/// neither crossing a separator. Patterns without wildcards come back as
+/// they are, whether the path exists or not.
+#[must_use]
+pub fn expand(pattern: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let name = component.as_os_str().to_string_lossy();
+        if !matches!(component, Component::Normal(_)) || !name.contains(['*', '?']) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+
+        paths = paths
+            .iter()
+            .flat_map(|dir| {
+                let mut children: Vec<_> = read_dir(dir)
+                    .filter(|child| {
+                        child
+                            .file_name()
+                            .and_then(|child| child.to_str())
+                            .is_some_and(|child| wildcard_match(&name, child))
+                    })
+                    .collect();
+                children.sort_unstable();
+                children
+            })
+            .collect();
+    }
+
+    paths
+}
+
+/// Entries of `dir`, the working directory when it is empty. Unreadable
+/// directories have none.
+fn read_dir(dir: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let entries = fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    });
+
+    entries
+        .into_iter()
+        .flatten()
+        .filter_map(std::result::Result::ok)
+        .map(move |entry| dir.join(entry.file_name()))
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for any one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let name: Vec<_> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Pattern index after the last `*` and the name index it resumes from
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                let Some((after_star, resume)) = star else {
+                    return false;
+                };
+                p = after_star;
+                n = resume + 1;
+                star = Some((after_star, n));
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{page, TestDir};
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("2024-*", "2024-01"));
+        assert!(wildcard_match("*-0?", "2024-01"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("2024-?", "2024-01"));
+        assert!(!wildcard_match("*.json", "page.html"));
+    }
+
+    #[test]
+    fn crawl_sources() {
+        let dir = TestDir::new();
+        let root = dir.path("crawl_sources");
+        let page = |source: &str, name: &str| {
+            let source = root.join(source);
+            fs::create_dir_all(&source).expect("Failed to create source");
+            let file = page(name, "text");
+            fs::write(
+                source.join(format!("{name}.json")),
+                serde_json::to_vec(&file).expect("Failed to serialize page"),
+            )
+            .expect("Failed to write page");
+        };
+        page("2024-01", "a");
+        page("2024-02", "b");
+        page("other", "c");
+
+        assert_eq!(
+            expand(&root.join("2024-*")),
+            [root.join("2024-01"), root.join("2024-02")]
+        );
+        assert_eq!(expand(&root.join("missing")), [root.join("missing")]);
+        assert!(expand(&root.join("19*")).is_empty());
+
+        let pages: Vec<_> = read_crawl_sources(&[root.join("2024-*"), root.join("2024-01")])
+            .expect("Failed to read sources")
+            .map(|page| {
+                let page = page.expect("Failed to read page");
+                (page.url, page.source)
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                (
+                    "https://example.com/a".to_string(),
+                    Some(root.join("2024-01").display().to_string())
+                ),
+                (
+                    "https://example.com/b".to_string(),
+                    Some(root.join("2024-02").display().to_string())
+                ),
+            ]
+        );
+
+        let page = read_crawl_sources(&[root.join("oth?r")])
+            .expect("Failed to read sources")
+            .next()
+            .expect("Missing page")
+            .expect("Failed to read page");
+        assert_eq!(page.source, None);
+        assert!(matches!(
+            read_crawl_sources(&[root.join("19*")]),
+            Err(Error::NoMatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,40 @@
+use super::doc_map::DocID;
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// When the documents of a build were crawled, written next to the postings
+/// database so recency ranking doesn't read the url map.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlTimes {
+    /// Seconds since the Unix epoch, for documents whose crawl time is known
+    pub times: Vec<(DocID, u64)>,
+}
+
+impl CrawlTimes {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.crawled", db_path.display()))
+    }
+
+    /// The crawl times at `path`, `None` for indexes built before they were
+    /// kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
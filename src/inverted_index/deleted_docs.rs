@@ -0,0 +1,40 @@
+use super::doc_map::DocID;
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Documents deleted since the index was last built or compacted, written
+/// next to the postings database so queries skip the postings they left
+/// behind.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedDocs {
+    pub doc_ids: Vec<DocID>,
+}
+
+impl DeletedDocs {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.deleted", db_path.display()))
+    }
+
+    /// The documents at `path`, `None` for indexes nothing was ever deleted
+    /// from.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
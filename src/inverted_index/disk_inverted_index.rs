@@ -1,30 +1,217 @@
+#[cfg(feature = "rkyv")]
+use super::archived::ArchivedPostings;
 use super::{
-    constants::{BOLD_WEIGHT, HEADER_WEIGHT, MAX_ITERATIONS, TEMP_FILE_SUFFIX, TITLE_WEIGHT},
-    doc_map::{Doc, DocID, DocMap, TF, TFIDF},
+    boost::DocBoosts,
+    build_report::BuildReport,
+    charset::decode_content,
+    constants::MAX_ITERATIONS,
+    corpus_stats::CorpusStats,
+    crawl_times::CrawlTimes,
+    deleted_docs::DeletedDocs,
+    doc_filter::{DocFilter, FlaggedDocs},
+    doc_ids::DocIds,
+    doc_lengths::DocLengths,
+    doc_map::{Doc, DocID, DocMap, DocPositions, DocTerms, Positions, Terms, TF, TFIDF},
+    feeds::{is_feed, read_feed},
+    fields::{check_field_name, field_term, FieldSelectors, IndexedFields, WEIGHTED_SELECTORS},
+    generation::Generation,
+    lock::IndexLock,
+    posting_runs::PostingRuns,
+    quality::{duplicate_title_penalty, page_quality, DocQuality},
+    synonyms::Synonyms,
+    term_stats::TermStats,
+    url_fields::url_field_terms,
+    word_frequencies::WordFrequencies,
 };
 use crate::{
     error::{Error, Result},
-    kv_database::database::KVDatabase,
-    tokenizer::Tokenizer,
+    kv_database::{
+        codec,
+        database::{KVDatabase, KVDatabaseWriter, MemoryKVDatabase},
+        read_at::ReadAt,
+        scratch,
+        seek_pos_map::entries_size,
+    },
+    links::document_links,
+    shutdown,
+    tokenizer::{Analyzer, Tokenizer},
 };
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+#[cfg(feature = "rkyv")]
+use rkyv::util::AlignedVec;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs::{rename, File},
-    io::BufReader,
-    path::PathBuf,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+    fs::{remove_file, File},
+    hash::Hash,
+    io::{BufReader, ErrorKind},
+    mem::size_of,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
+use url::Url;
+use uuid::Uuid;
 use walkdir::WalkDir;
 
+/// A fetched page as stored in the crawled data directory.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct CrawlFile {
-    url: String,
-    content: String,
-    encoding: String,
+pub struct CrawlFile {
+    pub url: String,
+    pub content: String,
+    /// Encoding the page was served in, which `content` still holds it in if
+    /// the crawler stored it a byte per character
+    pub encoding: String,
+    /// Seconds since the Unix epoch, missing from files of older crawls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crawled_at: Option<u64>,
+    /// Static boost multiplied into the scores of the page, to pin or demote
+    /// it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boost: Option<f32>,
+    /// Crawl the page was read from, for indexes built from several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Text of custom fields the document came with rather than its markup,
+    /// by name. Only clauses of their field match it, see [`FieldSelectors`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
 }
 
+impl CrawlFile {
+    /// The page with its content transcoded to UTF-8, see [`decode_content`].
+    #[must_use]
+    pub fn decoded(mut self) -> Self {
+        if let Cow::Owned(content) = decode_content(&self.content, &self.encoding) {
+            self.content = content;
+            self.encoding = "utf-8".to_string();
+        }
+        self
+    }
+}
+
+/// What a build does to documents besides indexing their text.
+#[derive(Default, Clone, Copy)]
+pub struct BuildOptions<'a> {
+    /// Picks the documents queries with safe search exclude
+    pub filter: Option<&'a dyn DocFilter>,
+    /// Words indexed at the positions of the key word wherever documents
+    /// contain it, see [`Synonyms`]
+    pub synonyms: Option<&'a BTreeMap<String, Vec<String>>>,
+    /// When the documents parsed so far are written out
+    pub flush: FlushPolicy,
+    /// Fingerprint of the config the build ran with, for its report
+    pub config_hash: Option<&'a str>,
+    /// CSS selectors of custom fields by name, see [`FieldSelectors`]
+    pub fields: Option<&'a BTreeMap<String, String>>,
+    /// Threads parsing documents, 0 for one per core
+    pub threads: usize,
+    /// How the text of documents is split and stemmed, which queries of the
+    /// index have to match
+    pub analyzer: Analyzer,
+}
+
+/// When a build writes out the documents it parsed, freeing their memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlushPolicy {
+    /// Documents parsed between flushes, 0 for no limit
+    pub batch_docs: u64,
+    /// Estimated mebibytes the parsed documents take before a flush, 0 for
+    /// no limit
+    pub memory_budget_mb: u64,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            batch_docs: MAX_ITERATIONS,
+            memory_budget_mb: 0,
+        }
+    }
+}
+
+impl FlushPolicy {
+    /// Whether a batch of `docs` documents estimated to take `bytes` is due to
+    /// be written out.
+    #[must_use]
+    pub const fn is_due(&self, docs: u64, bytes: u64) -> bool {
+        (self.batch_docs > 0 && docs >= self.batch_docs)
+            || (self.memory_budget_mb > 0 && bytes >= self.memory_budget_mb * 1024 * 1024)
+    }
+}
+
+/// What the indexer takes from the HTML of a page.
+#[derive(Debug, Default)]
+pub struct ParsedPage {
+    /// Weighted term frequencies
+    pub word_count: HashMap<String, u32>,
+    /// Tokens in the text of the page
+    pub num_tokens: usize,
+    pub title: Option<String>,
+    /// Primary subtag of the `lang` attribute of the root element
+    pub language: Option<String>,
+    /// Distinct pages linked to, other than the page itself
+    pub links: BTreeSet<String>,
+    /// Token offsets of every term in the text
+    pub positions: HashMap<String, Vec<u32>>,
+    /// Distinct words of the text, unstemmed
+    pub words: HashSet<String>,
+    /// Bytes of text, markup left out
+    pub text_len: usize,
+    /// Text of the custom fields of the build, by name
+    pub fields: BTreeMap<String, String>,
+}
+
+impl ParsedPage {
+    /// Rough bytes the postings, forward index entry and positions of the
+    /// page take in a build until they are written out.
+    fn batch_size(&self) -> u64 {
+        let terms: usize = self
+            .word_count
+            .keys()
+            .map(|term| 2 * term.len() + size_of::<TempTermIndex>() + size_of::<(String, TF)>())
+            .sum();
+        let positions: usize = self
+            .positions
+            .iter()
+            .map(|(term, positions)| {
+                term.len() + size_of::<(String, Vec<u32>)>() + positions.len() * size_of::<u32>()
+            })
+            .sum();
+        let title = self.title.as_ref().map_or(0, String::len);
+        let fields: usize = self
+            .fields
+            .iter()
+            .map(|(name, text)| name.len() + 2 * text.len())
+            .sum();
+
+        (terms + positions + title + fields + size_of::<Doc>()) as u64
+    }
+}
+
+/// Documents a build reads before parsing them all at once, enough to keep
+/// every thread busy without holding many pages in memory.
+const PARSE_CHUNK_DOCS: usize = 256;
+
+/// Encoded size of a [`TermIndex`] and of the length prefix of a posting list.
+const POSTING_LEN: u64 = 16;
+const POSTINGS_PREFIX_LEN: u64 = 8;
+
+/// One posting of a term.
+///
+/// Postings are stored as fixed-width bincode, [`POSTING_LEN`] bytes each,
+/// rather than varint/delta-coded blocks, so decoding is a plain copy with no
+/// bit unpacking that a SIMD path could speed up. Such a path would also need
+/// `unsafe` intrinsics, which the crate forbids.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
 pub struct TermIndex {
     pub doc_id: DocID,
     pub tf_idf: TFIDF,
@@ -36,15 +223,243 @@ pub struct TempTermIndex {
     pub tf: TF,
 }
 
+/// Buffers reused across posting reads so lookups don't allocate for the raw
+/// bytes of every posting list.
+#[derive(Debug, Default)]
+pub struct ReadBuffer {
+    bytes: Vec<u8>,
+    #[cfg(feature = "rkyv")]
+    aligned: AlignedVec,
+}
+
+/// Term frequencies of the documents holding a term, in doc ID order.
+pub type TermFreqs = Vec<(DocID, TF)>;
+
 pub type TempInvertedIndex = HashMap<String, Vec<TempTermIndex>>;
 pub type InvertedIndex = HashMap<String, Vec<TermIndex>>;
 
-pub struct DiskInvertedIndex {
-    pub db: KVDatabase<String, Vec<TermIndex>>,
-    pub url_map: KVDatabase<DocID, Doc>,
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BuildStats {
+    pub num_docs: u64,
+    /// Documents left out, like second copies of a url
+    pub skipped_docs: u64,
+    pub num_tokens: u64,
+    pub num_terms: u64,
+    pub parse_time: Duration,
+    pub flush_time: Duration,
+    pub score_time: Duration,
+}
+
+/// What [`DiskInvertedIndex::compact`] removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactStats {
+    /// How much smaller the db files of the index got
+    pub bytes_reclaimed: u64,
+    /// Postings of documents missing from the url map
+    pub dropped_postings: u64,
+    /// Terms left without postings
+    pub dropped_terms: u64,
+}
+
+impl Display for CompactStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Reclaimed {} bytes ({} postings and {} terms of documents no longer in the url map)",
+            self.bytes_reclaimed, self.dropped_postings, self.dropped_terms
+        )
+    }
+}
+
+/// What [`DiskInvertedIndex::repair`] rebuilt.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairStats {
+    /// Seek files rebuilt from their databases
+    pub seek_files: u64,
+    /// Bytes at the end of the databases that hold no readable record, left
+    /// out of the rebuilt seek files
+    pub truncated_bytes: u64,
+}
+
+impl Display for RepairStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rebuilt {} seek files, leaving out {} unreadable bytes at the end of the databases",
+            self.seek_files, self.truncated_bytes
+        )
+    }
+}
+
+/// What [`DiskInvertedIndex::update_with`] changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateStats {
+    /// Documents of urls the index didn't hold
+    pub added: u64,
+    /// Documents indexed again in place of their previous version
+    pub replaced: u64,
+    /// Documents of removed urls that were in the index
+    pub removed: u64,
+}
+
+impl Display for UpdateStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Added {} documents, reindexed {} and removed {}",
+            self.added, self.replaced, self.removed
+        )
+    }
+}
+
+/// Approximate memory an open index holds outside its files, in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryStats {
+    /// Entries of the postings, url map and archive seek maps
+    pub seek_maps: usize,
+    /// Term strings keying the postings, term stats and archive seek maps
+    pub dictionary: usize,
+    /// Preloaded terms held in memory, a count rather than bytes
+    pub cache_terms: usize,
+    /// Postings pinned by [`DiskInvertedIndex::preload`], terms included
+    pub cache: usize,
+}
+
+impl MemoryStats {
+    #[must_use]
+    pub const fn total(&self) -> usize {
+        self.seek_maps + self.dictionary + self.cache
+    }
+}
+
+impl Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = |bytes: usize| bytes as f64 / 1024.0 / 1024.0;
+        write!(
+            f,
+            "{:.1} MiB (seek maps {:.1} MiB, dictionary {:.1} MiB, cache {:.1} MiB for {} terms)",
+            mib(self.total()),
+            mib(self.seek_maps),
+            mib(self.dictionary),
+            mib(self.cache),
+            self.cache_terms
+        )
+    }
+}
+
+/// An index opened from its files.
+///
+/// Every index is a fixed set of files, each opened once for the life of the
+/// index: the databases below and the postings archive. Indexes aren't split
+/// into segments or shards, so open files grow with the number of hosted
+/// indexes rather than with their size, and there is nothing for a cache of
+/// file descriptors to open and close on demand.
+pub struct DiskInvertedIndex<R = File> {
+    pub db: KVDatabase<String, Vec<TermIndex>, R>,
+    pub url_map: KVDatabase<DocID, Doc, R>,
+    /// Reverse of the url map, missing from indexes built before it existed
+    url_ids: Option<KVDatabase<String, DocID, R>>,
+    /// Terms of every document, also missing from older indexes
+    forward: Option<KVDatabase<DocID, Terms, R>>,
+    /// Term positions of every document, also missing from older indexes
+    positions: Option<KVDatabase<DocID, Positions, R>>,
+    /// Stats of every term, also missing from older indexes
+    term_stats: Option<KVDatabase<String, TermStats, R>>,
+    /// Term frequencies behind the postings of every term, also missing from
+    /// older indexes
+    term_freqs: Option<KVDatabase<String, TermFreqs, R>>,
+    /// Missing from indexes built before corpus stats were kept
+    corpus_stats: Option<CorpusStats>,
+    /// Missing from indexes built before word frequencies were kept
+    word_frequencies: Option<WordFrequencies>,
+    /// Documents the filter of the build flagged
+    flagged: HashSet<DocID>,
+    /// Documents deleted since the last build or compaction, whose postings
+    /// are still in the index
+    deleted: HashSet<DocID>,
+    /// Documents the build scored below full quality
+    quality: HashMap<DocID, f32>,
+    /// Documents the build boosted or demoted
+    boosts: HashMap<DocID, f32>,
+    /// Documents whose crawl time is known
+    crawl_times: HashMap<DocID, u64>,
+    /// Latest of `crawl_times`, which ages count from
+    newest_crawl: u64,
+    /// Tokens of every document, missing from older indexes
+    doc_lengths: HashMap<DocID, u32>,
+    /// What the build did, missing from older indexes
+    build_report: Option<BuildReport>,
+    /// Names of the custom fields of the build
+    fields: Vec<String>,
+    /// Postings kept in memory by [`DiskInvertedIndex::preload`]
+    preloaded: HashMap<String, Vec<TermIndex>>,
+    generation: u64,
+    #[cfg(feature = "rkyv")]
+    archived: Option<ArchivedPostings>,
 }
 
+pub type MemoryInvertedIndex = DiskInvertedIndex<Vec<u8>>;
+
 impl DiskInvertedIndex {
+    /// File of the url → doc ID map kept next to the url map or url map seek
+    /// file at `path`.
+    #[must_use]
+    pub fn url_ids_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.urls", path.display()))
+    }
+
+    /// File of the doc ID → terms map kept next to the postings database or
+    /// seek file at `path`.
+    #[must_use]
+    pub fn forward_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.forward", path.display()))
+    }
+
+    /// File of the doc ID → term positions map kept next to the postings
+    /// database or seek file at `path`.
+    #[must_use]
+    pub fn positions_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.positions", path.display()))
+    }
+
+    /// File of the term → stats map kept next to the postings database or
+    /// seek file at `path`.
+    #[must_use]
+    pub fn term_stats_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.terms", path.display()))
+    }
+
+    /// File of the term → term frequencies map kept next to the postings
+    /// database or seek file at `path`.
+    #[must_use]
+    pub fn term_freqs_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.tfs", path.display()))
+    }
+
+    /// Removes the files a build derives from term frequencies, which
+    /// rewriting the postings database of `db_path` and `seek_path` from
+    /// anything but crawled pages can't reproduce.
+    pub(super) fn remove_derived_files(db_path: &Path, seek_path: &Path) -> Result<()> {
+        for path in [
+            Self::forward_path(db_path),
+            Self::forward_path(seek_path),
+            Self::positions_path(db_path),
+            Self::positions_path(seek_path),
+            Self::term_stats_path(db_path),
+            Self::term_stats_path(seek_path),
+            Self::term_freqs_path(db_path),
+            Self::term_freqs_path(seek_path),
+            WordFrequencies::path(db_path),
+        ] {
+            match remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn new(
         db_path: PathBuf,
         seek_path: PathBuf,
@@ -52,203 +467,2884 @@ impl DiskInvertedIndex {
         url_map_seek_path: PathBuf,
         crawled_data_path: PathBuf,
     ) -> Result<Self> {
-        create_index(
-            db_path.clone(),
-            seek_path.clone(),
+        Self::build(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            crawled_data_path,
+        )
+        .map(|(index, _)| index)
+    }
+
+    /// Same as [`DiskInvertedIndex::new`], also reporting how the build went.
+    pub fn build(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+        crawled_data_path: PathBuf,
+    ) -> Result<(Self, BuildStats)> {
+        Self::build_from_documents(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            read_crawled_data(crawled_data_path),
+        )
+    }
+
+    /// Builds the index from documents as they arrive rather than from the
+    /// crawled data directory, e.g. straight from the crawler.
+    ///
+    /// Fails with [`Error::Locked`] while another process builds the same
+    /// index.
+    pub fn build_from_documents<I>(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+        documents: I,
+    ) -> Result<(Self, BuildStats)>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
+    {
+        Self::build_with(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            documents,
+            BuildOptions::default(),
+        )
+    }
+
+    /// Same as [`DiskInvertedIndex::build_from_documents`], flagging the
+    /// documents `filter` picks for queries with safe search to exclude.
+    pub fn build_filtered<I>(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+        documents: I,
+        filter: &dyn DocFilter,
+    ) -> Result<(Self, BuildStats)>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
+    {
+        Self::build_with(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            documents,
+            BuildOptions {
+                filter: Some(filter),
+                ..BuildOptions::default()
+            },
+        )
+    }
+
+    /// Same as [`DiskInvertedIndex::build_from_documents`], flagging and
+    /// expanding documents as `options` say.
+    pub fn build_with<I>(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+        documents: I,
+        options: BuildOptions,
+    ) -> Result<(Self, BuildStats)>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
+    {
+        let _lock = IndexLock::exclusive(&db_path)?;
+        let generation = Generation::begin(&db_path)?;
+        let stats = create_index(
+            &db_path,
+            &seek_path,
             url_map_path.clone(),
             url_map_seek_path.clone(),
-            crawled_data_path,
+            documents,
+            options,
         )?;
+        CorpusStats {
+            num_docs: stats.num_docs,
+            num_tokens: stats.num_tokens,
+        }
+        .write(&CorpusStats::path(&db_path))?;
+        BuildReport {
+            analyzer: Some(options.analyzer),
+            ..BuildReport::new(&stats, options.config_hash)
+        }
+        .write(&BuildReport::path(&db_path))?;
 
-        let db = KVDatabase::from(db_path, seek_path)?;
-        let url_map = KVDatabase::from(url_map_path, url_map_seek_path)?;
+        #[cfg(feature = "rkyv")]
+        ArchivedPostings::write(
+            &KVDatabase::from(db_path.clone(), seek_path.clone())?,
+            &ArchivedPostings::data_path(&db_path),
+            &ArchivedPostings::seek_path(&seek_path),
+        )?;
+        generation.publish()?;
+
+        let index = Self::from(db_path, seek_path, url_map_path, url_map_seek_path)?;
 
-        Ok(Self { db, url_map })
+        Ok((index, stats))
     }
 
-    pub fn from(
+    /// Rewrites the index files without what nothing can reach anymore:
+    /// records no seek position points at, and the postings, terms and
+    /// forward index entries of documents missing from the url map. Postings
+    /// come out sorted by doc ID, and corpus stats count the documents left.
+    /// Postings are rescored for them too, except in indexes built before
+    /// term frequencies were kept.
+    ///
+    /// Fails with [`Error::Locked`] while another process builds the same
+    /// index.
+    pub fn compact(
         db_path: PathBuf,
         seek_path: PathBuf,
         url_map_path: PathBuf,
         url_map_seek_path: PathBuf,
-    ) -> Result<Self> {
-        let db = KVDatabase::from(db_path, seek_path)?;
-        let url_map = KVDatabase::from(url_map_path, url_map_seek_path)?;
+    ) -> Result<(Self, CompactStats)> {
+        let _lock = IndexLock::exclusive(&db_path)?;
+        let paths = (db_path, seek_path, url_map_path, url_map_seek_path);
+        let mut index = Self::open(paths.clone())?;
 
-        Ok(Self { db, url_map })
-    }
+        let generation = Generation::begin(&paths.0)?;
+        let stats = index.compact_files()?;
+        #[cfg(feature = "rkyv")]
+        ArchivedPostings::write(
+            &index.db,
+            &ArchivedPostings::data_path(&paths.0),
+            &ArchivedPostings::seek_path(&paths.1),
+        )?;
+        generation.publish()?;
+        drop(index);
 
-    pub fn get(&mut self, key: &str) -> Result<Option<Vec<TermIndex>>> {
-        self.db.get(&key.to_string())
-    }
+        let index = Self::from(paths.0, paths.1, paths.2, paths.3)?;
 
-    pub fn get_doc(&mut self, doc_id: DocID) -> Result<Option<Doc>> {
-        self.url_map.get(&doc_id)
+        Ok((index, stats))
     }
-}
 
-#[allow(clippy::too_many_lines)]
-fn create_index(
-    db_path: PathBuf,
-    seek_path: PathBuf,
-    url_map_path: PathBuf,
-    url_map_seek_path: PathBuf,
-    data_path: PathBuf,
-) -> Result<()> {
-    let tokenizer = Tokenizer::new()?;
+    /// Rebuilds the seek files of the index from its databases, for when they
+    /// were lost or can't be decoded. Opening an index never does so itself.
+    ///
+    /// Fails with [`Error::Locked`] while another process builds the same
+    /// index.
+    pub fn repair(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+    ) -> Result<(Self, RepairStats)> {
+        let _lock = IndexLock::exclusive(&db_path)?;
+        let rebuilt = [
+            rebuild_seek::<String, Vec<TermIndex>>(&db_path, &seek_path)?,
+            rebuild_seek::<DocID, Doc>(&url_map_path, &url_map_seek_path)?,
+            rebuild_seek::<String, DocID>(
+                &Self::url_ids_path(&url_map_path),
+                &Self::url_ids_path(&url_map_seek_path),
+            )?,
+            rebuild_seek::<DocID, Terms>(
+                &Self::forward_path(&db_path),
+                &Self::forward_path(&seek_path),
+            )?,
+            rebuild_seek::<DocID, Positions>(
+                &Self::positions_path(&db_path),
+                &Self::positions_path(&seek_path),
+            )?,
+            rebuild_seek::<String, TermStats>(
+                &Self::term_stats_path(&db_path),
+                &Self::term_stats_path(&seek_path),
+            )?,
+            rebuild_seek::<String, TermFreqs>(
+                &Self::term_freqs_path(&db_path),
+                &Self::term_freqs_path(&seek_path),
+            )?,
+        ];
+        let stats =
+            rebuilt
+                .into_iter()
+                .flatten()
+                .fold(RepairStats::default(), |stats, truncated| RepairStats {
+                    seek_files: stats.seek_files + 1,
+                    truncated_bytes: stats.truncated_bytes + truncated,
+                });
 
-    let mut db = KVDatabase::new(db_path.clone(), seek_path.clone())?;
-    let mut url_map = KVDatabase::new(url_map_path, url_map_seek_path)?;
+        let index = Self::from(db_path, seek_path, url_map_path, url_map_seek_path)?;
 
-    let mut inverted_index = TempInvertedIndex::new();
-    let mut doc_map = DocMap::new();
+        Ok((index, stats))
+    }
 
-    let mut num_docs = 0;
+    /// Removes `doc_id` from the url map, returning whether it was there.
+    /// Queries skip its postings from then on, and
+    /// [`DiskInvertedIndex::compact`] drops them from the index files.
+    ///
+    /// Fails with [`Error::Locked`] while another process builds the same
+    /// index.
+    pub fn delete_doc(&mut self, doc_id: DocID) -> Result<bool> {
+        let _lock = IndexLock::exclusive(self.db.db_path())?;
+        let Some(doc) = self.get_doc(doc_id)? else {
+            return Ok(false);
+        };
 
-    for (doc_id, entry) in WalkDir::new(data_path)
-        .into_iter()
-        .filter_map(std::result::Result::ok)
-        .filter(|e| e.file_type().is_file())
-        .enumerate()
+        self.url_map.remove(&[doc_id])?;
+        if let Some(url_ids) = &mut self.url_ids {
+            url_ids.remove(&[doc.url])?;
+        }
+        self.deleted.insert(doc_id);
+        DeletedDocs {
+            doc_ids: self.deleted.iter().copied().collect(),
+        }
+        .write(&DeletedDocs::path(self.db.db_path()))?;
+
+        Ok(true)
+    }
+
+    /// Indexes `documents` and removes the documents of the urls in
+    /// `removed`, leaving every other document as it is, doc ID and postings
+    /// included. Documents replace those of the same url, and postings are
+    /// then rescored for the new corpus, which also drops what
+    /// [`DiskInvertedIndex::compact`] would.
+    ///
+    /// Meant for the few pages a re-crawl changed: the documents are held in
+    /// memory rather than flushed as [`BuildOptions::flush`] says, and they
+    /// are analyzed the way the rest of the index was, `options.analyzer`
+    /// only applying to indexes whose build report doesn't say. Inlinks and
+    /// shared titles of the other documents, and the word frequencies behind
+    /// spelling correction, stay as the last build counted them.
+    ///
+    /// Fails with [`Error::MissingCompanion`] for indexes built before term
+    /// frequencies were kept, whose postings can't be rescored, and with
+    /// [`Error::Locked`] while another process builds the same index.
+    pub fn update_with<I>(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+        documents: I,
+        removed: &[String],
+        options: BuildOptions,
+    ) -> Result<(Self, UpdateStats)>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
     {
-        let data: CrawlFile = serde_json::from_reader(BufReader::new(File::open(entry.path())?))?;
+        let _lock = IndexLock::exclusive(&db_path)?;
+        let paths = (db_path, seek_path, url_map_path, url_map_seek_path);
+        let mut index = Self::open(paths.clone())?;
+        if !index.has_term_frequencies() {
+            return Err(Error::MissingCompanion {
+                path: Self::term_freqs_path(&paths.0),
+            });
+        }
+        let options = BuildOptions {
+            analyzer: index.analyzer().unwrap_or(options.analyzer),
+            ..options
+        };
 
-        let document = Html::parse_document(&data.content);
-        let doc_id = doc_id as DocID;
+        let generation = Generation::begin(&paths.0)?;
+        let stats = index.update_files(documents, removed, options, &DocIds::path(&paths.2))?;
+        #[cfg(feature = "rkyv")]
+        ArchivedPostings::write(
+            &index.db,
+            &ArchivedPostings::data_path(&paths.0),
+            &ArchivedPostings::seek_path(&paths.1),
+        )?;
+        generation.publish()?;
+        drop(index);
 
-        // Extract and filter all text
-        let mut word_count: HashMap<String, u32> = HashMap::new();
+        let index = Self::from(paths.0, paths.1, paths.2, paths.3)?;
 
-        let all_text = document.root_element().text().collect::<Vec<_>>();
-        let bolded_words = select_text(&document, "b, strong").unwrap_or_default();
-        let title_words = select_text(&document, "title").unwrap_or_default();
-        let header_words = select_text(&document, "h1, h2, h3, h4, h5").unwrap_or_default();
+        Ok((index, stats))
+    }
 
-        update_word_count(all_text, &tokenizer, &mut word_count, 1);
-        update_word_count(
-            title_words,
-            &tokenizer,
-            &mut word_count,
-            TITLE_WEIGHT as u32,
-        );
-        update_word_count(
-            bolded_words,
-            &tokenizer,
-            &mut word_count,
-            BOLD_WEIGHT as u32,
-        );
-        update_word_count(
-            header_words,
-            &tokenizer,
-            &mut word_count,
-            HEADER_WEIGHT as u32,
-        );
+    fn update_files<I>(
+        &mut self,
+        documents: I,
+        removed: &[String],
+        options: BuildOptions,
+        doc_ids_path: &Path,
+    ) -> Result<UpdateStats>
+    where
+        I: IntoIterator<Item = Result<CrawlFile>>,
+    {
+        let parser = DocParser::new(options)?;
+        let mut doc_ids = DocIds::read(doc_ids_path)?.unwrap_or_default();
+        let live = &self.url_map.seek_pos_map;
+        let mut stats = UpdateStats::default();
 
-        for (word, count) in word_count {
-            let index_data = TempTermIndex { doc_id, tf: count };
+        // A url listed twice keeps its first document, as in builds
+        let mut docs = Vec::new();
+        let mut indexed = HashSet::new();
+        for data in documents {
+            let data = data?;
+            let doc_id = doc_ids.allocate(&data.url);
+            if indexed.insert(doc_id) {
+                if live.contains_key(&doc_id) {
+                    stats.replaced += 1;
+                } else {
+                    stats.added += 1;
+                }
+                docs.push((doc_id, data));
+            }
+        }
+        let removed: Vec<_> = removed
+            .iter()
+            .filter_map(|url| doc_ids.get(url).map(|doc_id| (doc_id, url.clone())))
+            .filter(|(doc_id, _)| live.contains_key(doc_id) && !indexed.contains(doc_id))
+            .collect();
+        stats.removed = removed.len() as u64;
+        // Documents whose postings and signals go, replaced ones and those
+        // deleted earlier included
+        let stale: HashSet<DocID> = indexed
+            .iter()
+            .filter(|doc_id| live.contains_key(doc_id))
+            .chain(removed.iter().map(|(doc_id, _)| doc_id))
+            .chain(&self.deleted)
+            .copied()
+            .collect();
 
-            inverted_index.entry(word).or_default().push(index_data);
+        let mut batch = Batch::default();
+        let mut signals = CorpusSignals {
+            field_names: parser.fields.names().into_iter().collect(),
+            ..CorpusSignals::default()
+        };
+        for parsed in parser.parse(docs)? {
+            batch.add(parsed, options.filter, &mut signals);
+        }
+        // Pages linking to a replaced document were left as they are
+        for (doc_id, doc) in &mut batch.doc_map {
+            if let Some(previous) = self.get_doc(*doc_id)? {
+                doc.inlinks = previous.inlinks;
+            }
         }
 
-        doc_map.insert(doc_id, Doc::new(data.url));
+        self.merge_postings(batch.inverted_index, &stale)?;
+
+        // Records of removed documents go, those of the others are replaced
+        let (removed_ids, removed_urls): (Vec<_>, Vec<_>) = removed.into_iter().unzip();
+        self.url_map.remove(&removed_ids)?;
+        if let Some(url_ids) = &mut self.url_ids {
+            url_ids.remove(&removed_urls)?;
+        }
+        let quality = batch
+            .doc_map
+            .iter()
+            .filter_map(|(&doc_id, doc)| {
+                doc.quality
+                    .filter(|&quality| quality < 1.0)
+                    .map(|quality| (doc_id, quality))
+            })
+            .collect();
+        match &mut self.url_ids {
+            Some(url_ids) => insert_docs(&mut self.url_map, url_ids, batch.doc_map)?,
+            None => self.url_map.insert(batch.doc_map)?,
+        }
+        if let Some(forward) = &mut self.forward {
+            forward.insert(batch.doc_terms)?;
+        }
+        if let Some(positions) = &mut self.positions {
+            positions.insert(batch.doc_positions)?;
+        }
 
-        if doc_id % MAX_ITERATIONS == 0 {
-            db.extend(inverted_index)?;
-            url_map.insert(doc_map)?;
+        self.update_signals(&stale, signals, quality)?;
+        doc_ids.write(doc_ids_path)?;
+        self.compact_files()?;
 
-            inverted_index = TempInvertedIndex::new();
-            doc_map = DocMap::new();
+        Ok(stats)
+    }
 
-            println!("Processed {doc_id} documents");
+    /// Replaces the postings of the `stale` documents with those of
+    /// `inverted_index`. They are scored along with the rest once all
+    /// documents are in.
+    fn merge_postings(
+        &mut self,
+        inverted_index: TempInvertedIndex,
+        stale: &HashSet<DocID>,
+    ) -> Result<()> {
+        let mut postings = InvertedIndex::new();
+        let mut term_freqs = HashMap::new();
+        for (term, mut temp_postings) in inverted_index {
+            temp_postings.sort_unstable_by_key(|posting| posting.doc_id);
+            let tfs: TermFreqs = temp_postings
+                .iter()
+                .map(|posting| (posting.doc_id, posting.tf))
+                .collect();
+            let term_postings = temp_postings
+                .into_iter()
+                .map(|posting| TermIndex {
+                    doc_id: posting.doc_id,
+                    tf_idf: 0.0,
+                })
+                .collect();
+            term_freqs.insert(term.clone(), tfs);
+            postings.insert(term, term_postings);
+        }
+        if let Some(term_stats) = &mut self.term_stats {
+            let terms = &self.db.seek_pos_map;
+            term_stats.insert(
+                postings
+                    .keys()
+                    .filter(|term| !terms.contains_key(*term))
+                    .map(|term| (term.clone(), TermStats::default()))
+                    .collect(),
+            )?;
+        }
+        self.db.compact_with(|term, mut term_postings| {
+            term_postings.retain(|posting| !stale.contains(&posting.doc_id));
+            term_postings.extend(postings.remove(term).unwrap_or_default());
+            (!term_postings.is_empty()).then_some(term_postings)
+        })?;
+        self.db.insert(postings)?;
+        if let Some(db) = &mut self.term_freqs {
+            // Kept in doc ID order, which rescoring looks them up by
+            db.compact_with(|term, mut tfs| {
+                tfs.retain(|(doc_id, _)| !stale.contains(doc_id));
+                tfs.extend(term_freqs.remove(term).unwrap_or_default());
+                tfs.sort_unstable_by_key(|&(doc_id, _)| doc_id);
+                (!tfs.is_empty()).then_some(tfs)
+            })?;
+            db.insert(term_freqs)?;
         }
 
-        num_docs += 1;
+        Ok(())
     }
 
-    db.extend(inverted_index)?;
-    url_map.insert(doc_map)?;
+    /// Replaces what the files next to the index say about the `stale`
+    /// documents with what the documents of the update told, in `signals` and
+    /// their `quality`.
+    fn update_signals(
+        &mut self,
+        stale: &HashSet<DocID>,
+        signals: CorpusSignals,
+        quality: Vec<(DocID, f32)>,
+    ) -> Result<()> {
+        let db_path = self.db.db_path().to_path_buf();
 
-    calculate_scores(db, db_path, seek_path, num_docs)?;
+        self.flagged.retain(|doc_id| !stale.contains(doc_id));
+        self.flagged.extend(signals.flagged.doc_ids);
+        FlaggedDocs {
+            doc_ids: self.flagged.iter().copied().collect(),
+        }
+        .write(&FlaggedDocs::path(&db_path))?;
 
-    Ok(())
-}
+        self.quality.retain(|doc_id, _| !stale.contains(doc_id));
+        self.quality.extend(quality);
+        DocQuality {
+            scores: self
+                .quality
+                .iter()
+                .map(|(&id, &score)| (id, score))
+                .collect(),
+        }
+        .write(&DocQuality::path(&db_path))?;
 
-fn select_text<'a>(document: &'a Html, selector: &str) -> Result<Vec<&'a str>> {
-    Ok(document
-        .select(
-            &Selector::parse(selector)
-                .map_err(|e| Error::Generic(format!("Failed to parse selector: {e}")))?,
-        )
-        .map(|element| element.text().collect::<Vec<_>>())
-        .collect::<Vec<_>>()
-        .concat())
-}
+        self.boosts.retain(|doc_id, _| !stale.contains(doc_id));
+        self.boosts.extend(signals.boosts.boosts);
+        DocBoosts {
+            boosts: self
+                .boosts
+                .iter()
+                .map(|(&id, &boost)| (id, boost))
+                .collect(),
+        }
+        .write(&DocBoosts::path(&db_path))?;
 
-fn update_word_count(
-    title_words: Vec<&str>,
-    tokenizer: &Tokenizer,
-    word_count: &mut HashMap<String, u32>,
-    weight: u32,
-) {
-    title_words
-        .iter()
-        .map(|text| tokenizer.tokenize(text))
-        .for_each(|tokens| {
-            tokens.into_iter().for_each(|token| {
-                let count = word_count.entry(token).or_insert(0);
-                *count += weight;
-            });
-        });
-}
+        self.crawl_times.retain(|doc_id, _| !stale.contains(doc_id));
+        self.crawl_times.extend(signals.crawl_times.times);
+        CrawlTimes {
+            times: self
+                .crawl_times
+                .iter()
+                .map(|(&id, &time)| (id, time))
+                .collect(),
+        }
+        .write(&CrawlTimes::path(&db_path))?;
 
-pub fn calculate_scores(
-    mut db: KVDatabase<String, Vec<TempTermIndex>>,
-    db_path: PathBuf,
-    seek_path: PathBuf,
-    num_docs: u64,
-) -> Result<()> {
-    let temp_db_path = format!("{}{}", db_path.display(), TEMP_FILE_SUFFIX);
-    let temp_seek_path = format!("{}{}", seek_path.display(), TEMP_FILE_SUFFIX);
+        // Written by the compaction that follows, for the documents it keeps
+        if !self.doc_lengths.is_empty() {
+            self.doc_lengths.extend(signals.doc_lengths.lengths);
+        }
 
-    let mut temp_db = KVDatabase::new(temp_db_path.clone().into(), temp_seek_path.clone().into())?;
+        if let Some(word_frequencies) = &self.word_frequencies {
+            let mut words: HashMap<_, _> = word_frequencies.words.iter().cloned().collect();
+            for (word, count) in signals.word_frequencies {
+                *words.entry(word).or_default() += count;
+            }
+            WordFrequencies::from(words).write(&WordFrequencies::path(&db_path))?;
+        }
 
-    let mut final_map: HashMap<String, Vec<TermIndex>> = HashMap::new();
+        let mut fields: BTreeSet<_> = self.fields.drain(..).collect();
+        fields.extend(signals.field_names);
+        IndexedFields {
+            names: fields.into_iter().collect(),
+        }
+        .write(&IndexedFields::path(&db_path))
+    }
 
-    for (i, data) in db.into_iter().enumerate() {
-        let (key, value) = data?;
+    fn compact_files(&mut self) -> Result<CompactStats> {
+        let mut dropped_postings = 0;
+        let mut dropped_terms = 0;
 
-        let data_len = value.len();
+        // Records of removed documents go, and the corpus stats follow
+        let mut corpus_stats = CorpusStats::default();
+        let mut bytes_reclaimed = self.url_map.compact_with(|_, doc| {
+            corpus_stats.num_docs += 1;
+            corpus_stats.num_tokens += doc.num_tokens;
+            Some(doc)
+        })?;
+        let live = &self.url_map.seek_pos_map;
+        if self.corpus_stats.is_some() {
+            corpus_stats.write(&CorpusStats::path(self.db.db_path()))?;
+            self.corpus_stats = Some(corpus_stats);
+        }
+        if !self.doc_lengths.is_empty() {
+            self.doc_lengths
+                .retain(|doc_id, _| live.contains_key(doc_id));
+            DocLengths {
+                lengths: self
+                    .doc_lengths
+                    .iter()
+                    .map(|(&doc_id, &len)| (doc_id, len))
+                    .collect(),
+            }
+            .write(&DocLengths::path(self.db.db_path()))?;
+        }
 
-        let new_data = value
-            .iter()
-            .map(|index_data| {
-                let tf_idf =
-                    calculate_tf_idf(index_data.tf as f64, data_len as f64, num_docs as f64);
+        // Postings are rescored for the documents left, from the term
+        // frequencies of the build. Indexes without them keep their scores,
+        // whose idf then still counts the removed documents
+        let term_freqs = self.term_freqs.as_ref();
+        let num_docs = corpus_stats.num_docs;
+        let mut rescored = HashMap::new();
+        let mut read_error = None;
+        bytes_reclaimed += self.db.compact_with(|term, mut postings| {
+            let len = postings.len();
+            postings.retain(|posting| live.contains_key(&posting.doc_id));
+            postings.sort_by_key(|posting| posting.doc_id);
 
-                TermIndex {
-                    doc_id: index_data.doc_id,
-                    tf_idf,
+            dropped_postings += (len - postings.len()) as u64;
+            if postings.is_empty() {
+                dropped_terms += 1;
+                return None;
+            }
+            match term_freqs.map(|term_freqs| term_freqs.get(term)) {
+                Some(Ok(Some(tfs))) => {
+                    let stats = rescore(&mut postings, &tfs, num_docs);
+                    rescored.insert(term.clone(), stats);
                 }
-            })
-            .collect();
+                Some(Err(e)) => read_error = read_error.take().or(Some(e)),
+                Some(Ok(None)) | None => {}
+            }
+            Some(postings)
+        })?;
+        if let Some(e) = read_error {
+            return Err(e);
+        }
+        if let Some(url_ids) = &mut self.url_ids {
+            bytes_reclaimed +=
+                url_ids.compact_with(|_, doc_id| live.contains_key(&doc_id).then_some(doc_id))?;
+        }
+        if let Some(forward) = &mut self.forward {
+            bytes_reclaimed +=
+                forward.compact_with(|doc_id, terms| live.contains_key(doc_id).then_some(terms))?;
+        }
+        if let Some(positions) = &mut self.positions {
+            bytes_reclaimed += positions
+                .compact_with(|doc_id, positions| live.contains_key(doc_id).then_some(positions))?;
+        }
+        if let Some(term_stats) = &mut self.term_stats {
+            let terms = &self.db.seek_pos_map;
+            bytes_reclaimed += term_stats.compact_with(|term, stats| {
+                terms
+                    .contains_key(term)
+                    .then(|| rescored.get(term).copied().unwrap_or(stats))
+            })?;
+        }
+        if let Some(term_freqs) = &mut self.term_freqs {
+            bytes_reclaimed += term_freqs.compact_with(|_, mut tfs| {
+                tfs.retain(|(doc_id, _)| live.contains_key(doc_id));
+                (!tfs.is_empty()).then_some(tfs)
+            })?;
+        }
+        // Nothing is left for queries to skip
+        if !self.deleted.is_empty() {
+            self.deleted.clear();
+            DeletedDocs::default().write(&DeletedDocs::path(self.db.db_path()))?;
+        }
+
+        Ok(CompactStats {
+            bytes_reclaimed,
+            dropped_postings,
+            dropped_terms,
+        })
+    }
+
+    /// Opens the files of the last complete build. Fails while a build is
+    /// rewriting them, and reopens if one finished while they were being
+    /// opened, so the index never pairs files of different builds.
+    ///
+    /// Builds replace files rather than rewriting them, so an open index keeps
+    /// reading its own generation however many builds follow.
+    pub fn from(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        url_map_path: PathBuf,
+        url_map_seek_path: PathBuf,
+    ) -> Result<Self> {
+        let generation_path = Generation::path(&db_path);
+        let paths = (db_path, seek_path, url_map_path, url_map_seek_path);
 
-        final_map.insert(key, new_data);
+        loop {
+            let generation = Generation::read(&generation_path)?;
+            if !Generation::is_complete(generation) {
+                return Err(Error::Rebuilding { path: paths.0 });
+            }
 
-        if i % MAX_ITERATIONS as usize == 0 {
-            temp_db.extend(final_map)?;
-            final_map = HashMap::new();
-            println!("Translate {i} words to tf-idf scores");
+            let index = Self::open(paths.clone());
+            if Generation::read(&generation_path)? == generation {
+                return index.map(|index| Self {
+                    generation: generation / 2,
+                    ..index
+                });
+            }
         }
     }
 
-    temp_db.extend(final_map)?;
+    fn open(
+        (db_path, seek_path, url_map_path, url_map_seek_path): (PathBuf, PathBuf, PathBuf, PathBuf),
+    ) -> Result<Self> {
+        #[cfg(feature = "rkyv")]
+        let archive_paths = (
+            ArchivedPostings::data_path(&db_path),
+            ArchivedPostings::seek_path(&seek_path),
+        );
 
-    rename(temp_db_path, db_path)?;
-    rename(temp_seek_path, seek_path)?;
+        let corpus_stats_path = CorpusStats::path(&db_path);
+        let word_frequencies_path = WordFrequencies::path(&db_path);
+        let flagged_path = FlaggedDocs::path(&db_path);
+        let deleted_path = DeletedDocs::path(&db_path);
+        let quality_path = DocQuality::path(&db_path);
+        let boosts_path = DocBoosts::path(&db_path);
+        let crawl_times_path = CrawlTimes::path(&db_path);
+        let doc_lengths_path = DocLengths::path(&db_path);
+        let build_report_path = BuildReport::path(&db_path);
+        let fields_path = IndexedFields::path(&db_path);
+        let url_ids_paths = (
+            Self::url_ids_path(&url_map_path),
+            Self::url_ids_path(&url_map_seek_path),
+        );
+        let forward_paths = (Self::forward_path(&db_path), Self::forward_path(&seek_path));
+        let positions_paths = (
+            Self::positions_path(&db_path),
+            Self::positions_path(&seek_path),
+        );
+        let term_stats_paths = (
+            Self::term_stats_path(&db_path),
+            Self::term_stats_path(&seek_path),
+        );
+        let term_freqs_paths = (
+            Self::term_freqs_path(&db_path),
+            Self::term_freqs_path(&seek_path),
+        );
 
-    Ok(())
-}
+        let db = KVDatabase::from(db_path, seek_path)?;
+        let url_map = KVDatabase::from(url_map_path, url_map_seek_path)?;
+        let mut index = Self::with_databases(db, url_map)?;
 
-fn calculate_tf_idf(tf: f64, df: f64, n: f64) -> f64 {
-    (1.0 + tf.log10()) * (n / df).log10()
+        index.url_ids = index.open_companion(url_ids_paths)?;
+        index.forward = index.open_companion(forward_paths)?;
+        index.positions = index.open_companion(positions_paths)?;
+        index.term_stats = index.open_companion(term_stats_paths)?;
+        index.term_freqs = index.open_companion(term_freqs_paths)?;
+        index.corpus_stats = CorpusStats::read(&corpus_stats_path)?;
+        index.build_report = BuildReport::read(&build_report_path)?;
+        index.word_frequencies = WordFrequencies::read(&word_frequencies_path)?;
+        if let Some(flagged) = FlaggedDocs::read(&flagged_path)? {
+            index.flagged = flagged.doc_ids.into_iter().collect();
+        }
+        if let Some(deleted) = DeletedDocs::read(&deleted_path)? {
+            index.deleted = deleted.doc_ids.into_iter().collect();
+        }
+        if let Some(quality) = DocQuality::read(&quality_path)? {
+            index.quality = quality.scores.into_iter().collect();
+        }
+        if let Some(boosts) = DocBoosts::read(&boosts_path)? {
+            index.boosts = boosts.boosts.into_iter().collect();
+        }
+        if let Some(crawl_times) = CrawlTimes::read(&crawl_times_path)? {
+            index.crawl_times = crawl_times.times.into_iter().collect();
+            index.newest_crawl = index.crawl_times.values().copied().max().unwrap_or(0);
+        }
+        if let Some(doc_lengths) = DocLengths::read(&doc_lengths_path)? {
+            index.doc_lengths = doc_lengths.lengths.into_iter().collect();
+        }
+        if let Some(fields) = IndexedFields::read(&fields_path)? {
+            index.fields = fields.names;
+        }
+
+        #[cfg(feature = "rkyv")]
+        let index = index.with_archive(&archive_paths.0, &archive_paths.1)?;
+
+        Ok(index)
+    }
+
+    /// Opens a database the build wrote next to the index, `None` for indexes
+    /// built before it existed.
+    fn open_companion<K, V>(
+        &self,
+        (db_path, seek_path): (PathBuf, PathBuf),
+    ) -> Result<Option<KVDatabase<K, V>>>
+    where
+        K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+        V: Serialize + for<'de> Deserialize<'de> + Clone,
+    {
+        if !db_path.exists() {
+            return Ok(None);
+        }
+
+        let db = KVDatabase::from(db_path, seek_path)?;
+        if db.build_id() != self.build_id() {
+            return Err(Error::MixedBuild {
+                path: db.db_path().to_path_buf(),
+            });
+        }
+
+        Ok(Some(db))
+    }
+
+    /// Attaches the postings archive when one was written for this database.
+    #[cfg(feature = "rkyv")]
+    fn with_archive(mut self, data_path: &Path, seek_path: &Path) -> Result<Self> {
+        self.archived = ArchivedPostings::open(data_path, seek_path, self.db.database.size()?)?;
+        Ok(self)
+    }
+}
+
+impl MemoryInvertedIndex {
+    /// Loads an index whose files were downloaded into memory.
+    pub fn from_bytes(
+        db: Vec<u8>,
+        seek: &[u8],
+        url_map: Vec<u8>,
+        url_map_seek: &[u8],
+    ) -> Result<Self> {
+        Self::with_databases(
+            MemoryKVDatabase::from_bytes(db, seek)?,
+            MemoryKVDatabase::from_bytes(url_map, url_map_seek)?,
+        )
+    }
+}
+
+impl<R: ReadAt> DiskInvertedIndex<R> {
+    /// Fails unless the postings and url map were written by the same build.
+    fn with_databases(
+        db: KVDatabase<String, Vec<TermIndex>, R>,
+        url_map: KVDatabase<DocID, Doc, R>,
+    ) -> Result<Self> {
+        if url_map.build_id() != db.build_id() {
+            return Err(Error::MixedBuild {
+                path: url_map.db_path().to_path_buf(),
+            });
+        }
+
+        Ok(Self {
+            db,
+            url_map,
+            url_ids: None,
+            forward: None,
+            positions: None,
+            term_stats: None,
+            term_freqs: None,
+            corpus_stats: None,
+            word_frequencies: None,
+            flagged: HashSet::new(),
+            deleted: HashSet::new(),
+            quality: HashMap::new(),
+            boosts: HashMap::new(),
+            crawl_times: HashMap::new(),
+            newest_crawl: 0,
+            doc_lengths: HashMap::new(),
+            build_report: None,
+            fields: Vec::new(),
+            preloaded: HashMap::new(),
+            generation: 0,
+            #[cfg(feature = "rkyv")]
+            archived: None,
+        })
+    }
+
+    /// ID shared by the files of the build the index was opened from.
+    #[must_use]
+    pub const fn build_id(&self) -> Uuid {
+        self.db.build_id()
+    }
+}
+
+impl<R> DiskInvertedIndex<R> {
+    /// Number of the build the files were opened from, 0 for indexes built
+    /// before builds were counted.
+    #[must_use]
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Reports what the seek maps and preloaded postings keep in memory, to
+    /// help size machines and pick how many terms to preload.
+    #[must_use]
+    pub fn memory_stats(&self) -> MemoryStats {
+        #[cfg(feature = "rkyv")]
+        let archive = self.archived.as_ref().map(ArchivedPostings::seek_pos_map);
+        #[cfg(not(feature = "rkyv"))]
+        let archive = None::<&HashMap<String, _>>;
+        let term_stats = self.term_stats.as_ref().map(|stats| &stats.seek_pos_map);
+        let term_freqs = self.term_freqs.as_ref().map(|tfs| &tfs.seek_pos_map);
+        let term_maps = || {
+            std::iter::once(&self.db.seek_pos_map)
+                .chain(archive)
+                .chain(term_stats)
+                .chain(term_freqs)
+        };
+
+        MemoryStats {
+            seek_maps: term_maps().map(entries_size).sum::<usize>()
+                + entries_size(&self.url_map.seek_pos_map)
+                + self
+                    .url_ids
+                    .as_ref()
+                    .map_or(0, |url_ids| entries_size(&url_ids.seek_pos_map))
+                + self
+                    .forward
+                    .as_ref()
+                    .map_or(0, |forward| entries_size(&forward.seek_pos_map))
+                + self
+                    .positions
+                    .as_ref()
+                    .map_or(0, |positions| entries_size(&positions.seek_pos_map)),
+            dictionary: term_maps()
+                .flat_map(HashMap::keys)
+                .map(String::capacity)
+                .sum(),
+            cache_terms: self.preloaded.len(),
+            cache: self.preloaded.capacity() * (size_of::<(String, Vec<TermIndex>)>() + 1)
+                + self
+                    .preloaded
+                    .iter()
+                    .map(|(term, postings)| {
+                        term.capacity() + postings.capacity() * size_of::<TermIndex>()
+                    })
+                    .sum::<usize>(),
+        }
+    }
+}
+
+impl<R: ReadAt> DiskInvertedIndex<R> {
+    /// Postings of `key`, those of deleted documents left out. `None` when
+    /// none are left.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<TermIndex>>> {
+        let mut postings = match self.preloaded.get(key) {
+            Some(postings) => Some(postings.clone()),
+            None => self.db.get(&key.to_string())?,
+        };
+        if let Some(postings) = &mut postings {
+            postings.retain(|posting| !self.is_deleted(posting.doc_id));
+        }
+
+        Ok(postings.filter(|postings| !postings.is_empty()))
+    }
+
+    /// Calls `f` with the doc ID and score of every posting of `key`, reading
+    /// them in place from the postings archive when there is one. Postings
+    /// of deleted documents are skipped.
+    pub fn for_each_posting<F>(&self, key: &str, buffer: &mut ReadBuffer, mut f: F) -> Result<()>
+    where
+        F: FnMut(DocID, TFIDF),
+    {
+        let mut f = |doc_id, tf_idf| {
+            if !self.is_deleted(doc_id) {
+                f(doc_id, tf_idf);
+            }
+        };
+        if let Some(postings) = self.preloaded.get(key) {
+            for posting in postings {
+                f(posting.doc_id, posting.tf_idf);
+            }
+            return Ok(());
+        }
+
+        #[cfg(feature = "rkyv")]
+        if let Some(archived) = &self.archived {
+            archived.for_each(key, &mut buffer.aligned, f)?;
+            return Ok(());
+        }
+
+        let postings = self
+            .db
+            .get_with_buffer(&key.to_string(), &mut buffer.bytes)?;
+        for posting in postings.unwrap_or_default() {
+            f(posting.doc_id, posting.tf_idf);
+        }
+
+        Ok(())
+    }
+
+    /// Loads the postings of the `num_terms` terms with the largest postings
+    /// into memory, so the first queries for frequent terms don't wait on the
+    /// disk. Returns the number of terms loaded.
+    pub fn preload(&mut self, num_terms: usize) -> Result<usize> {
+        let mut terms = self
+            .db
+            .seek_pos_map
+            .iter()
+            .map(|(term, seek_pos)| (seek_pos.len, term))
+            .collect::<Vec<_>>();
+        terms.sort_unstable_by(|a, b| b.cmp(a));
+        terms.truncate(num_terms);
+
+        let mut preloaded = HashMap::with_capacity(terms.len());
+        for (_, term) in terms {
+            if let Some(postings) = self.db.get(term)? {
+                preloaded.insert(term.clone(), postings);
+            }
+        }
+        self.preloaded = preloaded;
+
+        Ok(self.preloaded.len())
+    }
+
+    /// Up to `n` postings of `key` spread evenly through its list, so the
+    /// sample is uniform over its documents. Lists longer than `n` are read
+    /// one sampled posting at a time rather than decoded whole, unless
+    /// documents were deleted, whose postings only a whole read can skip.
+    pub fn sample_postings(&self, key: &str, n: usize) -> Result<Vec<TermIndex>> {
+        let len = self.doc_frequency(key) as usize;
+        if n >= len || !self.deleted.is_empty() || self.preloaded.contains_key(key) {
+            let postings = self.get(key)?.unwrap_or_default();
+            return Ok(spread(postings.len(), n)
+                .map(|i| postings[i].clone())
+                .collect());
+        }
+
+        let key = key.to_string();
+        let mut bytes = [0; POSTING_LEN as usize];
+        let mut postings = Vec::with_capacity(n);
+        for i in spread(len, n) {
+            let offset = POSTINGS_PREFIX_LEN + i as u64 * POSTING_LEN;
+            if self.db.read_value_at(&key, offset, &mut bytes)? {
+                postings.push(codec::deserialize(&bytes)?);
+            }
+        }
+
+        Ok(postings)
+    }
+
+    /// Encoded size of the postings of `key`, which grows with its document
+    /// frequency. `None` when the term is not in the index.
+    #[must_use]
+    pub fn postings_len(&self, key: &str) -> Option<u64> {
+        self.db.value_len(&key.to_string())
+    }
+
+    /// Whether [`DiskInvertedIndex::preload`] loaded the postings of `key`.
+    #[must_use]
+    pub fn is_preloaded(&self, key: &str) -> bool {
+        self.preloaded.contains_key(key)
+    }
+
+    /// Number of documents in the url map.
+    #[must_use]
+    pub fn num_docs(&self) -> u64 {
+        self.url_map.seek_pos_map.len() as u64
+    }
+
+    /// Number of distinct terms in the index.
+    #[must_use]
+    pub fn num_terms(&self) -> u64 {
+        self.db.seek_pos_map.len() as u64
+    }
+
+    /// Number of documents containing `term`, worked out from the size of its
+    /// posting list without reading it.
+    #[must_use]
+    pub fn doc_frequency(&self, term: &str) -> u64 {
+        self.postings_len(term).map_or(0, |len| {
+            len.saturating_sub(POSTINGS_PREFIX_LEN) / POSTING_LEN
+        })
+    }
+
+    /// Document frequency, total term frequency and highest score of `term`,
+    /// as of the build, without decoding its postings. `None` for terms no
+    /// document contains.
+    pub fn term_stats(&self, term: &str) -> Result<Option<TermStats>> {
+        let Some(term_stats) = &self.term_stats else {
+            return Err(Error::MissingCompanion {
+                path: DiskInvertedIndex::term_stats_path(self.db.db_path()),
+            });
+        };
+
+        term_stats.get(&term.to_string())
+    }
+
+    /// Mean number of tokens per document, `None` for indexes built before
+    /// corpus stats were kept.
+    #[must_use]
+    pub fn average_doc_length(&self) -> Option<f64> {
+        self.corpus_stats
+            .as_ref()
+            .and_then(CorpusStats::average_doc_length)
+    }
+
+    /// What the build of the index did, `None` for indexes built before
+    /// reports were written.
+    #[must_use]
+    pub const fn build_report(&self) -> Option<&BuildReport> {
+        self.build_report.as_ref()
+    }
+
+    /// Analyzer the index was built with, `None` for indexes built before it
+    /// was recorded.
+    #[must_use]
+    pub fn analyzer(&self) -> Option<Analyzer> {
+        self.build_report
+            .as_ref()
+            .and_then(|report| report.analyzer)
+    }
+
+    /// Tokens in the text of `doc_id`, `None` for documents of indexes built
+    /// before lengths were kept.
+    #[must_use]
+    pub fn doc_length(&self, doc_id: DocID) -> Option<u64> {
+        self.doc_lengths.get(&doc_id).map(|&len| u64::from(len))
+    }
+
+    /// Whether the index keeps the term frequencies BM25 scores with, missing
+    /// from indexes built before they were kept.
+    #[must_use]
+    pub const fn has_term_frequencies(&self) -> bool {
+        self.term_freqs.is_some()
+    }
+
+    /// Term frequencies of `term` in the documents holding it, in doc ID
+    /// order and those of deleted documents left out. Empty for terms no
+    /// document contains.
+    pub fn term_frequencies(&self, term: &str) -> Result<TermFreqs> {
+        let Some(term_freqs) = &self.term_freqs else {
+            return Err(Error::MissingCompanion {
+                path: DiskInvertedIndex::term_freqs_path(self.db.db_path()),
+            });
+        };
+
+        let mut tfs = term_freqs.get(&term.to_string())?.unwrap_or_default();
+        tfs.retain(|(doc_id, _)| !self.is_deleted(*doc_id));
+        Ok(tfs)
+    }
+
+    /// Words of the corpus and their document frequencies, `None` for
+    /// indexes built before they were kept.
+    #[must_use]
+    pub const fn word_frequencies(&self) -> Option<&WordFrequencies> {
+        self.word_frequencies.as_ref()
+    }
+
+    /// Whether `doc_id` was deleted since the index was last built or
+    /// compacted, see [`DiskInvertedIndex::delete_doc`].
+    #[must_use]
+    pub fn is_deleted(&self, doc_id: DocID) -> bool {
+        self.deleted.contains(&doc_id)
+    }
+
+    /// Whether the filter of the build flagged `doc_id`. Nothing is flagged
+    /// in indexes built without a filter.
+    #[must_use]
+    pub fn is_flagged(&self, doc_id: DocID) -> bool {
+        self.flagged.contains(&doc_id)
+    }
+
+    /// Spam and boilerplate quality of `doc_id`, from 0.1 for junk to 1.
+    /// Documents of indexes built before quality was scored get 1.
+    #[must_use]
+    pub fn quality(&self, doc_id: DocID) -> f64 {
+        self.quality
+            .get(&doc_id)
+            .map_or(1.0, |&quality| f64::from(quality))
+    }
+
+    /// Seconds between the crawl of `doc_id` and the newest crawl of the
+    /// index. Counting from the index rather than the clock keeps rankings
+    /// from drifting while the index sits unchanged. `None` for documents
+    /// without a crawl time.
+    #[must_use]
+    pub fn age(&self, doc_id: DocID) -> Option<u64> {
+        self.crawl_times
+            .get(&doc_id)
+            .map(|&crawled_at| self.newest_crawl.saturating_sub(crawled_at))
+    }
+
+    /// Names of the custom fields the build indexed, see [`FieldSelectors`].
+    #[must_use]
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Static boost of `doc_id`, 1 for documents the build didn't boost.
+    #[must_use]
+    pub fn boost(&self, doc_id: DocID) -> f64 {
+        self.boosts
+            .get(&doc_id)
+            .map_or(1.0, |&boost| f64::from(boost))
+    }
+
+    /// Whether the index has a forward index, missing from indexes built
+    /// before it existed.
+    #[must_use]
+    pub const fn has_forward_index(&self) -> bool {
+        self.forward.is_some()
+    }
+
+    /// The terms of `doc_id` and their frequencies, read from the forward
+    /// index. `None` for documents missing from it.
+    pub fn doc_terms(&self, doc_id: DocID) -> Result<Option<Terms>> {
+        let Some(forward) = &self.forward else {
+            return Err(Error::MissingCompanion {
+                path: DiskInvertedIndex::forward_path(self.db.db_path()),
+            });
+        };
+
+        forward.get(&doc_id)
+    }
+
+    /// Whether the index keeps term positions, missing from indexes built
+    /// before they were kept.
+    #[must_use]
+    pub const fn has_positions(&self) -> bool {
+        self.positions.is_some()
+    }
+
+    /// Where the terms of `doc_id` occur in its text. `None` for documents
+    /// missing from the positions and for indexes without them.
+    pub fn doc_positions(&self, doc_id: DocID) -> Result<Option<Positions>> {
+        self.positions
+            .as_ref()
+            .map_or(Ok(None), |positions| positions.get(&doc_id))
+    }
+
+    /// The `n` terms of `doc_id` with the highest tf-idf, read from the
+    /// forward index.
+    pub fn top_terms(&self, doc_id: DocID, n: usize) -> Result<Vec<String>> {
+        let terms = self
+            .doc_terms(doc_id)?
+            .ok_or(Error::MissingDoc { doc_id })?;
+
+        let num_docs = self.num_docs() as f64;
+        let mut scored = terms
+            .into_iter()
+            .map(|(term, tf)| {
+                let df = self.doc_frequency(&term) as f64;
+                (calculate_tf_idf(f64::from(tf), df, num_docs), term)
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        Ok(scored.into_iter().take(n).map(|(_, term)| term).collect())
+    }
+
+    pub fn get_doc(&self, doc_id: DocID) -> Result<Option<Doc>> {
+        self.url_map.get(&doc_id)
+    }
+
+    /// The document crawled from `url`, looked up in the url → doc ID map, or
+    /// found with [`DiskInvertedIndex::find_doc_by_url`] for indexes without
+    /// one.
+    pub fn get_doc_by_url(&self, url: &str) -> Result<Option<(DocID, Doc)>> {
+        let Some(url_ids) = &self.url_ids else {
+            return self.find_doc_by_url(url);
+        };
+
+        let Some(doc_id) = url_ids.get(&url.to_string())? else {
+            return Ok(None);
+        };
+        Ok(self.get_doc(doc_id)?.map(|doc| (doc_id, doc)))
+    }
+
+    /// Scans the url map for the document crawled from `url`.
+    pub fn find_doc_by_url(&self, url: &str) -> Result<Option<(DocID, Doc)>> {
+        for entry in &self.url_map {
+            let (doc_id, doc) = entry?;
+            if doc.url == url {
+                return Ok(Some((doc_id, doc)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        self.db.verify()?;
+        if let Some(url_ids) = &self.url_ids {
+            url_ids.verify()?;
+        }
+        if let Some(forward) = &self.forward {
+            forward.verify()?;
+        }
+        if let Some(positions) = &self.positions {
+            positions.verify()?;
+        }
+        if let Some(term_stats) = &self.term_stats {
+            term_stats.verify()?;
+        }
+        if let Some(term_freqs) = &self.term_freqs {
+            term_freqs.verify()?;
+        }
+        self.url_map.verify()
+    }
+}
+
+/// Every file under `data_path`, parsed as a [`CrawlFile`]. Feeds and
+/// sitemaps are read as the pages of their entries, see [`read_feed`].
+pub fn read_crawled_data(data_path: PathBuf) -> impl Iterator<Item = Result<CrawlFile>> {
+    WalkDir::new(data_path)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .flat_map(|entry| {
+            if is_feed(entry.path()) {
+                return match read_feed(entry.path()) {
+                    Ok(pages) => pages.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+            }
+
+            vec![File::open(entry.path())
+                .map_err(Error::from)
+                .and_then(|file| {
+                    serde_json::from_reader(BufReader::new(file)).map_err(|source| {
+                        Error::InvalidPage {
+                            path: entry.into_path(),
+                            source,
+                        }
+                    })
+                })]
+        })
+}
+
+#[allow(clippy::too_many_lines)]
+fn create_index<I>(
+    db_path: &Path,
+    seek_path: &Path,
+    url_map_path: PathBuf,
+    url_map_seek_path: PathBuf,
+    documents: I,
+    options: BuildOptions,
+) -> Result<BuildStats>
+where
+    I: IntoIterator<Item = Result<CrawlFile>>,
+{
+    let parser = DocParser::new(options)?;
+    let mut signals = CorpusSignals {
+        field_names: parser.fields.names().into_iter().collect(),
+        ..CorpusSignals::default()
+    };
+
+    let build_id = Uuid::new_v4();
+    let mut runs = PostingRuns::new(db_path, build_id);
+    let doc_ids_path = DocIds::path(&url_map_path);
+    let mut doc_ids = DocIds::read(&doc_ids_path)?.unwrap_or_default();
+    let mut indexed = HashSet::new();
+    let mut url_ids = KVDatabase::with_build_id(
+        DiskInvertedIndex::url_ids_path(&url_map_path),
+        DiskInvertedIndex::url_ids_path(&url_map_seek_path),
+        build_id,
+    )?;
+    let mut url_map = KVDatabase::with_build_id(url_map_path, url_map_seek_path, build_id)?;
+    let mut forward = KVDatabase::with_build_id(
+        DiskInvertedIndex::forward_path(db_path),
+        DiskInvertedIndex::forward_path(seek_path),
+        build_id,
+    )?;
+    let mut positions = KVDatabase::with_build_id(
+        DiskInvertedIndex::positions_path(db_path),
+        DiskInvertedIndex::positions_path(seek_path),
+        build_id,
+    )?;
+
+    let mut batch = Batch::default();
+    let mut stats = BuildStats::default();
+    let mut phase_start = Instant::now();
+
+    let mut documents = documents.into_iter();
+    loop {
+        // Documents are read and given IDs in order, so builds stay
+        // deterministic however the parsing is spread over threads
+        let mut chunk = Vec::with_capacity(PARSE_CHUNK_DOCS);
+        let mut interrupted = false;
+        for data in documents.by_ref() {
+            if shutdown::requested() {
+                interrupted = true;
+                break;
+            }
+
+            let data = data?;
+            let doc_id = doc_ids.allocate(&data.url);
+            // A url listed twice keeps its first document
+            if !indexed.insert(doc_id) {
+                stats.skipped_docs += 1;
+                continue;
+            }
+
+            chunk.push((doc_id, data));
+            if chunk.len() == PARSE_CHUNK_DOCS {
+                break;
+            }
+        }
+        let exhausted = !interrupted && chunk.len() < PARSE_CHUNK_DOCS;
+
+        for parsed in parser.parse(chunk)? {
+            stats.num_tokens += parsed.page.num_tokens as u64;
+            batch.add(parsed, options.filter, &mut signals);
+
+            if options
+                .flush
+                .is_due(batch.doc_map.len() as u64, batch.bytes)
+            {
+                stats.parse_time += phase_start.elapsed();
+                phase_start = Instant::now();
+
+                let flushed = std::mem::take(&mut batch);
+                runs.spill(flushed.inverted_index)?;
+                insert_docs(&mut url_map, &mut url_ids, flushed.doc_map)?;
+                forward.insert(flushed.doc_terms)?;
+                positions.insert(flushed.doc_positions)?;
+
+                println!("Processed {} documents", stats.num_docs + 1);
+
+                stats.flush_time += phase_start.elapsed();
+                phase_start = Instant::now();
+            }
+
+            stats.num_docs += 1;
+        }
+
+        if interrupted {
+            // Keep what was parsed so far consistent on disk before bailing out
+            runs.remove()?;
+            insert_docs(&mut url_map, &mut url_ids, batch.doc_map)?;
+            forward.insert(batch.doc_terms)?;
+            positions.insert(batch.doc_positions)?;
+            doc_ids.write(&doc_ids_path)?;
+            return Err(Error::Interrupted);
+        }
+        if exhausted {
+            break;
+        }
+    }
+    stats.parse_time += phase_start.elapsed();
+    phase_start = Instant::now();
+
+    insert_docs(&mut url_map, &mut url_ids, batch.doc_map)?;
+    forward.insert(batch.doc_terms)?;
+    positions.insert(batch.doc_positions)?;
+    add_corpus_signals(&mut url_map, &signals.inlinks, &signals.titles)?
+        .write(&DocQuality::path(db_path))?;
+    WordFrequencies::from(signals.word_frequencies).write(&WordFrequencies::path(db_path))?;
+    signals.flagged.write(&FlaggedDocs::path(db_path))?;
+    DeletedDocs::default().write(&DeletedDocs::path(db_path))?;
+    signals.boosts.write(&DocBoosts::path(db_path))?;
+    signals.crawl_times.write(&CrawlTimes::path(db_path))?;
+    signals.doc_lengths.write(&DocLengths::path(db_path))?;
+    IndexedFields {
+        names: signals.field_names.into_iter().collect(),
+    }
+    .write(&IndexedFields::path(db_path))?;
+    doc_ids.write(&doc_ids_path)?;
+    stats.flush_time += phase_start.elapsed();
+    phase_start = Instant::now();
+
+    // Postings are written once, with their scores, rather than first with
+    // their term frequencies
+    stats.num_terms = calculate_scores(
+        runs,
+        batch.inverted_index,
+        (db_path, seek_path),
+        build_id,
+        stats.num_docs,
+    )?;
+    stats.score_time = phase_start.elapsed();
+
+    Ok(stats)
+}
+
+/// Documents parsed since they were last written out.
+#[derive(Default)]
+struct Batch {
+    inverted_index: TempInvertedIndex,
+    doc_map: DocMap,
+    doc_terms: DocTerms,
+    doc_positions: DocPositions,
+    /// Estimated memory the documents take
+    bytes: u64,
+}
+
+/// What documents tell beyond their own postings and records, written next
+/// to the index once all of them are parsed.
+#[derive(Default)]
+struct CorpusSignals {
+    /// Names of the custom fields of the documents
+    field_names: BTreeSet<String>,
+    /// Pages linking to each url, filled into the url map once all are parsed
+    inlinks: HashMap<String, u32>,
+    /// Documents containing each word, for spelling correction
+    word_frequencies: HashMap<String, u64>,
+    flagged: FlaggedDocs,
+    boosts: DocBoosts,
+    crawl_times: CrawlTimes,
+    doc_lengths: DocLengths,
+    /// Documents sharing each title, templated and mirrored pages being junk
+    titles: HashMap<String, u32>,
+}
+
+impl Batch {
+    /// Adds the postings, terms and record of `parsed` to the batch, and what
+    /// it tells about the corpus to `signals`.
+    fn add(
+        &mut self,
+        parsed: ParsedDoc,
+        filter: Option<&dyn DocFilter>,
+        signals: &mut CorpusSignals,
+    ) {
+        let ParsedDoc {
+            doc_id,
+            data,
+            page,
+            field_terms,
+            positions,
+        } = parsed;
+
+        signals.field_names.extend(page.fields.keys().cloned());
+        self.bytes += page.batch_size() + data.url.len() as u64;
+        let is_flagged = filter.is_some_and(|filter| filter.flags(&data, &page));
+        if is_flagged {
+            signals.flagged.doc_ids.push(doc_id);
+        }
+        signals.boosts.add(doc_id, data.boost);
+        if let Some(crawled_at) = data.crawled_at {
+            signals.crawl_times.times.push((doc_id, crawled_at));
+        }
+        signals
+            .doc_lengths
+            .lengths
+            .push((doc_id, page.num_tokens as u32));
+
+        for link in &page.links {
+            *signals.inlinks.entry(link.clone()).or_default() += 1;
+        }
+        for word in page.words {
+            *signals.word_frequencies.entry(word).or_default() += 1;
+        }
+        if let Some(title) = &page.title {
+            *signals.titles.entry(title.clone()).or_default() += 1;
+        }
+        let quality = page_quality(
+            data.content.len(),
+            page.text_len,
+            page.num_tokens,
+            page.links.len(),
+        );
+
+        let mut terms = Vec::with_capacity(page.word_count.len());
+        for (word, count) in page.word_count {
+            let index_data = TempTermIndex { doc_id, tf: count };
+
+            self.inverted_index
+                .entry(word.clone())
+                .or_default()
+                .push(index_data);
+            terms.push((word, count));
+        }
+        // Matched exactly by `site:` and `url:` clauses, not similar documents
+        for term in url_field_terms(&data.url) {
+            self.inverted_index
+                .entry(term)
+                .or_default()
+                .push(TempTermIndex { doc_id, tf: 1 });
+        }
+        // Matched by clauses of their field, also left out of similar documents
+        for (term, tf) in field_terms {
+            self.inverted_index
+                .entry(term)
+                .or_default()
+                .push(TempTermIndex { doc_id, tf });
+        }
+        self.doc_terms.insert(doc_id, terms);
+        self.doc_positions.insert(doc_id, positions);
+
+        self.doc_map.insert(
+            doc_id,
+            Doc {
+                url: data.url,
+                title: page.title,
+                num_tokens: page.num_tokens as u64,
+                language: page.language,
+                crawled_at: data.crawled_at,
+                inlinks: 0,
+                outlinks: page.links.len() as u32,
+                flagged: is_flagged,
+                quality: Some(quality as f32),
+                boost: data.boost,
+                source: data.source,
+                fields: page.fields,
+            },
+        );
+    }
+}
+
+/// Turns documents into what a build indexes of them, on a pool of threads
+/// sized by [`BuildOptions::threads`].
+struct DocParser {
+    tokenizer: Tokenizer,
+    synonyms: Synonyms,
+    fields: FieldSelectors,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool: rayon::ThreadPool,
+}
+
+/// A document of a build, parsed and ready to merge into the index.
+struct ParsedDoc {
+    doc_id: DocID,
+    data: CrawlFile,
+    page: ParsedPage,
+    /// Terms of the custom fields of the page and their frequencies
+    field_terms: HashMap<String, TF>,
+    /// Offsets of the terms of the page, sorted
+    positions: Positions,
+}
+
+impl DocParser {
+    fn new(options: BuildOptions) -> Result<Self> {
+        let tokenizer = Tokenizer::with_analyzer(options.analyzer)?;
+        let synonyms = options
+            .synonyms
+            .map(|synonyms| Synonyms::new(synonyms, &tokenizer))
+            .unwrap_or_default();
+        let fields = options
+            .fields
+            .map(FieldSelectors::new)
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            tokenizer,
+            synonyms,
+            fields,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(options.threads)
+                .build()
+                .map_err(|e| Error::TaskFailed {
+                    task: "Indexing",
+                    source: e.into(),
+                })?,
+        })
+    }
+
+    /// Parses `docs` in parallel, keeping their order.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse(&self, docs: Vec<(DocID, CrawlFile)>) -> Result<Vec<ParsedDoc>> {
+        self.pool.install(|| {
+            docs.into_par_iter()
+                .map(|(doc_id, data)| self.parse_doc(doc_id, data))
+                .collect()
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn parse(&self, docs: Vec<(DocID, CrawlFile)>) -> Result<Vec<ParsedDoc>> {
+        docs.into_iter()
+            .map(|(doc_id, data)| self.parse_doc(doc_id, data))
+            .collect()
+    }
+
+    fn parse_doc(&self, doc_id: DocID, data: CrawlFile) -> Result<ParsedDoc> {
+        let data = data.decoded();
+        let mut page = parse_page_with(&data.url, &data.content, &self.tokenizer, &self.fields);
+        for (name, text) in &data.fields {
+            check_field_name(name)?;
+            page.fields.insert(name.clone(), text.clone());
+        }
+        self.synonyms.expand(&mut page);
+
+        let mut field_terms: HashMap<String, TF> = HashMap::new();
+        for (name, text) in &page.fields {
+            for term in self.tokenizer.tokenize(text) {
+                *field_terms.entry(field_term(name, &term)).or_default() += 1;
+            }
+        }
+        let mut positions: Positions = std::mem::take(&mut page.positions).into_iter().collect();
+        positions.sort_unstable();
+
+        Ok(ParsedDoc {
+            doc_id,
+            data,
+            page,
+            field_terms,
+            positions,
+        })
+    }
+}
+
+/// Indexes of up to `n` of `len` items, spread evenly through them.
+fn spread(len: usize, n: usize) -> impl Iterator<Item = usize> {
+    let num_samples = n.min(len);
+    (0..num_samples).map(move |i| (2 * i + 1) * len / (2 * num_samples))
+}
+
+/// Writes `doc_map` to the url map and its reverse.
+pub(super) fn insert_docs(
+    url_map: &mut KVDatabase<DocID, Doc>,
+    url_ids: &mut KVDatabase<String, DocID>,
+    doc_map: DocMap,
+) -> Result<()> {
+    url_ids.insert(
+        doc_map
+            .iter()
+            .map(|(doc_id, doc)| (doc.url.clone(), *doc_id))
+            .collect(),
+    )?;
+    url_map.insert(doc_map)
+}
+
+/// Sets what only the whole corpus tells about the documents of `url_map`:
+/// how many pages link to them, and whether their title is shared. Returns
+/// the documents below full quality.
+fn add_corpus_signals(
+    url_map: &mut KVDatabase<DocID, Doc>,
+    inlinks: &HashMap<String, u32>,
+    titles: &HashMap<String, u32>,
+) -> Result<DocQuality> {
+    let mut changed = DocMap::new();
+    let mut quality = DocQuality::default();
+    for entry in url_map.iter() {
+        let (doc_id, mut doc) = entry?;
+        let linked = inlinks.get(&normalize_url(&doc.url)).copied();
+        let shared = doc
+            .title
+            .as_ref()
+            .and_then(|title| titles.get(title))
+            .copied()
+            .unwrap_or(1);
+
+        if let Some(count) = linked {
+            doc.inlinks = count;
+        }
+        if shared > 1 {
+            doc.quality = doc
+                .quality
+                .map(|quality| quality * duplicate_title_penalty(shared) as f32);
+        }
+
+        if let Some(score) = doc.quality.filter(|&score| score < 1.0) {
+            quality.scores.push((doc_id, score));
+        }
+        if linked.is_some() || shared > 1 {
+            changed.insert(doc_id, doc);
+        }
+    }
+
+    url_map.insert(changed)?;
+    Ok(quality)
+}
+
+/// `url` as links to it are written after parsing, or as is if it doesn't
+/// parse.
+fn normalize_url(url: &str) -> String {
+    Url::parse(url).map_or_else(|_| url.to_string(), String::from)
+}
+
+/// Parses the HTML `content` of the page crawled from `url`.
+#[must_use]
+pub fn parse_page(url: &str, content: &str, tokenizer: &Tokenizer) -> ParsedPage {
+    parse_page_with(url, content, tokenizer, &FieldSelectors::default())
+}
+
+/// Parses the HTML `content` of the page crawled from `url`, extracting the
+/// text of `fields`.
+#[must_use]
+pub fn parse_page_with(
+    url: &str,
+    content: &str,
+    tokenizer: &Tokenizer,
+    fields: &FieldSelectors,
+) -> ParsedPage {
+    let document = Html::parse_document(content);
+
+    // Extract and filter all text
+    let mut word_count: HashMap<String, u32> = HashMap::new();
+
+    let all_text = document.root_element().text().collect::<Vec<_>>();
+    let title_words = select_text(&document, "title").unwrap_or_default();
+
+    let num_tokens = update_word_count(&all_text, tokenizer, &mut word_count, 1);
+    let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+    for (position, term) in all_text
+        .iter()
+        .flat_map(|text| tokenizer.tokenize(text))
+        .enumerate()
+    {
+        positions.entry(term).or_default().push(position as u32);
+    }
+    let text_len = all_text.iter().map(|text| text.trim().len()).sum();
+    let words = all_text
+        .iter()
+        .flat_map(|text| tokenizer.words(text))
+        .collect();
+    for (selector, weight) in WEIGHTED_SELECTORS {
+        let words = select_text(&document, selector).unwrap_or_default();
+        update_word_count(&words, tokenizer, &mut word_count, weight as u32);
+    }
+
+    let title = title_words
+        .concat()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let language = document
+        .root_element()
+        .value()
+        .attr("lang")
+        .and_then(|lang| lang.split(['-', '_']).next())
+        .map(str::trim)
+        .filter(|lang| !lang.is_empty())
+        .map(str::to_ascii_lowercase);
+
+    let own_url = normalize_url(url);
+    let links = Url::parse(url)
+        .ok()
+        .and_then(|base| document_links(&base, &document).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(String::from)
+        .filter(|link| *link != own_url)
+        .collect();
+
+    ParsedPage {
+        word_count,
+        num_tokens,
+        title: (!title.is_empty()).then_some(title),
+        language,
+        links,
+        positions,
+        words,
+        text_len,
+        fields: fields.extract(&document),
+    }
+}
+
+/// Rebuilds the seek file of the database at `db_path`, returning how many
+/// bytes it left out, `None` for databases the index doesn't have.
+fn rebuild_seek<K, V>(db_path: &Path, seek_path: &Path) -> Result<Option<u64>>
+where
+    K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+    V: Serialize + for<'de> Deserialize<'de> + Clone,
+{
+    if !db_path.exists() {
+        return Ok(None);
+    }
+
+    let (_, truncated) =
+        KVDatabase::<K, V>::rebuild_seek_from_db(db_path.to_path_buf(), seek_path.to_path_buf())?;
+
+    Ok(Some(truncated))
+}
+
+fn select_text<'a>(document: &'a Html, selector: &str) -> Result<Vec<&'a str>> {
+    Ok(document
+        .select(
+            &Selector::parse(selector).map_err(|e| Error::InvalidSelector {
+                selector: selector.to_string(),
+                message: e.to_string(),
+            })?,
+        )
+        .map(|element| element.text().collect::<Vec<_>>())
+        .collect::<Vec<_>>()
+        .concat())
+}
+
+fn update_word_count(
+    title_words: &[&str],
+    tokenizer: &Tokenizer,
+    word_count: &mut HashMap<String, u32>,
+    weight: u32,
+) -> usize {
+    let mut num_tokens = 0;
+
+    title_words
+        .iter()
+        .map(|text| tokenizer.tokenize(text))
+        .for_each(|tokens| {
+            num_tokens += tokens.len();
+            for token in tokens {
+                let count = word_count.entry(token).or_insert(0);
+                *count += weight;
+            }
+        });
+
+    num_tokens
+}
+
+/// Writes the postings database of `db_path` and `seek_path` from the
+/// postings of `runs` followed by those still in `inverted_index`, with
+/// tf-idf scores in place of term frequencies.
+///
+/// Returns how many terms it holds.
+pub fn calculate_scores(
+    runs: PostingRuns,
+    mut inverted_index: TempInvertedIndex,
+    (db_path, seek_path): (&Path, &Path),
+    build_id: Uuid,
+    num_docs: u64,
+) -> Result<u64> {
+    let temp_db_path = scratch::temp_path(db_path);
+    let temp_seek_path = scratch::temp_path(seek_path);
+    let term_stats_path = DiskInvertedIndex::term_stats_path(db_path);
+    let term_stats_seek_path = DiskInvertedIndex::term_stats_path(seek_path);
+    let temp_term_stats_path = scratch::temp_path(&term_stats_path);
+    let temp_term_stats_seek_path = scratch::temp_path(&term_stats_seek_path);
+    let term_freqs_path = DiskInvertedIndex::term_freqs_path(db_path);
+    let term_freqs_seek_path = DiskInvertedIndex::term_freqs_path(seek_path);
+    let temp_term_freqs_path = scratch::temp_path(&term_freqs_path);
+    let temp_term_freqs_seek_path = scratch::temp_path(&term_freqs_seek_path);
+
+    let mut postings = KVDatabaseWriter::create(&temp_db_path, temp_seek_path.clone(), build_id)?;
+    let mut term_stats = KVDatabaseWriter::create(
+        &temp_term_stats_path,
+        temp_term_stats_seek_path.clone(),
+        build_id,
+    )?;
+    let mut term_freqs = KVDatabaseWriter::create(
+        &temp_term_freqs_path,
+        temp_term_freqs_seek_path.clone(),
+        build_id,
+    )?;
+
+    let mut terms = runs.terms();
+    terms.extend(inverted_index.keys().cloned());
+    terms.sort_unstable();
+    terms.dedup();
+
+    let mut num_terms = 0;
+    let mut skipped = 0;
+    for chunk in terms.chunks(MAX_ITERATIONS as usize) {
+        if shutdown::requested() {
+            drop((postings, term_stats, term_freqs));
+            remove_file(&temp_db_path)?;
+            remove_file(&temp_term_stats_path)?;
+            remove_file(&temp_term_freqs_path)?;
+            runs.remove()?;
+            return Err(Error::Interrupted);
+        }
+
+        let mut batch = Vec::with_capacity(chunk.len());
+        for term in chunk {
+            // A corrupt posting list loses its term rather than the whole build
+            let Ok(mut term_postings) = runs.postings(term) else {
+                skipped += 1;
+                continue;
+            };
+            term_postings.extend(inverted_index.remove(term).unwrap_or_default());
+            batch.push((term.clone(), term_postings));
+        }
+
+        num_terms += chunk.len();
+        for (key, new_data, stats, tfs) in score_terms(batch, num_docs) {
+            postings.write(key.clone(), &new_data)?;
+            term_stats.write(key.clone(), &stats)?;
+            term_freqs.write(key, &tfs)?;
+        }
+        println!("Translate {num_terms} words to tf-idf scores");
+    }
+
+    if skipped > 0 {
+        eprintln!("Skipped {skipped} corrupt posting lists");
+    }
+    runs.remove()?;
+
+    postings.finish()?;
+    term_stats.finish()?;
+    term_freqs.finish()?;
+
+    scratch::persist(&temp_db_path, db_path)?;
+    scratch::persist(&temp_seek_path, seek_path)?;
+    scratch::persist(&temp_term_stats_path, &term_stats_path)?;
+    scratch::persist(&temp_term_stats_seek_path, &term_stats_seek_path)?;
+    scratch::persist(&temp_term_freqs_path, &term_freqs_path)?;
+    scratch::persist(&temp_term_freqs_seek_path, &term_freqs_seek_path)?;
+
+    Ok((num_terms - skipped) as u64)
+}
+
+/// Posting lists of `batch` with their term frequencies turned into tf-idf
+/// scores, the stats of every term and the term frequencies themselves. Terms are scored on the rayon pool,
+/// each independently of the others.
+#[cfg(not(target_arch = "wasm32"))]
+fn score_terms(
+    batch: Vec<(String, Vec<TempTermIndex>)>,
+    num_docs: u64,
+) -> Vec<(String, Vec<TermIndex>, TermStats, TermFreqs)> {
+    batch
+        .into_par_iter()
+        .map(|(key, mut value)| score_term(key, &mut value, num_docs))
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn score_terms(
+    batch: Vec<(String, Vec<TempTermIndex>)>,
+    num_docs: u64,
+) -> Vec<(String, Vec<TermIndex>, TermStats, TermFreqs)> {
+    batch
+        .into_iter()
+        .map(|(key, mut value)| score_term(key, &mut value, num_docs))
+        .collect()
+}
+
+fn score_term(
+    key: String,
+    value: &mut [TempTermIndex],
+    num_docs: u64,
+) -> (String, Vec<TermIndex>, TermStats, TermFreqs) {
+    // Documents keep the IDs of earlier builds, so they come in any order
+    value.sort_unstable_by_key(|posting| posting.doc_id);
+    let data_len = value.len();
+
+    let mut stats = TermStats {
+        df: data_len as u64,
+        ..TermStats::default()
+    };
+    let new_data = value
+        .iter()
+        .map(|index_data| {
+            let tf_idf =
+                calculate_tf_idf(f64::from(index_data.tf), data_len as f64, num_docs as f64);
+            stats.total_tf += u64::from(index_data.tf);
+            stats.max_tfidf = stats.max_tfidf.max(tf_idf);
+
+            TermIndex {
+                doc_id: index_data.doc_id,
+                tf_idf,
+            }
+        })
+        .collect();
+    let tfs = value
+        .iter()
+        .map(|index_data| (index_data.doc_id, index_data.tf))
+        .collect();
+
+    (key, new_data, stats, tfs)
+}
+
+/// Scores `postings` again for a corpus of `num_docs` documents, with the term
+/// frequencies `tfs` of their documents. Returns the stats of the term.
+fn rescore(postings: &mut [TermIndex], tfs: &TermFreqs, num_docs: u64) -> TermStats {
+    let df = postings.len();
+    let mut stats = TermStats {
+        df: df as u64,
+        ..TermStats::default()
+    };
+    for posting in postings {
+        let tf = tfs
+            .binary_search_by_key(&posting.doc_id, |&(doc_id, _)| doc_id)
+            .map_or(0, |i| tfs[i].1);
+        posting.tf_idf = calculate_tf_idf(f64::from(tf), df as f64, num_docs as f64);
+        stats.total_tf += u64::from(tf);
+        stats.max_tfidf = stats.max_tfidf.max(posting.tf_idf);
+    }
+
+    stats
+}
+
+/// Zero for counts whose logarithm is undefined, which only a corrupt or
+/// hand-edited index produces.
+fn calculate_tf_idf(tf: f64, df: f64, n: f64) -> f64 {
+    if tf <= 0.0 || df <= 0.0 || n <= 0.0 {
+        return 0.0;
+    }
+
+    let tf_idf = (1.0 + tf.log10()) * (n / df).log10();
+    if tf_idf.is_finite() {
+        tf_idf
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{page, TestDir};
+
+    fn test_index() -> DiskInvertedIndex {
+        DiskInvertedIndex::from(
+            "tests/test-data/search_test_db.test".into(),
+            "tests/test-data/search_test_seek.test".into(),
+            "tests/test-data/search_test_url_map.test".into(),
+            "tests/test-data/search_test_url_map_seek.test".into(),
+        )
+        .expect("Failed to open test index")
+    }
+
+    #[test]
+    fn parse_page_metadata() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let page = parse_page(
+            "https://example.com/",
+            r#"<html lang="en-US"><head><title> Rust
+                and  cooking </title></head><body><p>pasta recipes</p></body></html>"#,
+            &tokenizer,
+        );
+
+        assert_eq!(page.title.as_deref(), Some("Rust and cooking"));
+        assert_eq!(page.language.as_deref(), Some("en"));
+        assert_eq!(page.num_tokens, 5);
+
+        let page = parse_page("https://example.com/", "<p>no title</p>", &tokenizer);
+        assert_eq!((page.title, page.language), (None, None));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn tf_idf_is_finite() {
+        assert_eq!(calculate_tf_idf(10.0, 1.0, 100.0), 4.0);
+        assert_eq!(calculate_tf_idf(1.0, 0.0, 100.0), 0.0);
+        assert_eq!(calculate_tf_idf(0.0, 1.0, 100.0), 0.0);
+        assert_eq!(calculate_tf_idf(1.0, 1.0, 0.0), 0.0);
+        assert_eq!(calculate_tf_idf(f64::NAN, 1.0, 100.0), 0.0);
+    }
+
+    #[test]
+    fn preload() {
+        let mut index = test_index();
+        let postings = index.get("eric").expect("Failed to read postings");
+
+        assert_eq!(index.preload(1).expect("Failed to preload"), 1);
+        assert!(index.preloaded.contains_key("eric"));
+        assert_eq!(
+            index.get("eric").expect("Failed to read postings"),
+            postings
+        );
+
+        assert_eq!(index.preload(10).expect("Failed to preload"), 2);
+        assert_eq!(index.preload(0).expect("Failed to preload"), 0);
+    }
+
+    #[test]
+    fn memory_stats() {
+        let mut index = test_index();
+        let stats = index.memory_stats();
+
+        assert!(stats.seek_maps > 0);
+        assert_eq!(stats.dictionary, "eric".len() + "minassian".len());
+        assert_eq!((stats.cache_terms, stats.cache), (0, 0));
+
+        index.preload(1).expect("Failed to preload");
+        let preloaded = index.memory_stats();
+
+        assert_eq!(preloaded.cache_terms, 1);
+        assert!(preloaded.cache >= "eric".len() + 3 * size_of::<TermIndex>());
+        assert_eq!(preloaded.total(), stats.total() + preloaded.cache);
+    }
+
+    #[test]
+    fn rebuild_keeps_open_generation() {
+        let dir = TestDir::new();
+        let build = |url: &str, content: &str| {
+            DiskInvertedIndex::build_from_documents(
+                dir.path("rebuild.db"),
+                dir.path("rebuild.seek"),
+                dir.path("rebuild_url_map.db"),
+                dir.path("rebuild_url_map.seek"),
+                [Ok(CrawlFile {
+                    url: url.to_string(),
+                    content: content.to_string(),
+                    ..page("", "")
+                })],
+            )
+            .map(|(index, _)| index)
+            .expect("Failed to build index")
+        };
+
+        let old = build("https://old.example/", "<p>apples and pears</p>");
+        let new = build("https://new.example/", "<p>cherries</p>");
+
+        assert_eq!(new.generation(), old.generation() + 1);
+        for (index, url, term) in [
+            (&old, "https://old.example/", "pear"),
+            (&new, "https://new.example/", "cherri"),
+        ] {
+            let doc = index.get_doc_by_url(url).expect("Failed to read doc");
+            assert_eq!(doc.map(|(_, doc)| doc.url), Some(url.to_string()));
+
+            let terms = index
+                .db
+                .iter()
+                .map(|entry| entry.map(|(term, _)| term))
+                .collect::<Result<Vec<_>>>()
+                .expect("Failed to read postings");
+            assert!(terms.contains(&term.to_string()));
+        }
+    }
+
+    #[test]
+    fn flush_policy() {
+        let dir = TestDir::new();
+        let policy = FlushPolicy {
+            batch_docs: 2,
+            memory_budget_mb: 1,
+        };
+        assert!(!policy.is_due(1, 1024));
+        assert!(policy.is_due(2, 1024));
+        assert!(policy.is_due(1, 1024 * 1024));
+        let unlimited = FlushPolicy {
+            batch_docs: 0,
+            memory_budget_mb: 0,
+        };
+        assert!(!unlimited.is_due(u64::MAX, u64::MAX));
+
+        let build = |name: &str, flush| {
+            let documents = ["<p>apples and pears</p>", "<p>pears</p>", "<p>apples</p>"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, content)| {
+                    Ok(CrawlFile {
+                        url: format!("https://example.com/{i}"),
+                        content: content.to_string(),
+                        ..page("", "")
+                    })
+                });
+            DiskInvertedIndex::build_with(
+                dir.path(format!("{name}.db")),
+                dir.path(format!("{name}.seek")),
+                dir.path(format!("{name}_url_map.db")),
+                dir.path(format!("{name}_url_map.seek")),
+                documents,
+                BuildOptions {
+                    flush,
+                    ..BuildOptions::default()
+                },
+            )
+            .map(|(index, _)| index)
+            .expect("Failed to build index")
+        };
+
+        let batched = build(
+            "flush_batched",
+            FlushPolicy {
+                batch_docs: 1,
+                memory_budget_mb: 0,
+            },
+        );
+        let whole = build("flush_whole", unlimited);
+        for (term, len) in [("appl", 2), ("pear", 2), ("site:example.com", 3)] {
+            let postings = batched.get(term).expect("Failed to read postings");
+            assert_eq!(
+                postings,
+                whole.get(term).expect("Failed to read postings"),
+                "{term}"
+            );
+            assert_eq!(postings.map_or(0, |postings| postings.len()), len, "{term}");
+        }
+    }
+
+    #[test]
+    fn parallel_parsing() {
+        let dir = TestDir::new();
+        let build = |name: &str, threads| {
+            // Spans several chunks, the last url repeating the first
+            let documents = (0..=2 * PARSE_CHUNK_DOCS).map(|i| {
+                let i = i % (2 * PARSE_CHUNK_DOCS);
+                Ok(page(
+                    &i.to_string(),
+                    &format!("page{} {}", i % 7, ["apples", "pears"][i % 2]),
+                ))
+            });
+            DiskInvertedIndex::build_with(
+                dir.path(format!("{name}.db")),
+                dir.path(format!("{name}.seek")),
+                dir.path(format!("{name}_url_map.db")),
+                dir.path(format!("{name}_url_map.seek")),
+                documents,
+                BuildOptions {
+                    threads,
+                    ..BuildOptions::default()
+                },
+            )
+            .expect("Failed to build index")
+        };
+
+        let (single, single_stats) = build("parallel_single", 1);
+        let (parallel, parallel_stats) = build("parallel_many", 4);
+        assert_eq!(parallel_stats.num_docs, 2 * PARSE_CHUNK_DOCS as u64);
+        assert_eq!(parallel_stats.skipped_docs, 1);
+        assert_eq!(parallel_stats.num_tokens, single_stats.num_tokens);
+        for term in ["appl", "pear", "page3", "site:example.com"] {
+            assert_eq!(
+                parallel.get(term).expect("Failed to read postings"),
+                single.get(term).expect("Failed to read postings"),
+                "{term}"
+            );
+        }
+        let doc = parallel.get_doc(5).expect("Failed to read doc");
+        assert_eq!(
+            doc.map(|doc| doc.url),
+            Some("https://example.com/5".to_string())
+        );
+    }
+
+    #[test]
+    fn stable_doc_ids() {
+        let dir = TestDir::new();
+        let build = |urls: &[&str]| {
+            let documents: Vec<_> = urls
+                .iter()
+                .map(|url| {
+                    Ok(CrawlFile {
+                        url: (*url).to_string(),
+                        ..page("", "apples")
+                    })
+                })
+                .collect();
+            DiskInvertedIndex::build_from_documents(
+                dir.path("stable_doc_ids.db"),
+                dir.path("stable_doc_ids.seek"),
+                dir.path("stable_doc_ids_url_map.db"),
+                dir.path("stable_doc_ids_url_map.seek"),
+                documents,
+            )
+            .map(|(index, _)| index)
+            .expect("Failed to build index")
+        };
+        let doc_id = |index: &DiskInvertedIndex, url| {
+            index
+                .get_doc_by_url(url)
+                .expect("Failed to read doc")
+                .map(|(doc_id, _)| doc_id)
+        };
+
+        let (a, b, c) = (
+            "https://a.example/",
+            "https://b.example/",
+            "https://c.example/",
+        );
+        let old = build(&[a, b, b]);
+        assert_eq!(old.num_docs(), 2);
+        let new = build(&[c, b]);
+
+        assert_eq!(doc_id(&new, b), doc_id(&old, b));
+        assert_eq!(doc_id(&new, a), None);
+        assert!(doc_id(&new, c) > doc_id(&old, a));
+        let postings = new
+            .get("appl")
+            .expect("Failed to read postings")
+            .expect("Missing postings");
+        assert!(postings.is_sorted_by_key(|posting| posting.doc_id));
+        assert_eq!(postings.len(), 2);
+    }
+
+    #[test]
+    fn unicode_terms() {
+        let dir = TestDir::new();
+        let text = "Müller straße café naïve 日本語 Ωmega";
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("unicode_terms.db"),
+            dir.path("unicode_terms.seek"),
+            dir.path("unicode_terms_url_map.db"),
+            dir.path("unicode_terms_url_map.seek"),
+            [Ok(CrawlFile {
+                url: "https://ünïcødé.example/".to_string(),
+                ..page("", text)
+            })],
+        )
+        .expect("Failed to build index");
+
+        let terms = Tokenizer::new()
+            .expect("Failed to create tokenizer")
+            .tokenize(text);
+        assert_eq!(terms.len(), 6);
+        for term in terms {
+            let postings = index.get(&term).expect("Failed to read postings");
+            assert_eq!(
+                postings.map(|postings| postings[0].doc_id),
+                Some(0),
+                "{term}"
+            );
+        }
+        let doc = index.get_doc(0).expect("Failed to read doc");
+        assert_eq!(
+            doc.map(|doc| doc.url),
+            Some("https://ünïcødé.example/".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_selector() {
+        let document = Html::parse_document("<title>Rust</title>");
+        assert_eq!(
+            select_text(&document, "title").expect("Failed to select title"),
+            ["Rust"]
+        );
+        assert!(matches!(
+            select_text(&document, "title["),
+            Err(Error::InvalidSelector { selector, .. }) if selector == "title["
+        ));
+    }
+
+    #[test]
+    fn mixed_builds() {
+        let dir = TestDir::new();
+        for name in ["first", "second"] {
+            DiskInvertedIndex::build_from_documents(
+                dir.path(format!("mixed_{name}.db")),
+                dir.path(format!("mixed_{name}.seek")),
+                dir.path(format!("mixed_{name}_url_map.db")),
+                dir.path(format!("mixed_{name}_url_map.seek")),
+                [Ok(CrawlFile {
+                    url: format!("https://{name}.example/"),
+                    ..page("", "mixed")
+                })],
+            )
+            .expect("Failed to build index");
+        }
+
+        for (db, seek, url_map, url_map_seek) in [
+            ("first", "first", "second", "second"),
+            ("first", "second", "first", "first"),
+        ] {
+            let opened = DiskInvertedIndex::from(
+                dir.path(format!("mixed_{db}.db")),
+                dir.path(format!("mixed_{seek}.seek")),
+                dir.path(format!("mixed_{url_map}_url_map.db")),
+                dir.path(format!("mixed_{url_map_seek}_url_map.seek")),
+            );
+            assert!(matches!(opened, Err(Error::MixedBuild { .. })));
+        }
+
+        let index = DiskInvertedIndex::from(
+            dir.path("mixed_first.db"),
+            dir.path("mixed_first.seek"),
+            dir.path("mixed_first_url_map.db"),
+            dir.path("mixed_first_url_map.seek"),
+        )
+        .expect("Failed to open index");
+        assert!(!index.build_id().is_nil());
+        assert!(test_index().build_id().is_nil());
+    }
+
+    #[test]
+    fn open_during_build() {
+        let dir = TestDir::new();
+        let db_path = dir.path("open_during_build.db");
+        let generation = Generation::begin(&db_path).expect("Failed to begin build");
+
+        let opened = DiskInvertedIndex::from(
+            db_path,
+            dir.path("open_during_build.seek"),
+            dir.path("open_during_build_url_map.db"),
+            dir.path("open_during_build_url_map.seek"),
+        );
+        assert!(matches!(opened, Err(Error::Rebuilding { .. })));
+
+        drop(generation);
+    }
+
+    #[test]
+    fn sample_postings() {
+        let dir = TestDir::new();
+        let page = |i: usize| {
+            Ok(page(
+                &i.to_string(),
+                &format!("apple {}", if i == 3 { "pear" } else { "" }),
+            ))
+        };
+        let (mut index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("sample_postings.db"),
+            dir.path("sample_postings.seek"),
+            dir.path("sample_postings_url_map.db"),
+            dir.path("sample_postings_url_map.seek"),
+            (0..10).map(page),
+        )
+        .expect("Failed to build index");
+        let sample = |index: &DiskInvertedIndex, term: &str, n: usize| {
+            index
+                .sample_postings(term, n)
+                .expect("Failed to sample postings")
+                .into_iter()
+                .map(|posting| posting.doc_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sample(&index, "appl", 3), [1, 5, 8]);
+        assert_eq!(sample(&index, "appl", 20), (0..10).collect::<Vec<_>>());
+        assert_eq!(sample(&index, "pear", 5), [3]);
+        assert!(sample(&index, "appl", 0).is_empty());
+        assert!(sample(&index, "missing", 5).is_empty());
+
+        let read = index
+            .sample_postings("appl", 3)
+            .expect("Failed to sample postings");
+        index
+            .preload(index.num_terms() as usize)
+            .expect("Failed to preload");
+        assert!(index.is_preloaded("appl"));
+        assert_eq!(
+            index
+                .sample_postings("appl", 3)
+                .expect("Failed to sample postings"),
+            read
+        );
+
+        // Deleted documents are left out of the list samples spread over
+        assert!(index.delete_doc(5).expect("Failed to delete document"));
+        assert_eq!(sample(&index, "appl", 3), [1, 4, 8]);
+        assert!(index.delete_doc(3).expect("Failed to delete document"));
+        assert!(sample(&index, "pear", 5).is_empty());
+        index.preloaded.clear();
+        assert_eq!(sample(&index, "appl", 20), [0, 1, 2, 4, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn corpus_accessors() {
+        let dir = TestDir::new();
+        let legacy = test_index();
+        assert_eq!(legacy.num_docs(), 3);
+        assert_eq!(legacy.num_terms(), 2);
+        assert_eq!(
+            legacy.doc_frequency("eric"),
+            legacy
+                .get("eric")
+                .expect("Failed to read postings")
+                .map_or(0, |p| p.len() as u64)
+        );
+        assert_eq!(legacy.doc_frequency("not_in_index"), 0);
+        assert_eq!(legacy.average_doc_length(), None);
+        assert!(matches!(
+            legacy.term_stats("eric"),
+            Err(Error::MissingCompanion { path }) if path.ends_with("search_test_db.test.terms")
+        ));
+
+        let page = |content: &str| {
+            Ok(CrawlFile {
+                url: format!("https://{content}.example/"),
+                ..page("", content)
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("corpus_stats.db"),
+            dir.path("corpus_stats.seek"),
+            dir.path("corpus_stats_url_map.db"),
+            dir.path("corpus_stats_url_map.seek"),
+            [page("apple apple"), page("apple pear plum")],
+        )
+        .expect("Failed to build index");
+
+        assert_eq!(index.num_docs(), 2);
+        assert_eq!(index.num_terms(), 3);
+        assert_eq!(index.doc_frequency("appl"), 2);
+        assert_eq!(index.doc_frequency("pear"), 1);
+        assert_eq!(index.average_doc_length(), Some(2.5));
+
+        let stats = index
+            .term_stats("appl")
+            .expect("Failed to read term stats")
+            .expect("Term should have stats");
+        let max_tfidf = index
+            .get("appl")
+            .expect("Failed to read postings")
+            .expect("Term should have postings")
+            .iter()
+            .map(|posting| posting.tf_idf)
+            .fold(0.0, f64::max);
+        assert_eq!(stats.df, 2);
+        assert_eq!(stats.total_tf, 3);
+        assert!((stats.max_tfidf - max_tfidf).abs() < f64::EPSILON);
+        assert_eq!(index.term_stats("kiwi").expect("Failed to read"), None);
+    }
+
+    #[test]
+    fn link_counts() {
+        let dir = TestDir::new();
+        let page = |name: &str, links: &[&str]| {
+            let links = links
+                .iter()
+                .map(|link| format!(r#"<a href="{link}">{link}</a>"#))
+                .collect::<Vec<_>>()
+                .concat();
+            Ok(CrawlFile {
+                url: format!("https://example.com/{name}"),
+                content: format!("<p>{name}</p>{links}"),
+                ..page("", "")
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("link_counts.db"),
+            dir.path("link_counts.seek"),
+            dir.path("link_counts_url_map.db"),
+            dir.path("link_counts_url_map.seek"),
+            [
+                page("a", &["b", "c", "b#top", "a", "https://other.example/"]),
+                page("b", &["/c"]),
+                page("c", &[]),
+            ],
+        )
+        .expect("Failed to build index");
+
+        let counts = |doc_id| {
+            let doc = index
+                .get_doc(doc_id)
+                .expect("Failed to read doc")
+                .expect("Document should exist");
+            (doc.inlinks, doc.outlinks)
+        };
+        assert_eq!(counts(0), (0, 3));
+        assert_eq!(counts(1), (1, 1));
+        assert_eq!(counts(2), (2, 0));
+    }
+
+    #[test]
+    fn quality() {
+        let dir = TestDir::new();
+        let page = |name: &str, html: String| {
+            Ok(CrawlFile {
+                url: format!("https://example.com/{name}"),
+                content: html,
+                ..page("", "")
+            })
+        };
+        let article = |title: &str| {
+            format!("<title>{title}</title><p>Fresh pasta with tomatoes and basil</p>")
+        };
+        let farm = (0..20)
+            .map(|i| format!(r#"<a href="/{i}">{i}</a>"#))
+            .collect::<Vec<_>>()
+            .concat();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("quality.db"),
+            dir.path("quality.seek"),
+            dir.path("quality_url_map.db"),
+            dir.path("quality_url_map.seek"),
+            [
+                page("article", article("Pasta")),
+                page("copy-1", article("Copy")),
+                page("copy-2", article("Copy")),
+                page("farm", farm),
+            ],
+        )
+        .expect("Failed to build index");
+
+        assert!((index.quality(0) - 1.0).abs() < 1e-6);
+        assert!((index.quality(1) - 0.5_f64.sqrt()).abs() < 1e-6);
+        assert!(index.quality(3) < 0.5);
+        let doc = index
+            .get_doc(2)
+            .expect("Failed to read doc")
+            .expect("Document should exist");
+        assert_eq!(doc.quality, Some(0.5_f32.sqrt()));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn compact() {
+        let dir = TestDir::new();
+        let paths = || {
+            (
+                dir.path("compact.db"),
+                dir.path("compact.seek"),
+                dir.path("compact_url_map.db"),
+                dir.path("compact_url_map.seek"),
+            )
+        };
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        let (mut index, _) = DiskInvertedIndex::build_from_documents(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            [
+                page("a", "apple shared"),
+                page("b", "banana shared"),
+                page("c", "cherry shared"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+
+        // Its postings stay behind, skipped by queries
+        assert!(index.delete_doc(1).expect("Failed to delete document"));
+        assert!(!index.delete_doc(1).expect("Failed to delete document"));
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let term = |word: &str| tokenizer.tokenize(word).remove(0);
+        assert!(index.is_deleted(1));
+        assert_eq!(index.get(&term("banana")).expect("Failed to get"), None);
+        let mut doc_ids = Vec::new();
+        index
+            .for_each_posting(&term("shared"), &mut ReadBuffer::default(), |doc_id, _| {
+                doc_ids.push(doc_id);
+            })
+            .expect("Failed to read postings");
+        assert_eq!(doc_ids, [0, 2]);
+        assert!(index
+            .get_doc_by_url("https://example.com/b")
+            .expect("Failed to look up url")
+            .is_none());
+        drop(index);
+
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        let (index, stats) =
+            DiskInvertedIndex::compact(db_path, seek_path, url_map_path, url_map_seek_path)
+                .expect("Failed to compact index");
+
+        // Its url and site terms go along with its words
+        assert_eq!(stats.dropped_postings, 5);
+        assert_eq!(stats.dropped_terms, 2);
+        assert!(stats.bytes_reclaimed > 0);
+        index.verify().expect("Compacted index should verify");
+        assert_eq!(index.num_docs(), 2);
+        assert_eq!(index.average_doc_length(), Some(2.0));
+        assert_eq!(index.doc_length(1), None);
+        assert!(!index.is_deleted(1));
+
+        assert_eq!(index.get(&term("banana")).expect("Failed to get"), None);
+        let shared = index
+            .get(&term("shared"))
+            .expect("Failed to get")
+            .expect("Term should exist");
+        let doc_ids: Vec<_> = shared.iter().map(|posting| posting.doc_id).collect();
+        assert_eq!(doc_ids, [0, 2]);
+        // Scores count the documents left, so `shared` is in every one
+        assert!(shared.iter().all(|posting| posting.tf_idf == 0.0));
+        let apple = index
+            .get(&term("apple"))
+            .expect("Failed to get")
+            .expect("Term should exist");
+        assert!((apple[0].tf_idf - 2_f64.log10()).abs() < 1e-12);
+        let stats = index
+            .term_stats(&term("shared"))
+            .expect("Failed to read term stats")
+            .expect("Term should have stats");
+        assert_eq!((stats.df, stats.total_tf), (2, 2));
+        assert!(index
+            .get_doc_by_url("https://example.com/b")
+            .expect("Failed to look up url")
+            .is_none());
+        assert!(matches!(
+            index.top_terms(1, 5),
+            Err(Error::MissingDoc { doc_id: 1 })
+        ));
+    }
+
+    #[test]
+    fn update() {
+        let dir = TestDir::new();
+        let paths = || {
+            (
+                dir.path("update.db"),
+                dir.path("update.seek"),
+                dir.path("update_url_map.db"),
+                dir.path("update_url_map.seek"),
+            )
+        };
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            [
+                page("a", "apple shared"),
+                page("b", "banana shared"),
+                page("c", "cherry shared"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let term = |word: &str| tokenizer.tokenize(word).remove(0);
+        let apple = index.get(&term("apple")).expect("Failed to get");
+        drop(index);
+
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        let (index, stats) = DiskInvertedIndex::update_with(
+            db_path,
+            seek_path,
+            url_map_path,
+            url_map_seek_path,
+            [page("b", "kiwi shared"), page("d", "date")].map(Ok),
+            &["https://example.com/c".to_string()],
+            BuildOptions::default(),
+        )
+        .expect("Failed to update index");
+
+        assert_eq!(
+            stats,
+            UpdateStats {
+                added: 1,
+                replaced: 1,
+                removed: 1,
+            }
+        );
+        index.verify().expect("Updated index should verify");
+        assert_eq!(index.num_docs(), 3);
+        // The corpus is as large as before, so untouched postings score the same
+        assert_eq!(index.get(&term("apple")).expect("Failed to get"), apple);
+        let doc_id = |url| {
+            index
+                .get_doc_by_url(url)
+                .expect("Failed to look up url")
+                .map(|(doc_id, _)| doc_id)
+        };
+        assert_eq!(doc_id("https://example.com/a"), Some(0));
+        assert_eq!(doc_id("https://example.com/b"), Some(1));
+        assert_eq!(doc_id("https://example.com/c"), None);
+        assert_eq!(doc_id("https://example.com/d"), Some(3));
+
+        assert_eq!(index.get(&term("banana")).expect("Failed to get"), None);
+        assert_eq!(index.get(&term("cherry")).expect("Failed to get"), None);
+        let postings = |word| {
+            index
+                .get(&term(word))
+                .expect("Failed to get")
+                .expect("Term should exist")
+                .iter()
+                .map(|posting| posting.doc_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(postings("kiwi"), [1]);
+        assert_eq!(postings("shared"), [0, 1]);
+        assert_eq!(postings("date"), [3]);
+        let stats = index
+            .term_stats(&term("kiwi"))
+            .expect("Failed to read term stats")
+            .expect("Term should have stats");
+        assert_eq!((stats.df, stats.total_tf), (1, 1));
+        assert_eq!(
+            index.top_terms(1, 5).expect("Failed to read top terms"),
+            [term("kiwi"), term("shared")]
+        );
+    }
+
+    #[test]
+    fn repair() {
+        let dir = TestDir::new();
+        let paths = || {
+            (
+                dir.path("repair.db"),
+                dir.path("repair.seek"),
+                dir.path("repair_url_map.db"),
+                dir.path("repair_url_map.seek"),
+            )
+        };
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        DiskInvertedIndex::build_from_documents(
+            db_path,
+            seek_path.clone(),
+            url_map_path,
+            url_map_seek_path.clone(),
+            [Ok(page("", "apple pie"))],
+        )
+        .expect("Failed to build index");
+
+        // Opening leaves broken seek files alone
+        remove_file(&url_map_seek_path).expect("Failed to remove seek file");
+        let term_stats_seek = DiskInvertedIndex::term_stats_path(&seek_path);
+        std::fs::write(&term_stats_seek, [0xff; 3]).expect("Failed to corrupt seek file");
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        assert!(
+            DiskInvertedIndex::from(db_path, seek_path, url_map_path, url_map_seek_path).is_err()
+        );
+        assert!(!paths().3.exists());
+        assert_eq!(
+            std::fs::read(&term_stats_seek).expect("Failed to read seek file"),
+            [0xff; 3]
+        );
+
+        let (db_path, seek_path, url_map_path, url_map_seek_path) = paths();
+        let (index, stats) =
+            DiskInvertedIndex::repair(db_path, seek_path, url_map_path, url_map_seek_path)
+                .expect("Failed to repair index");
+        assert_eq!(
+            stats,
+            RepairStats {
+                seek_files: 7,
+                truncated_bytes: 0,
+            }
+        );
+        assert_eq!(index.num_docs(), 1);
+        assert_eq!(
+            index
+                .term_stats("appl")
+                .expect("Failed to read term stats")
+                .map(|stats| stats.df),
+            Some(1)
+        );
+        index.verify().expect("Repaired index should be consistent");
+    }
+
+    #[test]
+    fn get_doc_by_url() {
+        let dir = TestDir::new();
+        let page = |url: &str| {
+            Ok(CrawlFile {
+                url: url.to_string(),
+                ..page("", "page")
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("url_ids.db"),
+            dir.path("url_ids.seek"),
+            dir.path("url_ids_url_map.db"),
+            dir.path("url_ids_url_map.seek"),
+            [page("https://a.example/"), page("https://b.example/")],
+        )
+        .expect("Failed to build index");
+
+        assert!(index.url_ids.is_some());
+        for (doc_id, url) in [(0, "https://a.example/"), (1, "https://b.example/")] {
+            let doc = index.get_doc_by_url(url).expect("Failed to look up url");
+            assert_eq!(
+                doc.map(|(id, doc)| (id, doc.url)),
+                Some((doc_id, url.to_string()))
+            );
+        }
+        assert!(index
+            .get_doc_by_url("https://c.example/")
+            .expect("Failed to look up url")
+            .is_none());
+
+        // Indexes without the reverse map fall back to scanning the url map
+        let legacy = test_index();
+        assert!(legacy.url_ids.is_none());
+        assert!(legacy
+            .get_doc_by_url("https://www.github.com/eric-minassian")
+            .expect("Failed to scan url map")
+            .is_some());
+    }
+
+    #[test]
+    fn find_doc_by_url() {
+        let index = test_index();
+
+        let (doc_id, doc) = index
+            .find_doc_by_url("https://www.github.com/eric-minassian")
+            .expect("Failed to scan url map")
+            .expect("Document should be found");
+        assert_eq!(doc.url, "https://www.github.com/eric-minassian");
+        assert_eq!(
+            index.get_doc(doc_id).expect("Failed to read doc"),
+            Some(doc)
+        );
+
+        assert!(index
+            .find_doc_by_url("https://example.com/missing")
+            .expect("Failed to scan url map")
+            .is_none());
+    }
 }
@@ -0,0 +1,99 @@
+use super::{
+    disk_inverted_index::{CrawlFile, ParsedPage},
+    doc_map::DocID,
+};
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Decides at index time which documents queries with safe search exclude,
+/// so deployments can plug in their own NSFW or spam classifiers.
+pub trait DocFilter {
+    /// Whether the page fetched as `file` and parsed into `page` is flagged.
+    fn flags(&self, file: &CrawlFile, page: &ParsedPage) -> bool;
+}
+
+/// Flags pages containing any of a list of words, the filter the config
+/// sets up.
+#[derive(Debug, Default, Clone)]
+pub struct KeywordFilter {
+    words: HashSet<String>,
+}
+
+impl KeywordFilter {
+    /// Words match whatever their case, but not other forms of them.
+    #[must_use]
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl DocFilter for KeywordFilter {
+    fn flags(&self, _file: &CrawlFile, page: &ParsedPage) -> bool {
+        !self.words.is_disjoint(&page.words)
+    }
+}
+
+/// Doc IDs of the documents a build flagged, written next to the postings
+/// database so queries can exclude them without reading the url map.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlaggedDocs {
+    pub doc_ids: Vec<DocID>,
+}
+
+impl FlaggedDocs {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.flagged", db_path.display()))
+    }
+
+    /// The documents at `path`, `None` for indexes built before flags were
+    /// kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inverted_index::disk_inverted_index::parse_page, test_util::page, tokenizer::Tokenizer,
+    };
+
+    #[test]
+    fn keyword_filter() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let filter = KeywordFilter::new(&["Casino".to_string()]);
+        let flags = |content: &str| {
+            let file = CrawlFile {
+                content: content.to_string(),
+                ..page("", "")
+            };
+            let page = parse_page(&file.url, &file.content, &tokenizer);
+            filter.flags(&file, &page)
+        };
+
+        assert!(flags("<p>Online CASINO bonus</p>"));
+        assert!(!flags("<p>Casinos of Monaco</p>"));
+        assert!(!flags("<p>Pasta recipes</p>"));
+    }
+}
@@ -0,0 +1,91 @@
+use super::doc_map::DocID;
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Doc IDs allocated to every url builds of an index have seen, written next
+/// to the url map.
+///
+/// A url keeps its ID across rebuilds, so incremental indexing, deletions and
+/// the link graph can refer to it. IDs of urls gone from the corpus stay
+/// reserved for them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocIds {
+    ids: HashMap<String, DocID>,
+    /// Lowest ID never allocated
+    next: DocID,
+}
+
+impl DocIds {
+    #[must_use]
+    pub fn path(url_map_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.ids", url_map_path.display()))
+    }
+
+    /// The allocations at `path`, `None` for indexes built before they were
+    /// kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+
+    #[must_use]
+    pub fn get(&self, url: &str) -> Option<DocID> {
+        self.ids.get(url).copied()
+    }
+
+    /// The ID of `url`, the next free one for urls never seen before.
+    pub fn allocate(&mut self, url: &str) -> DocID {
+        if let Some(doc_id) = self.get(url) {
+            return doc_id;
+        }
+
+        let doc_id = self.next;
+        self.ids.insert(url.to_string(), doc_id);
+        self.next += 1;
+
+        doc_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn allocate() {
+        let dir = TestDir::new();
+        let path = dir.path("allocate.ids");
+        let mut doc_ids = DocIds::default();
+        assert_eq!(doc_ids.allocate("https://example.com/a"), 0);
+        assert_eq!(doc_ids.allocate("https://example.com/b"), 1);
+        assert_eq!(doc_ids.allocate("https://example.com/a"), 0);
+        doc_ids.write(&path).expect("Failed to write doc IDs");
+
+        let mut doc_ids = DocIds::read(&path)
+            .expect("Failed to read doc IDs")
+            .expect("Missing doc IDs");
+        assert_eq!(doc_ids.get("https://example.com/b"), Some(1));
+        assert_eq!(doc_ids.allocate("https://example.com/c"), 2);
+        assert_eq!(doc_ids.get("https://example.com/d"), None);
+
+        fs::remove_file(&path).expect("Failed to remove doc IDs");
+        assert_eq!(DocIds::read(&path).expect("Failed to read doc IDs"), None);
+    }
+}
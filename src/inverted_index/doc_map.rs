@@ -1,20 +1,295 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+/// Version of the fields stored after the url of a [`Doc`]. Version 1 had no
+/// link counts, version 2 no flag, version 3 no quality, version 4 no boost,
+/// version 5 no source and version 6 no fields.
+const DOC_VERSION: u8 = 7;
+
+/// A document of the url map.
+///
+/// Binary formats store the url, a version byte and the remaining fields, so
+/// url maps written when documents were only a url still load, with the
+/// other fields empty. Human-readable formats get a plain struct.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct Doc {
     pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Tokens in the text of the page
+    #[serde(default)]
+    pub num_tokens: u64,
+    /// Primary language subtag the page declares, such as `en`
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Seconds since the Unix epoch when the page was fetched
+    #[serde(default)]
+    pub crawled_at: Option<u64>,
+    /// Other documents of the index linking to this one
+    #[serde(default)]
+    pub inlinks: u32,
+    /// Distinct pages this one links to, in the index or not
+    #[serde(default)]
+    pub outlinks: u32,
+    /// Whether the filter of the build flagged it, hiding it from queries
+    /// with safe search
+    #[serde(default)]
+    pub flagged: bool,
+    /// Spam and boilerplate heuristics of the build, from 0.1 for junk to 1.
+    /// `None` for documents indexed before quality was scored
+    #[serde(default)]
+    pub quality: Option<f32>,
+    /// Static boost multiplied into its scores, from the crawl file or the
+    /// boosts file of the build. `None` leaves scores as they are
+    #[serde(default)]
+    pub boost: Option<f32>,
+    /// Crawl the page was read from, for indexes built from several
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Text of the custom fields of the build, by name
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
 }
 
 impl Doc {
-    pub const fn new(url: String) -> Self {
-        Self { url }
+    #[must_use]
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            ..Self::default()
+        }
+    }
+}
+
+impl Serialize for Doc {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return Self::serialize(self, serializer);
+        }
+
+        let mut tuple = serializer.serialize_tuple(13)?;
+        tuple.serialize_element(&self.url)?;
+        tuple.serialize_element(&DOC_VERSION)?;
+        tuple.serialize_element(&self.title)?;
+        tuple.serialize_element(&self.num_tokens)?;
+        tuple.serialize_element(&self.language)?;
+        tuple.serialize_element(&self.crawled_at)?;
+        tuple.serialize_element(&self.inlinks)?;
+        tuple.serialize_element(&self.outlinks)?;
+        tuple.serialize_element(&self.flagged)?;
+        tuple.serialize_element(&self.quality)?;
+        tuple.serialize_element(&self.boost)?;
+        tuple.serialize_element(&self.source)?;
+        tuple.serialize_element(&self.fields)?;
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Doc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Self::deserialize(deserializer)
+        } else {
+            deserializer.deserialize_tuple(13, DocVisitor)
+        }
+    }
+}
+
+struct DocVisitor;
+
+impl<'de> Visitor<'de> for DocVisitor {
+    type Value = Doc;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a url followed by versioned document fields")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Doc, A::Error> {
+        let url = element(&mut seq, 0)?;
+
+        // Legacy records end right after the url, so there is no byte to read
+        let Ok(Some(version)) = seq.next_element::<u8>() else {
+            return Ok(Doc::new(url));
+        };
+        if !(1..=DOC_VERSION).contains(&version) {
+            return Err(de::Error::custom(format!(
+                "unsupported document version {version}"
+            )));
+        }
+
+        let mut doc = Doc {
+            url,
+            title: element(&mut seq, 2)?,
+            num_tokens: element(&mut seq, 3)?,
+            language: element(&mut seq, 4)?,
+            crawled_at: element(&mut seq, 5)?,
+            ..Doc::default()
+        };
+        if version >= 2 {
+            doc.inlinks = element(&mut seq, 6)?;
+            doc.outlinks = element(&mut seq, 7)?;
+        }
+        if version >= 3 {
+            doc.flagged = element(&mut seq, 8)?;
+        }
+        if version >= 4 {
+            doc.quality = element(&mut seq, 9)?;
+        }
+        if version >= 5 {
+            doc.boost = element(&mut seq, 10)?;
+        }
+        if version >= 6 {
+            doc.source = element(&mut seq, 11)?;
+        }
+        if version >= 7 {
+            doc.fields = element(&mut seq, 12)?;
+        }
+
+        Ok(doc)
     }
 }
 
+fn element<'de, A, T>(seq: &mut A, index: usize) -> Result<T, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &DocVisitor))
+}
+
 pub type DocID = u64;
 pub type TF = u32;
 pub type TFIDF = f64;
 
 pub type DocMap = HashMap<DocID, Doc>;
+/// Terms of a document with their weighted frequency
+pub type Terms = Vec<(String, TF)>;
+pub type DocTerms = HashMap<DocID, Terms>;
+/// Terms of a document with where they occur in its text, counted in tokens
+pub type Positions = Vec<(String, Vec<u32>)>;
+pub type DocPositions = HashMap<DocID, Positions>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_database::codec;
+
+    fn doc() -> Doc {
+        Doc {
+            url: "https://example.com/".to_string(),
+            title: Some("Example".to_string()),
+            num_tokens: 42,
+            language: Some("en".to_string()),
+            crawled_at: Some(1_700_000_000),
+            inlinks: 3,
+            outlinks: 5,
+            flagged: true,
+            quality: Some(0.5),
+            boost: Some(2.0),
+            source: Some("crawls/2024-01".to_string()),
+            fields: BTreeMap::from([("author".to_string(), "Ada Lovelace".to_string())]),
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let bytes = codec::serialize(&doc()).expect("Failed to serialize");
+        let decoded: Doc = codec::deserialize(&bytes).expect("Failed to deserialize");
+
+        assert_eq!(decoded, doc());
+    }
+
+    #[test]
+    fn legacy_doc() {
+        #[derive(Serialize)]
+        struct LegacyDoc {
+            url: String,
+        }
+
+        let bytes = codec::serialize(&LegacyDoc {
+            url: "https://example.com/".to_string(),
+        })
+        .expect("Failed to serialize");
+        let decoded: Doc = codec::deserialize(&bytes).expect("Failed to deserialize");
+
+        assert_eq!(decoded, Doc::new("https://example.com/".to_string()));
+    }
+
+    #[test]
+    fn version_1_doc() {
+        let doc = doc();
+        let bytes = codec::serialize(&(
+            &doc.url,
+            1_u8,
+            &doc.title,
+            doc.num_tokens,
+            &doc.language,
+            doc.crawled_at,
+        ))
+        .expect("Failed to serialize");
+        let decoded: Doc = codec::deserialize(&bytes).expect("Failed to deserialize");
+
+        assert_eq!(
+            decoded,
+            Doc {
+                inlinks: 0,
+                outlinks: 0,
+                flagged: false,
+                quality: None,
+                boost: None,
+                source: None,
+                fields: BTreeMap::new(),
+                ..doc
+            }
+        );
+    }
+
+    #[test]
+    fn version_2_doc() {
+        let doc = doc();
+        let bytes = codec::serialize(&(
+            &doc.url,
+            2_u8,
+            &doc.title,
+            doc.num_tokens,
+            &doc.language,
+            doc.crawled_at,
+            doc.inlinks,
+            doc.outlinks,
+        ))
+        .expect("Failed to serialize");
+        let decoded: Doc = codec::deserialize(&bytes).expect("Failed to deserialize");
+
+        assert_eq!(
+            decoded,
+            Doc {
+                flagged: false,
+                quality: None,
+                boost: None,
+                source: None,
+                fields: BTreeMap::new(),
+                ..doc
+            }
+        );
+    }
+
+    #[test]
+    fn json_fields() {
+        let json = serde_json::to_value(doc()).expect("Failed to serialize");
+        assert_eq!(json["title"], "Example");
+
+        let decoded: Doc = serde_json::from_str(r#"{"url": "https://example.com/"}"#)
+            .expect("Failed to deserialize");
+        assert_eq!(decoded, Doc::new("https://example.com/".to_string()));
+    }
+}
@@ -0,0 +1,556 @@
+use super::disk_inverted_index::CrawlFile;
+use crate::error::{Error, Result};
+use std::{collections::BTreeMap, fs, ops::Range, path::Path};
+
+/// Extensions of the files of a crawl read as feeds rather than crawl files.
+const FEED_EXTENSIONS: [&str; 3] = ["xml", "rss", "atom"];
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Whether the file at `path` is read as a feed, see [`read_feed`].
+#[must_use]
+pub fn is_feed(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            FEED_EXTENSIONS
+                .iter()
+                .any(|feed| extension.eq_ignore_ascii_case(feed))
+        })
+}
+
+/// The entries of the feed at `path` as pages, see [`parse_feed`].
+pub fn read_feed(path: &Path) -> Result<Vec<CrawlFile>> {
+    parse_feed(&fs::read_to_string(path)?).map_err(|message| Error::InvalidFeed {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// The entries of the RSS 2.0, RSS 1.0 or Atom feed `xml` as pages, or the
+/// urls of a sitemap embedding a `<content>` of theirs.
+///
+/// Pages are the HTML of the entry under its title, crawled at its publish
+/// date so recency ranking ages them from it. Entries without a link or any
+/// content have nothing to index and are left out.
+pub fn parse_feed(xml: &str) -> std::result::Result<Vec<CrawlFile>, String> {
+    let root = parse_xml(xml)?;
+    let entries = match root.local_name() {
+        "rss" => root
+            .child("channel")
+            .map(|channel| {
+                channel
+                    .children_named("item")
+                    .filter_map(rss_item)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        "RDF" => root.children_named("item").filter_map(rss_item).collect(),
+        "feed" => root
+            .children_named("entry")
+            .filter_map(|entry| atom_entry(entry, xml))
+            .collect(),
+        "urlset" => root.children_named("url").filter_map(sitemap_url).collect(),
+        name => return Err(format!("Unknown feed element <{name}>")),
+    };
+
+    Ok(entries)
+}
+
+fn rss_item(item: &Element) -> Option<CrawlFile> {
+    let url = item
+        .child_text("link")
+        .or_else(|| item.child_text("guid"))?;
+    let content = item
+        .child_text("encoded")
+        .or_else(|| item.child_text("description"))?;
+    let published = item
+        .child_text("pubDate")
+        .and_then(parse_rfc2822)
+        .or_else(|| item.child_text("date").and_then(parse_rfc3339));
+
+    Some(page(url, item.child_text("title"), content, published))
+}
+
+fn atom_entry(entry: &Element, xml: &str) -> Option<CrawlFile> {
+    let url = entry
+        .children_named("link")
+        .find(|link| link.attr("rel").is_none_or(|rel| rel == "alternate"))
+        .and_then(|link| link.attr("href"))?;
+    let content = entry
+        .child("content")
+        .or_else(|| entry.child("summary"))
+        .map(|content| {
+            // XHTML content is markup of the feed rather than escaped text
+            if content.attr("type") == Some("xhtml") {
+                xml[content.inner.clone()].trim().to_string()
+            } else {
+                content.text.trim().to_string()
+            }
+        })
+        .filter(|content| !content.is_empty())?;
+    let published = entry
+        .child_text("published")
+        .or_else(|| entry.child_text("updated"))
+        .and_then(parse_rfc3339);
+
+    Some(page(url, entry.child_text("title"), &content, published))
+}
+
+fn sitemap_url(url: &Element) -> Option<CrawlFile> {
+    Some(page(
+        url.child_text("loc")?,
+        url.child_text("title"),
+        url.child_text("content")?,
+        url.child_text("lastmod").and_then(parse_rfc3339),
+    ))
+}
+
+fn page(url: &str, title: Option<&str>, content: &str, published: Option<u64>) -> CrawlFile {
+    CrawlFile {
+        url: url.to_string(),
+        content: html_page(title, content),
+        encoding: "utf-8".to_string(),
+        crawled_at: published,
+        boost: None,
+        source: None,
+        fields: BTreeMap::new(),
+    }
+}
+
+/// An HTML page of the markup `body` under `title`.
+pub(super) fn html_page(title: Option<&str>, body: &str) -> String {
+    let title = title.map_or_else(String::new, |title| {
+        format!("<title>{}</title>", escape(title))
+    });
+
+    format!("<html><head>{title}</head><body>{body}</body></html>")
+}
+
+/// An element of an XML document, with the text directly inside it.
+#[derive(Debug, Default)]
+struct Element {
+    /// Name as written, namespace prefix included
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Self>,
+    text: String,
+    /// Byte range of the markup between its tags in the document
+    inner: Range<usize>,
+}
+
+impl Element {
+    /// Name without its namespace prefix, as feeds mix namespaces freely.
+    fn local_name(&self) -> &str {
+        self.name
+            .rsplit_once(':')
+            .map_or(self.name.as_str(), |(_, name)| name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Self> {
+        self.children
+            .iter()
+            .filter(move |child| child.local_name() == name)
+    }
+
+    fn child(&self, name: &str) -> Option<&Self> {
+        self.children
+            .iter()
+            .find(|child| child.local_name() == name)
+    }
+
+    /// Trimmed text of the first child called `name`, `None` when empty.
+    fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|child| child.text.trim())
+            .filter(|text| !text.is_empty())
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The root element of `xml`. Comments, processing instructions and
+/// doctypes are skipped, and entities other than the predefined and numeric
+/// ones are kept as written.
+fn parse_xml(xml: &str) -> std::result::Result<Element, String> {
+    // Elements opened and not yet closed, under a document element
+    let mut stack = vec![Element::default()];
+    let mut rest = xml;
+    while !rest.is_empty() {
+        let top = stack.last_mut().ok_or("Unbalanced elements")?;
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let (text, after) = after.split_once("]]>").ok_or("Unclosed CDATA section")?;
+            top.text.push_str(text);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.split_once("-->").ok_or("Unclosed comment")?.1;
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = rest.split_once('>').ok_or("Unclosed declaration")?.1;
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let (name, after) = after.split_once('>').ok_or("Unclosed end tag")?;
+            let name = name.trim();
+            if top.name != name || stack.len() < 2 {
+                return Err(format!("Unexpected </{name}>"));
+            }
+            let mut element = stack.pop().ok_or("Unbalanced elements")?;
+            element.inner.end = xml.len() - rest.len();
+            stack
+                .last_mut()
+                .ok_or("Unbalanced elements")?
+                .children
+                .push(element);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('<') {
+            let end = tag_end(after).ok_or("Unclosed start tag")?;
+            let (tag, empty) = after[..end]
+                .strip_suffix('/')
+                .map_or((&after[..end], false), |tag| (tag, true));
+            let (name, attrs) = tag
+                .split_once(|c: char| c.is_whitespace())
+                .unwrap_or((tag, ""));
+            rest = &after[end + 1..];
+            let inner_start = xml.len() - rest.len();
+            let element = Element {
+                name: name.to_string(),
+                attrs: parse_attrs(attrs)?,
+                inner: inner_start..inner_start,
+                ..Element::default()
+            };
+            if empty {
+                top.children.push(element);
+            } else {
+                stack.push(element);
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            top.text.push_str(&unescape(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    match stack.pop() {
+        Some(document) if stack.is_empty() => document
+            .children
+            .into_iter()
+            .next()
+            .ok_or_else(|| "No root element".to_string()),
+        Some(element) => Err(format!("Unclosed <{}>", element.name)),
+        None => Err("Unbalanced elements".to_string()),
+    }
+}
+
+/// Index of the `>` closing the tag `tag` starts, skipping quoted values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    tag.char_indices().find_map(|(i, c)| {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+        None
+    })
+}
+
+/// The `name="value"` attributes of a start tag.
+fn parse_attrs(mut attrs: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    let mut parsed = Vec::new();
+    loop {
+        attrs = attrs.trim_start();
+        if attrs.is_empty() {
+            return Ok(parsed);
+        }
+
+        let (name, value) = attrs
+            .split_once('=')
+            .ok_or_else(|| format!("Attribute without a value in `{attrs}`"))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+            .ok_or_else(|| format!("Unquoted attribute `{}`", name.trim()))?;
+        let (value, after) = value[1..]
+            .split_once(quote)
+            .ok_or_else(|| format!("Unclosed attribute `{}`", name.trim()))?;
+        parsed.push((name.trim().to_string(), unescape(value)));
+        attrs = after;
+    }
+}
+
+/// `text` with its predefined and numeric entities replaced.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                entity => {
+                    let code = entity.strip_prefix('#')?;
+                    let code = match code.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => code.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+        if let Some((c, end)) = decoded {
+            unescaped.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            unescaped.push('&');
+            rest = &rest[1..];
+        }
+    }
+    unescaped.push_str(rest);
+
+    unescaped
+}
+
+pub(super) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 date like
+/// `2024-01-15T09:30:00+01:00`, the date alone meaning its midnight in UTC.
+/// `None` for malformed dates and ones before the epoch.
+pub(super) fn parse_rfc3339(date: &str) -> Option<u64> {
+    let date = date.trim();
+    let (day, time) = date
+        .split_once(['T', 't', ' '])
+        .map_or((date, None), |(day, time)| (day, Some(time)));
+    let mut parts = day.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+
+    let (seconds, offset) = match time {
+        Some(time) => {
+            let zone_start = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+            let (clock, zone) = time.split_at(zone_start);
+            let offset = match zone {
+                "" | "Z" | "z" => 0,
+                zone => parse_offset(&zone.replace(':', ""))?,
+            };
+            (parse_clock(clock)?, offset)
+        }
+        None => (0, 0),
+    };
+
+    epoch_seconds(year, month, day, seconds, offset)
+}
+
+/// Seconds since the Unix epoch of an RFC 2822 date like
+/// `Mon, 15 Jan 2024 09:30:00 +0100`, as RSS writes them. `None` for
+/// malformed dates and ones before the epoch.
+fn parse_rfc2822(date: &str) -> Option<u64> {
+    let date = date.split_once(',').map_or(date, |(_, date)| date);
+    let mut parts = date.split_whitespace();
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?.get(..3)?.to_ascii_lowercase();
+    let month = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|name| *name == month)? as u32
+        + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    // Two-digit years as RFC 2822 reads obsolete ones
+    let year = match year {
+        0..=49 => year + 2000,
+        50..=999 => year + 1900,
+        _ => year,
+    };
+    let seconds = parse_clock(parts.next()?)?;
+    let offset = match parts.next() {
+        Some(zone) => parse_offset(zone)?,
+        None => 0,
+    };
+
+    epoch_seconds(year, month, day, seconds, offset)
+}
+
+/// Seconds into the day of `HH:MM` or `HH:MM:SS`, fractions dropped.
+fn parse_clock(clock: &str) -> Option<i64> {
+    let mut parts = clock.splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = match parts.next() {
+        Some(seconds) => seconds.split('.').next()?.parse().ok()?,
+        None => 0,
+    };
+    (hours < 24 && minutes < 60 && seconds <= 60).then_some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Seconds east of UTC of `+HHMM`, `-HHMM` or a zone name of RFC 2822.
+fn parse_offset(zone: &str) -> Option<i64> {
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "Z" | "UT" | "UTC" | "GMT" => 0,
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "CST" | "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        _ => {
+            let (sign, digits) = match zone.split_at_checked(1)? {
+                ("+", digits) => (1, digits),
+                ("-", digits) => (-1, digits),
+                _ => return None,
+            };
+            if digits.len() != 4 {
+                return None;
+            }
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = digits[2..].parse().ok()?;
+            return Some(sign * (hours * 3600 + minutes * 60));
+        }
+    };
+
+    Some(hours * 3600)
+}
+
+fn epoch_seconds(year: i64, month: u32, day: u32, seconds: i64, offset: i64) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY + seconds - offset;
+    u64::try_from(seconds).ok()
+}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+const fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rss() {
+        let pages = parse_feed(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- A news feed -->
+            <rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+              <channel>
+                <title>News</title>
+                <item>
+                  <title>Rust &amp; search</title>
+                  <link>https://example.com/rust</link>
+                  <description>Short</description>
+                  <content:encoded><![CDATA[<p>Fast <b>search</b></p>]]></content:encoded>
+                  <pubDate>Mon, 15 Jan 2024 10:30:00 +0100</pubDate>
+                </item>
+                <item>
+                  <guid>https://example.com/guid</guid>
+                  <description>&lt;p&gt;Escaped&lt;/p&gt;</description>
+                </item>
+                <item><title>No link</title><description>text</description></item>
+              </channel>
+            </rss>"#,
+        )
+        .expect("Failed to parse feed");
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].url, "https://example.com/rust");
+        assert_eq!(
+            pages[0].content,
+            "<html><head><title>Rust &amp; search</title></head>\
+             <body><p>Fast <b>search</b></p></body></html>"
+        );
+        assert_eq!(pages[0].crawled_at, Some(1_705_311_000));
+        assert_eq!(pages[1].url, "https://example.com/guid");
+        assert_eq!(
+            pages[1].content,
+            "<html><head></head><body><p>Escaped</p></body></html>"
+        );
+        assert_eq!(pages[1].crawled_at, None);
+    }
+
+    #[test]
+    fn atom_and_sitemap() {
+        let pages = parse_feed(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <entry>
+                <title type="text">Atom</title>
+                <link rel="self" href="https://example.com/self"/>
+                <link href='https://example.com/atom'/>
+                <updated>2024-01-15T09:30:00Z</updated>
+                <content type="xhtml"><div xmlns="http://www.w3.org/1999/xhtml">Hi <b>there</b>!</div></content>
+              </entry>
+            </feed>"#,
+        )
+        .expect("Failed to parse feed");
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].url, "https://example.com/atom");
+        assert_eq!(
+            pages[0].content,
+            "<html><head><title>Atom</title></head><body>\
+             <div xmlns=\"http://www.w3.org/1999/xhtml\">Hi <b>there</b>!</div></body></html>"
+        );
+        assert_eq!(pages[0].crawled_at, Some(1_705_311_000));
+
+        let pages = parse_feed(
+            "<urlset><url><loc>https://example.com/a</loc><lastmod>2024-01-15</lastmod>\
+             <content>&lt;p&gt;a&lt;/p&gt;</content></url>\
+             <url><loc>https://example.com/b</loc></url></urlset>",
+        )
+        .expect("Failed to parse sitemap");
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].crawled_at, Some(1_705_276_800));
+
+        for xml in [
+            "<rss><channel>",
+            "<feed></entry></feed>",
+            "<html></html>",
+            "",
+        ] {
+            assert!(parse_feed(xml).is_err(), "{xml}");
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339("2024-02-29t23:59:59.5-00:30"),
+            Some(1_709_252_999)
+        );
+        assert_eq!(
+            parse_rfc2822("Thu, 29 Feb 2024 23:59:59 GMT"),
+            Some(1_709_251_199)
+        );
+        assert_eq!(parse_rfc2822("1 Jan 70 01:00 +0100"), Some(0));
+        assert_eq!(parse_rfc2822("1 Jan 1970 00:00 PST"), Some(28_800));
+        assert_eq!(parse_rfc3339("1969-12-31"), None);
+        assert_eq!(parse_rfc3339("2024-13-01"), None);
+        assert_eq!(parse_rfc2822("15 Foo 2024 10:00:00 GMT"), None);
+    }
+}
@@ -0,0 +1,171 @@
+use super::constants::{BOLD_WEIGHT, HEADER_WEIGHT, TITLE_WEIGHT};
+use crate::{
+    error::{Error, Result},
+    kv_database::{codec, database::replace_file},
+};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Parts of every page whose terms count more than the rest of the text,
+/// with the weight they count with.
+pub const WEIGHTED_SELECTORS: [(&str, f32); 3] = [
+    ("title", TITLE_WEIGHT),
+    ("b, strong", BOLD_WEIGHT),
+    ("h1, h2, h3, h4, h5", HEADER_WEIGHT),
+];
+
+/// Prefixes of query clauses fields can't take the name of.
+const RESERVED_NAMES: [&str; 3] = ["filter", "site", "url"];
+
+/// Custom fields of a build, named CSS selectors whose text is indexed as
+/// terms of its own and kept with the document.
+///
+/// Terms of a field are prefixed with its name, like `author:smith`, so only
+/// `author:` clauses match them and they never collide with terms of the
+/// text.
+#[derive(Debug, Default)]
+pub struct FieldSelectors {
+    fields: Vec<(String, Selector)>,
+}
+
+impl FieldSelectors {
+    /// Compiles the selectors of `fields`, keyed by name, see
+    /// [`check_field_name`].
+    pub fn new(fields: &BTreeMap<String, String>) -> Result<Self> {
+        let fields = fields
+            .iter()
+            .map(|(name, selector)| {
+                check_field_name(name)?;
+                let selector = Selector::parse(selector).map_err(|e| Error::InvalidSelector {
+                    selector: selector.clone(),
+                    message: format!("{e}, in field `{name}`"),
+                })?;
+
+                Ok((name.clone(), selector))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { fields })
+    }
+
+    #[must_use]
+    pub fn names(&self) -> Vec<String> {
+        self.fields.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// The text each field selects in `document`, whitespace collapsed.
+    /// Fields selecting no text are left out.
+    #[must_use]
+    pub fn extract(&self, document: &Html) -> BTreeMap<String, String> {
+        self.fields
+            .iter()
+            .filter_map(|(name, selector)| {
+                let text = document
+                    .select(selector)
+                    .flat_map(|element| element.text())
+                    .flat_map(str::split_whitespace)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                (!text.is_empty()).then(|| (name.clone(), text))
+            })
+            .collect()
+    }
+}
+
+/// Fails for names other than lowercase letters, digits, `-` and `_`, and
+/// for prefixes queries already use.
+pub fn check_field_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !RESERVED_NAMES.contains(&name)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::InvalidFieldName {
+            name: name.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The term field `name` indexes for `term` of its text.
+#[must_use]
+pub fn field_term(name: &str, term: &str) -> String {
+    format!("{name}:{term}")
+}
+
+/// Names of the custom fields an index was built with, written next to the
+/// postings database so queries know which prefixes are fields.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedFields {
+    pub names: Vec<String>,
+}
+
+impl IndexedFields {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.fields", db_path.display()))
+    }
+
+    /// The fields at `path`, `None` for indexes built before fields existed.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract() {
+        let fields = FieldSelectors::new(&BTreeMap::from([
+            ("author".to_string(), ".byline".to_string()),
+            ("price".to_string(), ".price".to_string()),
+            ("sku".to_string(), "#sku".to_string()),
+        ]))
+        .expect("Failed to compile fields");
+        let document = Html::parse_document(
+            "<p class=\"byline\">Ada\n  Lovelace</p><p>text</p><span class=\"price\">12</span>\
+             <span class=\"price\">15</span>",
+        );
+
+        assert_eq!(
+            fields.extract(&document),
+            BTreeMap::from([
+                ("author".to_string(), "Ada Lovelace".to_string()),
+                ("price".to_string(), "12 15".to_string()),
+            ])
+        );
+        assert_eq!(fields.names(), ["author", "price", "sku"]);
+
+        for name in ["Author", "site", ""] {
+            let fields = BTreeMap::from([(name.to_string(), ".byline".to_string())]);
+            assert!(matches!(
+                FieldSelectors::new(&fields),
+                Err(Error::InvalidFieldName { name: invalid }) if invalid == name
+            ));
+        }
+        let fields = BTreeMap::from([("author".to_string(), "..".to_string())]);
+        assert!(matches!(
+            FieldSelectors::new(&fields),
+            Err(Error::InvalidSelector { selector, .. }) if selector == ".."
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    error::{Error, Result},
+    kv_database::database::replace_file,
+};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Build counter kept next to the postings database.
+///
+/// A build bumps it to an odd value before it touches any index file and to
+/// the next even value once all of them are in place, so readers can tell a
+/// complete set of files from one that is being rewritten.
+pub struct Generation {
+    path: PathBuf,
+    value: u64,
+}
+
+impl Generation {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.generation", db_path.display()))
+    }
+
+    /// Current value of the counter at `path`, 0 for indexes that never had
+    /// one.
+    pub fn read(path: &Path) -> Result<u64> {
+        match fs::read_to_string(path) {
+            Ok(value) => value.trim().parse().map_err(|_| Error::Corrupt {
+                path: path.to_path_buf(),
+                offset: 0,
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Marks the index files of `db_path` as being rewritten.
+    pub fn begin(db_path: &Path) -> Result<Self> {
+        let path = Self::path(db_path);
+        let current = Self::read(&path)?;
+        // An abandoned build left the counter odd already
+        let generation = Self {
+            value: current | 1,
+            path,
+        };
+        generation.write()?;
+
+        Ok(generation)
+    }
+
+    /// Marks the rewritten files as complete and returns the new generation.
+    pub fn publish(mut self) -> Result<u64> {
+        self.value += 1;
+        self.write()?;
+
+        Ok(self.value / 2)
+    }
+
+    /// Whether the files were complete when the counter read `value`.
+    #[must_use]
+    pub const fn is_complete(value: u64) -> bool {
+        value.is_multiple_of(2)
+    }
+
+    fn write(&self) -> Result<()> {
+        replace_file(&self.path, self.value.to_string().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn begin_and_publish() {
+        let dir = TestDir::new();
+        let db_path = dir.path("generation.db");
+        let path = Generation::path(&db_path);
+
+        assert_eq!(Generation::read(&path).expect("Failed to read"), 0);
+
+        let generation = Generation::begin(&db_path).expect("Failed to begin");
+        assert!(!Generation::is_complete(
+            Generation::read(&path).expect("Failed to read")
+        ));
+        assert_eq!(generation.publish().expect("Failed to publish"), 1);
+
+        // An abandoned build stays incomplete until the next one publishes
+        drop(Generation::begin(&db_path).expect("Failed to begin"));
+        let generation = Generation::begin(&db_path).expect("Failed to begin");
+        assert_eq!(Generation::read(&path).expect("Failed to read"), 3);
+        assert_eq!(generation.publish().expect("Failed to publish"), 2);
+        assert!(Generation::is_complete(
+            Generation::read(&path).expect("Failed to read")
+        ));
+    }
+}
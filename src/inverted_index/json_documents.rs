@@ -0,0 +1,340 @@
+use super::{
+    crawl_sources::expand,
+    disk_inverted_index::CrawlFile,
+    feeds::{escape, html_page, parse_rfc3339},
+    fields::check_field_name,
+};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Where the parts of a document sit in JSON datasets.
+///
+/// Paths are keys separated by dots, like `meta.author`. A number picks an
+/// element of an array, and any other key reaches into every element, so
+/// `authors.name` is the names of all authors. Text of several values is
+/// joined by spaces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonMapping {
+    /// Path of the url, which every document needs
+    pub url: String,
+    /// Path of the title, empty for none
+    pub title: String,
+    /// Paths of the text indexed as the body, one paragraph each
+    pub body: Vec<String>,
+    /// Path of the publish time, seconds since the Unix epoch or an RFC 3339
+    /// date. Empty for none
+    pub published: String,
+    /// Paths of metadata kept with documents and indexed as custom fields,
+    /// by field name
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Default for JsonMapping {
+    fn default() -> Self {
+        Self {
+            url: "url".to_string(),
+            title: "title".to_string(),
+            body: vec!["body".to_string()],
+            published: String::new(),
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl JsonMapping {
+    /// `document` as a page, its body and title in HTML so it indexes like
+    /// crawled ones.
+    pub fn page(&self, document: &Value) -> std::result::Result<CrawlFile, String> {
+        let url =
+            select_text(document, &self.url).ok_or_else(|| format!("No url at `{}`", self.url))?;
+        let title = select_text(document, &self.title);
+        let mut body = String::new();
+        for text in self
+            .body
+            .iter()
+            .filter_map(|path| select_text(document, path))
+        {
+            body.push_str("<p>");
+            body.push_str(&escape(&text));
+            body.push_str("</p>");
+        }
+        let published =
+            select(document, &self.published)
+                .into_iter()
+                .find_map(|value| match value {
+                    Value::Number(seconds) => seconds.as_u64(),
+                    Value::String(date) => date.parse().ok().or_else(|| parse_rfc3339(date)),
+                    _ => None,
+                });
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|(name, path)| Some((name.clone(), select_text(document, path)?)))
+            .collect();
+
+        Ok(CrawlFile {
+            url,
+            content: html_page(title.as_deref(), &body),
+            encoding: "utf-8".to_string(),
+            crawled_at: published,
+            boost: None,
+            source: None,
+            fields,
+        })
+    }
+}
+
+/// The documents of every JSON file `patterns` match, directories read
+/// recursively in file name order, as `mapping` maps them.
+///
+/// `.jsonl` and `.ndjson` files hold a document per line, other files a
+/// document or an array of them. A pattern matching nothing is an error
+/// rather than an empty dataset.
+pub fn read_json_documents(
+    patterns: &[PathBuf],
+    mapping: &JsonMapping,
+) -> Result<impl Iterator<Item = Result<CrawlFile>>> {
+    for name in mapping.fields.keys() {
+        check_field_name(name)?;
+    }
+
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let matched = expand(pattern);
+        if !matched.iter().any(|path| path.exists()) {
+            return Err(Error::NoMatch {
+                kind: "JSON document",
+                pattern: pattern.clone(),
+            });
+        }
+        files.extend(
+            matched
+                .into_iter()
+                .flat_map(|path| WalkDir::new(path).sort_by_file_name())
+                .filter_map(std::result::Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .map(walkdir::DirEntry::into_path),
+        );
+    }
+
+    let mapping = mapping.clone();
+    Ok(files
+        .into_iter()
+        .flat_map(move |path| read_file(path, mapping.clone())))
+}
+
+/// The pages of the documents of the file at `path`.
+fn read_file(path: PathBuf, mapping: JsonMapping) -> Box<dyn Iterator<Item = Result<CrawlFile>>> {
+    let file = match File::open(&path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => return Box::new(std::iter::once(Err(e.into()))),
+    };
+
+    if is_json_lines(&path) {
+        return Box::new(
+            file.lines()
+                .enumerate()
+                .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+                .map(move |(i, line)| {
+                    let document = serde_json::from_str(&line?).map_err(|e| e.to_string());
+                    page(&path, i + 1, document, &mapping)
+                }),
+        );
+    }
+
+    match serde_json::from_reader(file) {
+        Ok(Value::Array(documents)) => Box::new(
+            documents
+                .into_iter()
+                .enumerate()
+                .map(move |(i, document)| page(&path, i + 1, Ok(document), &mapping)),
+        ),
+        document => Box::new(std::iter::once(page(
+            &path,
+            1,
+            document.map_err(|e| e.to_string()),
+            &mapping,
+        ))),
+    }
+}
+
+fn page(
+    path: &Path,
+    index: usize,
+    document: std::result::Result<Value, String>,
+    mapping: &JsonMapping,
+) -> Result<CrawlFile> {
+    document
+        .and_then(|document| mapping.page(&document))
+        .map_err(|message| Error::InvalidDocument {
+            path: path.to_path_buf(),
+            index: index as u64,
+            message,
+        })
+}
+
+fn is_json_lines(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("jsonl") || extension.eq_ignore_ascii_case("ndjson")
+        })
+}
+
+/// The values at `path` in `document`, none for an empty path.
+fn select<'a>(document: &'a Value, path: &str) -> Vec<&'a Value> {
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let mut values = vec![document];
+    for key in path.split('.') {
+        values = values
+            .into_iter()
+            .flat_map(|value| match value {
+                Value::Object(object) => object.get(key).into_iter().collect(),
+                Value::Array(array) => key.parse::<usize>().map_or_else(
+                    |_| array.iter().filter_map(|value| value.get(key)).collect(),
+                    |i| array.get(i).into_iter().collect(),
+                ),
+                _ => Vec::new(),
+            })
+            .collect();
+    }
+
+    values
+}
+
+/// Text of the values at `path` in `document`, `None` when there is none.
+fn select_text(document: &Value, path: &str) -> Option<String> {
+    let mut words = Vec::new();
+    for value in select(document, path) {
+        push_text(value, &mut words);
+    }
+
+    let text = words.join(" ");
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// Pushes the strings, numbers and booleans of `value` onto `words`.
+fn push_text(value: &Value, words: &mut Vec<String>) {
+    match value {
+        Value::Null => {}
+        Value::String(text) => words.push(text.clone()),
+        Value::Number(_) | Value::Bool(_) => words.push(value.to_string()),
+        Value::Array(values) => {
+            for value in values {
+                push_text(value, words);
+            }
+        }
+        Value::Object(object) => {
+            for value in object.values() {
+                push_text(value, words);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn mapping() {
+        let mapping = JsonMapping {
+            url: "link".to_string(),
+            title: "meta.title".to_string(),
+            body: vec!["summary".to_string(), "sections.text".to_string()],
+            published: "meta.date".to_string(),
+            fields: BTreeMap::from([
+                ("author".to_string(), "authors.name".to_string()),
+                ("tag".to_string(), "tags.0".to_string()),
+                ("price".to_string(), "price".to_string()),
+            ]),
+        };
+        let document = json!({
+            "link": "https://example.com/a",
+            "meta": { "title": "Rust <3", "date": "2024-01-15" },
+            "summary": "Fast & safe",
+            "sections": [{ "text": "One" }, { "text": "Two" }],
+            "authors": [{ "name": "Ada" }, { "name": "Grace" }],
+            "tags": ["systems", "web"],
+            "price": 12.5
+        });
+
+        let page = mapping.page(&document).expect("Failed to map document");
+        assert_eq!(page.url, "https://example.com/a");
+        assert_eq!(
+            page.content,
+            "<html><head><title>Rust &lt;3</title></head>\
+             <body><p>Fast &amp; safe</p><p>One Two</p></body></html>"
+        );
+        assert_eq!(page.crawled_at, Some(1_705_276_800));
+        assert_eq!(
+            page.fields,
+            BTreeMap::from([
+                ("author".to_string(), "Ada Grace".to_string()),
+                ("price".to_string(), "12.5".to_string()),
+                ("tag".to_string(), "systems".to_string()),
+            ])
+        );
+
+        let page = JsonMapping::default()
+            .page(&json!({ "url": "https://example.com/b", "published": 10 }))
+            .expect("Failed to map document");
+        assert_eq!(page.crawled_at, None);
+        assert!(JsonMapping::default()
+            .page(&json!({ "body": "text" }))
+            .is_err());
+    }
+
+    #[test]
+    fn files() {
+        let dir = TestDir::new();
+        let root = dir.path("json_documents");
+        fs::create_dir_all(&root).expect("Failed to create dataset");
+        fs::write(
+            root.join("a.json"),
+            r#"[{"url": "https://example.com/1"}, {"url": "https://example.com/2"}]"#,
+        )
+        .expect("Failed to write documents");
+        fs::write(
+            root.join("b.jsonl"),
+            "{\"url\": \"https://example.com/3\"}\n\n{\"body\": \"no url\"}\n",
+        )
+        .expect("Failed to write documents");
+
+        let pages: Vec<_> = read_json_documents(&[root.join("*")], &JsonMapping::default())
+            .expect("Failed to read documents")
+            .map(|page| page.map(|page| page.url).map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                Ok("https://example.com/1".to_string()),
+                Ok("https://example.com/2".to_string()),
+                Ok("https://example.com/3".to_string()),
+                Err(format!(
+                    "Invalid document 3 of {}: No url at `url`",
+                    root.join("b.jsonl").display()
+                )),
+            ]
+        );
+        assert!(matches!(
+            read_json_documents(&[root.join("missing")], &JsonMapping::default()),
+            Err(Error::NoMatch { pattern, .. }) if pattern == root.join("missing")
+        ));
+    }
+}
@@ -0,0 +1,380 @@
+#[cfg(feature = "rkyv")]
+use super::archived::ArchivedPostings;
+use super::{
+    boost::DocBoosts,
+    constants::MAX_ITERATIONS,
+    corpus_stats::CorpusStats,
+    crawl_times::CrawlTimes,
+    deleted_docs::DeletedDocs,
+    disk_inverted_index::{insert_docs, DiskInvertedIndex, TermIndex},
+    doc_filter::FlaggedDocs,
+    doc_lengths::DocLengths,
+    doc_map::{Doc, DocID, DocMap, TFIDF},
+    generation::Generation,
+    lock::IndexLock,
+    quality::DocQuality,
+};
+use crate::{
+    error::{Error, Result},
+    kv_database::{database::KVDatabase, read_at::ReadAt},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// One JSON object per document, then one per term
+    #[default]
+    Jsonl,
+}
+
+/// A line of an exported index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExportRecord {
+    /// A document of the url map, its fields other than the url in `meta`
+    Doc {
+        doc_id: DocID,
+        url: String,
+        meta: Map<String, Value>,
+    },
+    /// The posting list of a term
+    Term {
+        term: String,
+        postings: Vec<ExportPosting>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportPosting {
+    pub doc_id: DocID,
+    pub score: TFIDF,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportStats {
+    pub docs: u64,
+    pub terms: u64,
+}
+
+impl Display for ExportStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Exported {} documents and {} terms",
+            self.docs, self.terms
+        )
+    }
+}
+
+/// Writes every document of `index` by doc ID, then every term with its
+/// postings by term, one [`ExportRecord`] per line. Records are read one at
+/// a time, so indexes of any size stream through.
+pub fn export_jsonl<R: ReadAt, W: Write>(
+    index: &DiskInvertedIndex<R>,
+    mut writer: W,
+) -> Result<ExportStats> {
+    let mut stats = ExportStats::default();
+
+    let mut doc_ids: Vec<_> = index.url_map.seek_pos_map.keys().copied().collect();
+    doc_ids.sort_unstable();
+    for doc_id in doc_ids {
+        let doc = index.get_doc(doc_id)?.ok_or(Error::MissingDoc { doc_id })?;
+        let mut meta: Map<String, Value> = serde_json::from_value(serde_json::to_value(doc)?)?;
+        let url = match meta.remove("url") {
+            Some(Value::String(url)) => url,
+            _ => String::new(),
+        };
+
+        write_record(&mut writer, &ExportRecord::Doc { doc_id, url, meta })?;
+        stats.docs += 1;
+    }
+
+    let mut terms: Vec<_> = index.db.seek_pos_map.keys().cloned().collect();
+    terms.sort_unstable();
+    for term in terms {
+        let postings = index
+            .get(&term)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|posting| ExportPosting {
+                doc_id: posting.doc_id,
+                score: posting.tf_idf,
+            })
+            .collect();
+
+        write_record(&mut writer, &ExportRecord::Term { term, postings })?;
+        stats.terms += 1;
+    }
+
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Builds an index from the records of [`export_jsonl`], in any order.
+///
+/// Postings keep their exported scores, so an index moves between format
+/// versions unchanged, and test fixtures can be written by hand with only the
+/// `meta` fields they need.
+///
+/// Exports carry no forward index, term stats or word frequencies, so the
+/// index comes out without them. Fails with [`Error::Locked`] while another
+/// process builds the same index.
+pub fn import_jsonl<B: BufRead>(
+    db_path: PathBuf,
+    seek_path: PathBuf,
+    url_map_path: PathBuf,
+    url_map_seek_path: PathBuf,
+    reader: B,
+) -> Result<(DiskInvertedIndex, ExportStats)> {
+    let _lock = IndexLock::exclusive(&db_path)?;
+    let generation = Generation::begin(&db_path)?;
+
+    // Left from an earlier build, they would contradict the imported files
+    DiskInvertedIndex::remove_derived_files(&db_path, &seek_path)?;
+
+    let build_id = Uuid::new_v4();
+    let mut db = KVDatabase::with_build_id(db_path.clone(), seek_path.clone(), build_id)?;
+    let mut url_ids = KVDatabase::with_build_id(
+        DiskInvertedIndex::url_ids_path(&url_map_path),
+        DiskInvertedIndex::url_ids_path(&url_map_seek_path),
+        build_id,
+    )?;
+    let mut url_map =
+        KVDatabase::with_build_id(url_map_path.clone(), url_map_seek_path.clone(), build_id)?;
+
+    let mut stats = ExportStats::default();
+    let mut corpus_stats = CorpusStats::default();
+    let mut flagged = FlaggedDocs::default();
+    let mut quality = DocQuality::default();
+    let mut boosts = DocBoosts::default();
+    let mut crawl_times = CrawlTimes::default();
+    let mut doc_lengths = DocLengths::default();
+    let mut doc_map = DocMap::new();
+    let mut postings_map: HashMap<String, Vec<TermIndex>> = HashMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str(&line).map_err(|source| Error::InvalidRecord {
+            line: i as u64 + 1,
+            source,
+        })?;
+        match record {
+            ExportRecord::Doc {
+                doc_id,
+                url,
+                mut meta,
+            } => {
+                meta.insert("url".to_string(), Value::String(url));
+                let doc: Doc = serde_json::from_value(Value::Object(meta)).map_err(|source| {
+                    Error::InvalidRecord {
+                        line: i as u64 + 1,
+                        source,
+                    }
+                })?;
+
+                corpus_stats.num_docs += 1;
+                corpus_stats.num_tokens += doc.num_tokens;
+                if doc.flagged {
+                    flagged.doc_ids.push(doc_id);
+                }
+                if let Some(score) = doc.quality.filter(|&score| score < 1.0) {
+                    quality.scores.push((doc_id, score));
+                }
+                boosts.add(doc_id, doc.boost);
+                if let Some(crawled_at) = doc.crawled_at {
+                    crawl_times.times.push((doc_id, crawled_at));
+                }
+                doc_lengths.lengths.push((doc_id, doc.num_tokens as u32));
+                doc_map.insert(doc_id, doc);
+                stats.docs += 1;
+            }
+            ExportRecord::Term { term, postings } => {
+                let mut postings: Vec<_> = postings
+                    .into_iter()
+                    .map(|posting| TermIndex {
+                        doc_id: posting.doc_id,
+                        tf_idf: posting.score,
+                    })
+                    .collect();
+                postings.sort_by_key(|posting| posting.doc_id);
+                postings_map.insert(term, postings);
+                stats.terms += 1;
+            }
+        }
+
+        if doc_map.len() + postings_map.len() >= MAX_ITERATIONS as usize {
+            db.insert(postings_map)?;
+            insert_docs(&mut url_map, &mut url_ids, doc_map)?;
+            postings_map = HashMap::new();
+            doc_map = DocMap::new();
+        }
+    }
+    db.insert(postings_map)?;
+    insert_docs(&mut url_map, &mut url_ids, doc_map)?;
+    drop((db, url_map, url_ids));
+
+    corpus_stats.write(&CorpusStats::path(&db_path))?;
+    flagged.write(&FlaggedDocs::path(&db_path))?;
+    DeletedDocs::default().write(&DeletedDocs::path(&db_path))?;
+    quality.write(&DocQuality::path(&db_path))?;
+    boosts.write(&DocBoosts::path(&db_path))?;
+    crawl_times.write(&CrawlTimes::path(&db_path))?;
+    doc_lengths.write(&DocLengths::path(&db_path))?;
+    #[cfg(feature = "rkyv")]
+    ArchivedPostings::write(
+        &KVDatabase::from(db_path.clone(), seek_path.clone())?,
+        &ArchivedPostings::data_path(&db_path),
+        &ArchivedPostings::seek_path(&seek_path),
+    )?;
+    generation.publish()?;
+
+    let index = DiskInvertedIndex::from(db_path, seek_path, url_map_path, url_map_seek_path)?;
+    Ok((index, stats))
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &ExportRecord) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::CrawlFile;
+    use crate::test_util::{page, TestDir};
+
+    #[test]
+    fn export() {
+        let dir = TestDir::new();
+        let page = |name: &str, text: &str| {
+            Ok(CrawlFile {
+                content: format!("<title>{name}</title><p>{text}</p>"),
+                ..page(name, "")
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("export.db"),
+            dir.path("export.seek"),
+            dir.path("export_url_map.db"),
+            dir.path("export_url_map.seek"),
+            [page("pasta", "fresh pasta"), page("pesto", "basil")],
+        )
+        .expect("Failed to build index");
+
+        let mut output = Vec::new();
+        let stats = export_jsonl(&index, &mut output).expect("Failed to export");
+        let records: Vec<ExportRecord> = String::from_utf8(output)
+            .expect("Export should be UTF-8")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Failed to parse record"))
+            .collect();
+
+        assert_eq!(stats.docs, 2);
+        assert_eq!(records.len() as u64, stats.docs + stats.terms);
+        let ExportRecord::Doc { doc_id, url, meta } = &records[0] else {
+            panic!("Documents should come first");
+        };
+        assert_eq!((*doc_id, url.as_str()), (0, "https://example.com/pasta"));
+        assert_eq!(meta["title"], "pasta");
+        assert!(!meta.contains_key("url"));
+
+        let basil = records
+            .iter()
+            .find_map(|record| match record {
+                ExportRecord::Term { term, postings } if term == "basil" => Some(postings),
+                _ => None,
+            })
+            .expect("Terms should be exported");
+        assert_eq!(basil.len(), 1);
+        assert_eq!(basil[0].doc_id, 1);
+    }
+
+    #[test]
+    fn import() {
+        let dir = TestDir::new();
+        let page = |name: &str, text: &str| {
+            Ok(CrawlFile {
+                content: format!("<title>{name}</title><p>{text}</p>"),
+                crawled_at: Some(1_700_000_000),
+                ..page(name, "")
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("import_source.db"),
+            dir.path("import_source.seek"),
+            dir.path("import_source_url_map.db"),
+            dir.path("import_source_url_map.seek"),
+            [page("pasta", "fresh pasta"), page("pesto", "basil pasta")],
+        )
+        .expect("Failed to build index");
+        let mut exported = Vec::new();
+        export_jsonl(&index, &mut exported).expect("Failed to export");
+
+        let (imported, stats) = import_jsonl(
+            dir.path("import.db"),
+            dir.path("import.seek"),
+            dir.path("import_url_map.db"),
+            dir.path("import_url_map.seek"),
+            exported.as_slice(),
+        )
+        .expect("Failed to import");
+        let mut reexported = Vec::new();
+        export_jsonl(&imported, &mut reexported).expect("Failed to export");
+
+        assert_eq!(stats.docs, 2);
+        assert_eq!(reexported, exported);
+        assert_eq!(imported.average_doc_length(), index.average_doc_length());
+        assert_eq!(
+            imported
+                .find_doc_by_url("https://example.com/pesto")
+                .expect("Failed to look up url")
+                .map(|(doc_id, _)| doc_id),
+            Some(1)
+        );
+
+        // Fixtures only need the fields they use
+        let fixture = concat!(
+            r#"{"term": "pasta", "postings": [{"doc_id": 7, "score": 1.5}]}"#,
+            "\n",
+            r#"{"doc_id": 7, "url": "https://example.com/", "meta": {}}"#,
+        );
+        let (fixture, _) = import_jsonl(
+            dir.path("import.db"),
+            dir.path("import.seek"),
+            dir.path("import_url_map.db"),
+            dir.path("import_url_map.seek"),
+            fixture.as_bytes(),
+        )
+        .expect("Failed to import fixture");
+        assert_eq!(
+            fixture.get("pasta").expect("Failed to read postings"),
+            Some(vec![TermIndex {
+                doc_id: 7,
+                tf_idf: 1.5
+            }])
+        );
+
+        let invalid = import_jsonl(
+            dir.path("import.db"),
+            dir.path("import.seek"),
+            dir.path("import_url_map.db"),
+            dir.path("import_url_map.seek"),
+            &b"{}\n{\"term\": 1}"[..],
+        );
+        assert!(matches!(invalid, Err(Error::InvalidRecord { line: 1, .. })));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::error::{Error, Result};
+use std::{
+    fs::{File, TryLockError},
+    path::{Path, PathBuf},
+};
+
+/// Advisory lock on the lock file next to the postings database, released
+/// when dropped.
+///
+/// Builds hold it exclusively, so two processes never rewrite the same index
+/// at once. Readers that must not see a build start while they work can hold
+/// it shared; plain searches don't need to, builds never disturb open files.
+#[derive(Debug)]
+pub struct IndexLock {
+    _file: File,
+}
+
+impl IndexLock {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.lock", db_path.display()))
+    }
+
+    /// Locks the index of `db_path` for writing, failing with
+    /// [`Error::Locked`] while any other lock is held.
+    pub fn exclusive(db_path: &Path) -> Result<Self> {
+        Self::acquire(db_path, File::try_lock)
+    }
+
+    /// Locks the index of `db_path` against writers, failing with
+    /// [`Error::Locked`] while one holds it.
+    pub fn shared(db_path: &Path) -> Result<Self> {
+        Self::acquire(db_path, File::try_lock_shared)
+    }
+
+    fn acquire(
+        db_path: &Path,
+        try_lock: fn(&File) -> core::result::Result<(), TryLockError>,
+    ) -> Result<Self> {
+        let path = Self::path(db_path);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match try_lock(&file) {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(Error::Locked { path }),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn exclusive_and_shared() {
+        let dir = TestDir::new();
+        let db_path = dir.path("index_lock.db");
+
+        let writer = IndexLock::exclusive(&db_path).expect("Failed to lock index");
+        assert!(matches!(
+            IndexLock::exclusive(&db_path),
+            Err(Error::Locked { .. })
+        ));
+        assert!(matches!(
+            IndexLock::shared(&db_path),
+            Err(Error::Locked { .. })
+        ));
+        drop(writer);
+
+        let reader = IndexLock::shared(&db_path).expect("Failed to lock index");
+        IndexLock::shared(&db_path).expect("Readers should share the lock");
+        assert!(matches!(
+            IndexLock::exclusive(&db_path),
+            Err(Error::Locked { .. })
+        ));
+        drop(reader);
+
+        IndexLock::exclusive(&db_path).expect("Lock should be free again");
+    }
+}
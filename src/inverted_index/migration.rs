@@ -0,0 +1,249 @@
+#[cfg(feature = "rkyv")]
+use super::archived::ArchivedPostings;
+use super::{
+    constants::MAX_ITERATIONS,
+    disk_inverted_index::{DiskInvertedIndex, TermIndex},
+    doc_map::{Doc, DocID},
+    generation::Generation,
+    jsonl::import_jsonl,
+    lock::IndexLock,
+};
+use crate::{
+    error::Result,
+    kv_database::database::{has_header, KVDatabase},
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::{remove_file, rename, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// How the postings database of an index is laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// An export of the whole index, one JSON object per line
+    JsonLines,
+    /// Bare values that only the seek file can find, written before files
+    /// had headers
+    Headerless,
+    /// Records behind file headers tying them to their build
+    Current,
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::JsonLines => "JSON lines",
+            Self::Headerless => "headerless",
+            Self::Current => "current",
+        })
+    }
+}
+
+/// The layout of the postings database at `db_path`.
+pub fn detect(db_path: &Path) -> Result<Layout> {
+    if has_header(db_path)? {
+        return Ok(Layout::Current);
+    }
+
+    let mut reader = BufReader::new(File::open(db_path)?);
+    let starts_with_brace = loop {
+        let buffer = reader.fill_buf()?;
+        let Some(&byte) = buffer.first() else {
+            break false;
+        };
+        if byte.is_ascii_whitespace() {
+            reader.consume(1);
+        } else {
+            break byte == b'{';
+        }
+    };
+
+    Ok(if starts_with_brace {
+        Layout::JsonLines
+    } else {
+        Layout::Headerless
+    })
+}
+
+/// Upgrades the index at the given paths in place to the current layout,
+/// rewriting it a record at a time rather than recrawling. Returns the layout
+/// it was in.
+///
+/// An export found where the postings database belongs is imported from a
+/// copy left next to it until the import succeeds. Headerless files keep
+/// their records but lose the forward index and term stats, which only a
+/// rebuild from crawled pages restores.
+pub fn migrate(
+    db_path: PathBuf,
+    seek_path: PathBuf,
+    url_map_path: PathBuf,
+    url_map_seek_path: PathBuf,
+) -> Result<Layout> {
+    let layout = detect(&db_path)?;
+    match layout {
+        Layout::Current => {}
+        Layout::JsonLines => {
+            let export_path = PathBuf::from(format!("{}.jsonl", db_path.display()));
+            rename(&db_path, &export_path)?;
+
+            let reader = BufReader::new(File::open(&export_path)?);
+            import_jsonl(db_path, seek_path, url_map_path, url_map_seek_path, reader)?;
+            remove_file(export_path)?;
+        }
+        Layout::Headerless => {
+            upgrade_headerless(&db_path, &seek_path, &url_map_path, &url_map_seek_path)?;
+        }
+    }
+
+    Ok(layout)
+}
+
+/// Rewrites headerless files under headers of a new build, and adds the url
+/// → doc ID map they predate.
+fn upgrade_headerless(
+    db_path: &Path,
+    seek_path: &Path,
+    url_map_path: &Path,
+    url_map_seek_path: &Path,
+) -> Result<()> {
+    let _lock = IndexLock::exclusive(db_path)?;
+    let generation = Generation::begin(db_path)?;
+    DiskInvertedIndex::remove_derived_files(db_path, seek_path)?;
+
+    let build_id = Uuid::new_v4();
+    let mut db: KVDatabase<String, Vec<TermIndex>> =
+        KVDatabase::from(db_path.to_path_buf(), seek_path.to_path_buf())?;
+    db.rewrite_with(build_id, |_, postings| Some(postings))?;
+
+    // Decoding and encoding every doc also moves it to the newest version
+    let mut url_map: KVDatabase<DocID, Doc> =
+        KVDatabase::from(url_map_path.to_path_buf(), url_map_seek_path.to_path_buf())?;
+    url_map.rewrite_with(build_id, |_, doc| Some(doc))?;
+
+    let mut url_ids = KVDatabase::with_build_id(
+        DiskInvertedIndex::url_ids_path(url_map_path),
+        DiskInvertedIndex::url_ids_path(url_map_seek_path),
+        build_id,
+    )?;
+    let mut batch = HashMap::new();
+    for entry in &url_map {
+        let (doc_id, doc) = entry?;
+        batch.insert(doc.url, doc_id);
+        if batch.len() >= MAX_ITERATIONS as usize {
+            url_ids.insert(std::mem::take(&mut batch))?;
+        }
+    }
+    url_ids.insert(batch)?;
+
+    #[cfg(feature = "rkyv")]
+    ArchivedPostings::write(
+        &db,
+        &ArchivedPostings::data_path(db_path),
+        &ArchivedPostings::seek_path(seek_path),
+    )?;
+    generation.publish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::jsonl::export_jsonl;
+    use crate::test_util::TestDir;
+    use std::fs::{copy, read};
+
+    fn copy_test_index(dir: &TestDir, name: &str) -> [PathBuf; 4] {
+        [
+            ("db", ".db"),
+            ("seek", ".seek"),
+            ("url_map", "_url_map.db"),
+            ("url_map_seek", "_url_map.seek"),
+        ]
+        .map(|(file, suffix)| {
+            let path = dir.path(format!("{name}{suffix}"));
+            copy(format!("tests/test-data/search_test_{file}.test"), &path)
+                .expect("Failed to copy test index");
+            path
+        })
+    }
+
+    fn open([db, seek, url_map, url_map_seek]: [PathBuf; 4]) -> DiskInvertedIndex {
+        DiskInvertedIndex::from(db, seek, url_map, url_map_seek).expect("Failed to open index")
+    }
+
+    fn export(index: &DiskInvertedIndex) -> Vec<u8> {
+        let mut exported = Vec::new();
+        export_jsonl(index, &mut exported).expect("Failed to export");
+        exported
+    }
+
+    #[test]
+    fn headerless() {
+        let dir = TestDir::new();
+        let paths = copy_test_index(&dir, "migrate_headerless");
+        let before = export(&open(paths.clone()));
+        let [db, seek, url_map, url_map_seek] = paths.clone();
+        assert_eq!(detect(&db).expect("Failed to detect"), Layout::Headerless);
+
+        let layout = migrate(
+            db.clone(),
+            seek.clone(),
+            url_map.clone(),
+            url_map_seek.clone(),
+        )
+        .expect("Failed to migrate");
+        assert_eq!(layout, Layout::Headerless);
+        assert_eq!(detect(&db).expect("Failed to detect"), Layout::Current);
+
+        let index = open(paths);
+        assert_eq!(export(&index), before);
+        let doc = index
+            .get_doc(1)
+            .expect("Failed to read doc")
+            .expect("Missing doc");
+        assert_eq!(
+            index
+                .find_doc_by_url(&doc.url)
+                .expect("Failed to look up url")
+                .map(|(doc_id, _)| doc_id),
+            Some(1)
+        );
+
+        // Upgraded files are left alone
+        let bytes = read(&db).expect("Failed to read db");
+        let layout = migrate(db.clone(), seek, url_map, url_map_seek).expect("Failed to migrate");
+        assert_eq!(layout, Layout::Current);
+        assert_eq!(read(&db).expect("Failed to read db"), bytes);
+    }
+
+    #[test]
+    fn json_lines() {
+        let dir = TestDir::new();
+        let exported = export(&open(copy_test_index(&dir, "migrate_source")));
+        let [db, seek, url_map, url_map_seek] = [
+            "migrate_jsonl.db",
+            "migrate_jsonl.seek",
+            "migrate_jsonl_url_map.db",
+            "migrate_jsonl_url_map.seek",
+        ]
+        .map(|name| dir.path(name));
+        std::fs::write(&db, &exported).expect("Failed to write export");
+        assert_eq!(detect(&db).expect("Failed to detect"), Layout::JsonLines);
+
+        let layout = migrate(
+            db.clone(),
+            seek.clone(),
+            url_map.clone(),
+            url_map_seek.clone(),
+        )
+        .expect("Failed to migrate");
+        assert_eq!(layout, Layout::JsonLines);
+        assert_eq!(export(&open([db, seek, url_map, url_map_seek])), exported);
+        assert!(!dir.path("migrate_jsonl.db.jsonl").exists());
+    }
+}
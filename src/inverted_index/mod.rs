@@ -1,3 +1,30 @@
+#[cfg(feature = "rkyv")]
+pub mod archived;
+pub mod boost;
+pub mod build_report;
+pub mod charset;
 pub mod constants;
+pub mod corpus_stats;
+pub mod crawl_sources;
+pub mod crawl_times;
+pub mod deleted_docs;
 pub mod disk_inverted_index;
+pub mod doc_filter;
+pub mod doc_ids;
+pub mod doc_lengths;
 pub mod doc_map;
+pub mod feeds;
+pub mod fields;
+pub mod generation;
+pub mod json_documents;
+pub mod jsonl;
+pub mod lock;
+pub mod migration;
+pub mod posting_iterator;
+pub mod posting_runs;
+pub mod posting_stats;
+pub mod quality;
+pub mod synonyms;
+pub mod term_stats;
+pub mod url_fields;
+pub mod word_frequencies;
@@ -0,0 +1,331 @@
+use super::{
+    disk_inverted_index::TermIndex,
+    doc_map::{DocID, TFIDF},
+};
+
+/// A posting list walked in doc ID order, so query operators can be written
+/// once whatever holds the postings.
+///
+/// A fresh iterator sits before its first posting: [`PostingIterator::doc`]
+/// is `None` until [`PostingIterator::next_doc`] or
+/// [`PostingIterator::advance`] moves it.
+pub trait PostingIterator {
+    /// Doc ID of the current posting, `None` before the first one and once
+    /// exhausted.
+    fn doc(&self) -> Option<DocID>;
+
+    /// Moves to the next posting and returns its doc ID.
+    fn next_doc(&mut self) -> Option<DocID>;
+
+    /// Moves to the first posting whose doc ID is at least `target` and
+    /// returns its doc ID. Never moves backwards.
+    fn advance(&mut self, target: DocID) -> Option<DocID>;
+
+    /// Weighted score of the current posting, 0 when there is none.
+    fn score(&self) -> TFIDF;
+
+    /// Upper bound of the postings left, to visit the rarest lists first.
+    fn cost(&self) -> usize;
+}
+
+impl<I: PostingIterator + ?Sized> PostingIterator for Box<I> {
+    fn doc(&self) -> Option<DocID> {
+        (**self).doc()
+    }
+
+    fn next_doc(&mut self) -> Option<DocID> {
+        (**self).next_doc()
+    }
+
+    fn advance(&mut self, target: DocID) -> Option<DocID> {
+        (**self).advance(target)
+    }
+
+    fn score(&self) -> TFIDF {
+        (**self).score()
+    }
+
+    fn cost(&self) -> usize {
+        (**self).cost()
+    }
+}
+
+/// Postings decoded into memory.
+pub struct DecodedPostings {
+    postings: Vec<TermIndex>,
+    weight: f64,
+    position: Option<usize>,
+}
+
+impl DecodedPostings {
+    /// Scores of `postings` are multiplied by `weight`.
+    #[must_use]
+    pub fn new(mut postings: Vec<TermIndex>, weight: f64) -> Self {
+        // Builds write postings in doc ID order, anything else is sorted once
+        if !postings.is_sorted_by_key(|posting| posting.doc_id) {
+            postings.sort_by_key(|posting| posting.doc_id);
+        }
+
+        Self {
+            postings,
+            weight,
+            position: None,
+        }
+    }
+
+    fn current(&self) -> Option<&TermIndex> {
+        self.position
+            .and_then(|position| self.postings.get(position))
+    }
+}
+
+impl PostingIterator for DecodedPostings {
+    fn doc(&self) -> Option<DocID> {
+        self.current().map(|posting| posting.doc_id)
+    }
+
+    fn next_doc(&mut self) -> Option<DocID> {
+        let next = self.position.map_or(0, |position| position + 1);
+        self.position = Some(next.min(self.postings.len()));
+        self.doc()
+    }
+
+    fn advance(&mut self, target: DocID) -> Option<DocID> {
+        if self.doc().is_some_and(|doc| doc >= target) {
+            return self.doc();
+        }
+
+        let start = self.position.unwrap_or(0).min(self.postings.len());
+        let skipped = self.postings[start..].partition_point(|posting| posting.doc_id < target);
+        self.position = Some(start + skipped);
+        self.doc()
+    }
+
+    fn score(&self) -> TFIDF {
+        self.current()
+            .map_or(0.0, |posting| self.weight * posting.tf_idf)
+    }
+
+    fn cost(&self) -> usize {
+        let read = self.position.map_or(0, |position| position + 1);
+        self.postings.len().saturating_sub(read)
+    }
+}
+
+/// Documents found in every one of its lists, scored with the sum of their
+/// scores. The rarest list leads and the others skip ahead to it.
+pub struct Intersection<I> {
+    iterators: Vec<I>,
+    doc: Option<DocID>,
+}
+
+impl<I: PostingIterator> Intersection<I> {
+    /// An intersection of no lists matches nothing.
+    #[must_use]
+    pub fn new(mut iterators: Vec<I>) -> Self {
+        iterators.sort_by_key(PostingIterator::cost);
+        Self {
+            iterators,
+            doc: None,
+        }
+    }
+
+    /// Moves every list to the first document at or after `target` that all
+    /// of them contain.
+    fn align(&mut self, mut target: DocID) -> Option<DocID> {
+        'candidates: loop {
+            for iterator in &mut self.iterators[1..] {
+                let doc = iterator.advance(target)?;
+                if doc > target {
+                    target = self.iterators[0].advance(doc)?;
+                    continue 'candidates;
+                }
+            }
+
+            return Some(target);
+        }
+    }
+}
+
+impl<I: PostingIterator> PostingIterator for Intersection<I> {
+    fn doc(&self) -> Option<DocID> {
+        self.doc
+    }
+
+    fn next_doc(&mut self) -> Option<DocID> {
+        let lead = self.iterators.first_mut()?.next_doc();
+        self.doc = lead.and_then(|lead| self.align(lead));
+        self.doc
+    }
+
+    fn advance(&mut self, target: DocID) -> Option<DocID> {
+        let lead = self.iterators.first_mut()?.advance(target);
+        self.doc = lead.and_then(|lead| self.align(lead));
+        self.doc
+    }
+
+    fn score(&self) -> TFIDF {
+        if self.doc.is_none() {
+            return 0.0;
+        }
+        self.iterators.iter().map(PostingIterator::score).sum()
+    }
+
+    fn cost(&self) -> usize {
+        self.iterators.first().map_or(0, PostingIterator::cost)
+    }
+}
+
+/// Documents found in any of its lists, scored with the sum of the scores of
+/// the lists containing them.
+pub struct Union<I> {
+    iterators: Vec<I>,
+    doc: Option<DocID>,
+    started: bool,
+}
+
+impl<I: PostingIterator> Union<I> {
+    #[must_use]
+    pub const fn new(iterators: Vec<I>) -> Self {
+        Self {
+            iterators,
+            doc: None,
+            started: false,
+        }
+    }
+
+    fn lowest(&self) -> Option<DocID> {
+        self.iterators.iter().filter_map(PostingIterator::doc).min()
+    }
+}
+
+impl<I: PostingIterator> PostingIterator for Union<I> {
+    fn doc(&self) -> Option<DocID> {
+        self.doc
+    }
+
+    fn next_doc(&mut self) -> Option<DocID> {
+        let current = self.doc;
+        for iterator in &mut self.iterators {
+            if !self.started || (current.is_some() && iterator.doc() == current) {
+                iterator.next_doc();
+            }
+        }
+        self.started = true;
+        self.doc = self.lowest();
+        self.doc
+    }
+
+    fn advance(&mut self, target: DocID) -> Option<DocID> {
+        for iterator in &mut self.iterators {
+            if !self.started || iterator.doc().is_some_and(|doc| doc < target) {
+                iterator.advance(target);
+            }
+        }
+        self.started = true;
+        self.doc = self.lowest();
+        self.doc
+    }
+
+    fn score(&self) -> TFIDF {
+        if self.doc.is_none() {
+            return 0.0;
+        }
+        self.iterators
+            .iter()
+            .filter(|iterator| iterator.doc() == self.doc)
+            .map(PostingIterator::score)
+            .sum()
+    }
+
+    fn cost(&self) -> usize {
+        self.iterators.iter().map(PostingIterator::cost).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn postings(docs: &[DocID], weight: f64) -> DecodedPostings {
+        let postings = docs
+            .iter()
+            .map(|&doc_id| TermIndex {
+                doc_id,
+                tf_idf: 1.0,
+            })
+            .collect();
+        DecodedPostings::new(postings, weight)
+    }
+
+    fn drain<I: PostingIterator>(mut iterator: I) -> Vec<(DocID, f64)> {
+        let mut docs = Vec::new();
+        while let Some(doc) = iterator.next_doc() {
+            docs.push((doc, iterator.score()));
+        }
+        docs
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn decoded() {
+        let mut iterator = postings(&[7, 1, 4, 9], 2.0);
+
+        assert_eq!(iterator.doc(), None);
+        assert_eq!(iterator.cost(), 4);
+        assert_eq!(iterator.next_doc(), Some(1));
+        assert_eq!(iterator.score(), 2.0);
+        assert_eq!(iterator.advance(5), Some(7));
+        assert_eq!(iterator.advance(3), Some(7));
+        assert_eq!(iterator.cost(), 1);
+        assert_eq!(iterator.next_doc(), Some(9));
+        assert_eq!(iterator.next_doc(), None);
+        assert_eq!(iterator.next_doc(), None);
+        assert_eq!(iterator.score(), 0.0);
+    }
+
+    #[test]
+    fn intersection() {
+        let intersection = Intersection::new(vec![
+            postings(&[1, 3, 5, 7, 9, 11], 1.0),
+            postings(&[3, 7, 11], 2.0),
+            postings(&[2, 3, 4, 11, 12], 0.5),
+        ]);
+
+        assert_eq!(drain(intersection), [(3, 3.5), (11, 3.5)]);
+        assert!(drain(Intersection::<DecodedPostings>::new(Vec::new())).is_empty());
+
+        let mut intersection =
+            Intersection::new(vec![postings(&[1, 3, 5], 1.0), postings(&[1, 5], 1.0)]);
+        assert_eq!(intersection.advance(2), Some(5));
+        assert_eq!(intersection.next_doc(), None);
+    }
+
+    #[test]
+    fn union() {
+        let union = Union::new(vec![
+            postings(&[1, 5], 1.0),
+            postings(&[2, 5, 8], 2.0),
+            postings(&[], 1.0),
+        ]);
+
+        assert_eq!(drain(union), [(1, 1.0), (2, 2.0), (5, 3.0), (8, 2.0)]);
+
+        let mut union = Union::new(vec![postings(&[1, 5], 1.0), postings(&[2, 6], 1.0)]);
+        assert_eq!(union.advance(3), Some(5));
+        assert_eq!(union.next_doc(), Some(6));
+        assert_eq!(union.next_doc(), None);
+    }
+
+    #[test]
+    fn nested() {
+        // (a or b) and c
+        let union = Union::new(vec![postings(&[1, 4], 1.0), postings(&[2, 6], 1.0)]);
+        let intersection = Intersection::new(vec![
+            Box::new(union) as Box<dyn PostingIterator>,
+            Box::new(postings(&[2, 3, 4], 1.0)),
+        ]);
+
+        assert_eq!(drain(intersection), [(2, 2.0), (4, 2.0)]);
+    }
+}
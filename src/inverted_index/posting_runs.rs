@@ -0,0 +1,151 @@
+use super::disk_inverted_index::{TempInvertedIndex, TempTermIndex};
+use crate::{
+    error::Result,
+    kv_database::{
+        database::{KVDatabase, KVDatabaseWriter},
+        scratch,
+    },
+};
+use std::{
+    fs::remove_file,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// Posting lists of a build written out a batch of documents at a time, for
+/// corpora whose postings don't fit in memory.
+///
+/// Each run is written once and read back when the postings are scored,
+/// rather than merged into one file on every flush.
+pub struct PostingRuns {
+    db_path: PathBuf,
+    build_id: Uuid,
+    runs: Vec<Run>,
+}
+
+struct Run {
+    db: KVDatabase<String, Vec<TempTermIndex>>,
+    db_path: PathBuf,
+    seek_path: PathBuf,
+}
+
+impl PostingRuns {
+    /// No runs yet, for the build `build_id` of the postings database at
+    /// `db_path`.
+    #[must_use]
+    pub fn new(db_path: &Path, build_id: Uuid) -> Self {
+        Self {
+            db_path: db_path.to_path_buf(),
+            build_id,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Writes the postings of `inverted_index` as a run after the others, so
+    /// its documents must come after theirs.
+    pub fn spill(&mut self, inverted_index: TempInvertedIndex) -> Result<()> {
+        if inverted_index.is_empty() {
+            return Ok(());
+        }
+
+        let name = format!("{}.run{}", self.db_path.display(), self.runs.len());
+        let db_path = scratch::temp_path(Path::new(&name));
+        let seek_path = scratch::temp_path(Path::new(&format!("{name}.seek")));
+
+        let mut writer = KVDatabaseWriter::create(&db_path, seek_path.clone(), self.build_id)?;
+        for (term, postings) in inverted_index {
+            writer.write(term, &postings)?;
+        }
+        writer.finish()?;
+
+        self.runs.push(Run {
+            db: KVDatabase::from(db_path.clone(), seek_path.clone())?,
+            db_path,
+            seek_path,
+        });
+
+        Ok(())
+    }
+
+    /// Every term with postings in some run, each once.
+    #[must_use]
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms: Vec<_> = self
+            .runs
+            .iter()
+            .flat_map(|run| run.db.seek_pos_map.keys().cloned())
+            .collect();
+        terms.sort_unstable();
+        terms.dedup();
+
+        terms
+    }
+
+    /// The postings of `term` in every run, in the order they were written.
+    pub fn postings(&self, term: &String) -> Result<Vec<TempTermIndex>> {
+        let mut postings = Vec::new();
+        for run in &self.runs {
+            postings.extend(run.db.get(term)?.into_iter().flatten());
+        }
+
+        Ok(postings)
+    }
+
+    /// Deletes the files of the runs.
+    pub fn remove(self) -> Result<()> {
+        for run in self.runs {
+            drop(run.db);
+            remove_file(&run.db_path)?;
+            remove_file(&run.seek_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn posting_runs() {
+        let dir = TestDir::new();
+        let db_path = &dir.path("posting_runs.db");
+        let posting = |doc_id, tf| TempTermIndex { doc_id, tf };
+
+        let mut runs = PostingRuns::new(db_path, Uuid::new_v4());
+        runs.spill(TempInvertedIndex::from([
+            ("apple".to_string(), vec![posting(0, 2), posting(1, 1)]),
+            ("pear".to_string(), vec![posting(1, 3)]),
+        ]))
+        .expect("Failed to spill run");
+        runs.spill(TempInvertedIndex::new())
+            .expect("Failed to spill run");
+        runs.spill(TempInvertedIndex::from([(
+            "apple".to_string(),
+            vec![posting(2, 1)],
+        )]))
+        .expect("Failed to spill run");
+        assert_eq!(runs.runs.len(), 2);
+
+        assert_eq!(runs.terms(), ["apple", "pear"]);
+        assert_eq!(
+            runs.postings(&"apple".to_string())
+                .expect("Failed to read postings"),
+            [posting(0, 2), posting(1, 1), posting(2, 1)]
+        );
+        assert!(runs
+            .postings(&"plum".to_string())
+            .expect("Failed to read postings")
+            .is_empty());
+
+        let paths: Vec<_> = runs
+            .runs
+            .iter()
+            .flat_map(|run| [run.db_path.clone(), run.seek_path.clone()])
+            .collect();
+        runs.remove().expect("Failed to remove runs");
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+}
@@ -0,0 +1,174 @@
+use super::disk_inverted_index::DiskInvertedIndex;
+use crate::{error::Result, kv_database::read_at::ReadAt};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Equal-width buckets of the score histograms.
+pub const SCORE_BUCKETS: usize = 10;
+
+/// How postings are spread over the terms of an index, for picking
+/// stopwords and champion list thresholds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PostingStats {
+    pub num_terms: u64,
+    pub num_postings: u64,
+    /// Terms by posting list length, bucket `i` counting lengths from `2^i`
+    /// up to `2^(i + 1) - 1`
+    pub length_histogram: Vec<u64>,
+    /// Terms with the longest posting lists, longest first
+    pub heaviest_terms: Vec<TermStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermStats {
+    pub term: String,
+    pub doc_frequency: u64,
+    pub min_score: f64,
+    pub max_score: f64,
+    /// Postings per bucket of [`SCORE_BUCKETS`] equal-width buckets from
+    /// `min_score` to `max_score`
+    pub score_histogram: Vec<u64>,
+}
+
+impl PostingStats {
+    /// Lengths come from the seek map, so only the postings of the `top`
+    /// heaviest terms are read.
+    pub fn collect<R: ReadAt>(index: &DiskInvertedIndex<R>, top: usize) -> Result<Self> {
+        let mut num_postings = 0;
+        let mut length_histogram = Vec::new();
+        let mut terms = Vec::with_capacity(index.db.seek_pos_map.len());
+
+        for term in index.db.seek_pos_map.keys() {
+            let doc_frequency = index.doc_frequency(term);
+            num_postings += doc_frequency;
+            if doc_frequency > 0 {
+                let bucket = doc_frequency.ilog2() as usize;
+                if length_histogram.len() <= bucket {
+                    length_histogram.resize(bucket + 1, 0);
+                }
+                length_histogram[bucket] += 1;
+            }
+            terms.push((doc_frequency, term));
+        }
+
+        terms.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        let heaviest_terms = terms
+            .into_iter()
+            .take(top)
+            .map(|(doc_frequency, term)| {
+                let scores: Vec<_> = index
+                    .get(term)?
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|posting| posting.tf_idf)
+                    .collect();
+                Ok(TermStats::new(term.clone(), doc_frequency, &scores))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            num_terms: index.num_terms(),
+            num_postings,
+            length_histogram,
+            heaviest_terms,
+        })
+    }
+}
+
+impl TermStats {
+    fn new(term: String, doc_frequency: u64, scores: &[f64]) -> Self {
+        let finite = scores.iter().copied().filter(|score| score.is_finite());
+        let min_score = finite.clone().fold(f64::INFINITY, f64::min);
+        let max_score = finite.clone().fold(f64::NEG_INFINITY, f64::max);
+
+        let mut score_histogram = vec![0; SCORE_BUCKETS];
+        let width = (max_score - min_score) / SCORE_BUCKETS as f64;
+        for score in finite {
+            let bucket = if width > 0.0 {
+                (((score - min_score) / width) as usize).min(SCORE_BUCKETS - 1)
+            } else {
+                0
+            };
+            score_histogram[bucket] += 1;
+        }
+
+        // Both bounds stay infinite when there is no finite score
+        let finite_or_zero = |score: f64| if score.is_finite() { score } else { 0.0 };
+        Self {
+            term,
+            doc_frequency,
+            min_score: finite_or_zero(min_score),
+            max_score: finite_or_zero(max_score),
+            score_histogram,
+        }
+    }
+}
+
+impl Display for PostingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} terms, {} postings",
+            self.num_terms, self.num_postings
+        )?;
+
+        writeln!(f, "Posting list lengths:")?;
+        for (bucket, count) in self.length_histogram.iter().enumerate() {
+            let low = 1_u64 << bucket;
+            writeln!(f, "  {low:>8}-{:<8} {count}", 2 * low - 1)?;
+        }
+
+        write!(f, "Heaviest terms:")?;
+        for term in &self.heaviest_terms {
+            let histogram = term
+                .score_histogram
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(
+                f,
+                "\n  {:<20} {:>8} docs, scores {:.3}..{:.3} [{histogram}]",
+                term.term, term.doc_frequency, term.min_score, term.max_score
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect() {
+        let index = DiskInvertedIndex::from(
+            "tests/test-data/search_test_db.test".into(),
+            "tests/test-data/search_test_seek.test".into(),
+            "tests/test-data/search_test_url_map.test".into(),
+            "tests/test-data/search_test_url_map_seek.test".into(),
+        )
+        .expect("Failed to open index");
+
+        let stats = PostingStats::collect(&index, 2).expect("Failed to collect stats");
+
+        assert_eq!(stats.num_terms, index.num_terms());
+        assert_eq!(stats.length_histogram.iter().sum::<u64>(), stats.num_terms);
+        assert_eq!(stats.heaviest_terms.len(), 2);
+        assert!(stats.heaviest_terms[0].doc_frequency >= stats.heaviest_terms[1].doc_frequency);
+        for term in &stats.heaviest_terms {
+            assert_eq!(term.score_histogram.iter().sum::<u64>(), term.doc_frequency);
+            assert!(term.min_score <= term.max_score);
+        }
+    }
+
+    #[test]
+    fn score_histogram() {
+        let term = TermStats::new("rust".to_string(), 4, &[0.0, 0.5, 1.0, 0.95]);
+
+        assert_eq!(term.score_histogram, [1, 0, 0, 0, 0, 1, 0, 0, 0, 2]);
+        assert_eq!(term.min_score.to_bits(), 0.0_f64.to_bits());
+        assert_eq!(term.max_score.to_bits(), 1.0_f64.to_bits());
+    }
+}
@@ -0,0 +1,90 @@
+use super::doc_map::DocID;
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Share of the HTML that is text below which a page reads as boilerplate.
+const MIN_TEXT_RATIO: f64 = 0.05;
+/// Distinct links per token above which a page reads as a link farm.
+const MAX_LINKS_PER_TOKEN: f64 = 0.3;
+/// Lowest quality of a page, so junk sinks without vanishing.
+const MIN_QUALITY: f64 = 0.1;
+
+/// Quality of a page judged from its own HTML, from [`MIN_QUALITY`] for junk
+/// to 1. Pages mostly made of markup or of links score lower.
+#[must_use]
+pub fn page_quality(html_len: usize, text_len: usize, num_tokens: usize, num_links: usize) -> f64 {
+    let text_ratio = if html_len == 0 {
+        1.0
+    } else {
+        text_len as f64 / html_len as f64
+    };
+    let links_per_token = num_links as f64 / num_tokens.max(1) as f64;
+
+    let text = (text_ratio / MIN_TEXT_RATIO).min(1.0);
+    let links = (MAX_LINKS_PER_TOKEN / links_per_token).min(1.0);
+    (text * links).max(MIN_QUALITY)
+}
+
+/// Multiplier of the quality of a page whose title `count` documents share,
+/// as templated or mirrored pages do.
+#[must_use]
+pub fn duplicate_title_penalty(count: u32) -> f64 {
+    1.0 / f64::from(count.max(1)).sqrt()
+}
+
+/// Quality of the documents a build scored below 1, written next to the
+/// postings database so the ranker can weigh them without reading the url
+/// map.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocQuality {
+    pub scores: Vec<(DocID, f32)>,
+}
+
+impl DocQuality {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.quality", db_path.display()))
+    }
+
+    /// The scores at `path`, `None` for indexes built before they were kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn heuristics() {
+        // A plain article
+        assert_eq!(page_quality(1000, 400, 80, 5), 1.0);
+        // Mostly markup
+        assert!((page_quality(10_000, 100, 20, 2) - 0.2).abs() < 1e-9);
+        // A link per word
+        assert!((page_quality(1000, 400, 100, 100) - 0.3).abs() < 1e-9);
+        // Both at once never drop below the floor
+        assert_eq!(page_quality(100_000, 10, 2, 50), MIN_QUALITY);
+
+        assert_eq!(duplicate_title_penalty(1), 1.0);
+        assert_eq!(duplicate_title_penalty(4), 0.5);
+    }
+}
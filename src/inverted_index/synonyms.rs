@@ -0,0 +1,117 @@
+use super::disk_inverted_index::ParsedPage;
+use crate::tokenizer::Tokenizer;
+use std::collections::{BTreeMap, HashMap};
+
+/// Terms a build indexes alongside the terms of documents, at the same
+/// positions, so queries for a synonym match without expanding them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Synonyms {
+    terms: HashMap<String, Vec<String>>,
+}
+
+impl Synonyms {
+    /// Words of `synonyms` go through `tokenizer` like the text of documents,
+    /// so they match whatever form the index stores. Keys analyzing to more
+    /// or less than one term are left out.
+    #[must_use]
+    pub fn new(synonyms: &BTreeMap<String, Vec<String>>, tokenizer: &Tokenizer) -> Self {
+        let mut terms: HashMap<String, Vec<String>> = HashMap::new();
+        for (word, words) in synonyms {
+            let Ok([term]) = <[String; 1]>::try_from(tokenizer.tokenize(word)) else {
+                continue;
+            };
+            let entry = terms.entry(term.clone()).or_default();
+            entry.extend(
+                words
+                    .iter()
+                    .flat_map(|word| tokenizer.tokenize(word))
+                    .filter(|synonym| *synonym != term),
+            );
+            entry.sort_unstable();
+            entry.dedup();
+        }
+        terms.retain(|_, synonyms| !synonyms.is_empty());
+
+        Self { terms }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Adds the synonyms of the terms of `page` to it, counted as often and
+    /// at the same positions as the terms. Synonyms added don't bring their
+    /// own, and the page keeps its number of tokens.
+    pub fn expand(&self, page: &mut ParsedPage) {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut counts = Vec::new();
+        for (term, &count) in &page.word_count {
+            for synonym in self.terms.get(term).into_iter().flatten() {
+                counts.push((synonym.clone(), count));
+            }
+        }
+        for (synonym, count) in counts {
+            *page.word_count.entry(synonym).or_default() += count;
+        }
+
+        let mut positions = Vec::new();
+        for (term, term_positions) in &page.positions {
+            for synonym in self.terms.get(term).into_iter().flatten() {
+                positions.push((synonym.clone(), term_positions.clone()));
+            }
+        }
+        for (synonym, term_positions) in positions {
+            let entry = page.positions.entry(synonym).or_default();
+            entry.extend(term_positions);
+            entry.sort_unstable();
+            entry.dedup();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::parse_page;
+
+    #[test]
+    fn expand() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let synonyms = Synonyms::new(
+            &BTreeMap::from([
+                (
+                    "Cars".to_string(),
+                    vec!["automobile".to_string(), "car".to_string()],
+                ),
+                ("fast food".to_string(), vec!["burger".to_string()]),
+                ("the".to_string(), vec!["the".to_string()]),
+            ]),
+            &tokenizer,
+        );
+        let car = tokenizer.tokenize("car").remove(0);
+        let automobile = tokenizer.tokenize("automobile").remove(0);
+        assert_eq!(synonyms.terms.len(), 1);
+        assert_eq!(synonyms.terms[&car], std::slice::from_ref(&automobile));
+
+        let mut page = parse_page(
+            "https://example.com/",
+            "<p>A red car, an automobile and a blue car</p>",
+            &tokenizer,
+        );
+        let num_tokens = page.num_tokens;
+        let car_positions = page.positions[&car].clone();
+        synonyms.expand(&mut page);
+
+        assert_eq!(page.word_count[&car], 2);
+        assert_eq!(page.word_count[&automobile], 3);
+        let mut positions = page.positions[&automobile].clone();
+        positions.retain(|position| car_positions.contains(position));
+        assert_eq!(positions, car_positions);
+        assert_eq!(page.positions[&automobile].len(), 3);
+        assert_eq!(page.num_tokens, num_tokens);
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// Figures of a term a build keeps next to its postings, so tools can
+/// inspect the index without decoding whole posting lists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TermStats {
+    /// Documents containing the term
+    pub df: u64,
+    /// Weighted occurrences of the term across all documents
+    pub total_tf: u64,
+    /// Highest tf-idf score of the term in any document
+    pub max_tfidf: f64,
+}
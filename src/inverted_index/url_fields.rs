@@ -0,0 +1,120 @@
+use url::{Host, Url};
+
+/// Prefix of the terms `site:` clauses match.
+const SITE_PREFIX: &str = "site:";
+/// Prefix of the terms `url:` clauses match.
+const URL_PREFIX: &str = "url:";
+
+/// Terms indexed for the page at `url` next to the terms of its text.
+///
+/// One is for the url and one for its host and every domain above it, so
+/// `site:` and `url:` clauses match exactly rather than through the
+/// analyzer. Prefixed, they never collide with terms of the text.
+#[must_use]
+pub fn url_field_terms(url: &str) -> Vec<String> {
+    let Some(url) = parse(url) else {
+        return Vec::new();
+    };
+
+    let mut terms: Vec<_> = url_term_of(&url).into_iter().collect();
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            terms.push(format!("{SITE_PREFIX}{domain}"));
+            terms.extend(
+                domain
+                    .match_indices('.')
+                    .map(|(i, _)| format!("{SITE_PREFIX}{}", &domain[i + 1..])),
+            );
+        }
+        Some(host) => terms.push(format!("{SITE_PREFIX}{host}")),
+        None => {}
+    }
+
+    terms
+}
+
+/// The term of pages hosted at `site` or on its subdomains. Hosts are
+/// lowercased and internationalized ones punycoded, and a scheme or path
+/// around the host is ignored. `None` for text holding no host.
+#[must_use]
+pub fn site_term(site: &str) -> Option<String> {
+    let url = parse(site)?;
+    let host = url.host_str()?.trim_end_matches('.');
+
+    Some(format!("{SITE_PREFIX}{host}"))
+}
+
+/// The term of the page at `url`, normalized like the host of
+/// [`site_term`]. Scheme and fragment are ignored, the path and query string
+/// kept as they are. `None` for text holding no host.
+#[must_use]
+pub fn url_term(url: &str) -> Option<String> {
+    url_term_of(&parse(url)?)
+}
+
+fn url_term_of(url: &Url) -> Option<String> {
+    let host = url.host_str()?.trim_end_matches('.');
+    let port = url
+        .port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    let query = url
+        .query()
+        .map(|query| format!("?{query}"))
+        .unwrap_or_default();
+
+    Some(format!("{URL_PREFIX}{host}{port}{}{query}", url.path()))
+}
+
+/// `url` parsed, read as an https url when it has no scheme of its own.
+fn parse(url: &str) -> Option<Url> {
+    let url = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{url}"))
+    };
+
+    url.ok().filter(Url::has_host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_fields() {
+        assert_eq!(
+            url_field_terms("https://Docs.Example.com/Guide?page=2#intro"),
+            [
+                "url:docs.example.com/Guide?page=2",
+                "site:docs.example.com",
+                "site:example.com",
+                "site:com",
+            ]
+        );
+        assert_eq!(
+            url_field_terms("http://127.0.0.1:8080/"),
+            ["url:127.0.0.1:8080/", "site:127.0.0.1"]
+        );
+        assert!(url_field_terms("not a url").is_empty());
+
+        assert_eq!(
+            site_term("EXAMPLE.com").as_deref(),
+            Some("site:example.com")
+        );
+        assert_eq!(
+            site_term("https://bücher.de/shop").as_deref(),
+            Some("site:xn--bcher-kva.de")
+        );
+        assert_eq!(site_term("xn--bcher-kva.de"), site_term("Bücher.de"));
+        assert_eq!(site_term(""), None);
+
+        assert_eq!(url_term("example.com").as_deref(), Some("url:example.com/"));
+        assert_eq!(
+            url_term("http://EXAMPLE.com/Guide#top"),
+            url_term("https://example.com/Guide")
+        );
+        assert_ne!(url_term("example.com/Guide"), url_term("example.com/guide"));
+    }
+}
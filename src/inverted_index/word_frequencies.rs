@@ -0,0 +1,48 @@
+use crate::{
+    error::Result,
+    kv_database::{codec, database::replace_file},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Words of the corpus as written, lowercased but not stemmed, with the
+/// number of documents containing each. A build writes them next to the
+/// postings database for spelling correction.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordFrequencies {
+    /// Sorted by word
+    pub words: Vec<(String, u64)>,
+}
+
+impl WordFrequencies {
+    #[must_use]
+    pub fn path(db_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.words", db_path.display()))
+    }
+
+    /// The words at `path`, `None` for indexes built before they were kept.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(codec::deserialize(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        replace_file(path, &codec::serialize(self)?)
+    }
+}
+
+impl From<HashMap<String, u64>> for WordFrequencies {
+    fn from(frequencies: HashMap<String, u64>) -> Self {
+        let mut words: Vec<_> = frequencies.into_iter().collect();
+        words.sort_unstable();
+        Self { words }
+    }
+}
@@ -0,0 +1,76 @@
+//! The encoding of every index file, pinned rather than left to bincode's
+//! defaults so files move between machines: little-endian, fixed-width
+//! integers, and lengths and `usize`s as `u64`.
+//!
+//! A db file is a [`FileHeader`](super::header::FileHeader) followed by
+//! records of `(key, value_len: u64)` and `value_len` bytes of value. A seek
+//! file is a header followed by the map of keys to value positions.
+
+use bincode::{DefaultOptions, Options};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+
+fn options() -> impl Options {
+    DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        // Records are decoded from the front of larger buffers
+        .allow_trailing_bytes()
+}
+
+pub fn serialize<T: ?Sized + Serialize>(value: &T) -> bincode::Result<Vec<u8>> {
+    options().serialize(value)
+}
+
+pub fn serialize_into<W: Write, T: ?Sized + Serialize>(
+    writer: W,
+    value: &T,
+) -> bincode::Result<()> {
+    options().serialize_into(writer, value)
+}
+
+pub fn serialized_size<T: ?Sized + Serialize>(value: &T) -> bincode::Result<u64> {
+    options().serialized_size(value)
+}
+
+pub fn deserialize<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> bincode::Result<T> {
+    options().deserialize(bytes)
+}
+
+/// Reads at most `limit` bytes, so a corrupt length prefix fails instead of
+/// allocating whatever it claims.
+pub fn deserialize_from<R: Read, T: DeserializeOwned>(reader: R, limit: u64) -> bincode::Result<T> {
+    options().with_limit(limit).deserialize_from(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{header::FileHeader, seek_pos_map::SeekPos};
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn pinned_encoding() {
+        let record = serialize(&("ab", 3_u64)).expect("Failed to serialize");
+        assert_eq!(
+            record,
+            [2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b', 3, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let seek_pos = serialize(&SeekPos::new(1, 0x0102)).expect("Failed to serialize");
+        assert_eq!(seek_pos, [1, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+
+        let posting = serialize(&(7_u64, 1.5_f64)).expect("Failed to serialize");
+        assert_eq!(
+            posting,
+            [7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f]
+        );
+
+        let header = FileHeader::new(FileHeader::DB_MAGIC, Uuid::from_u128(1))
+            .to_bytes()
+            .expect("Failed to serialize");
+        assert_eq!(header.len(), FileHeader::LEN);
+        assert_eq!(&header[..8], b"SEDB\x01\0\0\0");
+        assert_eq!(header[8], 1);
+    }
+}
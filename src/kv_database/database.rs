@@ -4,20 +4,37 @@ use std::io::Read;
 use std::{
     collections::HashMap,
     fmt::Display,
-    fs::{remove_file, rename, File},
+    fs::{remove_file, File},
     hash::Hash,
-    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+    io::{BufReader, BufWriter, ErrorKind, Seek, Write},
     marker::PhantomData,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use uuid::Uuid;
 
+use super::codec;
+use super::header::FileHeader;
+use super::read_at::ReadAt;
 use super::seek_pos_map::SeekPos;
-use super::{constants::TEMP_FILE_SUFFIX, seek_pos_map::SeekPosMap};
-
+use super::{scratch, seek_pos_map::SeekPosMap};
+
+/// Length in the header of a record marking its key removed, which no value
+/// is long enough to have.
+const TOMBSTONE: u64 = u64::MAX;
+
+/// A database whose values are read from `R`. The default is the on-disk
+/// file, which is also the only variant that supports writes.
+///
+/// Keys and values are stored as bincode, which length-prefixes strings and
+/// sequences, and values are located through the seek map rather than
+/// delimiters, so any bytes are safe in either.
+///
+/// Both files start with a header naming the build that wrote them, and
+/// opening a db with the seek file of another build fails.
 #[derive(Debug)]
-pub struct KVDatabase<K, V>
+pub struct KVDatabase<K, V, R = File>
 where
     K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
     V: Serialize + for<'de> Deserialize<'de> + Clone,
@@ -25,59 +42,303 @@ where
     db_path: PathBuf,
     seek_path: PathBuf,
     pub seek_pos_map: SeekPosMap<K>,
-    pub database: BufReader<File>,
+    pub database: R,
+    /// Size of `database` when it was opened, which no record may run past
+    database_len: u64,
+    build_id: Uuid,
     _marker: PhantomData<V>,
 }
 
-impl<K, V> KVDatabase<K, V>
+pub type MemoryKVDatabase<K, V> = KVDatabase<K, V, Vec<u8>>;
+
+impl<K, V> MemoryKVDatabase<K, V>
 where
     K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
     V: Serialize + for<'de> Deserialize<'de> + Clone,
 {
-    pub fn new(db_path: PathBuf, seek_path: PathBuf) -> Result<Self> {
-        let seek_pos_map: SeekPosMap<K> = SeekPosMap::new();
+    /// Opens a read-only database from the contents of a db and seek file, for
+    /// targets without a filesystem.
+    pub fn from_bytes(db: Vec<u8>, seek: &[u8]) -> Result<Self> {
+        let seek_file = parse_seek_file(Path::new(""), seek)?;
+        Self::assemble(PathBuf::new(), PathBuf::new(), db, seek_file)
+    }
+}
+
+impl<K, V, R> KVDatabase<K, V, R>
+where
+    K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+    V: Serialize + for<'de> Deserialize<'de> + Clone,
+    R: ReadAt,
+{
+    /// Pairs an opened db with its seek map, checking that both were written
+    /// by the same build.
+    fn assemble(
+        db_path: PathBuf,
+        seek_path: PathBuf,
+        database: R,
+        (build_id, seek_pos_map): (Uuid, SeekPosMap<K>),
+    ) -> Result<Self> {
+        let database_len = database.size()?;
+
+        let mut header = [0; FileHeader::LEN];
+        let db_header = if database_len >= FileHeader::LEN as u64 {
+            database.read_exact_at(&mut header, 0)?;
+            FileHeader::parse(&header, FileHeader::DB_MAGIC)?
+        } else {
+            None
+        };
 
-        let serialized = bincode::serialize(&seek_pos_map)?;
-        let mut file = File::create(&seek_path)?;
-        file.write_all(&serialized)?;
+        if db_header.map_or_else(Uuid::nil, |header| header.build_id()) != build_id {
+            return Err(Error::MixedBuild { path: seek_path });
+        }
 
         Ok(Self {
-            database: BufReader::new(File::create(&db_path)?),
             db_path,
             seek_path,
             seek_pos_map,
+            database,
+            database_len,
+            build_id,
             _marker: PhantomData,
         })
     }
+}
 
-    pub fn from(db_path: PathBuf, seek_path: PathBuf) -> Result<Self> {
-        let mut file = File::open(&seek_path)?;
+impl<K, V, R> KVDatabase<K, V, R>
+where
+    K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+    V: Serialize + for<'de> Deserialize<'de> + Clone,
+    R: ReadAt,
+{
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        self.get_with_buffer(key, &mut Vec::new())
+    }
+
+    /// Same as [`KVDatabase::get`], reading the serialized value into `buffer`
+    /// instead of a fresh allocation so hot paths can reuse it across lookups.
+    pub fn get_with_buffer(&self, key: &K, buffer: &mut Vec<u8>) -> Result<Option<V>> {
+        let Some(seek_pos) = self.seek_pos_map.get(key) else {
+            return Ok(None);
+        };
+
+        read_record(self.source(), key, seek_pos, buffer)?;
+
+        decode_record(key, seek_pos, buffer).map(Some)
+    }
+
+    /// Size of the serialized value of `key`, known without reading it.
+    pub fn value_len(&self, key: &K) -> Option<u64> {
+        self.seek_pos_map.get(key).map(|seek_pos| seek_pos.len)
+    }
+
+    /// Reads the bytes of the serialized value of `key` from `offset` into
+    /// `buffer`, without the rest of the value. Returns `false` for keys not
+    /// in the database.
+    pub fn read_value_at(&self, key: &K, offset: u64, buffer: &mut [u8]) -> Result<bool> {
+        let Some(seek_pos) = self.seek_pos_map.get(key) else {
+            return Ok(false);
+        };
+
+        let end = offset.checked_add(buffer.len() as u64);
+        if end.is_none_or(|end| end > seek_pos.len) {
+            return Err(record_error(
+                key,
+                seek_pos,
+                Error::Corrupt {
+                    path: self.db_path.clone(),
+                    offset: seek_pos.pos.saturating_add(offset),
+                },
+            ));
+        }
+        let part = SeekPos::new(seek_pos.pos + offset, buffer.len() as u64);
+        let mut bytes = Vec::new();
+        read_record(self.source(), key, &part, &mut bytes)?;
+        buffer.copy_from_slice(&bytes);
+
+        Ok(true)
+    }
+
+    /// The serialized value of `key` at `seek_pos`.
+    fn read_bytes(&self, key: &K, seek_pos: &SeekPos) -> Result<Vec<u8>> {
         let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        let seek_pos_map: SeekPosMap<K> = bincode::deserialize(&buffer)?;
+        read_record(self.source(), key, seek_pos, &mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// The database file as [`read_record`] takes it: handle, path and length.
+    pub(super) fn source(&self) -> (&R, &Path, u64) {
+        (&self.database, &self.db_path, self.database_len)
+    }
+
+    /// ID of the build that wrote the database, nil for databases written
+    /// before builds had one.
+    pub const fn build_id(&self) -> Uuid {
+        self.build_id
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Checks that the database is readable and that every seek position lies
+    /// within it.
+    pub fn verify(&self) -> Result<()> {
+        let len = self.database.size()?;
+
+        for (key, seek_pos) in &self.seek_pos_map {
+            let end = seek_pos.pos.checked_add(seek_pos.len);
+            if end.is_none_or(|end| end > len) {
+                return Err(record_error(
+                    key,
+                    seek_pos,
+                    Error::Corrupt {
+                        path: self.db_path.clone(),
+                        offset: len,
+                    },
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V> KVDatabase<K, V>
+where
+    K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+    V: Serialize + for<'de> Deserialize<'de> + Clone,
+{
+    pub fn new(db_path: PathBuf, seek_path: PathBuf) -> Result<Self> {
+        Self::with_build_id(db_path, seek_path, Uuid::new_v4())
+    }
+
+    /// Creates an empty database belonging to the build `build_id`, for files
+    /// written together that must be opened together.
+    pub fn with_build_id(db_path: PathBuf, seek_path: PathBuf, build_id: Uuid) -> Result<Self> {
+        let seek_pos_map: SeekPosMap<K> = SeekPosMap::new();
+
+        write_seek_file(&seek_path, build_id, &seek_pos_map)?;
+
+        // Unlinked rather than truncated, so readers of the old files keep them
+        match remove_file(&db_path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let header = FileHeader::new(FileHeader::DB_MAGIC, build_id).to_bytes()?;
+        let mut database = File::create(&db_path)?;
+        database.write_all(&header)?;
 
         Ok(Self {
-            database: BufReader::new(File::open(&db_path)?),
+            database,
+            database_len: header.len() as u64,
             db_path,
             seek_path,
             seek_pos_map,
+            build_id,
             _marker: PhantomData,
         })
     }
 
-    pub fn get(&mut self, key: &K) -> Result<Option<V>> {
-        if let Some(seek_pos) = self.seek_pos_map.get(key) {
-            self.database.seek(SeekFrom::Start(seek_pos.pos))?;
-
-            let mut buffer = vec![0; seek_pos.len as usize];
-            self.database.read_exact(&mut buffer)?;
+    /// Opens an existing database. A missing or corrupt seek file is an
+    /// error, left for [`KVDatabase::rebuild_seek_from_db`] to repair since
+    /// openers may only have read access.
+    pub fn from(db_path: PathBuf, seek_path: PathBuf) -> Result<Self> {
+        let seek_file = read_seek_file(&seek_path)?;
+        let database = File::open(&db_path)?;
 
-            let value: V = bincode::deserialize(&buffer)?;
+        Self::assemble(db_path, seek_path, database, seek_file)
+    }
 
-            Ok(Some(value))
+    /// Regenerates the seek file by scanning the record headers of the
+    /// database, returning the database along with how many bytes at its end
+    /// were left out. Scanning stops at the first record that can't be read.
+    pub fn rebuild_seek_from_db(db_path: PathBuf, seek_path: PathBuf) -> Result<(Self, u64)> {
+        let database = File::open(&db_path)?;
+        let database_len = database.size()?;
+        let mut reader = BufReader::new(&database);
+        let mut seek_pos_map = SeekPosMap::new();
+
+        let mut header = [0; FileHeader::LEN];
+        let db_header = if database_len >= FileHeader::LEN as u64 {
+            reader.read_exact(&mut header)?;
+            FileHeader::parse(&header, FileHeader::DB_MAGIC)?
+        } else {
+            None
+        };
+        let build_id = db_header.map_or_else(Uuid::nil, |header| header.build_id());
+        let mut pos = if db_header.is_some() {
+            FileHeader::LEN as u64
         } else {
-            Ok(None)
+            reader.rewind()?;
+            0
+        };
+
+        while pos < database_len {
+            let header = codec::deserialize_from::<_, (K, u64)>(&mut reader, database_len - pos);
+            let Ok((key, len)) = header else {
+                break;
+            };
+            let value_pos = pos + codec::serialized_size(&(&key, len))?;
+            if len == TOMBSTONE {
+                seek_pos_map.remove(&key);
+                pos = value_pos;
+                continue;
+            }
+            let end = value_pos
+                .checked_add(len)
+                .filter(|&end| end <= database_len);
+            let (Some(end), Ok(skip)) = (end, i64::try_from(len)) else {
+                break;
+            };
+
+            reader.seek_relative(skip)?;
+            seek_pos_map.insert(key, SeekPos::new(value_pos, len));
+            pos = end;
+        }
+
+        write_seek_file(&seek_path, build_id, &seek_pos_map)?;
+
+        let database = Self {
+            database_len,
+            database,
+            db_path,
+            seek_path,
+            seek_pos_map,
+            build_id,
+            _marker: PhantomData,
+        };
+
+        Ok((database, database_len - pos))
+    }
+
+    /// Removes the records of `keys`, returning how many there were. Their
+    /// bytes stay in the db file until it is compacted, behind tombstones
+    /// that keep a rebuilt seek file from bringing them back.
+    pub fn remove(&mut self, keys: &[K]) -> Result<usize> {
+        let keys: Vec<_> = keys
+            .iter()
+            .filter(|key| self.seek_pos_map.contains_key(key))
+            .cloned()
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut writer = BufWriter::new(File::options().append(true).open(&self.db_path)?);
+        for key in &keys {
+            writer.write_all(&codec::serialize(&(key, TOMBSTONE))?)?;
         }
+        writer.flush()?;
+
+        for key in &keys {
+            self.seek_pos_map.remove(key);
+        }
+        write_seek_file(&self.seek_path, self.build_id, &self.seek_pos_map)?;
+        self.database_len = self.database.size()?;
+
+        Ok(keys.len())
     }
 
     pub fn insert(&mut self, hashmap: HashMap<K, V>) -> Result<()> {
@@ -85,50 +346,75 @@ where
             return Ok(());
         }
 
-        let temp_db_path = self.db_path.with_extension(TEMP_FILE_SUFFIX);
-        let mut temp_db_writer = BufWriter::new(File::create(&temp_db_path)?);
+        let temp_db_path = scratch::temp_path(&self.db_path);
+        let mut temp_db_writer = RecordWriter::create(&temp_db_path, self.build_id)?;
 
         let mut new_seek_pos_map: HashMap<K, SeekPos> = SeekPosMap::new();
 
         // Copy the old values to the new file
         for (key, seek_pos) in &self.seek_pos_map {
-            if !hashmap.contains_key(&key) {
-                self.database.seek(SeekFrom::Start(seek_pos.pos))?;
-                let mut buffer = vec![0; seek_pos.len as usize];
-                self.database.read_exact(&mut buffer)?;
-                new_seek_pos_map.insert(
-                    key.clone(),
-                    SeekPos::new(temp_db_writer.stream_position()?, seek_pos.len),
-                );
-
-                temp_db_writer.write_all(&buffer)?;
+            if !hashmap.contains_key(key) {
+                let buffer = self.read_bytes(key, seek_pos)?;
+                new_seek_pos_map.insert(key.clone(), temp_db_writer.write(key, &buffer)?);
             }
         }
 
         // Insert the new values
         for (key, value) in hashmap {
-            let value = bincode::serialize(&value)?;
-            new_seek_pos_map.insert(
-                key,
-                SeekPos::new(temp_db_writer.stream_position()?, value.len() as u64),
-            );
+            let seek_pos = temp_db_writer.write(&key, &codec::serialize(&value)?)?;
+            new_seek_pos_map.insert(key, seek_pos);
+        }
+
+        temp_db_writer.finish()?;
+        self.swap_in(&temp_db_path, new_seek_pos_map)
+    }
+
+    /// Rewrites the database with only the records its seek map points at,
+    /// passing every value through `f`, which drops the record by returning
+    /// `None`. Returns how many bytes the db file shrank by.
+    pub fn compact_with<F>(&mut self, f: F) -> Result<u64>
+    where
+        F: FnMut(&K, V) -> Option<V>,
+    {
+        self.rewrite_with(self.build_id, f)
+    }
 
-            temp_db_writer.write_all(&value)?;
+    /// Like [`KVDatabase::compact_with`], but moves the database to the build
+    /// `build_id`. Rewriting a file written before headers existed upgrades
+    /// it to the current format.
+    pub fn rewrite_with<F>(&mut self, build_id: Uuid, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&K, V) -> Option<V>,
+    {
+        let old_len = self.database_len;
+        let temp_db_path = scratch::temp_path(&self.db_path);
+        let mut temp_db_writer = RecordWriter::create(&temp_db_path, build_id)?;
+
+        let mut new_seek_pos_map: HashMap<K, SeekPos> = SeekPosMap::new();
+
+        for (key, seek_pos) in &self.seek_pos_map {
+            let value = decode_record(key, seek_pos, &self.read_bytes(key, seek_pos)?)?;
+            if let Some(value) = f(key, value) {
+                let seek_pos = temp_db_writer.write(key, &codec::serialize(&value)?)?;
+                new_seek_pos_map.insert(key.clone(), seek_pos);
+            }
         }
 
-        temp_db_writer.flush()?;
+        temp_db_writer.finish()?;
+        self.build_id = build_id;
+        self.swap_in(&temp_db_path, new_seek_pos_map)?;
 
-        // Write the new seek_pos_map to the self.seek_path
-        let serialized = bincode::serialize(&new_seek_pos_map)?;
-        let mut file = File::create(&self.seek_path)?;
-        file.write_all(&serialized)?;
+        Ok(old_len.saturating_sub(self.database_len))
+    }
 
-        // Remove the old db file and rename the temp_db file
-        remove_file(&self.db_path)?;
-        rename(temp_db_path, &self.db_path)?;
+    /// Replaces the database with the finished file at `temp_db_path`.
+    fn swap_in(&mut self, temp_db_path: &Path, seek_pos_map: SeekPosMap<K>) -> Result<()> {
+        scratch::persist(temp_db_path, &self.db_path)?;
+        write_seek_file(&self.seek_path, self.build_id, &seek_pos_map)?;
 
-        self.database = BufReader::new(File::open(&self.db_path)?);
-        self.seek_pos_map = new_seek_pos_map;
+        self.database = File::open(&self.db_path)?;
+        self.database_len = self.database.size()?;
+        self.seek_pos_map = seek_pos_map;
 
         Ok(())
     }
@@ -144,33 +430,24 @@ where
             return Ok(());
         }
 
-        let temp_db_path = self.db_path.with_extension(TEMP_FILE_SUFFIX);
-        let mut temp_db_writer = BufWriter::new(File::create(&temp_db_path)?);
+        let temp_db_path = scratch::temp_path(&self.db_path);
+        let mut temp_db_writer = RecordWriter::create(&temp_db_path, self.build_id)?;
 
         let mut new_seek_pos_map: HashMap<K, SeekPos> = SeekPosMap::new();
 
         // Copy the old values to the new file
         for (key, seek_pos) in &self.seek_pos_map {
-            if !hashmap.contains_key(&key) {
-                self.database.seek(SeekFrom::Start(seek_pos.pos))?;
-                let mut buffer = vec![0; seek_pos.len as usize];
-                self.database.read_exact(&mut buffer)?;
-                new_seek_pos_map.insert(
-                    key.clone(),
-                    SeekPos::new(temp_db_writer.stream_position()?, seek_pos.len),
-                );
-
-                temp_db_writer.write_all(&buffer)?;
+            if !hashmap.contains_key(key) {
+                let buffer = self.read_bytes(key, seek_pos)?;
+                new_seek_pos_map.insert(key.clone(), temp_db_writer.write(key, &buffer)?);
             }
         }
 
         // Insert the new values
         for (key, value) in hashmap {
             let new_value = if let Some(seek_pos) = self.seek_pos_map.get(&key) {
-                self.database.seek(SeekFrom::Start(seek_pos.pos))?;
-                let mut buffer = vec![0; seek_pos.len as usize];
-                self.database.read_exact(&mut buffer)?;
-                let mut old_value: V = bincode::deserialize(&buffer)?;
+                let mut old_value: V =
+                    decode_record(&key, seek_pos, &self.read_bytes(&key, seek_pos)?)?;
                 old_value.extend(value);
 
                 old_value
@@ -178,41 +455,207 @@ where
                 value
             };
 
-            let value = bincode::serialize(&new_value)?;
-            new_seek_pos_map.insert(
-                key,
-                SeekPos::new(temp_db_writer.stream_position()?, value.len() as u64),
-            );
-
-            temp_db_writer.write_all(&value)?;
+            let seek_pos = temp_db_writer.write(&key, &codec::serialize(&new_value)?)?;
+            new_seek_pos_map.insert(key, seek_pos);
         }
 
-        temp_db_writer.flush()?;
+        temp_db_writer.finish()?;
+        self.swap_in(&temp_db_path, new_seek_pos_map)
+    }
+}
 
-        // Write the new seek_pos_map to the self.seek_path
-        let serialized = bincode::serialize(&new_seek_pos_map)?;
-        let mut file = File::create(&self.seek_path)?;
-        file.write_all(&serialized)?;
+/// Appends records to a new database file, each value behind a header with
+/// its key and length so the seek map can be rebuilt from the file alone.
+struct RecordWriter {
+    writer: BufWriter<File>,
+    pos: u64,
+}
 
-        remove_file(&self.db_path)?;
-        rename(temp_db_path, &self.db_path)?;
+impl RecordWriter {
+    fn create(path: &Path, build_id: Uuid) -> Result<Self> {
+        let header = FileHeader::new(FileHeader::DB_MAGIC, build_id).to_bytes()?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&header)?;
 
-        self.database = BufReader::new(File::open(&self.db_path)?);
-        self.seek_pos_map = new_seek_pos_map;
+        Ok(Self {
+            writer,
+            pos: header.len() as u64,
+        })
+    }
+
+    /// Writes the record of `key` and returns where its value starts.
+    fn write<K: Serialize>(&mut self, key: &K, value: &[u8]) -> Result<SeekPos> {
+        let header = codec::serialize(&(key, value.len() as u64))?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(value)?;
+
+        let seek_pos = SeekPos::new(self.pos + header.len() as u64, value.len() as u64);
+        self.pos = seek_pos.pos + seek_pos.len;
+
+        Ok(seek_pos)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Writes a new database in one pass, for builds producing every record in turn.
+///
+/// Records go straight to the file rather than through a rewrite per batch,
+/// and the seek file is written once they are all in.
+pub struct KVDatabaseWriter<K, V> {
+    records: RecordWriter,
+    seek_path: PathBuf,
+    seek_pos_map: SeekPosMap<K>,
+    build_id: Uuid,
+    _marker: PhantomData<V>,
+}
+
+impl<K, V> KVDatabaseWriter<K, V>
+where
+    K: Serialize + Eq + Hash,
+    V: Serialize,
+{
+    /// Starts the database at `db_path` belonging to the build `build_id`. Its
+    /// seek file at `seek_path` is left as it is until [`Self::finish`].
+    pub fn create(db_path: &Path, seek_path: PathBuf, build_id: Uuid) -> Result<Self> {
+        Ok(Self {
+            records: RecordWriter::create(db_path, build_id)?,
+            seek_path,
+            seek_pos_map: SeekPosMap::new(),
+            build_id,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Appends the record of `key`, which replaces any written before it.
+    pub fn write(&mut self, key: K, value: &V) -> Result<()> {
+        let seek_pos = self.records.write(&key, &codec::serialize(value)?)?;
+        self.seek_pos_map.insert(key, seek_pos);
 
         Ok(())
     }
+
+    /// Flushes the records and writes the seek file pointing at them.
+    pub fn finish(self) -> Result<()> {
+        self.records.finish()?;
+        write_seek_file(&self.seek_path, self.build_id, &self.seek_pos_map)
+    }
+}
+
+fn read_seek_file<K>(seek_path: &Path) -> Result<(Uuid, SeekPosMap<K>)>
+where
+    K: for<'de> Deserialize<'de> + Eq + Hash,
+{
+    let mut buffer = Vec::new();
+    File::open(seek_path)?.read_to_end(&mut buffer)?;
+
+    parse_seek_file(seek_path, &buffer)
+}
+
+/// The build ID and seek map stored in `bytes`, read from `seek_path`.
+fn parse_seek_file<K>(seek_path: &Path, bytes: &[u8]) -> Result<(Uuid, SeekPosMap<K>)>
+where
+    K: for<'de> Deserialize<'de> + Eq + Hash,
+{
+    let (build_id, entries) = FileHeader::parse(bytes, FileHeader::SEEK_MAGIC)?
+        .map_or((Uuid::nil(), bytes), |header| {
+            (header.build_id(), &bytes[FileHeader::LEN..])
+        });
+    let seek_pos_map = codec::deserialize(entries).map_err(|source| Error::KeyDecode {
+        path: seek_path.to_path_buf(),
+        source,
+    })?;
+
+    Ok((build_id, seek_pos_map))
+}
+
+fn write_seek_file<K: Serialize>(
+    seek_path: &Path,
+    build_id: Uuid,
+    seek_pos_map: &SeekPosMap<K>,
+) -> Result<()> {
+    let mut bytes = FileHeader::new(FileHeader::SEEK_MAGIC, build_id).to_bytes()?;
+    codec::serialize_into(&mut bytes, seek_pos_map)?;
+
+    replace_file(seek_path, &bytes)
+}
+
+/// Reads the serialized value of `key` at `seek_pos` into `buffer`, checking
+/// first that it lies within the `database_len` bytes of `database`.
+pub(super) fn read_record<K: Display, R: ReadAt>(
+    (database, database_path, database_len): (&R, &Path, u64),
+    key: &K,
+    seek_pos: &SeekPos,
+    buffer: &mut Vec<u8>,
+) -> Result<()> {
+    let end = seek_pos.pos.checked_add(seek_pos.len);
+    if end.is_none_or(|end| end > database_len) {
+        return Err(record_error(
+            key,
+            seek_pos,
+            Error::Corrupt {
+                path: database_path.to_path_buf(),
+                offset: database_len,
+            },
+        ));
+    }
+
+    buffer.clear();
+    buffer.resize(seek_pos.len as usize, 0);
+    database
+        .read_exact_at(buffer, seek_pos.pos)
+        .map_err(|e| record_error(key, seek_pos, e))
+}
+
+pub(super) fn decode_record<K: Display, V: for<'de> Deserialize<'de>>(
+    key: &K,
+    seek_pos: &SeekPos,
+    bytes: &[u8],
+) -> Result<V> {
+    codec::deserialize(bytes).map_err(|e| record_error(key, seek_pos, e))
+}
+
+fn record_error<K: Display>(key: &K, seek_pos: &SeekPos, source: impl Into<Error>) -> Error {
+    Error::Record {
+        key: key.to_string(),
+        offset: seek_pos.pos,
+        source: Box::new(source.into()),
+    }
+}
+
+/// Writes `bytes` to a temporary file renamed over `path`, so the file is
+/// replaced as a whole and readers that already opened it keep the old one.
+pub(crate) fn replace_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let temp_path = scratch::temp_path(path);
+
+    File::create(&temp_path)?.write_all(bytes)?;
+    scratch::persist(&temp_path, path)
+}
+
+/// Whether the database at `db_path` starts with a file header, which files
+/// written before headers existed lack.
+pub fn has_header(db_path: &Path) -> Result<bool> {
+    let mut header = Vec::with_capacity(FileHeader::LEN);
+    File::open(db_path)?
+        .take(FileHeader::LEN as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(FileHeader::parse(&header, FileHeader::DB_MAGIC)?.is_some())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::test_util::TestDir;
     use tests::KVDatabase;
 
     use super::*;
 
     #[test]
     fn basic_str() {
-        let db_path = PathBuf::from("tests/basic_str.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("basic_str.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -231,7 +674,8 @@ mod tests {
 
     #[test]
     fn basic_int() {
-        let db_path = PathBuf::from("tests/basic_int.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("basic_int.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -248,9 +692,238 @@ mod tests {
         );
     }
 
+    #[test]
+    fn writer() {
+        let dir = TestDir::new();
+        let db_path = dir.path("writer.db");
+        let seek_path = db_path.with_extension("seek");
+        let build_id = Uuid::new_v4();
+
+        let mut writer = KVDatabaseWriter::create(&db_path, seek_path.clone(), build_id)
+            .expect("Failed to create writer");
+        writer
+            .write("hello".to_string(), &vec![1, 2, 3])
+            .expect("Failed to write value");
+        writer
+            .write("world".to_string(), &vec![4])
+            .expect("Failed to write value");
+        writer
+            .write("hello".to_string(), &vec![5])
+            .expect("Failed to write value");
+        writer.finish().expect("Failed to finish database");
+
+        let db: KVDatabase<String, Vec<i32>> =
+            KVDatabase::from(db_path, seek_path).expect("Failed to open database");
+        assert_eq!(db.build_id(), build_id);
+        assert_eq!(db.seek_pos_map.len(), 2);
+        assert_eq!(
+            db.get(&"hello".to_string()).expect("Failed to get value"),
+            Some(vec![5])
+        );
+        assert_eq!(
+            db.get(&"world".to_string()).expect("Failed to get value"),
+            Some(vec![4])
+        );
+    }
+
+    #[test]
+    fn get_with_buffer() {
+        let dir = TestDir::new();
+        let db_path = dir.path("get_with_buffer.db");
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("hello".to_string(), vec![1, 2, 3, 4]);
+        hashmap.insert("world".to_string(), vec![5]);
+
+        db.extend(hashmap).expect("Failed to insert hashmap");
+
+        let mut buffer = Vec::new();
+        for (key, value) in [("hello", Some(vec![1, 2, 3, 4])), ("world", Some(vec![5]))] {
+            assert_eq!(
+                db.get_with_buffer(&key.to_string(), &mut buffer)
+                    .expect("Failed to get value"),
+                value
+            );
+        }
+        assert_eq!(
+            db.get_with_buffer(&"missing".to_string(), &mut buffer)
+                .expect("Failed to get value"),
+            None
+        );
+    }
+
+    #[test]
+    fn rebuild_seek_from_db() {
+        let dir = TestDir::new();
+        let db_path = dir.path("rebuild_seek.db");
+        let seek_path = db_path.with_extension("seek");
+
+        let mut db = KVDatabase::new(db_path.clone(), seek_path.clone())
+            .expect("Failed to create DiskHashMap");
+        let mut hashmap = HashMap::new();
+        hashmap.insert("hello".to_string(), vec![1, 2, 3]);
+        hashmap.insert("world".to_string(), vec![4, 5, 6]);
+        db.insert(hashmap.clone())
+            .expect("Failed to insert hashmap");
+        db.extend(HashMap::from([("hello".to_string(), vec![7])]))
+            .expect("Failed to extend hashmap");
+        hashmap.insert("hello".to_string(), vec![1, 2, 3, 7]);
+
+        let read_all = |db: &KVDatabase<String, Vec<i32>>| {
+            db.iter()
+                .collect::<Result<HashMap<_, _>>>()
+                .expect("Failed to read values")
+        };
+
+        // Missing and corrupt seek files fail to open and are left as they are
+        remove_file(&seek_path).expect("Failed to remove seek file");
+        assert!(matches!(
+            KVDatabase::<String, Vec<i32>>::from(db_path.clone(), seek_path.clone()),
+            Err(Error::IO(e)) if e.kind() == ErrorKind::NotFound
+        ));
+        assert!(!seek_path.exists());
+        let (db, truncated) = KVDatabase::<String, Vec<i32>>::rebuild_seek_from_db(
+            db_path.clone(),
+            seek_path.clone(),
+        )
+        .expect("Failed to rebuild seek file");
+        assert_eq!(read_all(&db), hashmap);
+        assert_eq!(truncated, 0);
+
+        std::fs::write(&seek_path, [0xff; 3]).expect("Failed to corrupt seek file");
+        assert!(matches!(
+            KVDatabase::<String, Vec<i32>>::from(db_path.clone(), seek_path.clone()),
+            Err(Error::KeyDecode { .. })
+        ));
+        assert_eq!(
+            std::fs::read(&seek_path).expect("Failed to read seek file"),
+            [0xff; 3]
+        );
+        let (db, truncated) = KVDatabase::<String, Vec<i32>>::rebuild_seek_from_db(
+            db_path.clone(),
+            seek_path.clone(),
+        )
+        .expect("Failed to rebuild seek file");
+        assert_eq!(read_all(&db), hashmap);
+        assert_eq!(truncated, 0);
+        db.verify().expect("Rebuilt database should be consistent");
+
+        // A record cut short is left out, and its bytes reported
+        let len = db.database.size().expect("Failed to read db size");
+        let (key, last) = db
+            .seek_pos_map
+            .iter()
+            .find(|(_, seek_pos)| seek_pos.pos + seek_pos.len == len)
+            .expect("Last record should be in the seek file");
+        let last_start = last.pos
+            - codec::serialized_size(&(key, last.len)).expect("Failed to size record header");
+        File::options()
+            .write(true)
+            .open(&db_path)
+            .and_then(|file| file.set_len(len - 1))
+            .expect("Failed to truncate database");
+        let (db, truncated) =
+            KVDatabase::<String, Vec<i32>>::rebuild_seek_from_db(db_path, seek_path)
+                .expect("Failed to rebuild seek file");
+        assert_eq!(read_all(&db).len(), 1);
+        assert_eq!(truncated, len - 1 - last_start);
+    }
+
+    #[test]
+    fn remove() {
+        let dir = TestDir::new();
+        let db_path = dir.path("remove.db");
+        let seek_path = db_path.with_extension("seek");
+
+        let mut db = KVDatabase::new(db_path.clone(), seek_path.clone())
+            .expect("Failed to create DiskHashMap");
+        db.insert(HashMap::from([
+            ("hello".to_string(), vec![1, 2, 3]),
+            ("world".to_string(), vec![4, 5, 6]),
+        ]))
+        .expect("Failed to insert hashmap");
+
+        let removed = db
+            .remove(&["hello".to_string(), "missing".to_string()])
+            .expect("Failed to remove keys");
+        assert_eq!(removed, 1);
+        assert_eq!(db.get(&"hello".to_string()).expect("Failed to get"), None);
+        assert_eq!(
+            db.get(&"world".to_string()).expect("Failed to get"),
+            Some(vec![4, 5, 6])
+        );
+
+        // Tombstones outlive the seek file
+        let (db, _) = KVDatabase::<String, Vec<i32>>::rebuild_seek_from_db(
+            db_path.clone(),
+            seek_path.clone(),
+        )
+        .expect("Failed to rebuild seek file");
+        assert_eq!(db.seek_pos_map.len(), 1);
+        assert_eq!(db.get(&"hello".to_string()).expect("Failed to get"), None);
+
+        // Compaction reclaims the removed record and its tombstone
+        let mut db = KVDatabase::<String, Vec<i32>>::from(db_path, seek_path)
+            .expect("Failed to open database");
+        assert!(
+            db.compact_with(|_, value| Some(value))
+                .expect("Failed to compact")
+                > 0
+        );
+        assert_eq!(
+            db.get(&"world".to_string()).expect("Failed to get"),
+            Some(vec![4, 5, 6])
+        );
+    }
+
+    #[test]
+    fn adversarial_keys() {
+        let dir = TestDir::new();
+        let db_path = dir.path("adversarial_keys.db");
+        let keys = [
+            "",
+            "line\nbreak",
+            "\r\n",
+            "key:value|other,field",
+            "nul\0byte",
+            "\u{feff}ünïcødé",
+        ];
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+        db.extend(
+            keys.iter()
+                .map(|key| (key.to_string(), vec![key.to_string()]))
+                .collect(),
+        )
+        .expect("Failed to insert hashmap");
+        db.extend(
+            keys.iter()
+                .map(|key| (key.to_string(), vec![format!("{key}\n,:|")]))
+                .collect(),
+        )
+        .expect("Failed to extend hashmap");
+
+        let db: KVDatabase<String, Vec<String>> =
+            KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
+                .expect("Failed to restore DiskHashMap from path");
+
+        assert_eq!(db.iter().count(), keys.len());
+        for key in keys {
+            assert_eq!(
+                db.get(&key.to_string()).expect("Failed to get value"),
+                Some(vec![key.to_string(), format!("{key}\n,:|")])
+            );
+        }
+    }
+
     #[test]
     fn restore_from_path() {
-        let db_path = PathBuf::from("tests/restore_from_path.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("restore_from_path.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -262,7 +935,7 @@ mod tests {
         db.extend(hashmap.clone())
             .expect("Failed to insert hashmap");
 
-        let mut db2 = KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
+        let db2 = KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to restore DiskHashMap from path");
 
         assert_eq!(
@@ -277,7 +950,8 @@ mod tests {
 
     #[test]
     fn extend() {
-        let db_path = PathBuf::from("tests/extend.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("extend.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -311,7 +985,8 @@ mod tests {
 
     #[test]
     fn iterator() {
-        let db_path = PathBuf::from("tests/iterator.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("iterator.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -323,7 +998,7 @@ mod tests {
         db.extend(hashmap.clone())
             .expect("Failed to insert hashmap");
 
-        let mut iter = db.into_iter();
+        let mut iter = db.iter();
 
         let first_value = iter
             .next()
@@ -352,7 +1027,8 @@ mod tests {
 
     #[test]
     fn insert() {
-        let db_path = PathBuf::from("tests/insert.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("insert.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
@@ -392,6 +1068,216 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compact_with() {
+        let dir = TestDir::new();
+        let db_path = dir.path("compact_with.db");
+        let seek_path = db_path.with_extension("seek");
+
+        let mut db = KVDatabase::new(db_path.clone(), seek_path.clone())
+            .expect("Failed to create DiskHashMap");
+        db.insert(HashMap::from([
+            ("keep".to_string(), vec![3, 1, 2]),
+            ("drop".to_string(), vec![4]),
+        ]))
+        .expect("Failed to insert hashmap");
+
+        // Garbage past the last record, which no seek position points at
+        File::options()
+            .append(true)
+            .open(&db_path)
+            .and_then(|mut file| file.write_all(&[0; 64]))
+            .expect("Failed to append garbage");
+        let mut db: KVDatabase<String, Vec<u32>> =
+            KVDatabase::from(db_path, seek_path).expect("Failed to open database");
+
+        let reclaimed = db
+            .compact_with(|key, mut value| {
+                value.sort_unstable();
+                (key != "drop").then_some(value)
+            })
+            .expect("Failed to compact");
+
+        assert!(reclaimed > 64);
+        assert_eq!(
+            db.get(&"keep".to_string()).expect("Failed to get value"),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            db.get(&"drop".to_string()).expect("Failed to get value"),
+            None
+        );
+        db.verify().expect("Compacted database should verify");
+    }
+
+    #[test]
+    fn from_bytes() {
+        let dir = TestDir::new();
+        let db_path = dir.path("from_bytes.db");
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("hello".to_string(), vec![1, 2, 3]);
+        hashmap.insert("world".to_string(), vec![4, 5, 6]);
+
+        db.insert(hashmap).expect("Failed to insert hashmap");
+
+        let memory_db: MemoryKVDatabase<String, Vec<i32>> = KVDatabase::from_bytes(
+            std::fs::read(&db_path).expect("Failed to read db"),
+            &std::fs::read(db_path.with_extension("seek")).expect("Failed to read seek"),
+        )
+        .expect("Failed to load DiskHashMap from bytes");
+
+        assert_eq!(
+            memory_db
+                .get(&"world".to_string())
+                .expect("Failed to get value"),
+            Some(vec![4, 5, 6])
+        );
+        assert_eq!(
+            memory_db
+                .get(&"jeff".to_string())
+                .expect("Failed to get value"),
+            None
+        );
+    }
+
+    /// The checked-in golden files were written by this format and must keep
+    /// opening, and writing the same data must reproduce them byte for byte.
+    #[test]
+    fn golden_files() {
+        let dir = TestDir::new();
+        let golden_db = PathBuf::from("tests/test-data/golden_db.test");
+        let golden_seek = PathBuf::from("tests/test-data/golden_seek.test");
+        let build_id = Uuid::from_u128(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+        let value = vec![(1_u64, 0.5_f64), (u64::MAX, -2.25)];
+
+        let db_path = dir.path("golden.db");
+        let mut db =
+            KVDatabase::with_build_id(db_path.clone(), db_path.with_extension("seek"), build_id)
+                .expect("Failed to create DiskHashMap");
+        db.insert(HashMap::from([("ünïcødé".to_string(), value.clone())]))
+            .expect("Failed to insert hashmap");
+
+        let read = |path: &Path| std::fs::read(path).expect("Failed to read file");
+        assert_eq!(read(&db_path), read(&golden_db));
+        assert_eq!(read(&db_path.with_extension("seek")), read(&golden_seek));
+
+        let db: KVDatabase<String, Vec<(u64, f64)>> =
+            KVDatabase::from(golden_db, golden_seek).expect("Failed to open golden files");
+        assert_eq!(db.build_id(), build_id);
+        assert_eq!(
+            db.get(&"ünïcødé".to_string()).expect("Failed to get value"),
+            Some(value)
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let dir = TestDir::new();
+        let db_path = dir.path("verify.db");
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("hello".to_string(), vec![1, 2, 3]);
+        hashmap.insert("world".to_string(), vec![4, 5, 6]);
+
+        db.insert(hashmap).expect("Failed to insert hashmap");
+        db.verify().expect("Fresh database should be consistent");
+
+        File::options()
+            .write(true)
+            .open(&db_path)
+            .and_then(|file| file.set_len(FileHeader::LEN as u64 + 4))
+            .expect("Failed to truncate database");
+
+        let mut db: KVDatabase<String, Vec<i32>> =
+            KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
+                .expect("Failed to restore DiskHashMap from path");
+
+        assert!(db.verify().is_err());
+
+        db.seek_pos_map
+            .insert("hello".to_string(), SeekPos::new(u64::MAX, 2));
+        assert!(db.verify().is_err());
+    }
+
+    #[test]
+    fn bad_records() {
+        let dir = TestDir::new();
+        let db_path = dir.path("bad_records.db");
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("hello".to_string(), vec![1, 2, 3]);
+        db.insert(hashmap).expect("Failed to insert hashmap");
+
+        // Turn the length prefix of the value into garbage, then cut the file
+        // short of where the seek map says the value ends
+        let value_pos = db.seek_pos_map["hello"].pos;
+        let mut bytes = std::fs::read(&db_path).expect("Failed to read database");
+        bytes.truncate(value_pos as usize);
+        bytes.extend([0xff; 8]);
+        std::fs::write(&db_path, bytes).expect("Failed to corrupt database");
+
+        let mut db: KVDatabase<String, Vec<i32>> =
+            KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
+                .expect("Failed to restore DiskHashMap from path");
+        db.seek_pos_map
+            .insert("short".to_string(), SeekPos::new(value_pos + 4, 8));
+
+        for key in ["hello", "short"] {
+            let error = db
+                .get(&key.to_string())
+                .expect_err("Bad record should not decode");
+            assert!(matches!(error, Error::Record { key: ref k, .. } if k == key));
+        }
+        assert_eq!(
+            db.iter().filter(Result::is_err).count(),
+            db.seek_pos_map.len()
+        );
+    }
+
+    #[test]
+    fn skip_corrupt_records() {
+        let dir = TestDir::new();
+        let db_path = dir.path("skip_corrupt.db");
+
+        let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
+            .expect("Failed to create DiskHashMap");
+
+        let mut hashmap = HashMap::new();
+        hashmap.insert("good".to_string(), vec![1]);
+        hashmap.insert("bad".to_string(), vec![2, 3]);
+        db.insert(hashmap).expect("Failed to insert hashmap");
+
+        // Garbage length prefix for one of the values
+        let value_pos = db.seek_pos_map["bad"].pos as usize;
+        let mut bytes = std::fs::read(&db_path).expect("Failed to read database");
+        bytes[value_pos..value_pos + 8].fill(0xff);
+        std::fs::write(&db_path, bytes).expect("Failed to corrupt database");
+
+        let db: KVDatabase<String, Vec<i32>> =
+            KVDatabase::from(db_path.clone(), db_path.with_extension("seek"))
+                .expect("Failed to restore DiskHashMap from path");
+
+        assert_eq!(db.iter().filter(Result::is_err).count(), 1);
+
+        let mut entries = db.iter().skip_corrupt();
+        let records = entries
+            .by_ref()
+            .collect::<Result<Vec<_>>>()
+            .expect("Corrupt records should be skipped");
+        assert_eq!(records, vec![("good".to_string(), vec![1])]);
+        assert_eq!(entries.skipped(), 1);
+    }
+
     #[test]
     fn insert_struct() {
         #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -400,7 +1286,8 @@ mod tests {
             b: Vec<String>,
         }
 
-        let db_path = PathBuf::from("tests/insert_struct.db");
+        let dir = TestDir::new();
+        let db_path = dir.path("insert_struct.db");
 
         let mut db = KVDatabase::new(db_path.clone(), db_path.with_extension("seek"))
             .expect("Failed to create DiskHashMap");
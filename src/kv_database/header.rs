@@ -0,0 +1,85 @@
+use super::codec;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+use uuid::Uuid;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Leads the db and seek files, tying both to the build that wrote them.
+/// Files written before headers existed read as the nil build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct FileHeader {
+    magic: [u8; 4],
+    version: u32,
+    build_id: u128,
+}
+
+impl FileHeader {
+    pub const DB_MAGIC: [u8; 4] = *b"SEDB";
+    pub const SEEK_MAGIC: [u8; 4] = *b"SESK";
+    pub const LEN: usize = 4 + size_of::<u32>() + size_of::<u128>();
+
+    pub const fn new(magic: [u8; 4], build_id: Uuid) -> Self {
+        Self {
+            magic,
+            version: FORMAT_VERSION,
+            build_id: build_id.as_u128(),
+        }
+    }
+
+    pub const fn build_id(&self) -> Uuid {
+        Uuid::from_u128(self.build_id)
+    }
+
+    pub fn to_bytes(self) -> Result<Vec<u8>> {
+        Ok(codec::serialize(&self)?)
+    }
+
+    /// The header `bytes` start with, `None` if they don't start with `magic`.
+    pub fn parse(bytes: &[u8], magic: [u8; 4]) -> Result<Option<Self>> {
+        if !bytes.starts_with(&magic) || bytes.len() < Self::LEN {
+            return Ok(None);
+        }
+
+        let header: Self = codec::deserialize(&bytes[..Self::LEN])?;
+        if header.version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion {
+                found: header.version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        Ok(Some(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_version() {
+        let header = FileHeader::new(FileHeader::DB_MAGIC, Uuid::new_v4());
+        let bytes = header.to_bytes().expect("Failed to serialize header");
+        assert_eq!(
+            FileHeader::parse(&bytes, FileHeader::DB_MAGIC).expect("Failed to parse header"),
+            Some(header)
+        );
+        assert_eq!(
+            FileHeader::parse(&bytes, FileHeader::SEEK_MAGIC).expect("Failed to parse header"),
+            None
+        );
+
+        let future = FileHeader {
+            version: FORMAT_VERSION + 1,
+            ..header
+        };
+        let bytes = future.to_bytes().expect("Failed to serialize header");
+        assert!(matches!(
+            FileHeader::parse(&bytes, FileHeader::DB_MAGIC),
+            Err(Error::UnsupportedVersion { found, expected })
+                if found == FORMAT_VERSION + 1 && expected == FORMAT_VERSION
+        ));
+    }
+}
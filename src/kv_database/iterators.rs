@@ -1,60 +1,93 @@
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Iter as HashMapIter;
 use std::fmt::Display;
-use std::fs::File;
 use std::hash::Hash;
-use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::marker::PhantomData;
+use std::path::Path;
 
-use super::database::KVDatabase;
+use super::database::{decode_record, read_record, KVDatabase};
+use super::read_at::ReadAt;
 use super::seek_pos_map::SeekPos;
 
 use crate::error::{Error, Result};
 
-pub struct KVDatabaseIterator<'a, K, V> {
+pub struct KVDatabaseIterator<'a, K, V, R> {
     seek_pos_iter: HashMapIter<'a, K, SeekPos>,
-    database: &'a mut BufReader<File>,
+    source: (&'a R, &'a Path, u64),
+    buffer: Vec<u8>,
+    skip_corrupt: bool,
+    skipped: usize,
     _marker: PhantomData<*const V>,
 }
 
-impl<'a, K, V> Iterator for KVDatabaseIterator<'a, K, V>
+impl<K, V, R> KVDatabaseIterator<'_, K, V, R> {
+    /// Logs and skips records that can't be read or decoded instead of
+    /// yielding their errors, so one bad record doesn't stop a full pass.
+    #[must_use]
+    pub const fn skip_corrupt(mut self) -> Self {
+        self.skip_corrupt = true;
+        self
+    }
+
+    /// Number of records skipped so far.
+    #[must_use]
+    pub const fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+impl<K, V, R> Iterator for KVDatabaseIterator<'_, K, V, R>
 where
     K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
     V: Serialize + for<'de> Deserialize<'de> + Clone,
+    R: ReadAt,
 {
     type Item = Result<(K, V)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.seek_pos_iter.next().map(|(key, seek_pos)| {
-            self.database
-                .seek(SeekFrom::Start(seek_pos.pos))
-                .map_err(|e| Error::Generic(e.to_string()))?;
-
-            let mut buffer = vec![0; seek_pos.len as usize];
-            self.database
-                .read_exact(&mut buffer)
-                .map_err(|e| Error::Generic(e.to_string()))?;
+        loop {
+            let (key, seek_pos) = self.seek_pos_iter.next()?;
+            let record = read_record(self.source, key, seek_pos, &mut self.buffer)
+                .and_then(|()| decode_record(key, seek_pos, &self.buffer));
 
-            let value: V =
-                bincode::deserialize(&buffer).map_err(|e| Error::Generic(e.to_string()))?;
+            match record {
+                Err(e @ Error::Record { .. }) if self.skip_corrupt => {
+                    eprintln!("Skipping record of {}: {e}", self.source.1.display());
+                    self.skipped += 1;
+                }
+                record => return Some(record.map(|value| (key.clone(), value))),
+            }
+        }
+    }
+}
 
-            Ok((key.clone(), value))
-        })
+impl<K, V, R> KVDatabase<K, V, R>
+where
+    K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
+    V: Serialize + for<'de> Deserialize<'de> + Clone,
+    R: ReadAt,
+{
+    pub fn iter(&self) -> KVDatabaseIterator<'_, K, V, R> {
+        self.into_iter()
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a mut KVDatabase<K, V>
+impl<'a, K, V, R> IntoIterator for &'a KVDatabase<K, V, R>
 where
     K: Serialize + for<'de> Deserialize<'de> + Eq + Hash + Display + Clone,
     V: Serialize + for<'de> Deserialize<'de> + Clone,
+    R: ReadAt,
 {
     type Item = Result<(K, V)>;
-    type IntoIter = KVDatabaseIterator<'a, K, V>;
+    type IntoIter = KVDatabaseIterator<'a, K, V, R>;
 
     fn into_iter(self) -> Self::IntoIter {
         KVDatabaseIterator {
             seek_pos_iter: self.seek_pos_map.iter(),
-            database: &mut self.database,
+            source: self.source(),
+            buffer: Vec::new(),
+            skip_corrupt: false,
+            skipped: 0,
             _marker: PhantomData,
         }
     }
@@ -1,4 +1,8 @@
+pub(crate) mod codec;
 mod constants;
 pub mod database;
+mod header;
 mod iterators;
-mod seek_pos_map;
+pub mod read_at;
+pub mod scratch;
+pub(crate) mod seek_pos_map;
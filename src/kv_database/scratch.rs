@@ -0,0 +1,86 @@
+use super::constants::TEMP_FILE_SUFFIX;
+use crate::error::Result;
+use std::{
+    fs::{copy, remove_file, rename},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Makes rewrites build their temp files in `dir` rather than next to the
+/// files they replace. Only the first call has an effect, and an empty `dir`
+/// keeps the default.
+pub fn set_dir(dir: PathBuf) {
+    if !dir.as_os_str().is_empty() {
+        let _ = DIR.set(dir);
+    }
+}
+
+/// Where to build the file that will replace `path`.
+#[must_use]
+pub fn temp_path(path: &Path) -> PathBuf {
+    temp_path_in(DIR.get().map(PathBuf::as_path), path)
+}
+
+/// Where to build the file that will replace `path`, in `dir` if given. Names
+/// in a shared `dir` are unique, since files of different directories may
+/// share a name.
+fn temp_path_in(dir: Option<&Path>, path: &Path) -> PathBuf {
+    let Some(dir) = dir else {
+        return PathBuf::from(format!("{}.{TEMP_FILE_SUFFIX}", path.display()));
+    };
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{name}.{}-{id}.{TEMP_FILE_SUFFIX}", process::id()))
+}
+
+/// Moves the finished `temp_path` over `path`. Across filesystems, where a
+/// rename fails, the file is copied next to `path` first so it is still
+/// replaced as a whole.
+pub fn persist(temp_path: &Path, path: &Path) -> Result<()> {
+    match rename(temp_path, path) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let local_path = temp_path_in(None, path);
+            copy(temp_path, &local_path)?;
+            rename(local_path, path)?;
+            remove_file(temp_path)?;
+
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use std::fs::{create_dir_all, read, write};
+
+    #[test]
+    fn scratch_dir() {
+        let dir = TestDir::new();
+        let path = dir.path("scratch.db");
+        assert_eq!(temp_path_in(None, &path), dir.path("scratch.db.tmp"));
+
+        let scratch = dir.path("scratch");
+        create_dir_all(&scratch).expect("Failed to create scratch dir");
+        let first = temp_path_in(Some(&scratch), &path);
+        let second = temp_path_in(Some(&scratch), &path);
+        assert!(first.starts_with(&scratch));
+        assert_ne!(first, second);
+
+        write(&first, b"rewritten").expect("Failed to write temp file");
+        persist(&first, &path).expect("Failed to persist temp file");
+        assert_eq!(read(&path).expect("Failed to read file"), b"rewritten");
+        assert!(!first.exists());
+    }
+}
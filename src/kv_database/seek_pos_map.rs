@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, mem::size_of};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SeekPos {
@@ -14,3 +14,9 @@ impl SeekPos {
 }
 
 pub type SeekPosMap<K> = HashMap<K, SeekPos>;
+
+/// Approximate bytes `map` allocates for its entries, with one control byte
+/// per bucket. Heap data owned by the keys is not included.
+pub fn entries_size<K>(map: &SeekPosMap<K>) -> usize {
+    map.capacity() * (size_of::<(K, SeekPos)>() + 1)
+}
@@ -1,5 +1,28 @@
+pub mod bench;
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod crawler;
+#[cfg(unix)]
+pub mod daemon;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod display;
 pub mod error;
+pub mod eval;
 pub mod inverted_index;
 pub mod kv_database;
+pub mod links;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod query_log;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
 pub mod search;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod shutdown;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod slow_query_log;
+#[cfg(test)]
+mod test_util;
 pub mod tokenizer;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
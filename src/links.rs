@@ -0,0 +1,57 @@
+use crate::error::{Error, Result};
+use scraper::{Html, Selector};
+use url::Url;
+
+/// Absolute http(s) links of a page, without fragments, in document order.
+pub fn extract_links(base: &Url, html: &str) -> Result<Vec<Url>> {
+    document_links(base, &Html::parse_document(html))
+}
+
+/// Same as [`extract_links`] for a page that was already parsed.
+pub fn document_links(base: &Url, document: &Html) -> Result<Vec<Url>> {
+    let selector = Selector::parse("a[href]").map_err(|e| Error::InvalidSelector {
+        selector: "a[href]".to_string(),
+        message: e.to_string(),
+    })?;
+
+    Ok(document
+        .select(&selector)
+        .filter_map(|element| element.value().attr("href"))
+        .filter_map(|href| base.join(href).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(|mut url| {
+            url.set_fragment(None);
+            url
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_links() {
+        let base = Url::parse("https://example.com/docs/index.html").expect("Invalid base URL");
+        let html = r#"<a href="intro.html#top">Intro</a>
+            <a href="/about">About</a>
+            <a href="https://other.example/">Other</a>
+            <a href="mailto:me@example.com">Mail</a>
+            <a>No link</a>"#;
+
+        let links = extract_links(&base, html)
+            .expect("Failed to extract links")
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/docs/intro.html",
+                "https://example.com/about",
+                "https://other.example/",
+            ]
+        );
+    }
+}
@@ -1,88 +1,983 @@
-use clap::{Parser, ValueHint};
+use clap::{ArgGroup, Args as ClapArgs, Parser, Subcommand, ValueEnum, ValueHint};
+#[cfg(unix)]
+use search_engine::daemon::{self, DaemonRequest};
 use search_engine::{
+    bench::{
+        index::bench_index,
+        search::{bench_search, CacheMode, SearchBenchOptions},
+    },
+    config::{Config, HostedIndexConfig, PathsConfig},
+    crawler::{
+        crawl,
+        filter::ContentFilter,
+        page_path,
+        scope::Scope,
+        state::{self, CrawlState},
+        traps::TrapDetector,
+        CrawlOptions, CrawlOutput, CrawlStats,
+    },
     error::{Error, Result},
-    inverted_index::disk_inverted_index::DiskInvertedIndex,
-    search::engine::SearchEngine,
+    eval::{evaluate, parse_qrels, parse_queries},
+    inverted_index::{
+        boost::UrlBoosts,
+        crawl_sources::read_crawl_sources,
+        disk_inverted_index::{
+            BuildOptions, BuildStats, CrawlFile, DiskInvertedIndex, UpdateStats,
+        },
+        doc_filter::KeywordFilter,
+        doc_map::DocID,
+        json_documents::read_json_documents,
+        jsonl::{export_jsonl, import_jsonl, ExportFormat},
+        migration::{migrate, Layout},
+        posting_stats::PostingStats,
+    },
+    kv_database::scratch,
+    query_log::QueryLog,
+    repl,
+    search::{
+        batch::{run_batch, run_query, OutputFormat, ResultWriter},
+        engine::{MatchMode, RankingModel, SearchEngine},
+    },
+    server::{
+        self,
+        app::{AppState, HostedIndex},
+    },
+    shutdown,
+    slow_query_log::SlowQueryLog,
+    tokenizer::Analyzer,
 };
-use std::{io, path::PathBuf};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use url::Url;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Path to a TOML config file
+    #[arg(long, env = "SEARCH_ENGINE_CONFIG", value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+
     /// Restarts the database
-    #[arg(short, long, default_value_t = false, requires = "crawled_data_path")]
+    #[arg(short, long, default_value_t = false)]
     restart: bool,
 
-    /// Path to the crawled data
-    #[arg(short, long, default_value = "data", value_hint = ValueHint::FilePath)]
-    crawled_data: Option<PathBuf>,
+    /// Path to the crawled data. Given more than once, or as a glob
+    /// pattern, indexes every directory matched as one corpus
+    #[arg(short, long, value_hint = ValueHint::DirPath)]
+    crawled_data: Vec<PathBuf>,
 
     /// Path to the database
-    #[arg(short, long, default_value = "database.db", value_hint = ValueHint::FilePath)]
-    db: PathBuf,
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    db: Option<PathBuf>,
 
-    // Path to the seek position file
-    #[arg(short, long, default_value = "database.seek", value_hint = ValueHint::FilePath)]
-    db_seek: PathBuf,
+    /// Path to the seek position file
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    db_seek: Option<PathBuf>,
 
     /// Path to the URL map
-    #[arg(short, long, default_value = "url_map.db", value_hint = ValueHint::FilePath)]
-    url_map: PathBuf,
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    url_map: Option<PathBuf>,
 
     /// Path to the URL map seek position file
-    #[arg(short, long, default_value = "url_map.seek", value_hint = ValueHint::FilePath)]
-    url_map_seek: PathBuf,
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    url_map_seek: Option<PathBuf>,
+
+    /// CSV file of url,boost lines boosting or demoting pages at build time
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    boosts: Option<PathBuf>,
+
+    /// Directory for the temp files of index rewrites
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    temp_dir: Option<PathBuf>,
+
+    /// Threads parsing documents when building the index, 0 for one per core
+    #[arg(long)]
+    threads: Option<usize>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Searches the index interactively or runs a file of queries
+    Search {
+        /// Runs a single query instead of starting the REPL
+        #[arg(conflicts_with = "queries_file")]
+        query: Option<String>,
+
+        /// File with one query per line to run in batch
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        queries_file: Option<PathBuf>,
+
+        /// Where to write the results of a single or batch query (stdout if omitted)
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+
+        /// Output format of a single or batch query
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Jsonl)]
+        format: OutputFormat,
+
+        /// Number of results to keep per query
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+
+        /// Whether results must contain any or all of the query terms
+        #[arg(short, long, value_enum, default_value_t = MatchMode::Any)]
+        match_mode: MatchMode,
+
+        /// Leaves documents flagged at index time out of the results
+        #[arg(long)]
+        safe_search: bool,
+
+        /// Scoring model, the config's when omitted
+        #[arg(long, value_enum)]
+        model: Option<Model>,
+
+        /// Term frequency saturation of BM25 [default: 1.2]
+        #[arg(long)]
+        k1: Option<f64>,
+
+        /// Document length normalization of BM25, from 0 to 1 [default: 0.75]
+        #[arg(long)]
+        b: Option<f64>,
+    },
+    /// Crawls the web from seed URLs into the crawled data directory
+    Crawl(CrawlArgs),
+    /// Crawls the web from seed URLs and indexes the pages as they arrive,
+    /// without writing them to the crawled data directory
+    CrawlIndex(CrawlArgs),
+    /// Re-fetches crawled pages that are due and reindexes if any changed
+    Recrawl,
+    /// Rewrites the index without records and postings nothing refers to
+    Compact,
+    /// Upgrades index files written by older versions in place
+    Migrate,
+    /// Rebuilds lost or unreadable seek files of the index from its databases
+    Repair,
+    /// Prints corpus, memory and posting list statistics of the index
+    Stats {
+        /// Heaviest terms to list with their score histograms
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Serves the search engine over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(short, long)]
+        addr: Option<SocketAddr>,
+    },
+    /// Serves newline-delimited JSON queries over a unix socket
+    #[cfg(unix)]
+    Daemon {
+        /// Path of the unix socket
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        socket: Option<PathBuf>,
+    },
+    /// Queries a running daemon and prints its JSON response
+    #[cfg(unix)]
+    Client {
+        /// Query to run
+        query: String,
+
+        /// Path of the daemon's unix socket
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        socket: Option<PathBuf>,
+
+        /// Number of results to return
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Scores the current ranking against relevance judgments
+    Eval {
+        /// File of `<query id>\t<query text>` lines
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        queries: PathBuf,
+
+        /// TREC qrels file, `<query id> <iteration> <url> <grade>` per line
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        qrels: PathBuf,
+
+        /// Number of results retrieved per query
+        #[arg(long, default_value_t = 1000)]
+        depth: usize,
+
+        /// Also print the metrics of every query
+        #[arg(long, default_value_t = false)]
+        per_query: bool,
+    },
+    /// Writes the documents and postings of the index in a portable format
+    Export {
+        /// Format of the export
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Jsonl)]
+        format: ExportFormat,
+
+        /// Where to write the export (stdout if omitted)
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+    /// Builds the index from an export, replacing the current one
+    Import {
+        /// Export to read (stdin if omitted)
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: Option<PathBuf>,
+    },
+    /// Prints what the index stores about one document
+    #[command(group(ArgGroup::new("target").required(true).args(["id", "url"])))]
+    Doc {
+        /// Document ID, as used in the url map
+        #[arg(long)]
+        id: Option<DocID>,
+
+        /// URL the document was crawled from
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Deletes one document from the index, leaving its postings for
+    /// `compact` to drop
+    #[command(group(ArgGroup::new("target").required(true).args(["id", "url"])))]
+    Delete {
+        /// Document ID, as used in the url map
+        #[arg(long)]
+        id: Option<DocID>,
+
+        /// URL the document was crawled from
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Measures indexing and query performance
+    Bench {
+        #[command(subcommand)]
+        target: BenchCommand,
+    },
+}
+
+#[derive(ClapArgs, Debug)]
+struct CrawlArgs {
+    /// URLs to start from, links are followed on their hosts only
+    #[arg(required = true)]
+    seeds: Vec<Url>,
+
+    /// Pages fetched before the crawl stops
+    #[arg(short, long)]
+    max_pages: Option<usize>,
+
+    /// Requests in flight at once
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Links followed away from the seeds
+    #[arg(long)]
+    max_depth: Option<usize>,
+}
+
+/// Scoring models of the `--model` flag.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Model {
+    /// The tf-idf scores stored in the index
+    TfIdf,
+    /// Okapi BM25, tuned with `--k1` and `--b`
+    Bm25,
+}
+
+#[derive(Subcommand, Debug)]
+enum BenchCommand {
+    /// Builds a scratch index from a sample corpus and reports throughput
+    Index {
+        /// Corpus to index, defaults to the crawled data path
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        corpus: Option<PathBuf>,
+
+        /// Directory for the scratch index files
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        out_dir: Option<PathBuf>,
+    },
+    /// Measures query latency percentiles against the existing index
+    Search {
+        /// File with one query per line
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        queries: PathBuf,
+
+        /// Whether caches are warmed up before measuring
+        #[arg(short, long, value_enum, default_value_t = CacheMode::Warm)]
+        mode: CacheMode,
+
+        /// Number of passes over the query file
+        #[arg(short, long, default_value_t = 1)]
+        iterations: usize,
+
+        /// Number of results to resolve per query
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+impl Args {
+    /// Command line flags take precedence over the config file and environment.
+    fn apply_to(&self, config: &mut Config) {
+        let paths = &mut config.paths;
+        if let [crawled_data, ..] = self.crawled_data.as_slice() {
+            paths.crawled_data.clone_from(crawled_data);
+            paths.crawled_sources.clone_from(&self.crawled_data);
+        }
+        for (flag, value) in [
+            (&self.db, &mut paths.db),
+            (&self.db_seek, &mut paths.db_seek),
+            (&self.url_map, &mut paths.url_map),
+            (&self.url_map_seek, &mut paths.url_map_seek),
+            (&self.boosts, &mut paths.boosts),
+            (&self.temp_dir, &mut paths.temp_dir),
+        ] {
+            if let Some(flag) = flag {
+                value.clone_from(flag);
+            }
+        }
+        if let Some(threads) = self.threads {
+            config.indexing.threads = threads;
+        }
+
+        match &self.command {
+            Some(
+                Command::Crawl(CrawlArgs {
+                    max_pages,
+                    concurrency,
+                    max_depth,
+                    ..
+                })
+                | Command::CrawlIndex(CrawlArgs {
+                    max_pages,
+                    concurrency,
+                    max_depth,
+                    ..
+                }),
+            ) => {
+                if let Some(max_pages) = max_pages {
+                    config.crawler.max_pages = *max_pages;
+                }
+                if let Some(concurrency) = concurrency {
+                    config.crawler.concurrency = *concurrency;
+                }
+                if let Some(max_depth) = max_depth {
+                    config.crawler.max_depth = *max_depth;
+                }
+            }
+            Some(Command::Search { model, k1, b, .. }) => {
+                let model = match model {
+                    Some(Model::TfIdf) => RankingModel::TfIdf,
+                    Some(Model::Bm25)
+                        if !matches!(config.ranking.scorer, RankingModel::Bm25 { .. }) =>
+                    {
+                        RankingModel::BM25
+                    }
+                    _ => config.ranking.scorer,
+                };
+                config.ranking.scorer = match model {
+                    RankingModel::Bm25 {
+                        k1: model_k1,
+                        b: model_b,
+                    } => RankingModel::Bm25 {
+                        k1: k1.unwrap_or(model_k1),
+                        b: b.unwrap_or(model_b),
+                    },
+                    RankingModel::TfIdf => RankingModel::TfIdf,
+                };
+            }
+            Some(Command::Serve { addr: Some(addr) }) => config.server.addr = *addr,
+            #[cfg(unix)]
+            Some(
+                Command::Daemon {
+                    socket: Some(socket),
+                }
+                | Command::Client {
+                    socket: Some(socket),
+                    ..
+                },
+            ) => config.daemon.socket.clone_from(socket),
+            _ => {}
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    shutdown::install_handler()?;
+
+    let mut config = Config::load(args.config.as_deref())?;
+    args.apply_to(&mut config);
+    scratch::set_dir(config.paths.temp_dir.clone());
 
-    let db = if args.restart {
-        DiskInvertedIndex::new(
-            args.db,
-            args.db_seek,
-            args.url_map,
-            args.url_map_seek,
-            args.crawled_data
-                .ok_or_else(|| Error::Generic("Crawled data path is required".to_string()))?,
-        )?
+    match args.command {
+        Some(Command::Bench { target }) => bench(target, &config),
+        Some(Command::Crawl(CrawlArgs { seeds, .. })) => {
+            println!("{}", crawl_pages(&config, seeds, config.crawler.max_depth)?);
+            Ok(())
+        }
+        Some(Command::CrawlIndex(CrawlArgs { seeds, .. })) => crawl_index(&config, seeds),
+        Some(Command::Doc { id, url }) => print_doc(args.restart, &config, id, url),
+        Some(Command::Delete { id, url }) => delete_doc(config.paths, id, url),
+        Some(Command::Stats { top }) => print_stats(args.restart, &config, top),
+        Some(Command::Export { format, output }) => export(args.restart, &config, format, output),
+        Some(Command::Import { input }) => import(config.paths, input),
+        Some(Command::Recrawl) => recrawl(&config),
+        Some(Command::Compact) => compact(config.paths),
+        Some(Command::Migrate) => migrate_index(config.paths),
+        Some(Command::Repair) => repair(config.paths),
+        Some(Command::Eval {
+            queries,
+            qrels,
+            depth,
+            per_query,
+        }) => {
+            let search_engine = open_search_engine(args.restart, &config)?;
+            let queries = parse_queries(BufReader::new(File::open(queries)?))?;
+            let qrels = parse_qrels(BufReader::new(File::open(qrels)?))?;
+
+            let report = evaluate(&search_engine, &queries, &qrels, depth)?;
+
+            if per_query {
+                for query in &report.queries {
+                    println!(
+                        "{}\tAP={:.4}\tnDCG@10={:.4}\tRR={:.4}",
+                        query.id, query.average_precision, query.ndcg_at_10, query.reciprocal_rank
+                    );
+                }
+            }
+            println!("{report}");
+            Ok(())
+        }
+        #[cfg(unix)]
+        Some(Command::Daemon { .. }) => {
+            let mut search_engine = open_search_engine(args.restart, &config)?;
+            preload(&mut search_engine, config.daemon.preload_terms)?;
+            daemon::serve(&search_engine, &config.daemon.socket)
+        }
+        #[cfg(unix)]
+        Some(Command::Client { query, limit, .. }) => {
+            let response = daemon::query(&config.daemon.socket, &DaemonRequest { query, limit })?;
+            println!("{response}");
+            Ok(())
+        }
+        Some(Command::Serve { .. }) => serve(args.restart, &config, args.config),
+        Some(Command::Search {
+            query: Some(query),
+            output,
+            format,
+            limit,
+            match_mode,
+            safe_search,
+            ..
+        }) => {
+            let search_engine = open_query_engine(args.restart, &config, match_mode, safe_search)?;
+            let mut writer = ResultWriter::new(open_output(output)?, format);
+            writer.write(&run_query(&search_engine, &query, limit)?)?;
+            writer.finish()
+        }
+        Some(Command::Search {
+            queries_file: Some(queries_file),
+            output,
+            format,
+            limit,
+            match_mode,
+            safe_search,
+            ..
+        }) => {
+            let search_engine = open_query_engine(args.restart, &config, match_mode, safe_search)?;
+            let queries = BufReader::new(File::open(queries_file)?);
+            let mut writer = ResultWriter::new(open_output(output)?, format);
+            let num_queries = run_batch(&search_engine, queries, &mut writer, limit)?;
+            writer.finish()?;
+            eprintln!("Ran {num_queries} queries");
+            Ok(())
+        }
+        Some(Command::Search {
+            match_mode,
+            safe_search,
+            ..
+        }) => {
+            let search_engine = open_query_engine(args.restart, &config, match_mode, safe_search)?;
+            run_repl(&search_engine, &config)
+        }
+        None => {
+            let search_engine = open_search_engine(args.restart, &config)?;
+            run_repl(&search_engine, &config)
+        }
+    }
+}
+
+fn serve(restart: bool, config: &Config, config_path: Option<PathBuf>) -> Result<()> {
+    let (hosted, default_index) = if config.indexes.is_empty() {
+        let index = HostedIndexConfig {
+            paths: config.paths.clone(),
+            ..HostedIndexConfig::default()
+        };
+        (BTreeMap::from([("default".to_string(), index)]), "default")
     } else {
-        DiskInvertedIndex::from(args.db, args.db_seek, args.url_map, args.url_map_seek)?
+        (config.indexes.clone(), config.server.default_index.as_str())
     };
 
-    let mut search_engine = SearchEngine::new(db)?;
-    let mut input_buffer = String::new();
+    let indexes = hosted
+        .into_iter()
+        .map(|(name, index)| {
+            let db = open_index(restart, index.paths.clone(), index.analyzer, config)?;
+            let mut search_engine = SearchEngine::with_analyzer(db, index.analyzer)?
+                .with_limits(config.query_limits)
+                .with_ranking(config.ranking.clone());
+            preload(&mut search_engine, index.preload_terms)?;
+            Ok((name, HostedIndex::new(search_engine, index)))
+        })
+        .collect::<Result<_>>()?;
+    let state = AppState::new(indexes, default_index.to_string())?
+        .with_limits(&config.server)
+        .with_rankings(config.server.rankings.clone())
+        .with_query_log(&config.query_log)?
+        .with_slow_query_log(&config.slow_query_log)?;
 
-    loop {
-        println!("Enter a search query (type 'exit' to quit):");
-        io::stdin()
-            .read_line(&mut input_buffer)
-            .expect("Failed to read line");
+    tokio::runtime::Runtime::new()?.block_on(server::app::serve(
+        state,
+        config.server.addr,
+        config_path,
+    ))
+}
 
-        if input_buffer.trim() == "exit" {
-            break;
+fn crawl_options(
+    config: &Config,
+    seeds: Vec<Url>,
+    max_depth: usize,
+    output: CrawlOutput,
+) -> CrawlOptions {
+    let crawler = &config.crawler;
+    CrawlOptions {
+        scope: Scope::new(&crawler.domains, &crawler.path_prefixes, &seeds),
+        seeds,
+        output,
+        max_pages: crawler.max_pages,
+        concurrency: crawler.concurrency,
+        user_agent: crawler.user_agent.clone(),
+        delay: Duration::from_millis(crawler.delay_ms),
+        filter: ContentFilter::new(&crawler.content_types, crawler.max_page_bytes),
+        max_depth,
+        traps: TrapDetector::new(crawler.max_url_variants),
+    }
+}
+
+/// Loads the crawl state and saves it again even when the crawl fails, so
+/// pages fetched before an interruption are not fetched again right away.
+fn crawl_pages(config: &Config, seeds: Vec<Url>, max_depth: usize) -> Result<CrawlStats> {
+    let output = CrawlOutput::Dir(config.paths.crawled_data.clone());
+    let options = crawl_options(config, seeds, max_depth, output);
+
+    let mut history = CrawlState::load(&config.crawler.state)?;
+    let stats = tokio::runtime::Runtime::new()?.block_on(crawl(options, &mut history));
+    history.save(&config.crawler.state)?;
+
+    stats
+}
+
+/// The indexer runs on its own thread and takes pages from the crawler as
+/// they are fetched. Every page goes into the new index, so the crawl starts
+/// from an empty history and leaves the saved one alone.
+fn crawl_index(config: &Config, seeds: Vec<Url>) -> Result<()> {
+    let (sender, receiver) = mpsc::sync_channel(config.crawler.concurrency.max(1));
+    let boosts = UrlBoosts::read(&config.paths.boosts)?;
+    let indexer = thread::spawn({
+        let config = config.clone();
+        move || {
+            build_index(
+                config.paths.clone(),
+                Analyzer::default(),
+                boosts.apply(receiver.into_iter().map(Ok)),
+                &config,
+            )
+            .map(|(_, build)| build)
+        }
+    });
+
+    let options = crawl_options(
+        config,
+        seeds,
+        config.crawler.max_depth,
+        CrawlOutput::Channel(sender),
+    );
+    let stats =
+        tokio::runtime::Runtime::new()?.block_on(crawl(options, &mut CrawlState::default()));
+    let build = indexer
+        .join()
+        .map_err(|_| Error::Generic("Indexer thread panicked".to_string()))?;
+
+    // A failed indexer also fails the crawl, so its error is the one to report
+    let build = build?;
+    println!("{}", stats?);
+    println!("Indexed {} documents", build.num_docs);
+    Ok(())
+}
+
+/// Pages are due based on their age and how often they changed before. Those
+/// that changed are indexed again and those that are gone removed, leaving
+/// the rest of the index alone. Indexes built from several crawls, or before
+/// updates were possible, are rebuilt from the crawled data instead.
+fn recrawl(config: &Config) -> Result<()> {
+    let history = CrawlState::load(&config.crawler.state)?;
+    let due = history
+        .due(state::now())
+        .into_iter()
+        .filter_map(|url| Url::parse(&url).ok())
+        .collect::<Vec<_>>();
+    if due.is_empty() {
+        println!("No pages are due for a re-crawl");
+        return Ok(());
+    }
+
+    let stats = crawl_pages(config, due, 0)?;
+    println!("{stats}");
+
+    if stats.changed == 0 && stats.gone == 0 {
+        return Ok(());
+    }
+
+    if config.paths.crawled_sources.is_empty() && config.paths.db.exists() {
+        let crawled = CrawlState::load(&config.crawler.state)?;
+        let changed: Vec<_> = crawled
+            .pages()
+            .filter(|(url, page)| {
+                history
+                    .get(url)
+                    .is_none_or(|previous| previous.last_changed != page.last_changed)
+            })
+            .map(|(url, _)| url.clone())
+            .collect();
+        let removed: Vec<_> = history
+            .pages()
+            .filter(|(url, _)| crawled.get(url).is_none())
+            .map(|(url, _)| url.clone())
+            .collect();
+
+        match update_index(config, &changed, &removed) {
+            Ok(update) => {
+                println!("{update}");
+                return Ok(());
+            }
+            Err(Error::MissingCompanion { path }) => {
+                println!("{} is missing, rebuilding the index", path.display());
+            }
+            Err(e) => return Err(e),
         }
+    }
+
+    let documents = crawled_documents(&config.paths, config)?;
+    let (_, build) = build_index(config.paths.clone(), Analyzer::default(), documents, config)?;
+    println!("Reindexed {} documents", build.num_docs);
 
-        let start_time = std::time::Instant::now();
+    Ok(())
+}
+
+/// Indexes the saved copies of the `changed` pages of the crawled data again
+/// and removes the `removed` ones from the index of `config`.
+fn update_index(config: &Config, changed: &[String], removed: &[String]) -> Result<UpdateStats> {
+    let paths = config.paths.clone();
+    let boosts = UrlBoosts::read(&paths.boosts)?;
+    let documents = changed.iter().map(|url| {
+        let file = File::open(page_path(&paths.crawled_data, url))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    });
+    let filter = doc_filter(config);
+    let config_hash = config.fingerprint()?;
+
+    DiskInvertedIndex::update_with(
+        paths.db,
+        paths.db_seek,
+        paths.url_map,
+        paths.url_map_seek,
+        boosts.apply(documents),
+        removed,
+        build_options(config, &filter, &config_hash, Analyzer::default()),
+    )
+    .map(|(_, update)| update)
+}
+
+fn print_doc(restart: bool, config: &Config, id: Option<DocID>, url: Option<String>) -> Result<()> {
+    let index = open_index(restart, config.paths.clone(), Analyzer::default(), config)?;
+
+    let doc = match (id, url) {
+        (Some(id), _) => index.get_doc(id)?.map(|doc| (id, doc)),
+        (None, Some(url)) => index.get_doc_by_url(&url)?,
+        (None, None) => None,
+    };
+    let (id, doc) = doc.ok_or_else(|| Error::Generic("Document not found".to_string()))?;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({ "id": id, "doc": doc }))?
+    );
+    Ok(())
+}
+
+fn delete_doc(paths: PathsConfig, id: Option<DocID>, url: Option<String>) -> Result<()> {
+    let mut index =
+        DiskInvertedIndex::from(paths.db, paths.db_seek, paths.url_map, paths.url_map_seek)?;
+
+    let id = match (id, url) {
+        (Some(id), _) => Some(id),
+        (None, Some(url)) => index.get_doc_by_url(&url)?.map(|(id, _)| id),
+        (None, None) => None,
+    };
+    let deleted = match id {
+        Some(id) => index.delete_doc(id)?.then_some(id),
+        None => None,
+    };
+    let id = deleted.ok_or_else(|| Error::Generic("Document not found".to_string()))?;
+
+    println!("Deleted document {id}, run `compact` to drop its postings");
+    Ok(())
+}
+
+fn run_repl(search_engine: &SearchEngine, config: &Config) -> Result<()> {
+    let query_log = QueryLog::open(&config.query_log.path)?;
+    let slow_query_log = SlowQueryLog::open(&config.slow_query_log)?;
+    repl::run(
+        search_engine,
+        &config.repl.history,
+        &query_log,
+        slow_query_log.as_ref(),
+    )
+}
+
+fn print_stats(restart: bool, config: &Config, top: usize) -> Result<()> {
+    let index = open_index(restart, config.paths.clone(), Analyzer::default(), config)?;
+
+    println!("{} documents", index.num_docs());
+    if let Some(average) = index.average_doc_length() {
+        println!("{average:.1} tokens per document");
+    }
+    if let Some(report) = index.build_report() {
+        println!("{report}");
+    }
+    println!("Index memory: {}", index.memory_stats());
+    println!("{}", PostingStats::collect(&index, top)?);
+
+    Ok(())
+}
+
+fn export(
+    restart: bool,
+    config: &Config,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let index = open_index(restart, config.paths.clone(), Analyzer::default(), config)?;
+
+    let stats = match format {
+        ExportFormat::Jsonl => export_jsonl(&index, open_output(output)?)?,
+    };
+    eprintln!("{stats}");
+
+    Ok(())
+}
+
+fn import(paths: PathsConfig, input: Option<PathBuf>) -> Result<()> {
+    let input: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let (_, stats) = import_jsonl(
+        paths.db,
+        paths.db_seek,
+        paths.url_map,
+        paths.url_map_seek,
+        input,
+    )?;
+    println!(
+        "Imported {} documents and {} terms",
+        stats.docs, stats.terms
+    );
+
+    Ok(())
+}
+
+fn migrate_index(paths: PathsConfig) -> Result<()> {
+    let layout = migrate(paths.db, paths.db_seek, paths.url_map, paths.url_map_seek)?;
+    if layout == Layout::Current {
+        println!("Index is already in the current format");
+    } else {
+        println!("Upgraded index from the {layout} format");
+    }
+
+    Ok(())
+}
 
-        let search_results = search_engine.search(input_buffer.trim())?;
+fn open_output(path: Option<PathBuf>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+fn preload(search_engine: &mut SearchEngine, num_terms: usize) -> Result<()> {
+    if num_terms > 0 {
+        let start_time = Instant::now();
+        let loaded = search_engine.preload(num_terms)?;
+        println!("Preloaded {loaded} terms in {:.2?}", start_time.elapsed());
+    }
+    println!("Index memory: {}", search_engine.memory_stats());
+
+    Ok(())
+}
+
+fn open_search_engine(restart: bool, config: &Config) -> Result<SearchEngine> {
+    Ok(SearchEngine::new(open_index(
+        restart,
+        config.paths.clone(),
+        Analyzer::default(),
+        config,
+    )?)?
+    .with_limits(config.query_limits)
+    .with_ranking(config.ranking.clone()))
+}
+
+/// Opens the engine of the `search` command.
+fn open_query_engine(
+    restart: bool,
+    config: &Config,
+    match_mode: MatchMode,
+    safe_search: bool,
+) -> Result<SearchEngine> {
+    Ok(open_search_engine(restart, config)?
+        .with_match_mode(match_mode)
+        .with_safe_search(safe_search))
+}
+
+fn compact(paths: PathsConfig) -> Result<()> {
+    let (_, stats) =
+        DiskInvertedIndex::compact(paths.db, paths.db_seek, paths.url_map, paths.url_map_seek)?;
+    println!("{stats}");
+    Ok(())
+}
+
+fn repair(paths: PathsConfig) -> Result<()> {
+    let (_, stats) =
+        DiskInvertedIndex::repair(paths.db, paths.db_seek, paths.url_map, paths.url_map_seek)?;
+    println!("{stats}");
+    Ok(())
+}
+
+/// Flags the documents of a build that queries with safe search exclude.
+fn doc_filter(config: &Config) -> KeywordFilter {
+    KeywordFilter::new(&config.safe_search.flagged_words)
+}
+
+/// The pages of the crawled data of `paths`, boosted as its boosts file says.
+fn crawled_documents(
+    paths: &PathsConfig,
+    config: &Config,
+) -> Result<impl Iterator<Item = Result<CrawlFile>>> {
+    let boosts = UrlBoosts::read(&paths.boosts)?;
+    let sources = if paths.crawled_sources.is_empty() {
+        std::slice::from_ref(&paths.crawled_data)
+    } else {
+        &paths.crawled_sources
+    };
+    let documents = read_json_documents(&paths.json_documents, &config.indexing.json)?;
+    Ok(boosts.apply(read_crawl_sources(sources)?.chain(documents)))
+}
+
+/// Builds the index of `paths` from `documents` analyzed with `analyzer`,
+/// flagging and expanding them as `config` says.
+fn build_index<I>(
+    paths: PathsConfig,
+    analyzer: Analyzer,
+    documents: I,
+    config: &Config,
+) -> Result<(DiskInvertedIndex, BuildStats)>
+where
+    I: IntoIterator<Item = Result<CrawlFile>>,
+{
+    let filter = doc_filter(config);
+    let config_hash = config.fingerprint()?;
+    DiskInvertedIndex::build_with(
+        paths.db,
+        paths.db_seek,
+        paths.url_map,
+        paths.url_map_seek,
+        documents,
+        build_options(config, &filter, &config_hash, analyzer),
+    )
+}
+
+/// How documents are indexed as `config` says.
+fn build_options<'a>(
+    config: &'a Config,
+    filter: &'a KeywordFilter,
+    config_hash: &'a str,
+    analyzer: Analyzer,
+) -> BuildOptions<'a> {
+    BuildOptions {
+        filter: Some(filter),
+        synonyms: Some(&config.indexing.synonyms),
+        fields: Some(&config.indexing.fields),
+        flush: config.indexing.flush,
+        config_hash: Some(config_hash),
+        threads: config.indexing.threads,
+        analyzer,
+    }
+}
+
+/// Opens the index of `paths`, or rebuilds it with `analyzer` first.
+fn open_index(
+    restart: bool,
+    paths: PathsConfig,
+    analyzer: Analyzer,
+    config: &Config,
+) -> Result<DiskInvertedIndex> {
+    if restart {
+        let documents = crawled_documents(&paths, config)?;
+        build_index(paths, analyzer, documents, config).map(|(index, _)| index)
+    } else {
+        DiskInvertedIndex::from(paths.db, paths.db_seek, paths.url_map, paths.url_map_seek)
+    }
+}
+
+fn bench(target: BenchCommand, config: &Config) -> Result<()> {
+    match target {
+        BenchCommand::Index { corpus, out_dir } => {
+            let corpus = corpus.unwrap_or_else(|| config.paths.crawled_data.clone());
+            let out_dir =
+                out_dir.unwrap_or_else(|| std::env::temp_dir().join("search-engine-bench"));
+
+            println!("{}", bench_index(&corpus, &out_dir)?);
+        }
+        BenchCommand::Search {
+            queries,
+            mode,
+            iterations,
+            limit,
+        } => {
+            let queries = BufReader::new(File::open(queries)?)
+                .lines()
+                .map(|line| line.map(|line| line.trim().to_string()))
+                .filter(|line| line.as_ref().map_or(true, |line| !line.is_empty()))
+                .collect::<io::Result<Vec<_>>>()?;
 
-        println!(
-            "Found {} results in {:?}",
-            search_results.len(),
-            start_time.elapsed()
-        );
+            let report = bench_search(
+                || open_search_engine(false, config),
+                &queries,
+                SearchBenchOptions {
+                    mode,
+                    iterations,
+                    limit,
+                },
+            )?;
 
-        println!("Top 10 results:");
-        for (i, result) in search_engine
-            .search(input_buffer.trim())?
-            .iter()
-            .take(10)
-            .enumerate()
-        {
-            println!("{}. {:?}", i + 1, result);
+            println!("{report}");
         }
-        input_buffer.clear();
     }
 
     Ok(())
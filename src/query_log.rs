@@ -0,0 +1,142 @@
+use crate::error::Result;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+
+/// Queries users ran, appended one per line as they arrive and counted in
+/// memory, so popular ones can be suggested as users type.
+///
+/// Server queries are followed by a tab and the ranking that served them,
+/// for comparing ranking experiments offline.
+pub struct QueryLog {
+    state: Mutex<State>,
+}
+
+struct State {
+    file: File,
+    counts: HashMap<String, u64>,
+}
+
+impl QueryLog {
+    /// Opens the log at `path`, creating it when missing, and counts the
+    /// queries already in it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut counts = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    let query = line
+                        .split_once('\t')
+                        .map_or(line.as_str(), |(query, _)| query);
+                    if let Some(query) = normalize(query) {
+                        *counts.entry(query).or_insert(0) += 1;
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            state: Mutex::new(State { file, counts }),
+        })
+    }
+
+    /// Appends `query` to the log, along with the `ranking` that served it.
+    /// Blank queries are ignored.
+    pub fn record(&self, query: &str, ranking: Option<&str>) -> Result<()> {
+        let Some(query) = normalize(query) else {
+            return Ok(());
+        };
+
+        // A poisoned log only holds counts, keep appending to it
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match ranking {
+            Some(ranking) => writeln!(state.file, "{query}\t{ranking}")?,
+            None => writeln!(state.file, "{query}")?,
+        }
+        *state.counts.entry(query).or_insert(0) += 1;
+        drop(state);
+
+        Ok(())
+    }
+
+    /// Up to `limit` logged queries starting with `prefix`, most frequent
+    /// first. Matching ignores case and runs of whitespace.
+    #[must_use]
+    pub fn suggest_queries(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let mut normalized = normalize(prefix).unwrap_or_default();
+        // A finished word only completes to queries going on to the next one
+        if !normalized.is_empty() && prefix.ends_with(char::is_whitespace) {
+            normalized.push(' ');
+        }
+        let prefix = normalized;
+
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut matches: Vec<_> = state
+            .counts
+            .iter()
+            .filter(|(query, _)| query.starts_with(&prefix))
+            .map(|(query, count)| (*count, query.clone()))
+            .collect();
+        drop(state);
+
+        matches.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, query)| query)
+            .collect()
+    }
+}
+
+/// The query in lowercase with runs of whitespace collapsed, `None` when
+/// blank.
+fn normalize(query: &str) -> Option<String> {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!query.is_empty()).then(|| query.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use std::fs;
+
+    #[test]
+    fn suggests_popular_queries() {
+        let dir = TestDir::new();
+        let path = dir.path("suggests_popular_queries.log");
+        fs::write(&path, "rust book\nRust  Cargo\n\nrust cargo\n").expect("Failed to write log");
+
+        let log = QueryLog::open(&path).expect("Failed to open query log");
+        log.record("rust async", None).expect("Failed to record");
+        log.record("rust book", Some("default"))
+            .expect("Failed to record");
+        log.record("rust book", Some("fresh"))
+            .expect("Failed to record");
+        log.record("pasta", None).expect("Failed to record");
+
+        assert_eq!(
+            log.suggest_queries("Rust ", 2),
+            ["rust book".to_string(), "rust cargo".to_string()]
+        );
+        assert_eq!(log.suggest_queries("past", 5), ["pasta".to_string()]);
+        assert!(log.suggest_queries("pasta ", 5).is_empty());
+        assert!(log.suggest_queries("cooking", 5).is_empty());
+
+        // Recorded queries survive a reopen, whatever ranking served them
+        drop(log);
+        let lines = fs::read_to_string(&path).expect("Failed to read query log");
+        assert!(lines.ends_with("rust book\tdefault\nrust book\tfresh\npasta\n"));
+        let log = QueryLog::open(&path).expect("Failed to reopen query log");
+        assert_eq!(log.suggest_queries("rust", 1), ["rust book".to_string()]);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    display::ResultTable,
+    error::Result,
+    query_log::QueryLog,
+    search::{
+        engine::{SearchEngine, SearchOutcome},
+        search_result::SearchResult,
+    },
+    shutdown,
+    slow_query_log::{SlowQuery, SlowQueryLog},
+};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+use std::{path::Path, time::Instant};
+
+const PROMPT: &str = "> ";
+const NUM_RESULTS: usize = 10;
+const NUM_SUGGESTIONS: usize = 10;
+/// Turns printing a trace after each query on or off
+const TRACE_COMMAND: &str = ":trace";
+
+/// Completes the line to popular logged queries on tab.
+struct QueryCompleter<'a> {
+    query_log: &'a QueryLog,
+}
+
+impl Completer for QueryCompleter<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let prefix = line.get(..pos).unwrap_or(line);
+        Ok((0, self.query_log.suggest_queries(prefix, NUM_SUGGESTIONS)))
+    }
+}
+
+impl Hinter for QueryCompleter<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for QueryCompleter<'_> {}
+
+impl Validator for QueryCompleter<'_> {}
+
+impl Helper for QueryCompleter<'_> {}
+
+pub fn run(
+    search_engine: &SearchEngine,
+    history_path: &Path,
+    query_log: &QueryLog,
+    slow_query_log: Option<&SlowQueryLog>,
+) -> Result<()> {
+    let mut editor = Editor::<_, DefaultHistory>::new()?;
+    editor.set_helper(Some(QueryCompleter { query_log }));
+
+    // A missing history file just means this is the first session.
+    let _ = editor.load_history(history_path);
+
+    println!(
+        "Enter a search query (type '{TRACE_COMMAND}' to toggle query traces, 'exit' or Ctrl-D to quit):"
+    );
+
+    let mut trace = false;
+    loop {
+        // Nothing is lost by ending the session while it waits for input, so
+        // SIGTERM ends it right away rather than after the next query
+        let idle = shutdown::idle();
+        if shutdown::requested() {
+            break;
+        }
+        let line = editor.readline(PROMPT);
+        drop(idle);
+
+        let line = match line {
+            Ok(line) => line,
+            // Ctrl-C abandons the current line, like in a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let query = line.trim();
+        if query.is_empty() {
+            continue;
+        }
+        if query == "exit" {
+            break;
+        }
+        if query == TRACE_COMMAND {
+            trace = !trace;
+            println!("Query traces {}", if trace { "on" } else { "off" });
+            continue;
+        }
+
+        // Persist every entry right away so a terminated session loses nothing.
+        // Failing to do so doesn't end the session
+        editor.add_history_entry(query)?;
+        if let Err(e) = editor.append_history(history_path) {
+            eprintln!("Failed to save history: {e}");
+        }
+        if let Err(e) = query_log.record(query, None) {
+            eprintln!("Failed to log query: {e}");
+        }
+
+        if let Err(e) = search(search_engine, query, trace, slow_query_log) {
+            eprintln!("Search failed: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+fn search(
+    search_engine: &SearchEngine,
+    query: &str,
+    print_trace: bool,
+    slow_query_log: Option<&SlowQueryLog>,
+) -> Result<()> {
+    let start_time = Instant::now();
+
+    let mut top_results: Vec<SearchResult> = Vec::with_capacity(NUM_RESULTS);
+    let SearchOutcome {
+        total,
+        partial_results,
+        trace,
+    } = search_engine.search_streaming_traced(
+        query,
+        search_engine.ranking(),
+        NUM_RESULTS,
+        print_trace || slow_query_log.is_some(),
+        |result| {
+            top_results.push(result);
+            true
+        },
+    )?;
+    let elapsed = start_time.elapsed();
+
+    if let Some(log) = slow_query_log.filter(|log| log.is_slow(elapsed)) {
+        let slow_query = SlowQuery {
+            index: None,
+            query,
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            total,
+            trace: trace.as_ref(),
+        };
+        if let Err(e) = log.record(&slow_query) {
+            eprintln!("Failed to log slow query: {e}");
+        }
+    }
+
+    println!("Found {total} results in {elapsed:?}");
+    if partial_results {
+        println!("Results are partial, the query hit the engine's limits");
+    }
+    if let Some(trace) = trace.filter(|_| print_trace) {
+        println!("Trace:\n{trace}");
+    }
+    if let Some(correction) = search_engine.did_you_mean(query) {
+        println!("Did you mean: {correction}");
+    }
+
+    if !top_results.is_empty() {
+        println!("{}", ResultTable::for_stdout().render(&top_results, query));
+    }
+
+    Ok(())
+}
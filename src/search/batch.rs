@@ -0,0 +1,202 @@
+use super::{engine::SearchEngine, search_result::SearchResult};
+use crate::{error::Result, kv_database::read_at::ReadAt};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{BufRead, Write};
+
+const CSV_HEADER: &str = "query,rank,url,score,title";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per query
+    #[default]
+    Jsonl,
+    /// One row per result with a header line
+    Csv,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryResults {
+    pub query: String,
+    pub total: usize,
+    pub results: Vec<SearchResult>,
+}
+
+pub struct ResultWriter<W: Write> {
+    output: W,
+    format: OutputFormat,
+    wrote_header: bool,
+}
+
+impl<W: Write> ResultWriter<W> {
+    pub const fn new(output: W, format: OutputFormat) -> Self {
+        Self {
+            output,
+            format,
+            wrote_header: false,
+        }
+    }
+
+    pub fn write(&mut self, query_results: &QueryResults) -> Result<()> {
+        match self.format {
+            OutputFormat::Jsonl => {
+                serde_json::to_writer(&mut self.output, query_results)?;
+                writeln!(self.output)?;
+            }
+            OutputFormat::Csv => {
+                if !self.wrote_header {
+                    writeln!(self.output, "{CSV_HEADER}")?;
+                    self.wrote_header = true;
+                }
+
+                for (i, result) in query_results.results.iter().enumerate() {
+                    writeln!(
+                        self.output,
+                        "{},{},{},{},{}",
+                        csv_field(&query_results.query),
+                        i + 1,
+                        csv_field(&result.url),
+                        result.score,
+                        csv_field(result.title.as_deref().unwrap_or_default())
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Ranks `query` and keeps its top `limit` results.
+pub fn run_query<S: ReadAt>(
+    search_engine: &SearchEngine<S>,
+    query: &str,
+    limit: usize,
+) -> Result<QueryResults> {
+    let mut results = Vec::new();
+    let total = search_engine.search_streaming(query, limit, |result| {
+        results.push(result);
+        true
+    })?;
+
+    Ok(QueryResults {
+        query: query.to_string(),
+        total,
+        results,
+    })
+}
+
+/// Runs every non-empty line of `queries` and writes the ranked results of
+/// each query to `output`. Returns the number of queries run.
+pub fn run_batch<S, R, W>(
+    search_engine: &SearchEngine<S>,
+    queries: R,
+    output: &mut ResultWriter<W>,
+    limit: usize,
+) -> Result<usize>
+where
+    S: ReadAt,
+    R: BufRead,
+    W: Write,
+{
+    let mut num_queries = 0;
+
+    for line in queries.lines() {
+        let line = line?;
+        let query = line.trim();
+        if query.is_empty() {
+            continue;
+        }
+
+        output.write(&run_query(search_engine, query, limit)?)?;
+
+        num_queries += 1;
+    }
+
+    Ok(num_queries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::DiskInvertedIndex;
+
+    #[test]
+    fn test_run_batch() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+
+        let mut output = Vec::new();
+        let mut writer = ResultWriter::new(&mut output, OutputFormat::Jsonl);
+        let num_queries = run_batch(
+            &search_engine,
+            &b"eric\n\nnot_in_index\n"[..],
+            &mut writer,
+            1,
+        )
+        .expect("Failed to run batch");
+        writer.finish().expect("Failed to flush output");
+
+        assert_eq!(num_queries, 2);
+        assert_eq!(
+            String::from_utf8(output).expect("Output is not UTF-8"),
+            concat!(
+                r#"{"query":"eric","total":3,"results":[{"url":"https://www.ericminassian.com/","score":9.1}]}"#,
+                "\n",
+                r#"{"query":"not_in_index","total":0,"results":[]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let mut output = Vec::new();
+        let mut writer = ResultWriter::new(&mut output, OutputFormat::Csv);
+
+        for query in ["eric", "say \"hi\", eric"] {
+            writer
+                .write(&QueryResults {
+                    query: query.to_string(),
+                    total: 1,
+                    results: vec![SearchResult::new(
+                        "https://www.ericminassian.com/".to_string(),
+                        9.1,
+                    )
+                    .with_title(Some("Eric, Minassian".to_string()))],
+                })
+                .expect("Failed to write results");
+        }
+        writer.finish().expect("Failed to flush output");
+
+        assert_eq!(
+            String::from_utf8(output).expect("Output is not UTF-8"),
+            concat!(
+                "query,rank,url,score,title\n",
+                "eric,1,https://www.ericminassian.com/,9.1,\"Eric, Minassian\"\n",
+                "\"say \"\"hi\"\", eric\",1,https://www.ericminassian.com/,9.1,\"Eric, Minassian\"\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    engine::MatchMode,
+    query::{Clause, Query},
+};
+
+const AND: &str = "AND";
+const OR: &str = "OR";
+const NOT: &str = "NOT";
+
+/// A query combining clauses with `AND`, `OR`, `NOT` and parentheses.
+///
+/// `NOT` binds tightest, then `AND`, then `OR`. Clauses next to each other
+/// combine as the [`MatchMode`] says, except before a `NOT`, which always
+/// narrows what comes before it. Negations only take documents out of what
+/// other clauses match, so a group made only of them matches nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BooleanQuery {
+    Clause(Clause),
+    And(Vec<Self>),
+    Or(Vec<Self>),
+    Not(Box<Self>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// A clause as [`Query::parse`] reads it
+    Text(String),
+}
+
+impl BooleanQuery {
+    /// Parses `query` when it holds an operator or a parenthesis, `None` for
+    /// queries that run as a plain [`Query`]. Operators are uppercase, so
+    /// `and` stays a word. No query fails to parse: stray operators and
+    /// parentheses are ignored, and groups left open close at the end.
+    #[must_use]
+    pub fn parse(query: &str, match_mode: MatchMode) -> Option<Self> {
+        let tokens = lex(query);
+        if tokens.iter().all(|token| matches!(token, Token::Text(_))) {
+            return None;
+        }
+
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+            match_mode,
+        };
+        Some(parser.or().unwrap_or(Self::Or(Vec::new())))
+    }
+
+    /// The clauses outside negations, whose terms add to the score of
+    /// matches.
+    #[must_use]
+    pub fn scored_clauses(&self) -> Query {
+        let mut query = Query::default();
+        self.collect_scored(&mut query.clauses);
+
+        query
+    }
+
+    fn collect_scored(&self, clauses: &mut Vec<Clause>) {
+        match self {
+            Self::Clause(clause) => clauses.push(clause.clone()),
+            Self::And(operands) | Self::Or(operands) => {
+                for operand in operands {
+                    operand.collect_scored(clauses);
+                }
+            }
+            Self::Not(_) => {}
+        }
+    }
+}
+
+/// Splits `query` into operators, parentheses and clauses. Quoted phrases
+/// stay one clause, whatever they hold.
+fn lex(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            '(' | ')' if !quoted => {
+                push_word(&mut word, &mut tokens);
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            c if c.is_whitespace() && !quoted => push_word(&mut word, &mut tokens),
+            c => word.push(c),
+        }
+    }
+    push_word(&mut word, &mut tokens);
+
+    tokens
+}
+
+fn push_word(word: &mut String, tokens: &mut Vec<Token>) {
+    if word.is_empty() {
+        return;
+    }
+
+    tokens.push(match word.as_str() {
+        AND => Token::And,
+        OR => Token::Or,
+        NOT => Token::Not,
+        _ => Token::Text(word.clone()),
+    });
+    word.clear();
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Parentheses open at `pos`
+    depth: usize,
+    match_mode: MatchMode,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Option<BooleanQuery> {
+        let mut operands = Vec::new();
+        loop {
+            operands.extend(self.and());
+            match self.peek() {
+                Some(Token::Close) if self.depth > 0 => break,
+                Some(Token::Or | Token::Close) => self.pos += 1,
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        combine(operands, BooleanQuery::Or)
+    }
+
+    fn and(&mut self) -> Option<BooleanQuery> {
+        let mut operands = Vec::new();
+        loop {
+            operands.extend(self.unary());
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                Some(Token::Not) => {}
+                Some(Token::Text(_) | Token::Open) if self.match_mode == MatchMode::All => {}
+                _ => break,
+            }
+        }
+
+        combine(operands, BooleanQuery::And)
+    }
+
+    fn unary(&mut self) -> Option<BooleanQuery> {
+        match self.peek()? {
+            Token::Not => {
+                self.pos += 1;
+                self.unary()
+                    .map(|operand| BooleanQuery::Not(Box::new(operand)))
+            }
+            Token::Open => {
+                self.pos += 1;
+                self.depth += 1;
+                let group = self.or();
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                }
+                self.depth -= 1;
+                group
+            }
+            Token::Text(text) => {
+                let clause = Query::parse(text).clauses.into_iter().next();
+                self.pos += 1;
+                clause.map(BooleanQuery::Clause)
+            }
+            Token::And | Token::Or | Token::Close => None,
+        }
+    }
+}
+
+/// `operands` joined by `operator`, the operand itself when there is one.
+fn combine(
+    mut operands: Vec<BooleanQuery>,
+    operator: fn(Vec<BooleanQuery>) -> BooleanQuery,
+) -> Option<BooleanQuery> {
+    match operands.len() {
+        0 => None,
+        1 => operands.pop(),
+        _ => Some(operator(operands)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> BooleanQuery {
+        BooleanQuery::Clause(Query::parse(text).clauses.remove(0))
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(BooleanQuery::parse("rust and cargo", MatchMode::Any), None);
+
+        assert_eq!(
+            BooleanQuery::parse("rust OR go AND NOT java", MatchMode::Any),
+            Some(BooleanQuery::Or(vec![
+                word("rust"),
+                BooleanQuery::And(vec![word("go"), BooleanQuery::Not(Box::new(word("java")))]),
+            ]))
+        );
+        assert_eq!(
+            BooleanQuery::parse("(rust OR go) \"web server\" NOT java", MatchMode::All),
+            Some(BooleanQuery::And(vec![
+                BooleanQuery::Or(vec![word("rust"), word("go")]),
+                word("\"web server\""),
+                BooleanQuery::Not(Box::new(word("java"))),
+            ]))
+        );
+        // Clauses next to each other combine as the match mode says
+        assert_eq!(
+            BooleanQuery::parse("rust go NOT java", MatchMode::Any),
+            Some(BooleanQuery::Or(vec![
+                word("rust"),
+                BooleanQuery::And(vec![word("go"), BooleanQuery::Not(Box::new(word("java")))]),
+            ]))
+        );
+
+        // Stray operators and parentheses
+        assert_eq!(
+            BooleanQuery::parse(") AND rust OR (go", MatchMode::Any),
+            Some(BooleanQuery::Or(vec![word("rust"), word("go")]))
+        );
+        assert_eq!(
+            BooleanQuery::parse("NOT", MatchMode::Any),
+            Some(BooleanQuery::Or(Vec::new()))
+        );
+    }
+
+    #[test]
+    fn scored_clauses() {
+        let query = BooleanQuery::parse("+rust^2 OR (go NOT java)", MatchMode::Any)
+            .expect("Failed to parse query");
+
+        assert_eq!(query.scored_clauses().to_string(), "+rust^2 go");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::inverted_index::doc_map::Terms;
+use std::collections::HashMap;
+
+/// Results at the top of a ranking that diversification reorders. The ones
+/// below keep their order.
+pub const DIVERSIFY_CANDIDATES: usize = 50;
+
+/// Orders `candidates`, ranked best first with their terms, by maximal
+/// marginal relevance.
+///
+/// Each pick maximizes its relevance minus `diversity` times its similarity
+/// to the closest result picked before it, so near duplicates sink below
+/// pages saying something else. A `diversity` of 0 keeps the ranking as is.
+///
+/// Relevance is the score relative to the best one, similarity the cosine of
+/// the term frequencies of two documents.
+#[must_use]
+pub fn mmr_order(candidates: &[(u64, f64)], terms: &[Terms], diversity: f64) -> Vec<(u64, f64)> {
+    let best = candidates.first().map_or(0.0, |(_, score)| *score);
+    let relevance = |score: f64| {
+        if best > 0.0 && best.is_finite() {
+            score / best
+        } else {
+            0.0
+        }
+    };
+    let vectors: Vec<_> = terms.iter().map(TermVector::new).collect();
+
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    // Highest similarity of each remaining candidate to the picked ones
+    let mut closest = vec![0.0_f64; candidates.len()];
+    let mut picked = Vec::with_capacity(candidates.len());
+
+    while !remaining.is_empty() {
+        let mmr = |i: usize| {
+            (1.0 - diversity).mul_add(relevance(candidates[i].1), -diversity * closest[i])
+        };
+        // The earliest of equally good candidates wins, keeping ties in rank order
+        let (position, _) = remaining.iter().enumerate().fold(
+            (0, f64::NEG_INFINITY),
+            |(best_position, best_mmr), (position, &i)| {
+                let mmr = mmr(i);
+                if mmr > best_mmr {
+                    (position, mmr)
+                } else {
+                    (best_position, best_mmr)
+                }
+            },
+        );
+        let pick = remaining.remove(position);
+
+        for &i in &remaining {
+            closest[i] = closest[i].max(vectors[pick].cosine(&vectors[i]));
+        }
+        picked.push(candidates[pick]);
+    }
+
+    picked
+}
+
+/// Term frequencies of a document with their Euclidean norm.
+struct TermVector<'a> {
+    frequencies: HashMap<&'a str, f64>,
+    norm: f64,
+}
+
+impl<'a> TermVector<'a> {
+    fn new(terms: &'a Terms) -> Self {
+        let frequencies: HashMap<_, _> = terms
+            .iter()
+            .map(|(term, tf)| (term.as_str(), f64::from(*tf)))
+            .collect();
+        let norm = frequencies.values().map(|tf| tf * tf).sum::<f64>().sqrt();
+
+        Self { frequencies, norm }
+    }
+
+    fn cosine(&self, other: &Self) -> f64 {
+        if self.norm == 0.0 || other.norm == 0.0 {
+            return 0.0;
+        }
+
+        let dot: f64 = self
+            .frequencies
+            .iter()
+            .filter_map(|(term, tf)| other.frequencies.get(term).map(|other_tf| tf * other_tf))
+            .sum();
+        dot / (self.norm * other.norm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(words: &[&str]) -> Terms {
+        words.iter().map(|word| ((*word).to_string(), 1)).collect()
+    }
+
+    #[test]
+    fn sinks_near_duplicates() {
+        let candidates = [(0, 10.0), (1, 9.5), (2, 8.0)];
+        let terms = [
+            terms(&["pasta", "carbonara", "recipe"]),
+            terms(&["pasta", "carbonara", "recipe"]),
+            terms(&["pesto", "basil", "recipe"]),
+        ];
+
+        let ids =
+            |ranked: Vec<(u64, f64)>| ranked.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(mmr_order(&candidates, &terms, 0.0)), [0, 1, 2]);
+        assert_eq!(ids(mmr_order(&candidates, &terms, 0.5)), [0, 2, 1]);
+        // Scores are kept, only the order changes
+        assert_eq!(mmr_order(&candidates, &terms, 0.5)[1], (2, 8.0));
+    }
+
+    #[test]
+    fn cosine() {
+        let pasta = terms(&["pasta", "recipe"]);
+        let pesto = terms(&["pesto", "recipe"]);
+        let empty = Terms::new();
+
+        assert!((TermVector::new(&pasta).cosine(&TermVector::new(&pasta)) - 1.0).abs() < 1e-9);
+        assert!((TermVector::new(&pasta).cosine(&TermVector::new(&pesto)) - 0.5).abs() < 1e-9);
+        assert!(
+            TermVector::new(&pasta)
+                .cosine(&TermVector::new(&empty))
+                .abs()
+                < 1e-9
+        );
+    }
+}
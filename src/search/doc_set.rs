@@ -0,0 +1,136 @@
+use crate::inverted_index::{disk_inverted_index::TermIndex, doc_map::DocID};
+use std::{cmp::Ordering, slice};
+
+/// Doc IDs in ascending order without duplicates, for applications combining
+/// the postings of [`SearchEngine::postings`] into their own retrieval.
+///
+/// [`SearchEngine::postings`]: super::engine::SearchEngine::postings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocSet {
+    doc_ids: Vec<DocID>,
+}
+
+impl DocSet {
+    /// The documents of `postings`.
+    #[must_use]
+    pub fn from_postings(postings: &[TermIndex]) -> Self {
+        postings.iter().map(|posting| posting.doc_id).collect()
+    }
+
+    /// Documents in both sets.
+    #[must_use]
+    pub fn intersect(&self, other: &Self) -> Self {
+        self.merge(other, false, true, false)
+    }
+
+    /// Documents in either set.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        self.merge(other, true, true, true)
+    }
+
+    /// Documents in this set but not in `other`.
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.merge(other, true, false, false)
+    }
+
+    #[must_use]
+    pub fn contains(&self, doc_id: DocID) -> bool {
+        self.doc_ids.binary_search(&doc_id).is_ok()
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.doc_ids.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.doc_ids.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, DocID> {
+        self.doc_ids.iter()
+    }
+
+    /// Walks both sets in step, keeping the documents only this set, both
+    /// sets or only `other` holds as asked.
+    fn merge(&self, other: &Self, only_self: bool, both: bool, only_other: bool) -> Self {
+        let (mut a, mut b) = (
+            self.doc_ids.iter().peekable(),
+            other.doc_ids.iter().peekable(),
+        );
+        let mut doc_ids = Vec::new();
+
+        loop {
+            let (doc_id, keep) = match (a.peek(), b.peek()) {
+                (Some(&&x), Some(&&y)) => match x.cmp(&y) {
+                    Ordering::Less => (a.next().copied(), only_self),
+                    Ordering::Greater => (b.next().copied(), only_other),
+                    Ordering::Equal => {
+                        b.next();
+                        (a.next().copied(), both)
+                    }
+                },
+                (Some(_), None) if only_self => (a.next().copied(), true),
+                (None, Some(_)) if only_other => (b.next().copied(), true),
+                _ => break,
+            };
+            if let Some(doc_id) = doc_id.filter(|_| keep) {
+                doc_ids.push(doc_id);
+            }
+        }
+
+        Self { doc_ids }
+    }
+}
+
+impl FromIterator<DocID> for DocSet {
+    fn from_iter<I: IntoIterator<Item = DocID>>(iter: I) -> Self {
+        let mut doc_ids: Vec<_> = iter.into_iter().collect();
+        doc_ids.sort_unstable();
+        doc_ids.dedup();
+
+        Self { doc_ids }
+    }
+}
+
+impl<'a> IntoIterator for &'a DocSet {
+    type Item = &'a DocID;
+    type IntoIter = slice::Iter<'a, DocID>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for DocSet {
+    type Item = DocID;
+    type IntoIter = std::vec::IntoIter<DocID>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.doc_ids.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_algebra() {
+        let a: DocSet = [5, 1, 3, 3, 9].into_iter().collect();
+        let b: DocSet = [3, 4, 9, 12].into_iter().collect();
+        let ids = |set: DocSet| set.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(ids(a.clone()), [1, 3, 5, 9]);
+        assert_eq!(ids(a.intersect(&b)), [3, 9]);
+        assert_eq!(ids(a.union(&b)), [1, 3, 4, 5, 9, 12]);
+        assert_eq!(ids(a.difference(&b)), [1, 5]);
+        assert_eq!(ids(b.difference(&a)), [4, 12]);
+        assert!(a.intersect(&DocSet::default()).is_empty());
+        assert_eq!(a.union(&DocSet::default()), a);
+        assert!(a.contains(5) && !a.contains(4));
+    }
+}
@@ -1,63 +1,1008 @@
 use crate::{
     error::{Error, Result},
-    inverted_index::disk_inverted_index::DiskInvertedIndex,
-    tokenizer::Tokenizer,
+    inverted_index::{
+        build_report::BuildReport,
+        disk_inverted_index::{DiskInvertedIndex, MemoryStats, ReadBuffer, TermIndex},
+        posting_iterator::{DecodedPostings, Intersection, PostingIterator},
+        term_stats::TermStats,
+    },
+    kv_database::read_at::ReadAt,
+    tokenizer::{Analyzer, Tokenizer},
 };
-use std::collections::HashMap;
+use clap::ValueEnum;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fs::File, sync::OnceLock};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::task;
 
-use super::search_result::SearchResult;
+use super::{
+    boolean::BooleanQuery,
+    diversify::{mmr_order, DIVERSIFY_CANDIDATES},
+    proximity::{contains_phrase, proximity_boost, PROXIMITY_CANDIDATES},
+    query::Query,
+    ranking::{split_custom_field, RankingConfig, WeightedQuery, WeightedTerm},
+    search_result::SearchResult,
+    spelling::SpellChecker,
+    trace::QueryTrace,
+};
+
+/// Terms of a document queried to find ones like it
+const MORE_LIKE_THIS_TERMS: usize = 10;
+
+thread_local! {
+    /// Read buffer of the thread running a query, rayon workers included
+    static READ_BUFFER: RefCell<ReadBuffer> = RefCell::default();
+}
+
+/// Which documents a query matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// Documents containing any of the terms
+    #[default]
+    Any,
+    /// Only documents containing every term
+    All,
+}
+
+/// How [`SearchEngine::search_async`] runs a query.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Results to return at most
+    pub limit: usize,
+    /// Ranking to rank with, the engine's own when `None`
+    pub ranking: Option<RankingConfig>,
+    /// Whether to return a [`QueryTrace`] of the query
+    pub trace: bool,
+}
+
+/// Caps on the work of one query, so a pathological query over a huge index
+/// returns part of its matches instead of tying up the engine. 0 lifts a cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryLimits {
+    /// Matches scored, the ones past it in doc ID order are left out
+    pub max_candidates: usize,
+    /// Postings decoded across all terms. Optional terms that don't fit are
+    /// skipped, rarest first kept, and queries whose required terms don't fit
+    /// match nothing
+    pub max_postings: u64,
+}
+
+/// How the postings of a query compare to [`QueryLimits::max_postings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Budget {
+    /// Every term fits
+    Fits,
+    /// Some optional terms were dropped so the rest fit
+    Trimmed,
+    /// The terms that can't be dropped don't fit, so the query matches
+    /// nothing
+    Exceeded,
+}
+
+/// How the postings of a term score the documents holding it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum RankingModel {
+    /// The tf-idf scores the build stored in the postings
+    #[default]
+    TfIdf,
+    /// Okapi BM25, with `k1` saturating term frequencies and `b` from 0 to 1
+    /// normalizing them by document length
+    Bm25 { k1: f64, b: f64 },
+}
+
+impl RankingModel {
+    /// BM25 with its usual parameters.
+    pub const BM25: Self = Self::Bm25 { k1: 1.2, b: 0.75 };
+}
 
-pub struct SearchEngine {
-    inverted_index_db: DiskInvertedIndex,
+/// What came of a streamed query besides its results.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchOutcome {
+    /// Matching documents
+    pub total: usize,
+    /// Whether the query hit one of the engine's [`QueryLimits`], so matches
+    /// may be missing
+    pub partial_results: bool,
+    pub trace: Option<QueryTrace>,
+}
+
+pub struct SearchEngine<R = File> {
+    inverted_index_db: DiskInvertedIndex<R>,
     tokenizer: Tokenizer,
+    ranking: RankingConfig,
+    limits: QueryLimits,
+    /// Whether the index keeps term frequencies, without which every ranking
+    /// scores with tf-idf
+    has_term_freqs: bool,
+    /// Built on the first correction, `None` without word frequencies
+    spell_checker: OnceLock<Option<SpellChecker>>,
 }
 
-impl SearchEngine {
-    pub fn new(inverted_index_db: DiskInvertedIndex) -> Result<Self> {
+impl<R: ReadAt> SearchEngine<R> {
+    /// Queries `inverted_index_db` with the analyzer it was built with.
+    pub fn new(inverted_index_db: DiskInvertedIndex<R>) -> Result<Self> {
+        let analyzer = inverted_index_db.analyzer().unwrap_or_default();
+        Self::with_analyzer(inverted_index_db, analyzer)
+    }
+
+    /// Fails with [`Error::AnalyzerMismatch`] for indexes built with another
+    /// analyzer, whose terms queries would miss.
+    pub fn with_analyzer(
+        inverted_index_db: DiskInvertedIndex<R>,
+        analyzer: Analyzer,
+    ) -> Result<Self> {
+        if let Some(built) = inverted_index_db
+            .analyzer()
+            .filter(|&built| built != analyzer)
+        {
+            return Err(Error::AnalyzerMismatch {
+                path: inverted_index_db.db.db_path().to_path_buf(),
+                built,
+                requested: analyzer,
+            });
+        }
+
         Ok(Self {
+            has_term_freqs: inverted_index_db.has_term_frequencies(),
             inverted_index_db,
-            tokenizer: Tokenizer::new()?,
+            tokenizer: Tokenizer::with_analyzer(analyzer)?,
+            ranking: RankingConfig::default(),
+            limits: QueryLimits::default(),
+            spell_checker: OnceLock::new(),
         })
     }
 
-    pub fn search(&mut self, query: &str) -> Result<Vec<SearchResult>> {
-        let mut document_ids: HashMap<u64, f64> = HashMap::new();
+    #[must_use]
+    pub const fn with_match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.ranking.match_mode = match_mode;
+        self
+    }
 
-        let stemmed_tokens = self.tokenizer.tokenize(query);
+    /// Leaves documents the index flagged out of the results.
+    #[must_use]
+    pub const fn with_safe_search(mut self, safe_search: bool) -> Self {
+        self.ranking.safe_search = safe_search;
+        self
+    }
 
-        for token in stemmed_tokens {
-            if let Some(document_indexes) = self.inverted_index_db.get(&token)? {
-                for document_index in document_indexes {
-                    *document_ids.entry(document_index.doc_id).or_insert(0.0) +=
-                        document_index.tf_idf;
-                }
+    #[must_use]
+    pub const fn ranking(&self) -> &RankingConfig {
+        &self.ranking
+    }
+
+    /// Ranking used by queries that don't bring their own.
+    #[must_use]
+    pub fn with_ranking(mut self, ranking: RankingConfig) -> Self {
+        self.ranking = ranking;
+        self
+    }
+
+    #[must_use]
+    pub const fn limits(&self) -> QueryLimits {
+        self.limits
+    }
+
+    #[must_use]
+    pub const fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The model `ranking` scores postings with. Indexes built before term
+    /// frequencies were kept fall back to tf-idf, which BM25 can't do without.
+    #[must_use]
+    pub const fn scorer(&self, ranking: &RankingConfig) -> RankingModel {
+        if self.has_term_freqs {
+            ranking.scorer
+        } else {
+            RankingModel::TfIdf
+        }
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let ranked = self.rank(query)?;
+
+        ranked
+            .into_iter()
+            .map(|(doc_id, score)| self.resolve(doc_id, score))
+            .collect()
+    }
+
+    /// Ranks `query` and hands results to `on_result` one at a time, best match
+    /// first, as soon as each document has been resolved from the url map.
+    /// Stops early once `limit` results were emitted or `on_result` returns
+    /// `false`. Returns the total number of matching documents.
+    pub fn search_streaming<F>(&self, query: &str, limit: usize, on_result: F) -> Result<usize>
+    where
+        F: FnMut(SearchResult) -> bool,
+    {
+        self.search_streaming_with(query, &self.ranking, limit, on_result)
+    }
+
+    /// Same as [`SearchEngine::search_streaming`], ranking with `ranking`
+    /// instead of the engine's own.
+    pub fn search_streaming_with<F>(
+        &self,
+        query: &str,
+        ranking: &RankingConfig,
+        limit: usize,
+        on_result: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(SearchResult) -> bool,
+    {
+        self.stream_ranked(query, ranking, limit, on_result, None)
+            .map(|(total, _)| total)
+    }
+
+    /// Same as [`SearchEngine::search_streaming_with`], also telling whether
+    /// the results are partial and returning a trace of how the query ran if
+    /// `trace` is set.
+    pub fn search_streaming_traced<F>(
+        &self,
+        query: &str,
+        ranking: &RankingConfig,
+        limit: usize,
+        trace: bool,
+        on_result: F,
+    ) -> Result<SearchOutcome>
+    where
+        F: FnMut(SearchResult) -> bool,
+    {
+        let mut trace = trace.then(QueryTrace::default);
+        let (total, partial_results) =
+            self.stream_ranked(query, ranking, limit, on_result, trace.as_mut())?;
+
+        Ok(SearchOutcome {
+            total,
+            partial_results,
+            trace,
+        })
+    }
+
+    fn stream_ranked<F>(
+        &self,
+        query: &str,
+        ranking: &RankingConfig,
+        limit: usize,
+        mut on_result: F,
+        trace: Option<&mut QueryTrace>,
+    ) -> Result<(usize, bool)>
+    where
+        F: FnMut(SearchResult) -> bool,
+    {
+        let (ranked, partial_results) = self.rank_with(query, ranking, trace)?;
+        let total = ranked.len();
+
+        for (doc_id, score) in ranked.into_iter().take(limit) {
+            if !on_result(self.resolve(doc_id, score)?) {
+                break;
             }
         }
 
-        let mut document_ids: Vec<_> = document_ids.into_iter().collect();
-        document_ids.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Greater));
+        Ok((total, partial_results))
+    }
+
+    /// Up to `k` documents similar to `doc_id`, found by querying its terms
+    /// with the highest tf-idf. `doc_id` itself is never returned.
+    pub fn more_like_this(&self, doc_id: u64, k: usize) -> Result<Vec<SearchResult>> {
+        let terms: Vec<_> = self
+            .inverted_index_db
+            .top_terms(doc_id, MORE_LIKE_THIS_TERMS)?
+            .into_iter()
+            .map(|term| (term, 1.0))
+            .collect();
+
+        let mut ranked: Vec<_> = self
+            .accumulate(&terms, self.scorer(&self.ranking))?
+            .into_iter()
+            .filter(|(id, _)| *id != doc_id)
+            .collect();
+        ranked.sort_by(rank_order);
 
-        document_ids
+        ranked
             .into_iter()
+            .take(k)
+            .map(|(doc_id, score)| self.resolve(doc_id, score))
+            .collect()
+    }
+
+    /// `query` with the words missing from the corpus replaced by their
+    /// likeliest spelling. `None` when there was nothing to correct or the
+    /// index was built before word frequencies were kept.
+    pub fn did_you_mean(&self, query: &str) -> Option<String> {
+        let spell_checker = self
+            .spell_checker
+            .get_or_init(|| {
+                self.inverted_index_db
+                    .word_frequencies()
+                    .map(SpellChecker::new)
+            })
+            .as_ref()?;
+
+        let mut query = Query::parse(query);
+        let mut corrected = false;
+        for clause in query
+            .clauses
+            .iter_mut()
+            .filter(|clause| clause.field.is_none())
+            .filter(|clause| {
+                split_custom_field(&clause.text, self.inverted_index_db.fields()).is_none()
+            })
+        {
+            let words = self.tokenizer.words(&clause.text);
+            if words
+                .iter()
+                .all(|word| spell_checker.correct(word).is_none())
+            {
+                continue;
+            }
+
+            corrected = true;
+            clause.text = words
+                .iter()
+                .map(|word| spell_checker.correct(word).unwrap_or(word))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+
+        corrected.then(|| query.to_string())
+    }
+
+    /// See [`DiskInvertedIndex::term_stats`], for the term `word` analyzes
+    /// to. `None` for words analyzed away, like stopwords.
+    pub fn term_stats(&self, word: &str) -> Result<Option<(String, TermStats)>> {
+        let Some(term) = self.tokenizer.tokenize(word).into_iter().next() else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .inverted_index_db
+            .term_stats(&term)?
+            .map(|stats| (term, stats)))
+    }
+
+    /// Postings of the term `word` analyzes to in doc ID order, for
+    /// applications building their own retrieval with [`DocSet`]. Empty for
+    /// words analyzed away and terms not in the index.
+    ///
+    /// [`DocSet`]: super::doc_set::DocSet
+    pub fn postings(&self, word: &str) -> Result<Vec<TermIndex>> {
+        let Some(term) = self.tokenizer.tokenize(word).into_iter().next() else {
+            return Ok(Vec::new());
+        };
+
+        let mut postings = Vec::new();
+        self.for_each_posting(&term, self.scorer(&self.ranking), |doc_id, tf_idf| {
+            postings.push(TermIndex { doc_id, tf_idf });
+        })?;
+        if !postings.is_sorted_by_key(|posting| posting.doc_id) {
+            postings.sort_by_key(|posting| posting.doc_id);
+        }
+
+        Ok(postings)
+    }
+
+    /// See [`DiskInvertedIndex::preload`].
+    pub fn preload(&mut self, num_terms: usize) -> Result<usize> {
+        self.inverted_index_db.preload(num_terms)
+    }
+
+    /// See [`DiskInvertedIndex::memory_stats`].
+    #[must_use]
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.inverted_index_db.memory_stats()
+    }
+
+    /// See [`DiskInvertedIndex::build_report`].
+    #[must_use]
+    pub const fn build_report(&self) -> Option<&BuildReport> {
+        self.inverted_index_db.build_report()
+    }
+
+    /// Checks that the underlying index files are accessible and consistent.
+    pub fn verify(&self) -> Result<()> {
+        self.inverted_index_db.verify()
+    }
+
+    pub(super) fn rank(&self, query: &str) -> Result<Vec<(u64, f64)>> {
+        self.rank_with(query, &self.ranking, None)
+            .map(|(ranked, _)| ranked)
+    }
+
+    /// Ranks the matches of `query`, filling in `trace` when given. Also
+    /// returns whether the query hit one of the engine's [`QueryLimits`].
+    fn rank_with(
+        &self,
+        query: &str,
+        ranking: &RankingConfig,
+        mut trace: Option<&mut QueryTrace>,
+    ) -> Result<(Vec<(u64, f64)>, bool)> {
+        let boolean = BooleanQuery::parse(query, ranking.match_mode);
+        let flat = boolean
+            .as_ref()
+            .map_or_else(|| Query::parse(query), BooleanQuery::scored_clauses);
+        let mut weighted =
+            ranking.weighted_query(&flat, &self.tokenizer, self.inverted_index_db.fields());
+        let budget = if boolean.is_some() {
+            self.fits_postings_budget(&weighted)
+        } else {
+            self.fit_postings_budget(&weighted.required, &mut weighted.optional)
+        };
+        let mut partial_results = budget != Budget::Fits;
+        let mut scored_terms: Vec<_> = weighted
+            .required
+            .iter()
+            .chain(&weighted.optional)
+            .filter(|(_, weight)| *weight > 0.0)
+            .map(|(term, _)| term.as_str())
+            .collect();
+        scored_terms.sort_unstable();
+        scored_terms.dedup();
+        if let Some(trace) = trace.as_deref_mut() {
+            *trace = QueryTrace::new(
+                &self.inverted_index_db,
+                &weighted.required,
+                &weighted.optional,
+            );
+            if budget == Budget::Exceeded {
+                trace.postings_read = 0;
+                trace.bytes_read = 0;
+                trace.cache_hits = 0;
+            }
+        }
+        let document_ids = if budget == Budget::Exceeded {
+            HashMap::new()
+        } else if let Some(boolean) = &boolean {
+            let mut scores = self
+                .evaluate(boolean, ranking, &mut partial_results)?
+                .unwrap_or_default();
+            partial_results |= self.cap_candidates(&mut scores);
+            scores
+        } else {
+            let (scores, capped) = self.match_weighted(&weighted, self.scorer(ranking))?;
+            partial_results |= capped;
+            scores
+        };
+
+        let index = &self.inverted_index_db;
+        let num_matches = document_ids.len();
+        let mut document_ids: Vec<_> = document_ids
+            .into_iter()
+            .filter(|(doc_id, _)| !(ranking.safe_search && index.is_flagged(*doc_id)))
             .map(|(doc_id, score)| {
+                let quality = index.quality(doc_id).powf(ranking.quality_weight);
+                let recency = ranking.recency(index.age(doc_id));
+                (doc_id, score * quality * index.boost(doc_id) * recency)
+            })
+            .collect();
+        document_ids.sort_by(rank_order);
+        if ranking.proximity_weight > 0.0 {
+            self.rerank_by_proximity(&mut document_ids, &scored_terms, ranking.proximity_weight)?;
+        }
+        let num_diversified = if ranking.diversity > 0.0 {
+            self.diversify(&mut document_ids, ranking.diversity)?
+        } else {
+            0
+        };
+
+        if let Some(trace) = trace {
+            trace.docs_scored = num_matches;
+            trace.docs_filtered = num_matches - document_ids.len();
+            trace.docs_diversified = num_diversified;
+        }
+
+        Ok((document_ids, partial_results))
+    }
+
+    /// Scores of the documents `weighted` matches under `model`. Also returns
+    /// whether [`QueryLimits::max_candidates`] left some out.
+    fn match_weighted(
+        &self,
+        weighted: &WeightedQuery,
+        model: RankingModel,
+    ) -> Result<(HashMap<u64, f64>, bool)> {
+        if weighted.required.is_empty() {
+            let mut scores = self.accumulate(&weighted.optional, model)?;
+            let capped = self.cap_candidates(&mut scores);
+            return Ok((scores, capped));
+        }
+
+        let (mut scores, capped) = self.intersect(&weighted.required, model)?;
+        for phrase in &weighted.phrases {
+            self.retain_phrase(&mut scores, phrase)?;
+        }
+        for (term, weight) in &weighted.optional {
+            self.for_each_posting(term, model, |doc_id, tf_idf| {
+                if let Some(score) = scores.get_mut(&doc_id) {
+                    *score += weight * tf_idf;
+                }
+            })?;
+        }
+
+        Ok((scores, capped))
+    }
+
+    /// Scores of the documents `query` matches, each the sum of the scores of
+    /// the clauses it matches. `None` for clauses left without terms, like
+    /// ones made only of stopwords, which don't constrain matches. Sets
+    /// `capped` when [`QueryLimits::max_candidates`] left some out.
+    fn evaluate(
+        &self,
+        query: &BooleanQuery,
+        ranking: &RankingConfig,
+        capped: &mut bool,
+    ) -> Result<Option<HashMap<u64, f64>>> {
+        match query {
+            BooleanQuery::Clause(clause) => {
+                let query = Query {
+                    clauses: vec![clause.clone()],
+                };
+                let weighted = ranking.weighted_query(
+                    &query,
+                    &self.tokenizer,
+                    self.inverted_index_db.fields(),
+                );
+                if weighted.required.is_empty() && weighted.optional.is_empty() {
+                    return Ok(None);
+                }
+
+                let (scores, clause_capped) =
+                    self.match_weighted(&weighted, self.scorer(ranking))?;
+                *capped |= clause_capped;
+                Ok(Some(scores))
+            }
+            BooleanQuery::And(operands) => {
+                let mut scores: Option<HashMap<u64, f64>> = None;
+                let mut negated = Vec::new();
+                for operand in operands {
+                    if let BooleanQuery::Not(operand) = operand {
+                        negated.push(operand);
+                        continue;
+                    }
+                    if scores.as_ref().is_some_and(HashMap::is_empty) {
+                        break;
+                    }
+                    let Some(matches) = self.evaluate(operand, ranking, capped)? else {
+                        continue;
+                    };
+                    scores = Some(match scores {
+                        None => matches,
+                        Some(mut scores) => {
+                            scores.retain(|doc_id, _| matches.contains_key(doc_id));
+                            for (doc_id, score) in &mut scores {
+                                *score += matches[doc_id];
+                            }
+                            scores
+                        }
+                    });
+                }
+
+                if scores.is_none() && !negated.is_empty() {
+                    scores = Some(HashMap::new());
+                }
+                if let Some(scores) = &mut scores {
+                    for operand in negated {
+                        if scores.is_empty() {
+                            break;
+                        }
+                        if let Some(matches) = self.evaluate(operand, ranking, capped)? {
+                            scores.retain(|doc_id, _| !matches.contains_key(doc_id));
+                        }
+                    }
+                }
+
+                Ok(scores)
+            }
+            BooleanQuery::Or(operands) => {
+                let mut scores = None;
+                for operand in operands {
+                    if let Some(matches) = self.evaluate(operand, ranking, capped)? {
+                        scores = Some(match scores {
+                            None => matches,
+                            Some(scores) => merge_scores(scores, matches),
+                        });
+                    }
+                }
+
+                Ok(scores)
+            }
+            BooleanQuery::Not(_) => Ok(Some(HashMap::new())),
+        }
+    }
+
+    /// Whether the postings of every term of `weighted` fit in
+    /// `max_postings`. Boolean queries can't drop terms to fit, so they are
+    /// never [`Budget::Trimmed`].
+    fn fits_postings_budget(&self, weighted: &WeightedQuery) -> Budget {
+        let max_postings = self.limits.max_postings;
+        let postings: u64 = weighted
+            .required
+            .iter()
+            .chain(&weighted.optional)
+            .map(|(term, _)| self.inverted_index_db.doc_frequency(term))
+            .sum();
+
+        if max_postings == 0 || postings <= max_postings {
+            Budget::Fits
+        } else {
+            Budget::Exceeded
+        }
+    }
+
+    /// Drops the `optional` terms whose postings would take the query past
+    /// `max_postings`, keeping the rarest ones first.
+    fn fit_postings_budget(
+        &self,
+        required: &[WeightedTerm],
+        optional: &mut Vec<WeightedTerm>,
+    ) -> Budget {
+        let max_postings = self.limits.max_postings;
+        if max_postings == 0 {
+            return Budget::Fits;
+        }
+
+        let index = &self.inverted_index_db;
+        let required_postings = required
+            .iter()
+            .map(|(term, _)| index.doc_frequency(term))
+            .sum();
+        let Some(mut budget) = max_postings.checked_sub(required_postings) else {
+            return Budget::Exceeded;
+        };
+
+        let mut by_frequency: Vec<_> = optional
+            .iter()
+            .map(|(term, _)| index.doc_frequency(term))
+            .enumerate()
+            .collect();
+        by_frequency.sort_by_key(|&(_, postings)| postings);
+        let mut keep = vec![false; optional.len()];
+        for (i, postings) in by_frequency {
+            if postings <= budget {
+                budget -= postings;
+                keep[i] = true;
+            }
+        }
+
+        let num_terms = optional.len();
+        let mut keep = keep.into_iter();
+        optional.retain(|_| keep.next().unwrap_or(false));
+        if optional.len() < num_terms {
+            Budget::Trimmed
+        } else {
+            Budget::Fits
+        }
+    }
+
+    /// Leaves the first `max_candidates` matches in doc ID order in `scores`,
+    /// the same ones an intersection stopping early keeps. Returns whether it
+    /// left any out.
+    fn cap_candidates(&self, scores: &mut HashMap<u64, f64>) -> bool {
+        let max_candidates = self.limits.max_candidates;
+        if max_candidates == 0 || scores.len() <= max_candidates {
+            return false;
+        }
+
+        let mut doc_ids: Vec<_> = scores.keys().copied().collect();
+        let (_, &mut cutoff, _) = doc_ids.select_nth_unstable(max_candidates);
+        scores.retain(|&doc_id, _| doc_id < cutoff);
+        true
+    }
+
+    /// Leaves out of `scores` the documents without the terms of `phrase`
+    /// next to each other in order. Indexes without term positions can't
+    /// tell, so their matches are kept.
+    fn retain_phrase(&self, scores: &mut HashMap<u64, f64>, phrase: &[String]) -> Result<()> {
+        if !self.inverted_index_db.has_positions() {
+            return Ok(());
+        }
+
+        let mut misses = Vec::new();
+        for &doc_id in scores.keys() {
+            let positions = self
+                .inverted_index_db
+                .doc_positions(doc_id)?
+                .unwrap_or_default();
+            let lists: Option<Vec<_>> = phrase
+                .iter()
+                .map(|term| {
+                    let i = positions
+                        .binary_search_by(|(other, _)| other.as_str().cmp(term))
+                        .ok()?;
+                    Some(positions[i].1.as_slice())
+                })
+                .collect();
+            if !lists.is_some_and(|lists| contains_phrase(&lists)) {
+                misses.push(doc_id);
+            }
+        }
+        for doc_id in misses {
+            scores.remove(&doc_id);
+        }
+
+        Ok(())
+    }
+
+    /// Multiplies the scores at the top of `ranked` by the
+    /// [`proximity_boost`] of the `terms` of the query in each document, and
+    /// reorders it. Indexes without term positions keep their ranking.
+    fn rerank_by_proximity(
+        &self,
+        ranked: &mut [(u64, f64)],
+        terms: &[&str],
+        weight: f64,
+    ) -> Result<()> {
+        if terms.len() < 2 || !self.inverted_index_db.has_positions() {
+            return Ok(());
+        }
+
+        let num_candidates = ranked.len().min(PROXIMITY_CANDIDATES);
+        let top = &mut ranked[..num_candidates];
+        for (doc_id, score) in top.iter_mut() {
+            let Some(positions) = self.inverted_index_db.doc_positions(*doc_id)? else {
+                continue;
+            };
+            let lists: Vec<_> = positions
+                .iter()
+                .filter(|(term, _)| terms.contains(&term.as_str()))
+                .map(|(_, positions)| positions.as_slice())
+                .collect();
+            *score *= proximity_boost(&lists, weight);
+        }
+        top.sort_by(rank_order);
+
+        Ok(())
+    }
+
+    /// Reorders the top of `ranked` with [`mmr_order`] and returns how many
+    /// documents it reordered. Indexes without a forward index keep their
+    /// ranking.
+    fn diversify(&self, ranked: &mut [(u64, f64)], diversity: f64) -> Result<usize> {
+        if !self.inverted_index_db.has_forward_index() {
+            return Ok(0);
+        }
+
+        let num_candidates = ranked.len().min(DIVERSIFY_CANDIDATES);
+        let top = &mut ranked[..num_candidates];
+        let terms = top
+            .iter()
+            .map(|(doc_id, _)| {
                 self.inverted_index_db
-                    .get_doc(doc_id)
-                    .and_then(|doc_opt| {
-                        doc_opt.ok_or_else(|| Error::Generic("Document not found".to_string()))
-                    })
-                    .map(|doc| SearchResult::new(doc.url, score))
+                    .doc_terms(*doc_id)
+                    .map(Option::unwrap_or_default)
             })
-            .collect()
+            .collect::<Result<Vec<_>>>()?;
+        let diversified = mmr_order(top, &terms, diversity.min(1.0));
+        top.copy_from_slice(&diversified);
+
+        Ok(num_candidates)
+    }
+
+    /// Fetches and decodes the postings of every term on the rayon pool, so the
+    /// reads of a multi-term query overlap, and merges the partial scores.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn accumulate(&self, terms: &[WeightedTerm], model: RankingModel) -> Result<HashMap<u64, f64>> {
+        terms
+            .par_iter()
+            .try_fold(HashMap::new, |scores, term| {
+                self.add_scores(scores, term, model)
+            })
+            .try_reduce(HashMap::new, |a, b| Ok(merge_scores(a, b)))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn accumulate(&self, terms: &[WeightedTerm], model: RankingModel) -> Result<HashMap<u64, f64>> {
+        terms.iter().try_fold(HashMap::new(), |scores, term| {
+            self.add_scores(scores, term, model)
+        })
+    }
+
+    /// The rarest list leads the intersection and the others skip ahead to
+    /// its documents. A term missing from the index ends the query before any
+    /// postings are read. Stops after `max_candidates` matches, returning
+    /// whether more were left.
+    fn intersect(
+        &self,
+        weighted_terms: &[WeightedTerm],
+        model: RankingModel,
+    ) -> Result<(HashMap<u64, f64>, bool)> {
+        if weighted_terms
+            .iter()
+            .any(|(term, _)| self.inverted_index_db.postings_len(term).is_none())
+        {
+            return Ok((HashMap::new(), false));
+        }
+
+        let lists = weighted_terms
+            .iter()
+            .map(|term| self.decoded_postings(term, model))
+            .collect::<Result<_>>()?;
+        let mut intersection = Intersection::new(lists);
+
+        let mut scores = HashMap::new();
+        while let Some(doc_id) = intersection.next_doc() {
+            if self.limits.max_candidates > 0 && scores.len() == self.limits.max_candidates {
+                return Ok((scores, true));
+            }
+            scores.insert(doc_id, intersection.score());
+        }
+
+        Ok((scores, false))
+    }
+
+    fn decoded_postings(
+        &self,
+        (term, weight): &WeightedTerm,
+        model: RankingModel,
+    ) -> Result<DecodedPostings> {
+        let mut postings = Vec::new();
+        self.for_each_posting(term, model, |doc_id, tf_idf| {
+            postings.push(TermIndex { doc_id, tf_idf });
+        })?;
+
+        Ok(DecodedPostings::new(postings, *weight))
+    }
+
+    fn add_scores(
+        &self,
+        mut scores: HashMap<u64, f64>,
+        (term, weight): &WeightedTerm,
+        model: RankingModel,
+    ) -> Result<HashMap<u64, f64>> {
+        self.for_each_posting(term, model, |doc_id, tf_idf| {
+            *scores.entry(doc_id).or_insert(0.0) += weight * tf_idf;
+        })?;
+
+        Ok(scores)
+    }
+
+    /// Hands `f` the doc ID and score of every posting of `token`, scored by
+    /// `model`.
+    fn for_each_posting<F>(&self, token: &str, model: RankingModel, mut f: F) -> Result<()>
+    where
+        F: FnMut(u64, f64),
+    {
+        let score = self.term_scorer(token, model)?;
+        READ_BUFFER.with_borrow_mut(|buffer| {
+            self.inverted_index_db
+                .for_each_posting(token, buffer, |doc_id, tf_idf| {
+                    f(doc_id, score(doc_id, tf_idf));
+                })
+        })
+    }
+
+    /// Turns the stored tf-idf of a posting of `term` into its score under
+    /// `model`, see [`SearchEngine::scorer`]. BM25 reads the term frequencies
+    /// the build kept, and counts documents as they are now, deleted ones
+    /// left out. Documents without a length get the average one.
+    fn term_scorer(
+        &self,
+        term: &str,
+        model: RankingModel,
+    ) -> Result<impl Fn(u64, f64) -> f64 + '_> {
+        let index = &self.inverted_index_db;
+        let (tfs, idf, average_len) = match model {
+            RankingModel::TfIdf => (Vec::new(), 0.0, 1.0),
+            RankingModel::Bm25 { .. } => {
+                let tfs = index.term_frequencies(term)?;
+                let df = tfs.len() as f64;
+                let num_docs = (index.num_docs() as f64).max(df);
+                let idf = ((num_docs - df + 0.5) / (df + 0.5)).ln_1p();
+                let average_len = index.average_doc_length().filter(|&len| len > 0.0);
+                (tfs, idf, average_len.unwrap_or(1.0))
+            }
+        };
+
+        Ok(move |doc_id, tf_idf| match model {
+            RankingModel::TfIdf => tf_idf,
+            RankingModel::Bm25 { k1, b } => {
+                let tf = tfs
+                    .binary_search_by_key(&doc_id, |&(doc_id, _)| doc_id)
+                    .map_or(0.0, |i| f64::from(tfs[i].1));
+                let len = index
+                    .doc_length(doc_id)
+                    .map_or(average_len, |len| len as f64);
+                let norm = k1 * (1.0 - b + b * len / average_len);
+                idf * tf * (k1 + 1.0) / (tf + norm)
+            }
+        })
+    }
+
+    /// The result for `doc_id` scored `score`, with the url and title of the
+    /// document.
+    pub fn resolve(&self, doc_id: u64, score: f64) -> Result<SearchResult> {
+        self.inverted_index_db
+            .get_doc(doc_id)
+            .and_then(|doc_opt| doc_opt.ok_or(Error::MissingDoc { doc_id }))
+            .map(|doc| {
+                SearchResult::new(doc.url, score)
+                    .with_title(doc.title)
+                    .with_fields(doc.fields)
+            })
+    }
+}
+
+/// Best score first, with NaN scores last and ties in doc ID order, so the
+/// same query always ranks the same way.
+pub(super) fn rank_order(a: &(u64, f64), b: &(u64, f64)) -> Ordering {
+    let score = |score: f64| {
+        if score.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            score
+        }
+    };
+
+    score(b.1)
+        .total_cmp(&score(a.1))
+        .then_with(|| a.0.cmp(&b.0))
+}
+
+/// Adds the smaller map into the larger one.
+fn merge_scores(a: HashMap<u64, f64>, b: HashMap<u64, f64>) -> HashMap<u64, f64> {
+    let (mut into, from) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    for (doc_id, score) in from {
+        *into.entry(doc_id).or_insert(0.0) += score;
+    }
+
+    into
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<R: ReadAt + Send + 'static> SearchEngine<R> {
+    /// Runs [`SearchEngine::search_streaming`] on tokio's blocking pool, so
+    /// async servers don't stall their runtime on index reads. Returns the
+    /// results along with the [`SearchOutcome`], holding a trace if `options`
+    /// asked for one.
+    pub async fn search_async(
+        self: Arc<Self>,
+        query: String,
+        options: SearchOptions,
+    ) -> Result<(Vec<SearchResult>, SearchOutcome)> {
+        task::spawn_blocking(move || {
+            let ranking = options.ranking.as_ref().unwrap_or_else(|| self.ranking());
+            let mut results = Vec::new();
+            let outcome = self.search_streaming_traced(
+                &query,
+                ranking,
+                options.limit,
+                options.trace,
+                |result| {
+                    results.push(result);
+                    true
+                },
+            )?;
+
+            Ok((results, outcome))
+        })
+        .await
+        .map_err(Error::task_failed("Search"))?
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::inverted_index::{
+        disk_inverted_index::{BuildOptions, CrawlFile, MemoryInvertedIndex},
+        doc_filter::KeywordFilter,
+    };
+    use crate::search::doc_set::DocSet;
+    use crate::test_util::{page, TestDir};
+    use std::collections::BTreeMap;
 
     #[test]
+    #[allow(clippy::float_cmp)]
     fn test_search() {
-        let mut search_engine = SearchEngine::new(
+        let search_engine = SearchEngine::new(
             DiskInvertedIndex::from(
                 "tests/test-data/search_test_db.test".into(),
                 "tests/test-data/search_test_seek.test".into(),
@@ -68,7 +1013,7 @@ mod tests {
         )
         .expect("Failed to create search engine");
 
-        let results = search_engine.search("eric").unwrap();
+        let results = search_engine.search("eric").expect("Failed to search");
         assert_eq!(results.len(), 3);
 
         assert_eq!(results[0].url, "https://www.ericminassian.com/");
@@ -82,9 +1027,812 @@ mod tests {
         assert_eq!(results[2].score, 1.2);
     }
 
+    #[test]
+    fn rank_order_is_deterministic() {
+        let mut ranked = [
+            (4, 1.0),
+            (3, f64::NAN),
+            (2, 2.0),
+            (1, 1.0),
+            (0, f64::NEG_INFINITY),
+        ];
+        ranked.sort_by(rank_order);
+
+        let doc_ids: Vec<_> = ranked.iter().map(|(doc_id, _)| *doc_id).collect();
+        assert_eq!(doc_ids, vec![2, 1, 4, 0, 3]);
+    }
+
+    #[test]
+    fn more_like_this() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("more_like_this.db"),
+            dir.path("more_like_this.seek"),
+            dir.path("more_like_this_url_map.db"),
+            dir.path("more_like_this_url_map.seek"),
+            [
+                page("fruit", "apple pear banana"),
+                page("orchard", "apple pear cherry"),
+                page("rust", "rust cargo crate"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+
+        let results = search_engine
+            .more_like_this(0, 5)
+            .expect("Failed to find similar documents");
+        let urls: Vec<_> = results.iter().map(|result| result.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/orchard"]);
+
+        assert!(matches!(
+            search_engine.more_like_this(7, 5),
+            Err(Error::MissingDoc { doc_id: 7 })
+        ));
+
+        let legacy = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to open index"),
+        )
+        .expect("Failed to create search engine");
+        assert!(matches!(
+            legacy.more_like_this(0, 5),
+            Err(Error::MissingCompanion { .. })
+        ));
+    }
+
+    #[test]
+    fn postings_set_algebra() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+        let docs = |word| {
+            DocSet::from_postings(
+                &search_engine
+                    .postings(word)
+                    .expect("Failed to read postings"),
+            )
+        };
+
+        let eric = docs("Eric");
+        assert_eq!(eric.len(), 3);
+        assert!(docs("the").is_empty());
+        assert!(docs("unindexed").is_empty());
+
+        let minassian = docs("minassian");
+        assert_eq!(eric.intersect(&minassian), minassian);
+        assert_eq!(eric.union(&minassian), eric);
+        let rest = eric.difference(&minassian);
+        assert_eq!(rest.iter().copied().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(
+            search_engine
+                .resolve(0, 1.0)
+                .expect("Failed to resolve")
+                .url,
+            "https://www.ericminassian.com/"
+        );
+    }
+
+    #[test]
+    fn diversify() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("diversify.db"),
+            dir.path("diversify.seek"),
+            dir.path("diversify_url_map.db"),
+            dir.path("diversify_url_map.seek"),
+            [
+                page("carbonara", "pasta pasta pasta carbonara guanciale"),
+                page(
+                    "carbonara-copy",
+                    "pasta pasta pasta carbonara guanciale egg",
+                ),
+                page("pesto", "pasta pasta basil pine nuts"),
+                page("rust", "rust cargo"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |diversity| {
+            let ranking = RankingConfig {
+                diversity,
+                ..RankingConfig::default()
+            };
+            let mut urls = Vec::new();
+            search_engine
+                .search_streaming_with("pasta", &ranking, 3, |result| {
+                    urls.push(result.url);
+                    true
+                })
+                .expect("Failed to search");
+            urls
+        };
+
+        assert_eq!(
+            urls(0.0),
+            [
+                "https://example.com/carbonara",
+                "https://example.com/carbonara-copy",
+                "https://example.com/pesto"
+            ]
+        );
+        assert_eq!(
+            urls(0.5),
+            [
+                "https://example.com/carbonara",
+                "https://example.com/pesto",
+                "https://example.com/carbonara-copy"
+            ]
+        );
+    }
+
+    #[test]
+    fn did_you_mean() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("did_you_mean.db"),
+            dir.path("did_you_mean.seek"),
+            dir.path("did_you_mean_url_map.db"),
+            dir.path("did_you_mean_url_map.seek"),
+            [
+                page("carbonara", "Pasta recipes"),
+                page("pesto", "pasta recipes"),
+                page("glue", "paste"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+
+        assert_eq!(
+            search_engine.did_you_mean("Pasts +recipse^2"),
+            Some("pasta +recipes^2".to_string())
+        );
+        assert_eq!(search_engine.did_you_mean("pasta recipes"), None);
+    }
+
+    #[test]
+    fn safe_search() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_filtered(
+            dir.path("safe_search.db"),
+            dir.path("safe_search.seek"),
+            dir.path("safe_search_url_map.db"),
+            dir.path("safe_search_url_map.seek"),
+            [
+                page("pasta", "pasta recipes"),
+                page("casino", "pasta casino bonus"),
+            ]
+            .map(Ok),
+            &KeywordFilter::new(&["casino".to_string()]),
+        )
+        .expect("Failed to build index");
+        let flagged = index
+            .get_doc(1)
+            .expect("Failed to read doc")
+            .expect("Document should exist")
+            .flagged;
+        assert!(flagged);
+
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let results = |safe_search| {
+            let ranking = RankingConfig {
+                safe_search,
+                ..RankingConfig::default()
+            };
+            let mut urls = Vec::new();
+            let total = search_engine
+                .search_streaming_with("pasta", &ranking, 10, |result| {
+                    urls.push(result.url);
+                    true
+                })
+                .expect("Failed to search");
+            (urls.len(), total)
+        };
+
+        assert_eq!(results(false), (2, 2));
+        assert_eq!(results(true), (1, 1));
+    }
+
+    #[test]
+    fn doc_boost() {
+        let dir = TestDir::new();
+        let page = |name: &str, boost| {
+            Ok(CrawlFile {
+                boost,
+                ..page(name, &format!("{name} recipes"))
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("doc_boost.db"),
+            dir.path("doc_boost.seek"),
+            dir.path("doc_boost_url_map.db"),
+            dir.path("doc_boost_url_map.seek"),
+            [
+                page("pasta-demoted", Some(0.5)),
+                page("pasta-plain", None),
+                page("pasta-pinned", Some(3.0)),
+                page("pesto", None),
+            ],
+        )
+        .expect("Failed to build index");
+        assert_eq!(
+            index
+                .get_doc(2)
+                .expect("Failed to read doc")
+                .expect("Document should exist")
+                .boost,
+            Some(3.0)
+        );
+
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let results = search_engine.search("pasta").expect("Failed to search");
+        let urls: Vec<_> = results.iter().map(|result| result.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/pasta-pinned",
+                "https://example.com/pasta-plain",
+                "https://example.com/pasta-demoted"
+            ]
+        );
+        assert!((results[0].score / results[1].score - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn recency() {
+        let dir = TestDir::new();
+        let day = 86_400;
+        let page = |name: &str, text: &str, crawled_at| {
+            Ok(CrawlFile {
+                crawled_at,
+                ..page(name, text)
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("recency.db"),
+            dir.path("recency.seek"),
+            dir.path("recency_url_map.db"),
+            dir.path("recency_url_map.seek"),
+            [
+                page("old", "old news", Some(1_700_000_000)),
+                page("undated", "undated news", None),
+                page("new", "new news", Some(1_700_000_000 + 10 * day)),
+                page("weather", "sunny weather", Some(1_700_000_000)),
+            ],
+        )
+        .expect("Failed to build index");
+        assert_eq!(index.age(0), Some(10 * day));
+        assert_eq!(index.age(1), None);
+
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |recency_half_life_days| {
+            let ranking = RankingConfig {
+                recency_half_life_days,
+                ..RankingConfig::default()
+            };
+            let mut urls = Vec::new();
+            search_engine
+                .search_streaming_with("news", &ranking, 10, |result| {
+                    urls.push(
+                        result
+                            .url
+                            .trim_start_matches("https://example.com/")
+                            .to_string(),
+                    );
+                    true
+                })
+                .expect("Failed to search");
+            urls
+        };
+
+        // Equally relevant, so only recency tells them apart
+        assert_eq!(urls(0.0), ["old", "undated", "new"]);
+        assert_eq!(urls(1.0), ["new", "old", "undated"]);
+    }
+
+    #[test]
+    fn proximity() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("proximity.db"),
+            dir.path("proximity.seek"),
+            dir.path("proximity_url_map.db"),
+            dir.path("proximity_url_map.seek"),
+            [
+                page("apart", "learning to cook takes time and a machine"),
+                page("adjacent", "machine learning takes time to cook"),
+                page("pasta", "pasta recipes"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |proximity_weight| {
+            let ranking = RankingConfig {
+                proximity_weight,
+                ..RankingConfig::default()
+            };
+            let mut urls = Vec::new();
+            search_engine
+                .search_streaming_with("machine learning", &ranking, 10, |result| {
+                    urls.push(
+                        result
+                            .url
+                            .trim_start_matches("https://example.com/")
+                            .to_string(),
+                    );
+                    true
+                })
+                .expect("Failed to search");
+            urls
+        };
+
+        // Equally relevant, so only proximity tells them apart
+        assert_eq!(urls(0.0), ["apart", "adjacent"]);
+        assert_eq!(urls(0.5), ["adjacent", "apart"]);
+    }
+
+    #[test]
+    fn stopword_phrase() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("stopword_phrase.db"),
+            dir.path("stopword_phrase.seek"),
+            dir.path("stopword_phrase_url_map.db"),
+            dir.path("stopword_phrase_url_map.seek"),
+            [
+                page("hamlet", "to be or not to be that is the question"),
+                page("shuffled", "not to be or to be"),
+                page("pasta", "pasta recipes"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index)
+            .expect("Failed to create search engine")
+            .with_ranking(RankingConfig {
+                stopwords: ["to", "be", "or", "not"].map(String::from).to_vec(),
+                ..RankingConfig::default()
+            });
+
+        let results = search_engine
+            .search("to be or not to be")
+            .expect("Failed to search");
+        let urls: Vec<_> = results.iter().map(|result| result.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/hamlet"]);
+    }
+
+    #[test]
+    fn quoted_phrase() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("quoted_phrase.db"),
+            dir.path("quoted_phrase.seek"),
+            dir.path("quoted_phrase_url_map.db"),
+            dir.path("quoted_phrase_url_map.seek"),
+            [
+                page("ml", "an intro to machine learning"),
+                page("shuffled", "learning about a machine"),
+                page("tools", "machine tools"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |query: &str| {
+            let mut urls: Vec<_> = search_engine
+                .search(query)
+                .expect("Failed to search")
+                .into_iter()
+                .map(|result| result.url)
+                .collect();
+            urls.sort_unstable();
+            urls
+        };
+
+        assert_eq!(urls("\"machine learning\""), ["https://example.com/ml"]);
+        assert_eq!(
+            urls("\"machine learning\" tools"),
+            ["https://example.com/ml"]
+        );
+        assert_eq!(urls("machine learning").len(), 3);
+    }
+
+    #[test]
+    fn boolean_query() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("boolean_query.db"),
+            dir.path("boolean_query.seek"),
+            dir.path("boolean_query_url_map.db"),
+            dir.path("boolean_query_url_map.seek"),
+            [
+                page("rust", "rust web server"),
+                page("go", "go web server"),
+                page("java", "java web server"),
+                page("cli", "rust command line"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |query: &str| {
+            let mut urls: Vec<_> = search_engine
+                .search(query)
+                .expect("Failed to search")
+                .into_iter()
+                .map(|result| {
+                    result
+                        .url
+                        .trim_start_matches("https://example.com/")
+                        .to_string()
+                })
+                .collect();
+            urls.sort_unstable();
+            urls
+        };
+
+        assert_eq!(urls("rust AND server"), ["rust"]);
+        assert_eq!(urls("(rust OR go) AND web"), ["go", "rust"]);
+        assert_eq!(urls("server NOT (java OR go)"), ["rust"]);
+        assert_eq!(urls("rust OR java NOT web"), ["cli", "rust"]);
+        assert!(urls("NOT rust").is_empty());
+    }
+
+    #[test]
+    fn bm25() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("bm25.db"),
+            dir.path("bm25.seek"),
+            dir.path("bm25_url_map.db"),
+            dir.path("bm25_url_map.seek"),
+            [
+                page("short", "apple"),
+                page("long", &format!("apple apple {}", "filler ".repeat(40))),
+                page("other", "banana"),
+            ]
+            .map(Ok),
+        )
+        .expect("Failed to build index");
+        assert_eq!(index.doc_length(1), Some(42));
+        assert_eq!(
+            index
+                .term_frequencies("appl")
+                .expect("Failed to read term frequencies"),
+            [(0, 1), (1, 2)]
+        );
+
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let top = |scorer| {
+            let ranking = RankingConfig {
+                scorer,
+                ..RankingConfig::default()
+            };
+            let mut top = None;
+            search_engine
+                .search_streaming_with("apple", &ranking, 1, |result| {
+                    top = Some(result.url);
+                    false
+                })
+                .expect("Failed to search");
+            top.expect("Search should find apple")
+        };
+        assert_eq!(top(RankingModel::TfIdf), "https://example.com/long");
+        assert_eq!(top(RankingModel::BM25), "https://example.com/short");
+        assert_eq!(
+            top(RankingModel::Bm25 { k1: 1.2, b: 0.0 }),
+            "https://example.com/long"
+        );
+    }
+
+    #[test]
+    fn build_analyzer() {
+        let dir = TestDir::new();
+        let (index, _) = DiskInvertedIndex::build_with(
+            dir.path("build_analyzer.db"),
+            dir.path("build_analyzer.seek"),
+            dir.path("build_analyzer_url_map.db"),
+            dir.path("build_analyzer_url_map.seek"),
+            [Ok(page("", "Running tests"))],
+            BuildOptions {
+                analyzer: Analyzer::Simple,
+                ..BuildOptions::default()
+            },
+        )
+        .expect("Failed to build index");
+        assert_eq!(index.analyzer(), Some(Analyzer::Simple));
+        assert!(index
+            .get("running")
+            .expect("Failed to read postings")
+            .is_some());
+        assert!(index.get("run").expect("Failed to read postings").is_none());
+
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        assert_eq!(
+            search_engine.search("running").expect("Failed to search")[0].url,
+            "https://example.com/"
+        );
+
+        let reopened = DiskInvertedIndex::from(
+            dir.path("build_analyzer.db"),
+            dir.path("build_analyzer.seek"),
+            dir.path("build_analyzer_url_map.db"),
+            dir.path("build_analyzer_url_map.seek"),
+        )
+        .expect("Failed to open index");
+        assert!(matches!(
+            SearchEngine::with_analyzer(reopened, Analyzer::English),
+            Err(Error::AnalyzerMismatch {
+                built: Analyzer::Simple,
+                requested: Analyzer::English,
+                ..
+            })
+        ));
+    }
+
+    fn bm25_engine(dir: &TestDir, name: &str, pages: &[(&str, &str)]) -> SearchEngine {
+        let documents = pages.iter().map(|(name, text)| Ok(page(name, text)));
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path(format!("{name}.db")),
+            dir.path(format!("{name}.seek")),
+            dir.path(format!("{name}_url_map.db")),
+            dir.path(format!("{name}_url_map.seek")),
+            documents,
+        )
+        .expect("Failed to build index");
+
+        SearchEngine::new(index)
+            .expect("Failed to create search engine")
+            .with_ranking(RankingConfig {
+                scorer: RankingModel::Bm25 { k1: 1.2, b: 0.0 },
+                ..RankingConfig::default()
+            })
+    }
+
+    fn scores(search_engine: &SearchEngine, query: &str) -> Vec<(String, f64)> {
+        search_engine
+            .search(query)
+            .expect("Failed to search")
+            .into_iter()
+            .map(|result| (result.url, result.score))
+            .collect()
+    }
+
+    #[test]
+    fn bm25_term_in_every_document() {
+        let dir = TestDir::new();
+        let search_engine = bm25_engine(
+            &dir,
+            "bm25_every_document",
+            &[("once", "apple pear"), ("thrice", "apple apple apple")],
+        );
+
+        let scores = scores(&search_engine, "apple");
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0].0, "https://example.com/thrice");
+        assert!(scores[0].1 > scores[1].1 && scores[1].1 > 0.0);
+    }
+
+    #[test]
+    fn bm25_without_term_frequencies() {
+        let dir = TestDir::new();
+        let pages = [("once", "apple pear"), ("twice", "apple apple")];
+        let tf_idf = scores(
+            &bm25_engine(&dir, "bm25_legacy", &pages).with_ranking(RankingConfig::default()),
+            "apple",
+        );
+
+        // Indexes built before term frequencies were kept score with tf-idf
+        for path in ["bm25_legacy.db", "bm25_legacy.seek"] {
+            std::fs::remove_file(DiskInvertedIndex::term_freqs_path(&dir.path(path)))
+                .expect("Failed to remove term frequencies");
+        }
+        let index = DiskInvertedIndex::from(
+            dir.path("bm25_legacy.db"),
+            dir.path("bm25_legacy.seek"),
+            dir.path("bm25_legacy_url_map.db"),
+            dir.path("bm25_legacy_url_map.seek"),
+        )
+        .expect("Failed to open index");
+        let search_engine = SearchEngine::new(index)
+            .expect("Failed to create search engine")
+            .with_ranking(RankingConfig {
+                scorer: RankingModel::BM25,
+                ..RankingConfig::default()
+            });
+        assert_eq!(
+            search_engine.scorer(search_engine.ranking()),
+            RankingModel::TfIdf
+        );
+        assert_eq!(scores(&search_engine, "apple"), tf_idf);
+    }
+
+    #[test]
+    fn bm25_after_delete() {
+        let dir = TestDir::new();
+        let mut search_engine = bm25_engine(
+            &dir,
+            "bm25_deleted",
+            &[
+                ("deleted", "apple apple"),
+                ("kept", "apple"),
+                ("other", "banana"),
+            ],
+        );
+        let fresh = bm25_engine(
+            &dir,
+            "bm25_fresh",
+            &[("kept", "apple"), ("other", "banana")],
+        );
+
+        assert!(search_engine
+            .inverted_index_db
+            .delete_doc(0)
+            .expect("Failed to delete document"));
+        assert_eq!(
+            scores(&search_engine, "apple"),
+            scores(&fresh, "apple"),
+            "deleted"
+        );
+
+        let (index, _) = DiskInvertedIndex::compact(
+            dir.path("bm25_deleted.db"),
+            dir.path("bm25_deleted.seek"),
+            dir.path("bm25_deleted_url_map.db"),
+            dir.path("bm25_deleted_url_map.seek"),
+        )
+        .expect("Failed to compact index");
+        search_engine.inverted_index_db = index;
+        assert_eq!(
+            scores(&search_engine, "apple"),
+            scores(&fresh, "apple"),
+            "compacted"
+        );
+    }
+
+    #[test]
+    fn url_fields() {
+        let dir = TestDir::new();
+        let page = |url: &str, text: &str| {
+            Ok(CrawlFile {
+                url: url.to_string(),
+                ..page("", text)
+            })
+        };
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path("url_fields.db"),
+            dir.path("url_fields.seek"),
+            dir.path("url_fields_url_map.db"),
+            dir.path("url_fields_url_map.seek"),
+            [
+                page("https://docs.example.com/guide", "pasta guide"),
+                page("https://example.com/pasta", "pasta recipe"),
+                page("https://other.org/pasta", "pasta sauce"),
+                page("https://other.org/weather", "sunny weather"),
+            ],
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+        let urls = |query: &str| {
+            let mut urls: Vec<_> = search_engine
+                .search(query)
+                .expect("Failed to search")
+                .into_iter()
+                .map(|result| result.url)
+                .collect();
+            urls.sort();
+            urls
+        };
+
+        assert_eq!(
+            urls("pasta site:example.com"),
+            [
+                "https://docs.example.com/guide",
+                "https://example.com/pasta"
+            ]
+        );
+        assert_eq!(
+            urls("pasta site:DOCS.Example.com"),
+            ["https://docs.example.com/guide"]
+        );
+        assert_eq!(
+            urls("site:other.org"),
+            ["https://other.org/pasta", "https://other.org/weather"]
+        );
+        assert_eq!(
+            urls("url:http://other.org/pasta"),
+            ["https://other.org/pasta"]
+        );
+        // Url terms stay out of the analyzed text
+        assert!(urls("example").is_empty());
+        assert!(urls("pasta site:example").is_empty());
+    }
+
+    #[test]
+    fn custom_fields() {
+        let dir = TestDir::new();
+        let page = |url: &str, content: &str| {
+            Ok(CrawlFile {
+                url: url.to_string(),
+                content: content.to_string(),
+                ..page("", "")
+            })
+        };
+        let fields = BTreeMap::from([("author".to_string(), ".byline".to_string())]);
+        let (index, _) = DiskInvertedIndex::build_with(
+            dir.path("custom_fields.db"),
+            dir.path("custom_fields.seek"),
+            dir.path("custom_fields_url_map.db"),
+            dir.path("custom_fields_url_map.seek"),
+            [
+                page(
+                    "https://example.com/engines",
+                    "<p class=\"byline\">Ada Lovelace</p><p>analytical engines</p>",
+                ),
+                page(
+                    "https://example.com/babbage",
+                    "<p class=\"byline\">Charles Babbage</p><p>engines by Lovelace</p>",
+                ),
+                Ok(CrawlFile {
+                    fields: BTreeMap::from([("venue".to_string(), "Royal Society".to_string())]),
+                    ..page("https://example.com/notes", "<p>notes on engines</p>")
+                        .expect("Failed to create page")
+                }),
+            ],
+            BuildOptions {
+                fields: Some(&fields),
+                ..BuildOptions::default()
+            },
+        )
+        .expect("Failed to build index");
+        assert_eq!(index.fields(), ["author", "venue"]);
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+
+        let results = search_engine
+            .search("engines +author:lovelace")
+            .expect("Failed to search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://example.com/engines");
+        assert_eq!(results[0].fields["author"], "Ada Lovelace");
+        // Field text of the markup is also indexed as text, unlike fields
+        // documents come with
+        assert_eq!(
+            search_engine
+                .search("lovelace")
+                .expect("Failed to search")
+                .len(),
+            2
+        );
+        let results = search_engine
+            .search("venue:royal")
+            .expect("Failed to search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://example.com/notes");
+        assert!(search_engine
+            .search("royal")
+            .expect("Failed to search")
+            .is_empty());
+    }
+
     #[test]
     fn test_search_no_results() {
-        let mut search_engine = SearchEngine::new(
+        let search_engine = SearchEngine::new(
             DiskInvertedIndex::from(
                 "tests/test-data/search_test_db.test".into(),
                 "tests/test-data/search_test_seek.test".into(),
@@ -95,7 +1843,318 @@ mod tests {
         )
         .expect("Failed to create search engine");
 
-        let results = search_engine.search("not_in_index").unwrap();
+        let results = search_engine
+            .search("not_in_index")
+            .expect("Failed to search");
         assert_eq!(results.len(), 0);
     }
+
+    #[test]
+    fn test_search_in_memory() {
+        let read = |path: &str| std::fs::read(path).expect("Failed to read test data");
+
+        let search_engine = SearchEngine::new(
+            MemoryInvertedIndex::from_bytes(
+                read("tests/test-data/search_test_db.test"),
+                &read("tests/test-data/search_test_seek.test"),
+                read("tests/test-data/search_test_url_map.test"),
+                &read("tests/test-data/search_test_url_map_seek.test"),
+            )
+            .expect("Failed to load index from bytes"),
+        )
+        .expect("Failed to create search engine");
+
+        let results = search_engine.search("eric").expect("Failed to search");
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].url, "https://www.ericminassian.com/");
+    }
+
+    #[test]
+    fn test_search_all_terms() {
+        let open = |match_mode| {
+            SearchEngine::new(
+                DiskInvertedIndex::from(
+                    "tests/test-data/search_test_db.test".into(),
+                    "tests/test-data/search_test_seek.test".into(),
+                    "tests/test-data/search_test_url_map.test".into(),
+                    "tests/test-data/search_test_url_map_seek.test".into(),
+                )
+                .expect("Failed to create search engine"),
+            )
+            .expect("Failed to create search engine")
+            .with_match_mode(match_mode)
+        };
+
+        let any = open(MatchMode::Any)
+            .search("eric minassian")
+            .expect("Failed to search");
+        assert_eq!(any.len(), 3);
+        assert_eq!(any[0].url, "https://www.ericminassian.com/");
+
+        let all = open(MatchMode::All);
+        let results = all.search("eric minassian").expect("Failed to search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://www.github.com/eric-minassian");
+        assert!((results[0].score - 4.4).abs() < 1e-9);
+
+        assert!(all
+            .search("eric not_in_index")
+            .expect("Failed to search")
+            .is_empty());
+    }
+
+    #[test]
+    fn must_and_filter_clauses() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+        let scores = |query: &str| {
+            search_engine
+                .search(query)
+                .expect("Failed to search")
+                .into_iter()
+                .map(|result| (result.url, result.score))
+                .collect::<Vec<_>>()
+        };
+
+        let must = scores("+minassian eric");
+        assert_eq!(must.len(), 1);
+        assert_eq!(must[0].0, "https://www.github.com/eric-minassian");
+        assert!((must[0].1 - 4.4).abs() < 1e-9);
+
+        // Filters narrow the matches without scoring them
+        let eric = scores("eric");
+        let filtered = scores("eric filter:minassian");
+        assert_eq!(filtered.len(), 1);
+        assert!(eric.contains(&filtered[0]));
+
+        assert!(scores("eric +not_in_index").is_empty());
+    }
+
+    #[test]
+    fn test_search_concurrently() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+
+        std::thread::scope(|scope| {
+            let searches = (0..4)
+                .map(|_| scope.spawn(|| search_engine.search("eric")))
+                .collect::<Vec<_>>();
+
+            for search in searches {
+                let results = search
+                    .join()
+                    .expect("Search thread panicked")
+                    .expect("Failed to search");
+                assert_eq!(results.len(), 3);
+                assert_eq!(results[0].url, "https://www.ericminassian.com/");
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_search_async() {
+        let search_engine = Arc::new(
+            SearchEngine::new(
+                DiskInvertedIndex::from(
+                    "tests/test-data/search_test_db.test".into(),
+                    "tests/test-data/search_test_seek.test".into(),
+                    "tests/test-data/search_test_url_map.test".into(),
+                    "tests/test-data/search_test_url_map_seek.test".into(),
+                )
+                .expect("Failed to create search engine"),
+            )
+            .expect("Failed to create search engine"),
+        );
+
+        let options = SearchOptions {
+            limit: 2,
+            ..SearchOptions::default()
+        };
+        let (results, outcome) = search_engine
+            .search_async("eric".to_string(), options)
+            .await
+            .expect("Failed to search");
+
+        assert_eq!(outcome.total, 3);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].url, "https://www.ericminassian.com/");
+        assert_eq!(outcome.trace, None);
+        assert!(!outcome.partial_results);
+    }
+
+    #[tokio::test]
+    async fn task_failed() {
+        let source = task::spawn_blocking(|| panic!("search panicked"))
+            .await
+            .expect_err("Task should have panicked");
+        let error = Error::task_failed("Search")(source);
+        assert!(error.to_string().starts_with("Search task failed: "));
+        assert!(matches!(
+            error,
+            Error::TaskFailed { task: "Search", source }
+                if source.downcast_ref::<task::JoinError>().is_some_and(task::JoinError::is_panic)
+        ));
+    }
+
+    #[test]
+    fn trace() {
+        let mut search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+        search_engine.preload(1).expect("Failed to preload");
+        let trace = |query: &str| {
+            search_engine
+                .search_streaming_traced(query, search_engine.ranking(), 10, true, |_| true)
+                .map(|outcome| (outcome.total, outcome.trace.expect("Missing trace")))
+                .expect("Failed to search")
+        };
+
+        let (total, eric) = trace("eric minassian");
+        assert_eq!(total, eric.docs_scored);
+        assert_eq!(eric.terms.len(), 2);
+        assert!(!eric.short_circuited);
+        assert_eq!(
+            eric.postings_read,
+            eric.terms.iter().map(|term| term.postings).sum::<u64>()
+        );
+        let (preloaded, read): (Vec<_>, Vec<_>) =
+            eric.terms.iter().partition(|term| term.preloaded);
+        assert_eq!(eric.cache_hits, preloaded.len());
+        assert_eq!(
+            eric.bytes_read,
+            read.iter().map(|term| term.bytes).sum::<u64>()
+        );
+
+        let (total, missing) = trace("+eric +qwertyuiop");
+        assert_eq!(total, 0);
+        assert!(missing.short_circuited);
+        assert_eq!(missing.postings_read, 0);
+        assert_eq!(missing.terms[1].postings, 0);
+    }
+
+    #[test]
+    fn query_limits() {
+        let search_engine = |limits| {
+            SearchEngine::new(
+                DiskInvertedIndex::from(
+                    "tests/test-data/search_test_db.test".into(),
+                    "tests/test-data/search_test_seek.test".into(),
+                    "tests/test-data/search_test_url_map.test".into(),
+                    "tests/test-data/search_test_url_map_seek.test".into(),
+                )
+                .expect("Failed to create search engine"),
+            )
+            .expect("Failed to create search engine")
+            .with_limits(limits)
+        };
+        let search = |search_engine: &SearchEngine, query: &str| {
+            let outcome = search_engine
+                .search_streaming_traced(query, search_engine.ranking(), 10, false, |_| true)
+                .expect("Failed to search");
+            (outcome.total, outcome.partial_results)
+        };
+
+        let unlimited = search_engine(QueryLimits::default());
+        assert_eq!(search(&unlimited, "eric"), (3, false));
+
+        let candidates = search_engine(QueryLimits {
+            max_candidates: 2,
+            ..QueryLimits::default()
+        });
+        assert_eq!(search(&candidates, "eric"), (2, true));
+        assert_eq!(search(&candidates, "+eric"), (2, true));
+        assert_eq!(search(&candidates, "+eric +minassian"), (1, false));
+
+        // "eric" has 3 postings and "minassian" 1
+        let postings = search_engine(QueryLimits {
+            max_postings: 2,
+            ..QueryLimits::default()
+        });
+        assert_eq!(search(&postings, "eric minassian"), (1, true));
+        assert_eq!(search(&postings, "minassian"), (1, false));
+        assert_eq!(search(&postings, "+eric"), (0, true));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn search_with_ranking() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+        let ranking = RankingConfig {
+            term_weights: [("eric".to_string(), 2.0)].into(),
+            ..RankingConfig::default()
+        };
+
+        let mut scores = Vec::new();
+        search_engine
+            .search_streaming_with("eric", &ranking, 10, |result| {
+                scores.push(result.score);
+                true
+            })
+            .expect("Failed to search");
+
+        assert_eq!(scores, [18.2, 4.8, 2.4]);
+    }
+
+    #[test]
+    fn test_search_streaming() {
+        let search_engine = SearchEngine::new(
+            DiskInvertedIndex::from(
+                "tests/test-data/search_test_db.test".into(),
+                "tests/test-data/search_test_seek.test".into(),
+                "tests/test-data/search_test_url_map.test".into(),
+                "tests/test-data/search_test_url_map_seek.test".into(),
+            )
+            .expect("Failed to create search engine"),
+        )
+        .expect("Failed to create search engine");
+
+        let mut streamed = Vec::new();
+        let total = search_engine
+            .search_streaming("eric", 2, |result| {
+                streamed.push(result.url);
+                true
+            })
+            .expect("Failed to search");
+
+        assert_eq!(total, 3);
+        assert_eq!(
+            streamed,
+            vec![
+                "https://www.ericminassian.com/",
+                "https://www.linkedin.com/in/minassian-eric/"
+            ]
+        );
+    }
 }
@@ -1,2 +1,12 @@
+pub mod batch;
+pub mod boolean;
+pub mod diversify;
+pub mod doc_set;
 pub mod engine;
-mod search_result;
+pub mod multi_index;
+pub mod proximity;
+pub mod query;
+pub mod ranking;
+pub mod search_result;
+pub mod spelling;
+pub mod trace;
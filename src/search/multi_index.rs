@@ -0,0 +1,135 @@
+use crate::{error::Result, kv_database::read_at::ReadAt};
+use std::fs::File;
+
+use super::{
+    engine::{rank_order, SearchEngine},
+    search_result::SearchResult,
+};
+
+/// Searches several indexes, e.g. one per site or per month, as if they were
+/// one.
+///
+/// Scores of different indexes aren't comparable, since tf-idf depends on the
+/// size of each corpus, so the scores of every index are divided by its best
+/// score for the query before the results are merged.
+pub struct MultiIndexSearchEngine<R = File> {
+    engines: Vec<(String, SearchEngine<R>)>,
+}
+
+impl<R: ReadAt> MultiIndexSearchEngine<R> {
+    /// Searches the named `engines`, the ones listed first winning ties.
+    #[must_use]
+    pub const fn new(engines: Vec<(String, SearchEngine<R>)>) -> Self {
+        Self { engines }
+    }
+
+    /// Up to `limit` results of `query` across all indexes, best match first,
+    /// each naming its index, and the total number of matching documents.
+    pub fn search(&self, query: &str, limit: usize) -> Result<(Vec<SearchResult>, usize)> {
+        let mut total = 0;
+        let mut merged = Vec::new();
+
+        for (position, (_, engine)) in self.engines.iter().enumerate() {
+            let ranked = engine.rank(query)?;
+            total += ranked.len();
+
+            // Only the top `limit` of an index can make the merged top `limit`
+            let best = ranked.first().map_or(0.0, |(_, score)| *score);
+            merged.extend(
+                ranked
+                    .into_iter()
+                    .take(limit)
+                    .map(|(doc_id, score)| (position, doc_id, normalize(score, best))),
+            );
+        }
+
+        merged.sort_by(|a, b| rank_order(&(a.1, a.2), &(b.1, b.2)).then_with(|| a.0.cmp(&b.0)));
+
+        let results = merged
+            .into_iter()
+            .take(limit)
+            .map(|(position, doc_id, score)| {
+                let (name, engine) = &self.engines[position];
+                engine
+                    .resolve(doc_id, score)
+                    .map(|result| result.with_index(name.clone()))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((results, total))
+    }
+}
+
+/// `score` relative to the best score of its index, 0 when that is unusable.
+fn normalize(score: f64, best: f64) -> f64 {
+    if best > 0.0 && best.is_finite() {
+        score / best
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_index::disk_inverted_index::{CrawlFile, DiskInvertedIndex};
+    use crate::test_util::{page, TestDir};
+
+    fn engine(dir: &TestDir, name: &str, pages: &[(&str, &str)]) -> SearchEngine {
+        let pages = pages.iter().map(|(path, text)| {
+            Ok(CrawlFile {
+                url: format!("https://{name}.example/{path}"),
+                ..page("", text)
+            })
+        });
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path(format!("multi_index_{name}.db")),
+            dir.path(format!("multi_index_{name}.seek")),
+            dir.path(format!("multi_index_{name}_url_map.db")),
+            dir.path(format!("multi_index_{name}_url_map.seek")),
+            pages,
+        )
+        .expect("Failed to build index");
+
+        SearchEngine::new(index).expect("Failed to create search engine")
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn merges_normalized_results() {
+        let dir = TestDir::new();
+        let search_engine = MultiIndexSearchEngine::new(vec![
+            (
+                "blog".to_string(),
+                engine(
+                    &dir,
+                    "blog",
+                    &[
+                        ("rust", "rust rust rust"),
+                        ("cargo", "rust cargo"),
+                        ("pasta", "pasta"),
+                    ],
+                ),
+            ),
+            (
+                "docs".to_string(),
+                engine(&dir, "docs", &[("book", "rust book"), ("other", "other")]),
+            ),
+        ]);
+
+        let (results, total) = search_engine.search("rust", 2).expect("Failed to search");
+
+        assert_eq!(total, 3);
+        let found: Vec<_> = results
+            .iter()
+            .map(|result| (result.index.as_deref(), result.url.as_str(), result.score))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Some("blog"), "https://blog.example/rust", 1.0),
+                (Some("docs"), "https://docs.example/book", 1.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+/// Results at the top of a ranking whose term positions proximity scoring
+/// reads. The ones below keep their scores.
+pub const PROXIMITY_CANDIDATES: usize = 100;
+
+/// Smallest number of tokens a window of text spans to hold a position from
+/// every list of `positions`, each sorted ascending. `None` without lists or
+/// with an empty one.
+#[must_use]
+pub fn min_window(positions: &[&[u32]]) -> Option<u32> {
+    if positions.is_empty() || positions.iter().any(|list| list.is_empty()) {
+        return None;
+    }
+
+    // Index into each list of the position the window holds
+    let mut cursors = vec![0; positions.len()];
+    let mut best = u32::MAX;
+    loop {
+        let (first, first_position) = cursors
+            .iter()
+            .enumerate()
+            .map(|(list, &cursor)| (list, positions[list][cursor]))
+            .min_by_key(|&(_, position)| position)?;
+        let last_position = cursors
+            .iter()
+            .enumerate()
+            .map(|(list, &cursor)| positions[list][cursor])
+            .max()?;
+        best = best.min(last_position - first_position + 1);
+
+        // Only moving the earliest position can shrink the window
+        cursors[first] += 1;
+        if cursors[first] == positions[first].len() {
+            return Some(best);
+        }
+    }
+}
+
+/// Multiplier of the score of a document whose query terms occur at
+/// `positions`.
+///
+/// Terms next to each other get 1 plus `weight`, the boost shrinking with
+/// every token between them. Documents holding fewer than two of the terms
+/// keep their score.
+#[must_use]
+pub fn proximity_boost(positions: &[&[u32]], weight: f64) -> f64 {
+    if positions.len() < 2 {
+        return 1.0;
+    }
+
+    min_window(positions).map_or(1.0, |window| {
+        let slack = window.saturating_sub(positions.len() as u32);
+        1.0 + weight / f64::from(1 + slack)
+    })
+}
+
+/// Whether some position of the first list of `positions` is followed by a
+/// position of every other list in turn, as the terms of a phrase are.
+#[must_use]
+pub fn contains_phrase(positions: &[&[u32]]) -> bool {
+    let Some((first, rest)) = positions.split_first() else {
+        return false;
+    };
+
+    first.iter().any(|&start| {
+        rest.iter()
+            .zip(1..)
+            .all(|(list, offset)| list.binary_search(&(start + offset)).is_ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        assert_eq!(min_window(&[&[3, 10], &[4, 20]]), Some(2));
+        assert_eq!(min_window(&[&[0, 9], &[5], &[7, 12]]), Some(5));
+        assert_eq!(min_window(&[&[1, 30], &[15], &[28]]), Some(16));
+        assert_eq!(min_window(&[&[1], &[]]), None);
+
+        assert!((proximity_boost(&[&[3], &[4]], 0.5) - 1.5).abs() < 1e-9);
+        assert!((proximity_boost(&[&[3], &[5]], 0.5) - 1.25).abs() < 1e-9);
+        assert!((proximity_boost(&[&[3]], 0.5) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn phrases() {
+        let to: &[u32] = &[0, 4, 9];
+        let be: &[u32] = &[1, 5];
+        assert!(contains_phrase(&[to, be, &[2], &[3], to, be]));
+        assert!(!contains_phrase(&[be, to]));
+        assert!(!contains_phrase(&[to, &[]]));
+        assert!(!contains_phrase(&[]));
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt::{self, Display};
+
+/// Prefix of clauses that constrain matches without scoring them.
+const FILTER_PREFIX: &str = "filter:";
+const SITE_PREFIX: &str = "site:";
+const URL_PREFIX: &str = "url:";
+
+/// How a clause takes part in matching and scoring, as in Lucene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occur {
+    /// `+word`: matches must contain it, and it adds to their score
+    Must,
+    /// `word`: adds to the score of documents containing it
+    Should,
+    /// `filter:word`: matches must contain it, without it adding to their
+    /// score
+    Filter,
+}
+
+/// Part of the url of documents a clause matches exactly, its text skipping
+/// the analyzer. Such clauses only ever filter matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// `site:example.com`: pages on the host or its subdomains
+    Site,
+    /// `url:example.com/page`: the page at the url, whatever its scheme
+    Url,
+}
+
+/// One whitespace-separated part of a query, or a quoted phrase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub occur: Occur,
+    /// Field matched instead of the text of documents
+    pub field: Option<Field>,
+    pub text: String,
+    /// Whether the words of `text` were quoted, so matches contain them next
+    /// to each other in this order
+    pub phrase: bool,
+    /// Multiplier of the scores of the clause, from a `^2.5` suffix
+    pub boost: f64,
+}
+
+/// A parsed query.
+///
+/// Documents must match every [`Occur::Must`] and [`Occur::Filter`] clause.
+/// Without either, they must match at least one [`Occur::Should`] clause.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+impl Query {
+    /// Parses `+word`, `filter:word`, `site:host`, `url:url` and `word^2.5`
+    /// clauses, and `"quoted phrases"` taking the same prefixes and suffix.
+    /// Anything else is read as a plain word, so no query fails to parse.
+    #[must_use]
+    pub fn parse(query: &str) -> Self {
+        let mut clauses = Vec::new();
+        let mut words = query.split_whitespace();
+        while let Some(word) = words.next() {
+            let (occur, word) = split_occur(word);
+            if let Some(start) = word.strip_prefix('"') {
+                clauses.push(phrase_clause(occur, start, &mut words));
+                continue;
+            }
+            let (field, word) = split_field(word);
+            let (text, boost) = split_boost(word);
+
+            clauses.push(Clause {
+                occur,
+                field,
+                text: text.to_string(),
+                phrase: false,
+                boost,
+            });
+        }
+
+        Self { clauses }
+    }
+}
+
+/// The phrase opening with `start`, taking words from `words` up to the one
+/// closing its quote. A phrase never closed runs to the end of the query.
+fn phrase_clause<'a>(
+    occur: Occur,
+    start: &'a str,
+    words: &mut impl Iterator<Item = &'a str>,
+) -> Clause {
+    let mut text = Vec::new();
+    let mut boost = 1.0;
+    let mut word = Some(start);
+    while let Some(next) = word {
+        let (inner, next_boost) = split_boost(next);
+        if let Some(inner) = inner.strip_suffix('"') {
+            text.push(inner);
+            boost = next_boost;
+            break;
+        }
+        text.push(next);
+        word = words.next();
+    }
+    text.retain(|word| !word.is_empty());
+
+    Clause {
+        occur,
+        field: None,
+        text: text.join(" "),
+        phrase: true,
+        boost,
+    }
+}
+
+/// How the clause `word` occurs and `word` without the prefix saying so.
+fn split_occur(word: &str) -> (Occur, &str) {
+    if let Some(word) = word.strip_prefix(FILTER_PREFIX) {
+        return (Occur::Filter, word);
+    }
+
+    word.strip_prefix('+')
+        .map_or((Occur::Should, word), |word| (Occur::Must, word))
+}
+
+/// The field the clause `word` matches and `word` without the prefix saying
+/// so.
+fn split_field(word: &str) -> (Option<Field>, &str) {
+    [(Field::Site, SITE_PREFIX), (Field::Url, URL_PREFIX)]
+        .into_iter()
+        .find_map(|(field, prefix)| Some((Some(field), word.strip_prefix(prefix)?)))
+        .unwrap_or((None, word))
+}
+
+/// `word` without its `^<boost>` suffix and the boost, 1 for words without a
+/// valid one.
+fn split_boost(word: &str) -> (&str, f64) {
+    word.rsplit_once('^')
+        .and_then(|(text, boost)| {
+            let boost: f64 = boost.parse().ok()?;
+            (boost.is_finite() && boost >= 0.0).then_some((text, boost))
+        })
+        .unwrap_or((word, 1.0))
+}
+
+impl Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.occur {
+            Occur::Must => write!(f, "+")?,
+            Occur::Should => {}
+            Occur::Filter => write!(f, "{FILTER_PREFIX}")?,
+        }
+        match self.field {
+            Some(Field::Site) => write!(f, "{SITE_PREFIX}")?,
+            Some(Field::Url) => write!(f, "{URL_PREFIX}")?,
+            None => {}
+        }
+        if self.phrase {
+            write!(f, "\"{}\"", self.text)?;
+        } else {
+            write!(f, "{}", self.text)?;
+        }
+        if (self.boost - 1.0).abs() > f64::EPSILON {
+            write!(f, "^{}", self.boost)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{clause}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clause(occur: Occur, text: &str, boost: f64) -> Clause {
+        Clause {
+            occur,
+            field: None,
+            text: text.to_string(),
+            phrase: false,
+            boost,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let query = Query::parse("+pasta^2 fresh filter:recipe sauce^abc tomato^-1");
+
+        assert_eq!(
+            query.clauses,
+            [
+                clause(Occur::Must, "pasta", 2.0),
+                clause(Occur::Should, "fresh", 1.0),
+                clause(Occur::Filter, "recipe", 1.0),
+                clause(Occur::Should, "sauce^abc", 1.0),
+                clause(Occur::Should, "tomato^-1", 1.0),
+            ]
+        );
+        assert_eq!(
+            query.to_string(),
+            "+pasta^2 fresh filter:recipe sauce^abc tomato^-1"
+        );
+
+        let query = Query::parse("+site:Example.com url:example.com/a^2 site");
+        let fields: Vec<_> = query
+            .clauses
+            .iter()
+            .map(|clause| (clause.occur, clause.field, clause.text.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                (Occur::Must, Some(Field::Site), "Example.com"),
+                (Occur::Should, Some(Field::Url), "example.com/a"),
+                (Occur::Should, None, "site"),
+            ]
+        );
+        assert_eq!(
+            query.to_string(),
+            "+site:Example.com url:example.com/a^2 site"
+        );
+    }
+
+    #[test]
+    fn phrases() {
+        let query = Query::parse("+\"Machine  learning\"^2 rust \"deep\" \"open ended");
+        let phrases: Vec<_> = query
+            .clauses
+            .iter()
+            .map(|clause| {
+                (
+                    clause.occur,
+                    clause.text.as_str(),
+                    clause.phrase,
+                    clause.boost,
+                )
+            })
+            .collect();
+        assert_eq!(
+            phrases,
+            [
+                (Occur::Must, "Machine learning", true, 2.0),
+                (Occur::Should, "rust", false, 1.0),
+                (Occur::Should, "deep", true, 1.0),
+                (Occur::Should, "open ended", true, 1.0),
+            ]
+        );
+        assert_eq!(
+            query.to_string(),
+            "+\"Machine learning\"^2 rust \"deep\" \"open ended\""
+        );
+    }
+}
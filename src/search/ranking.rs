@@ -0,0 +1,414 @@
+use crate::{
+    inverted_index::{
+        fields::field_term,
+        url_fields::{site_term, url_term},
+    },
+    tokenizer::Tokenizer,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{
+    engine::{MatchMode, RankingModel},
+    query::{Field, Occur, Query},
+};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// A term of a query and the weight its scores are multiplied by.
+pub type WeightedTerm = (String, f64);
+
+/// Terms of a query as the engine runs them.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct WeightedQuery {
+    /// Terms every match contains, filters weighing 0
+    pub required: Vec<WeightedTerm>,
+    /// Terms adding to the score of matches, expansions last. Without
+    /// required terms, matches contain at least one of them
+    pub optional: Vec<WeightedTerm>,
+    /// Terms matches contain next to each other in this order, for quoted
+    /// phrases of more than one term and queries made only of stopwords.
+    /// The latter run as an exact phrase rather than match nothing
+    pub phrases: Vec<Vec<String>>,
+}
+
+/// How queries are scored. Servers can host several under different names,
+/// so relevance experiments can run side by side on the same index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingConfig {
+    pub match_mode: MatchMode,
+    /// How postings score documents. Indexes built before term frequencies
+    /// were kept score with tf-idf whatever this says
+    pub scorer: RankingModel,
+    /// Multipliers of the scores of query words, 1 for words left out
+    pub term_weights: BTreeMap<String, f64>,
+    /// Words added to queries containing the key word. They add to the score
+    /// of matching documents but never make a document match
+    /// [`MatchMode::All`] queries
+    pub expansions: BTreeMap<String, Vec<String>>,
+    /// Multiplier of the scores of expansion words
+    pub expansion_weight: f64,
+    /// Words dropped from queries
+    pub stopwords: Vec<String>,
+    /// How much the top results are penalized for resembling results ranked
+    /// above them, from 0 for pure relevance to 1 for pure novelty
+    pub diversity: f64,
+    /// Whether documents the index flagged are left out of the results
+    pub safe_search: bool,
+    /// Power of the spam and boilerplate quality of documents their scores
+    /// are multiplied by, 0 ignoring it
+    pub quality_weight: f64,
+    /// Days after which the recency of a document halves, 0 ignoring crawl
+    /// times. Ages count from the newest crawl of the index
+    pub recency_half_life_days: f64,
+    /// Share of the score of a fresh document recency accounts for, from 0
+    /// to 1. Documents without a crawl time lose all of it
+    pub recency_weight: f64,
+    /// Boost of documents whose query terms occur next to each other,
+    /// shrinking as they occur further apart. 0 ignores term positions
+    pub proximity_weight: f64,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            match_mode: MatchMode::default(),
+            scorer: RankingModel::default(),
+            term_weights: BTreeMap::new(),
+            expansions: BTreeMap::new(),
+            expansion_weight: 0.5,
+            stopwords: Vec::new(),
+            diversity: 0.0,
+            safe_search: false,
+            quality_weight: 1.0,
+            recency_half_life_days: 0.0,
+            recency_weight: 0.5,
+            proximity_weight: 0.5,
+        }
+    }
+}
+
+impl RankingConfig {
+    /// Multiplier of the score of a document `age` seconds older than the
+    /// newest one, blending relevance with an exponential decay of its age.
+    pub(super) fn recency(&self, age: Option<u64>) -> f64 {
+        if self.recency_half_life_days <= 0.0 {
+            return 1.0;
+        }
+
+        let weight = self.recency_weight.clamp(0.0, 1.0);
+        let decay = age.map_or(0.0, |age| {
+            0.5_f64.powf(age as f64 / (self.recency_half_life_days * SECONDS_PER_DAY))
+        });
+        weight.mul_add(decay, 1.0 - weight)
+    }
+
+    /// The terms of `query` as the engine runs them. Words of the config go
+    /// through `tokenizer` like the query, so they match whatever form the
+    /// index stores. Clauses prefixed with one of the custom `fields` of the
+    /// index, like `author:smith`, match the terms of that field only.
+    pub(super) fn weighted_query(
+        &self,
+        query: &Query,
+        tokenizer: &Tokenizer,
+        fields: &[String],
+    ) -> WeightedQuery {
+        let stopwords: HashSet<_> = self
+            .stopwords
+            .iter()
+            .flat_map(|word| tokenizer.tokenize(word))
+            .collect();
+        let term_weights: HashMap<_, _> = self
+            .term_weights
+            .iter()
+            .flat_map(|(word, weight)| {
+                tokenizer
+                    .tokenize(word)
+                    .into_iter()
+                    .map(move |term| (term, *weight))
+            })
+            .collect();
+
+        let mut weighted = WeightedQuery::default();
+        // Terms adding to the score, which expansions are looked up for
+        let mut scored = Vec::new();
+        // Stopwords outside phrases, in order
+        let mut stopword_phrase = Vec::new();
+        for clause in &query.clauses {
+            if let Some(field) = clause.field {
+                let term = match field {
+                    Field::Site => site_term(&clause.text),
+                    Field::Url => url_term(&clause.text),
+                };
+                weighted.required.extend(term.map(|term| (term, 0.0)));
+                continue;
+            }
+
+            let terms = match split_custom_field(&clause.text, fields) {
+                Some((name, text)) => tokenizer
+                    .tokenize(text)
+                    .iter()
+                    .map(|term| field_term(name, term))
+                    .collect(),
+                None => tokenizer.tokenize(&clause.text),
+            };
+            for term in &terms {
+                let term = term.clone();
+                if stopwords.contains(&term) {
+                    // Phrases need their stopwords in matches to line up, but
+                    // they still don't score
+                    if !clause.phrase {
+                        stopword_phrase.push(term);
+                    } else if !weighted.required.iter().any(|(added, _)| *added == term) {
+                        weighted.required.push((term, 0.0));
+                    }
+                    continue;
+                }
+
+                let weight = term_weights.get(&term).copied().unwrap_or(1.0) * clause.boost;
+                match clause.occur {
+                    Occur::Must => weighted.required.push((term.clone(), weight)),
+                    Occur::Should if clause.phrase || self.match_mode == MatchMode::All => {
+                        weighted.required.push((term.clone(), weight));
+                    }
+                    Occur::Should => weighted.optional.push((term.clone(), weight)),
+                    Occur::Filter => {
+                        weighted.required.push((term, 0.0));
+                        continue;
+                    }
+                }
+                scored.push(term);
+            }
+            if clause.phrase && terms.len() > 1 {
+                weighted.phrases.push(terms);
+            }
+        }
+
+        let mut expansions: Vec<WeightedTerm> = Vec::new();
+        for (word, words) in &self.expansions {
+            if !tokenizer
+                .tokenize(word)
+                .iter()
+                .any(|term| scored.contains(term))
+            {
+                continue;
+            }
+
+            for term in words.iter().flat_map(|word| tokenizer.tokenize(word)) {
+                if !scored.contains(&term) && !expansions.iter().any(|(added, _)| *added == term) {
+                    expansions.push((term, self.expansion_weight));
+                }
+            }
+        }
+        weighted.optional.extend(expansions);
+
+        if weighted.required.is_empty() && weighted.optional.is_empty() {
+            for term in &stopword_phrase {
+                if !weighted.required.iter().any(|(added, _)| added == term) {
+                    weighted.required.push((term.clone(), 1.0));
+                }
+            }
+            if stopword_phrase.len() > 1 {
+                weighted.phrases.push(stopword_phrase);
+            }
+        }
+
+        weighted
+    }
+}
+
+/// The custom field of `fields` the clause `text` is prefixed with and
+/// `text` without the prefix.
+pub(super) fn split_custom_field<'a>(
+    text: &'a str,
+    fields: &'a [String],
+) -> Option<(&'a str, &'a str)> {
+    let (name, text) = text.split_once(':')?;
+    let name = fields.iter().find(|field| *field == name)?;
+
+    Some((name, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_and_expansions() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let ranking = RankingConfig {
+            term_weights: BTreeMap::from([("cooking".to_string(), 2.0)]),
+            expansions: BTreeMap::from([
+                (
+                    "pasta".to_string(),
+                    vec!["noodles".to_string(), "cooking".to_string()],
+                ),
+                ("rust".to_string(), vec!["cargo".to_string()]),
+            ]),
+            ..RankingConfig::default()
+        };
+
+        let weighted = ranking.weighted_query(&Query::parse("Cooks pasta"), &tokenizer, &[]);
+
+        let stem = |word: &str| tokenizer.tokenize(word).remove(0);
+        assert!(weighted.required.is_empty());
+        // Words already in the query aren't added again
+        assert_eq!(
+            weighted.optional,
+            [
+                (stem("cooking"), 2.0),
+                (stem("pasta"), 1.0),
+                (stem("noodles"), 0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn clauses() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let ranking = RankingConfig {
+            term_weights: BTreeMap::from([("pasta".to_string(), 2.0)]),
+            expansions: BTreeMap::from([("recipe".to_string(), vec!["cooking".to_string()])]),
+            ..RankingConfig::default()
+        };
+        let query = Query::parse("+pasta^1.5 fresh^0.5 filter:recipe");
+        let stem = |word: &str| tokenizer.tokenize(word).remove(0);
+
+        let weighted = ranking.weighted_query(&query, &tokenizer, &[]);
+        assert_eq!(
+            weighted.required,
+            [(stem("pasta"), 3.0), (stem("recipe"), 0.0)]
+        );
+        // Filters bring in no expansions
+        assert_eq!(weighted.optional, [(stem("fresh"), 0.5)]);
+
+        let all = RankingConfig {
+            match_mode: MatchMode::All,
+            ..ranking
+        };
+        let weighted = all.weighted_query(&query, &tokenizer, &[]);
+        assert_eq!(
+            weighted.required,
+            [
+                (stem("pasta"), 3.0),
+                (stem("fresh"), 0.5),
+                (stem("recipe"), 0.0)
+            ]
+        );
+        assert!(weighted.optional.is_empty());
+    }
+
+    #[test]
+    fn stopwords() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let ranking = RankingConfig {
+            stopwords: vec!["Recipes".to_string()],
+            ..RankingConfig::default()
+        };
+
+        let weighted = ranking.weighted_query(&Query::parse("pasta recipe"), &tokenizer, &[]);
+
+        assert_eq!(
+            weighted.optional,
+            [(tokenizer.tokenize("pasta").remove(0), 1.0)]
+        );
+        assert!(weighted.phrases.is_empty());
+    }
+
+    #[test]
+    fn stopword_phrase() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let ranking = RankingConfig {
+            stopwords: ["to", "be", "or", "not"].map(String::from).to_vec(),
+            ..RankingConfig::default()
+        };
+
+        let weighted = ranking.weighted_query(&Query::parse("to be or not to be"), &tokenizer, &[]);
+
+        assert_eq!(weighted.phrases, [["to", "be", "or", "not", "to", "be"]]);
+        assert_eq!(
+            weighted.required,
+            [
+                ("to".to_string(), 1.0),
+                ("be".to_string(), 1.0),
+                ("or".to_string(), 1.0),
+                ("not".to_string(), 1.0)
+            ]
+        );
+        assert!(weighted.optional.is_empty());
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let ranking = RankingConfig {
+            stopwords: vec!["of".to_string()],
+            ..RankingConfig::default()
+        };
+        let stem = |word: &str| tokenizer.tokenize(word).remove(0);
+
+        let weighted = ranking.weighted_query(
+            &Query::parse("\"history of rust\" \"pasta\" of"),
+            &tokenizer,
+            &[],
+        );
+
+        assert_eq!(
+            weighted.required,
+            [
+                (stem("history"), 1.0),
+                (stem("of"), 0.0),
+                (stem("rust"), 1.0),
+                (stem("pasta"), 1.0)
+            ]
+        );
+        assert!(weighted.optional.is_empty());
+        assert_eq!(
+            weighted.phrases,
+            [[stem("history"), stem("of"), stem("rust")]]
+        );
+    }
+
+    #[test]
+    fn custom_fields() {
+        let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
+        let fields = ["author".to_string()];
+        let stem = |word: &str| tokenizer.tokenize(word).remove(0);
+
+        let weighted = RankingConfig::default().weighted_query(
+            &Query::parse("+author:Lovelace^2 engines price:12"),
+            &tokenizer,
+            &fields,
+        );
+
+        assert_eq!(
+            weighted.required,
+            [(field_term("author", &stem("Lovelace")), 2.0)]
+        );
+        // Prefixes of no field of the index are read as text
+        let mut optional = vec![(stem("engines"), 1.0)];
+        optional.extend(
+            tokenizer
+                .tokenize("price:12")
+                .into_iter()
+                .map(|term| (term, 1.0)),
+        );
+        assert_eq!(weighted.optional, optional);
+    }
+
+    #[test]
+    fn recency() {
+        let ranking = RankingConfig {
+            recency_half_life_days: 2.0,
+            ..RankingConfig::default()
+        };
+        let day = 86_400;
+
+        assert!((ranking.recency(Some(0)) - 1.0).abs() < 1e-9);
+        assert!((ranking.recency(Some(2 * day)) - 0.75).abs() < 1e-9);
+        assert!((ranking.recency(Some(4 * day)) - 0.625).abs() < 1e-9);
+        assert!((ranking.recency(None) - 0.5).abs() < 1e-9);
+        assert!((RankingConfig::default().recency(None) - 1.0).abs() < 1e-9);
+    }
+}
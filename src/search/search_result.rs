@@ -1,11 +1,47 @@
-#[derive(Debug, Clone)]
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
     pub url: String,
     pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Name of the index the result came from, in federated searches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    /// Text of the custom fields of the document, by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
 }
 
 impl SearchResult {
+    #[must_use]
     pub const fn new(url: String, score: f64) -> Self {
-        Self { url, score }
+        Self {
+            url,
+            score,
+            title: None,
+            index: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_title(mut self, title: Option<String>) -> Self {
+        self.title = title;
+        self
+    }
+
+    #[must_use]
+    pub fn with_fields(mut self, fields: BTreeMap<String, String>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    #[must_use]
+    pub fn with_index(mut self, index: String) -> Self {
+        self.index = Some(index);
+        self
     }
 }
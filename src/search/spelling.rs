@@ -0,0 +1,180 @@
+use crate::inverted_index::word_frequencies::WordFrequencies;
+use std::collections::{HashMap, HashSet};
+
+/// Edits a correction may be away from the word typed.
+const MAX_DISTANCE: usize = 2;
+/// Leading characters of a word its deletes are taken from. Longer words
+/// rarely differ only past it, and it keeps the deletes index small.
+const PREFIX_LEN: usize = 7;
+
+/// Corrects words missing from the corpus to the most common corpus words a
+/// few edits away.
+///
+/// Candidates are found as in `SymSpell`: every word is indexed under the
+/// strings left by deleting up to [`MAX_DISTANCE`] characters of its prefix,
+/// so a typed word only has to generate its own deletes to meet every word
+/// within that distance.
+pub struct SpellChecker {
+    words: Vec<(String, u64)>,
+    ids: HashMap<String, usize>,
+    deletes: HashMap<String, Vec<usize>>,
+}
+
+impl SpellChecker {
+    #[must_use]
+    pub fn new(frequencies: &WordFrequencies) -> Self {
+        let words = frequencies.words.clone();
+        let mut ids = HashMap::with_capacity(words.len());
+        let mut deletes: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (id, (word, _)) in words.iter().enumerate() {
+            ids.insert(word.clone(), id);
+            for delete in prefix_deletes(word, MAX_DISTANCE) {
+                deletes.entry(delete).or_default().push(id);
+            }
+        }
+
+        Self {
+            words,
+            ids,
+            deletes,
+        }
+    }
+
+    /// The corpus word closest to `word`, the most common one among equally
+    /// close words. `None` when `word` is in the corpus or nothing is close.
+    #[must_use]
+    pub fn correct(&self, word: &str) -> Option<&str> {
+        if self.ids.contains_key(word) {
+            return None;
+        }
+
+        // Short words would be a couple of edits away from most others
+        let max_distance = MAX_DISTANCE.min(word.chars().count().saturating_sub(1) / 2);
+        if max_distance == 0 {
+            return None;
+        }
+
+        let candidates: HashSet<usize> = prefix_deletes(word, max_distance)
+            .iter()
+            .filter_map(|delete| self.deletes.get(delete))
+            .flatten()
+            .copied()
+            .collect();
+
+        candidates
+            .into_iter()
+            .filter_map(|id| {
+                let (candidate, frequency) = &self.words[id];
+                let distance = edit_distance(word, candidate);
+                (distance <= max_distance).then_some((distance, *frequency, candidate))
+            })
+            .min_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)))
+            .map(|(_, _, candidate)| candidate.as_str())
+    }
+}
+
+/// The prefix of `word` with up to `max_distance` characters deleted,
+/// itself included.
+fn prefix_deletes(word: &str, max_distance: usize) -> HashSet<String> {
+    let prefix: String = word.chars().take(PREFIX_LEN).collect();
+    let mut deletes = HashSet::from([prefix.clone()]);
+    let mut edge = vec![prefix];
+
+    for _ in 0..max_distance {
+        let mut next = Vec::new();
+        for word in &edge {
+            let chars: Vec<char> = word.chars().collect();
+            for skip in 0..chars.len() {
+                let delete: String = chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != skip)
+                    .map(|(_, c)| c)
+                    .collect();
+                if deletes.insert(delete.clone()) {
+                    next.push(delete);
+                }
+            }
+        }
+        edge = next;
+    }
+
+    deletes
+}
+
+/// Insertions, deletions, substitutions and swaps of adjacent characters
+/// turning `a` into `b`, no substring being edited twice.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut before_previous = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spell_checker(words: &[(&str, u64)]) -> SpellChecker {
+        let frequencies: HashMap<_, _> = words
+            .iter()
+            .map(|(word, frequency)| ((*word).to_string(), *frequency))
+            .collect();
+        SpellChecker::new(&WordFrequencies::from(frequencies))
+    }
+
+    #[test]
+    fn prefers_common_words() {
+        let spell_checker = spell_checker(&[
+            ("pasta", 40),
+            ("paste", 3),
+            ("recipe", 12),
+            ("recipes", 30),
+            ("international", 5),
+        ]);
+
+        assert_eq!(spell_checker.correct("pastx"), Some("pasta"));
+        assert_eq!(spell_checker.correct("pasts"), Some("pasta"));
+        // Closer words win over more common ones
+        assert_eq!(spell_checker.correct("recipr"), Some("recipe"));
+        assert_eq!(spell_checker.correct("rceipes"), Some("recipes"));
+        // Edits past the indexed prefix are still found
+        assert_eq!(
+            spell_checker.correct("internatoinal"),
+            Some("international")
+        );
+
+        assert_eq!(spell_checker.correct("pasta"), None);
+        assert_eq!(spell_checker.correct("rust"), None);
+        assert_eq!(spell_checker.correct("pa"), None);
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("pasta", "pasta"), 0);
+        assert_eq!(edit_distance("pasta", "psata"), 1);
+        assert_eq!(edit_distance("pasta", "past"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{inverted_index::disk_inverted_index::DiskInvertedIndex, kv_database::read_at::ReadAt};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+use super::ranking::WeightedTerm;
+
+/// What the engine did to answer a query, for diagnosing slow ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueryTrace {
+    /// Terms the query resolved to, required ones first
+    pub terms: Vec<TermTrace>,
+    /// Whether a required term missing from the index ended the query before
+    /// any postings were read
+    pub short_circuited: bool,
+    /// Postings decoded across all terms
+    pub postings_read: u64,
+    /// Encoded postings read from disk, preloaded ones aside
+    pub bytes_read: u64,
+    /// Terms whose postings were preloaded into memory
+    pub cache_hits: usize,
+    /// Documents matching the query
+    pub docs_scored: usize,
+    /// Matches safe search left out
+    pub docs_filtered: usize,
+    /// Top matches reordered for diversity
+    pub docs_diversified: usize,
+}
+
+/// How one term of a query was run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermTrace {
+    pub term: String,
+    pub weight: f64,
+    /// Whether every match contains the term
+    pub required: bool,
+    /// Documents in the postings of the term, 0 for terms not in the index
+    pub postings: u64,
+    /// Encoded size of the postings
+    pub bytes: u64,
+    /// Whether the postings were preloaded into memory
+    pub preloaded: bool,
+}
+
+impl QueryTrace {
+    /// The trace of reading the postings of the `required` and `optional`
+    /// terms of a query from `index`.
+    pub(super) fn new<R: ReadAt>(
+        index: &DiskInvertedIndex<R>,
+        required: &[WeightedTerm],
+        optional: &[WeightedTerm],
+    ) -> Self {
+        let terms: Vec<_> = required
+            .iter()
+            .map(|term| (term, true))
+            .chain(optional.iter().map(|term| (term, false)))
+            .map(|((term, weight), required)| TermTrace {
+                term: term.clone(),
+                weight: *weight,
+                required,
+                postings: index.doc_frequency(term),
+                bytes: index.postings_len(term).unwrap_or(0),
+                preloaded: index.is_preloaded(term),
+            })
+            .collect();
+        let short_circuited = required
+            .iter()
+            .any(|(term, _)| index.postings_len(term).is_none());
+
+        let mut trace = Self {
+            short_circuited,
+            ..Self::default()
+        };
+        if !short_circuited {
+            for term in &terms {
+                trace.postings_read += term.postings;
+                if term.preloaded {
+                    trace.cache_hits += 1;
+                } else {
+                    trace.bytes_read += term.bytes;
+                }
+            }
+        }
+        trace.terms = terms;
+
+        trace
+    }
+}
+
+impl Display for QueryTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for term in &self.terms {
+            writeln!(
+                f,
+                "  {} (weight {}{}): {} postings, {} bytes{}",
+                term.term,
+                term.weight,
+                if term.required { ", required" } else { "" },
+                term.postings,
+                term.bytes,
+                if term.preloaded { ", preloaded" } else { "" }
+            )?;
+        }
+        if self.short_circuited {
+            writeln!(f, "  A required term is missing, no postings read")?;
+        }
+        write!(
+            f,
+            "  Read {} postings ({} bytes, {} cache hits), scored {} docs, filtered {}, diversified {}",
+            self.postings_read,
+            self.bytes_read,
+            self.cache_hits,
+            self.docs_scored,
+            self.docs_filtered,
+            self.docs_diversified
+        )
+    }
+}
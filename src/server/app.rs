@@ -0,0 +1,585 @@
+use super::{
+    auth::ApiKeys,
+    cache::QueryCache,
+    error::ServerError,
+    handlers::{
+        build_report, healthz, index, index_build_report, index_search, index_search_stream,
+        index_term_stats, list_indexes, rate_limit, readyz, reload_index, require_api_key, search,
+        search_stream, suggest, term_stats,
+    },
+    limit::RateLimiter,
+};
+use crate::{
+    config::{Config, HostedIndexConfig, QueryLogConfig, ServerConfig, SlowQueryLogConfig},
+    error::{Error, Result},
+    inverted_index::disk_inverted_index::DiskInvertedIndex,
+    query_log::QueryLog,
+    search::engine::SearchEngine,
+    search::ranking::RankingConfig,
+    slow_query_log::{SlowQuery, SlowQueryLog},
+};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    net::TcpListener,
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+#[cfg(unix)]
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task,
+};
+
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Ranking of queries that don't name one.
+pub const DEFAULT_RANKING: &str = "default";
+
+pub type SharedEngine = Arc<SearchEngine>;
+pub type SharedState = Arc<AppState>;
+
+/// Files of a freshly built index generation. Paths left out keep pointing
+/// at the files of the current generation.
+#[derive(Debug, Default, Deserialize)]
+pub struct ReloadRequest {
+    pub db: Option<PathBuf>,
+    pub db_seek: Option<PathBuf>,
+    pub url_map: Option<PathBuf>,
+    pub url_map_seek: Option<PathBuf>,
+}
+
+/// An index that can be swapped for a new generation while queries keep
+/// running against the one they started on.
+pub struct HostedIndex {
+    engine: RwLock<SharedEngine>,
+    config: Mutex<HostedIndexConfig>,
+    generation: AtomicU64,
+}
+
+impl HostedIndex {
+    #[must_use]
+    pub fn new(search_engine: SearchEngine, config: HostedIndexConfig) -> Self {
+        Self {
+            engine: RwLock::new(Arc::new(search_engine)),
+            config: Mutex::new(config),
+            generation: AtomicU64::new(1),
+        }
+    }
+
+    /// The current generation, kept alive by the caller until it is done.
+    pub fn engine(&self) -> Result<SharedEngine> {
+        self.current().map(|(engine, _)| engine)
+    }
+
+    /// The current generation along with its number.
+    pub fn current(&self) -> Result<(SharedEngine, u64)> {
+        let engine = self
+            .engine
+            .read()
+            .map_err(|_| Error::Generic("Index lock poisoned".to_string()))?;
+        // Bumped under the write lock, so it matches the engine
+        Ok((Arc::clone(&engine), self.generation()))
+    }
+
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Opens and verifies the new generation before swapping it in, so a
+    /// broken build never replaces a working index. The new generation keeps
+    /// the query limits of the current one. Returns the new generation
+    /// number.
+    pub fn reload(&self, request: ReloadRequest) -> Result<u64> {
+        // Held for the whole reload so concurrent reloads don't interleave
+        let mut config = self
+            .config
+            .lock()
+            .map_err(|_| Error::Generic("Index config lock poisoned".to_string()))?;
+
+        let mut paths = config.paths.clone();
+        for (update, path) in [
+            (request.db, &mut paths.db),
+            (request.db_seek, &mut paths.db_seek),
+            (request.url_map, &mut paths.url_map),
+            (request.url_map_seek, &mut paths.url_map_seek),
+        ] {
+            if let Some(update) = update {
+                *path = update;
+            }
+        }
+
+        let index = DiskInvertedIndex::from(
+            paths.db.clone(),
+            paths.db_seek.clone(),
+            paths.url_map.clone(),
+            paths.url_map_seek.clone(),
+        )?;
+        let current = self.engine()?;
+        let mut search_engine = SearchEngine::with_analyzer(index, config.analyzer)?
+            .with_limits(current.limits())
+            .with_ranking(current.ranking().clone());
+        search_engine.verify()?;
+        search_engine.preload(config.preload_terms)?;
+
+        let mut engine = self
+            .engine
+            .write()
+            .map_err(|_| Error::Generic("Index lock poisoned".to_string()))?;
+        *engine = Arc::new(search_engine);
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        drop(engine);
+        config.paths = paths;
+        drop(config);
+
+        Ok(generation)
+    }
+}
+
+/// Named indexes hosted by one server, one of which also answers the
+/// top-level `/search` routes.
+pub struct AppState {
+    indexes: BTreeMap<String, HostedIndex>,
+    default_index: String,
+    query_slots: Arc<Semaphore>,
+    rate_limiter: Option<RateLimiter>,
+    api_keys: Option<ApiKeys>,
+    /// Swapped whole when the config file changes, so a query sees either
+    /// the old or the new rankings
+    rankings: RwLock<BTreeMap<String, RankingConfig>>,
+    /// Bumped whenever the rankings are replaced
+    rankings_version: AtomicU64,
+    query_cache: Option<QueryCache>,
+    query_log: Option<QueryLog>,
+    max_suggestions: usize,
+    slow_query_log: Option<SlowQueryLog>,
+}
+
+impl AppState {
+    pub fn new(indexes: BTreeMap<String, HostedIndex>, default_index: String) -> Result<Self> {
+        if !indexes.contains_key(&default_index) {
+            return Err(Error::Generic(format!(
+                "Default index `{default_index}` is not one of the hosted indexes"
+            )));
+        }
+
+        Ok(Self {
+            indexes,
+            default_index,
+            query_slots: Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
+            rate_limiter: None,
+            api_keys: None,
+            rankings: RwLock::default(),
+            rankings_version: AtomicU64::new(0),
+            query_cache: None,
+            query_log: None,
+            max_suggestions: 0,
+            slow_query_log: None,
+        })
+    }
+
+    /// Applies the concurrency cap, per-client rate limit, API keys and query
+    /// cache size of `config`.
+    #[must_use]
+    pub fn with_limits(self, config: &ServerConfig) -> Self {
+        Self {
+            query_slots: Arc::new(Semaphore::new(config.max_concurrent_queries.max(1))),
+            rate_limiter: RateLimiter::new(config.rate_limit, config.rate_limit_burst),
+            api_keys: ApiKeys::new(&config.api_keys),
+            query_cache: QueryCache::new(config.query_cache_size),
+            ..self
+        }
+    }
+
+    /// Hosts named rankings queries can pick instead of the index's own.
+    #[must_use]
+    pub fn with_rankings(self, rankings: BTreeMap<String, RankingConfig>) -> Self {
+        Self {
+            rankings: RwLock::new(rankings),
+            ..self
+        }
+    }
+
+    /// Replaces the hosted rankings. Queries already running keep the one
+    /// they started with.
+    pub fn set_rankings(&self, rankings: BTreeMap<String, RankingConfig>) {
+        let mut current = self
+            .rankings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *current = rankings;
+        self.rankings_version.fetch_add(1, Ordering::SeqCst);
+        drop(current);
+    }
+
+    /// Changes whenever [`AppState::set_rankings`] runs, to tell results of
+    /// the old rankings from those of the new ones.
+    #[must_use]
+    pub fn rankings_version(&self) -> u64 {
+        self.rankings_version.load(Ordering::SeqCst)
+    }
+
+    /// Logs the queries of every index to the log of `config` and suggests
+    /// queries from it.
+    pub fn with_query_log(self, config: &QueryLogConfig) -> Result<Self> {
+        Ok(Self {
+            query_log: Some(QueryLog::open(&config.path)?),
+            max_suggestions: config.max_suggestions,
+            ..self
+        })
+    }
+
+    /// Adds `query` and the `ranking` that served it to the query log.
+    /// Failing to do so doesn't fail the query.
+    pub fn record_query(&self, query: &str, ranking: &str) {
+        if let Some(Err(e)) = self
+            .query_log
+            .as_ref()
+            .map(|log| log.record(query, Some(ranking)))
+        {
+            eprintln!("Failed to log query: {e}");
+        }
+    }
+
+    /// Logs the queries of every index slower than the threshold of `config`.
+    pub fn with_slow_query_log(self, config: &SlowQueryLogConfig) -> Result<Self> {
+        Ok(Self {
+            slow_query_log: SlowQueryLog::open(config)?,
+            ..self
+        })
+    }
+
+    /// Whether queries need a trace for the slow query log.
+    #[must_use]
+    pub const fn logs_slow_queries(&self) -> bool {
+        self.slow_query_log.is_some()
+    }
+
+    /// Adds `query` to the slow query log if it took `elapsed` or more.
+    /// Failing to do so doesn't fail the query.
+    pub fn record_slow_query(&self, elapsed: Duration, query: &SlowQuery) {
+        let Some(log) = self.slow_query_log.as_ref() else {
+            return;
+        };
+        if log.is_slow(elapsed) {
+            if let Err(e) = log.record(query) {
+                eprintln!("Failed to log slow query: {e}");
+            }
+        }
+    }
+
+    /// See [`QueryLog::suggest_queries`], empty without a query log.
+    #[must_use]
+    pub fn suggest_queries(&self, prefix: &str, limit: Option<usize>) -> Vec<String> {
+        self.query_log.as_ref().map_or_else(Vec::new, |log| {
+            log.suggest_queries(prefix, limit.unwrap_or(self.max_suggestions))
+        })
+    }
+
+    /// The ranking called `name`, `None` for the index's own when `name` is
+    /// the default and no ranking took that name.
+    pub(super) fn ranking(
+        &self,
+        name: &str,
+    ) -> core::result::Result<Option<RankingConfig>, ServerError> {
+        let rankings = self.rankings.read().unwrap_or_else(PoisonError::into_inner);
+        match rankings.get(name) {
+            Some(ranking) => Ok(Some(ranking.clone())),
+            None if name == DEFAULT_RANKING => Ok(None),
+            None => Err(ServerError::UnknownRanking(name.to_string())),
+        }
+    }
+
+    /// Waits until fewer than `max_concurrent_queries` queries are running.
+    pub async fn query_slot(&self) -> Result<OwnedSemaphorePermit> {
+        Arc::clone(&self.query_slots)
+            .acquire_owned()
+            .await
+            .map_err(|e| Error::Generic(format!("Query slots closed: {e}")))
+    }
+
+    #[must_use]
+    pub const fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    #[must_use]
+    pub(super) const fn api_keys(&self) -> Option<&ApiKeys> {
+        self.api_keys.as_ref()
+    }
+
+    #[must_use]
+    pub(super) const fn query_cache(&self) -> Option<&QueryCache> {
+        self.query_cache.as_ref()
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&HostedIndex> {
+        self.indexes.get(name)
+    }
+
+    #[must_use]
+    pub fn default_index(&self) -> &HostedIndex {
+        &self.indexes[&self.default_index]
+    }
+
+    #[must_use]
+    pub fn default_index_name(&self) -> &str {
+        &self.default_index
+    }
+
+    pub fn indexes(&self) -> impl Iterator<Item = (&str, &HostedIndex)> {
+        self.indexes
+            .iter()
+            .map(|(name, index)| (name.as_str(), index))
+    }
+}
+
+pub fn router(state: SharedState) -> Router {
+    let queries = Router::new()
+        .route("/search", get(search))
+        .route("/search/stream", get(search_stream))
+        .route("/indexes/:name/search", get(index_search))
+        .route("/indexes/:name/search/stream", get(index_search_stream))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            rate_limit,
+        ));
+
+    // The page is static and, like the health checks, stays public
+    let protected = Router::new()
+        .merge(queries)
+        .route("/suggest", get(suggest))
+        .route("/indexes", get(list_indexes))
+        .route("/terms/:word", get(term_stats))
+        .route("/indexes/:name/terms/:word", get(index_term_stats))
+        .route("/report", get(build_report))
+        .route("/indexes/:name/report", get(index_build_report))
+        .route("/indexes/:name/reload", post(reload_index))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            require_api_key,
+        ));
+
+    Router::new()
+        .merge(protected)
+        .route("/", get(index))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
+
+/// Serves `state` on `addr`. With a `config_path`, edits to the rankings of
+/// that file apply to new queries without a restart.
+pub async fn serve(state: AppState, addr: SocketAddr, config_path: Option<PathBuf>) -> Result<()> {
+    let state = Arc::new(state);
+    let listener = TcpListener::bind(addr).await?;
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    if let Some(config_path) = config_path {
+        tokio::spawn(watch_config(Arc::clone(&state), config_path));
+    }
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(Arc::clone(&state)));
+
+    axum::serve(
+        listener,
+        router(state).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
+
+    println!("Server shut down");
+
+    Ok(())
+}
+
+/// Reloads every index from its current paths on SIGHUP, for rebuilds that
+/// rename the new files over the old ones.
+#[cfg(unix)]
+async fn reload_on_hangup(state: SharedState) {
+    let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+        return;
+    };
+
+    while hangup.recv().await.is_some() {
+        let state = Arc::clone(&state);
+        let reloaded = task::spawn_blocking(move || {
+            for (name, index) in state.indexes() {
+                match index.reload(ReloadRequest::default()) {
+                    Ok(generation) => println!("Reloaded index `{name}` (generation {generation})"),
+                    Err(e) => eprintln!("Failed to reload index `{name}`: {e}"),
+                }
+            }
+        })
+        .await;
+
+        if let Err(e) = reloaded {
+            eprintln!("Reload task failed: {e}");
+        }
+    }
+}
+
+/// Polls the modification time of the config file and applies its rankings
+/// whenever it changes. A config that fails to load is reported and the
+/// current rankings are kept.
+async fn watch_config(state: SharedState, path: PathBuf) {
+    let modified = |path: &PathBuf| {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let mut last_modified: Option<SystemTime> = modified(&path);
+    let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        match Config::load(Some(&path)) {
+            Ok(config) => {
+                state.set_rankings(config.server.rankings);
+                println!("Applied rankings of {}", path.display());
+            }
+            Err(e) => eprintln!(
+                "Kept the current rankings, {} is invalid: {e}",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM. In-flight requests are then allowed to
+/// finish while no new connections are accepted.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+
+    println!("Shutting down, waiting for in-flight queries");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{page, TestDir};
+    use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+
+    async fn serve_index(dir: &TestDir, name: &str, api_key: &str) -> String {
+        let documents = [Ok(page("apple", "apple pie"))];
+        let (index, _) = DiskInvertedIndex::build_from_documents(
+            dir.path(format!("{name}.db")),
+            dir.path(format!("{name}.seek")),
+            dir.path(format!("{name}_url_map.db")),
+            dir.path(format!("{name}_url_map.seek")),
+            documents,
+        )
+        .expect("Failed to build index");
+        let search_engine = SearchEngine::new(index).expect("Failed to create search engine");
+
+        let indexes = BTreeMap::from([(
+            "default".to_string(),
+            HostedIndex::new(search_engine, HostedIndexConfig::default()),
+        )]);
+        let state = AppState::new(indexes, "default".to_string())
+            .expect("Failed to create app state")
+            .with_limits(&ServerConfig {
+                api_keys: vec![api_key.to_string()],
+                ..ServerConfig::default()
+            });
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind test server");
+        let addr = listener
+            .local_addr()
+            .expect("Failed to get test server address");
+        let app = router(Arc::new(state)).into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn page_streams_with_api_key() {
+        let dir = TestDir::new();
+        let name = "server_stream_api_key";
+        let base = serve_index(&dir, name, "secret").await;
+        let client = Client::new();
+
+        let page = client
+            .get(format!("{base}/"))
+            .send()
+            .await
+            .expect("Failed to fetch page")
+            .text()
+            .await
+            .expect("Failed to read page");
+        // EventSource can't send headers, so the page must not stream with it
+        assert!(!page.contains("new EventSource"));
+        assert!(page.contains("\"X-API-Key\""));
+
+        let stream = format!("{base}/search/stream?q=apple");
+        let response = client.get(&stream).send().await.expect("Failed to search");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .get(&stream)
+            .header("Accept", "text/event-stream")
+            .header("X-API-Key", "secret")
+            .send()
+            .await
+            .expect("Failed to search");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream")));
+        let body = response.text().await.expect("Failed to read stream");
+        assert!(body.contains("event: result"));
+        assert!(body.contains("https://example.com/apple"));
+        assert!(body.contains("event: done"));
+    }
+}
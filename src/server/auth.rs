@@ -0,0 +1,80 @@
+use axum::http::{header, HeaderMap};
+
+/// Header carrying a key for clients that can't set `Authorization`.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Keys a client must present, as `Authorization: Bearer <key>` or in the
+/// `X-API-Key` header, to use the protected routes.
+pub struct ApiKeys {
+    keys: Vec<String>,
+}
+
+impl ApiKeys {
+    /// Returns `None` when no non-empty key is given, meaning every client is
+    /// let in.
+    #[must_use]
+    pub fn new(keys: &[String]) -> Option<Self> {
+        let keys: Vec<_> = keys.iter().filter(|key| !key.is_empty()).cloned().collect();
+        (!keys.is_empty()).then_some(Self { keys })
+    }
+
+    /// Whether `headers` carry one of the keys.
+    #[must_use]
+    pub fn allows(&self, headers: &HeaderMap) -> bool {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api_key = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+
+        bearer
+            .into_iter()
+            .chain(api_key)
+            .any(|presented| self.keys.iter().any(|key| same_key(key, presented.trim())))
+    }
+}
+
+/// Compares every byte however early the keys differ, so response times don't
+/// reveal how much of a guess was right.
+fn same_key(key: &str, presented: &str) -> bool {
+    key.len() == presented.len()
+        && key
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(name, HeaderValue::from_static(value))])
+    }
+
+    #[test]
+    fn disabled_without_keys() {
+        assert!(ApiKeys::new(&[]).is_none());
+        assert!(ApiKeys::new(&[String::new()]).is_none());
+    }
+
+    #[test]
+    fn bearer_and_header_keys() {
+        let keys = ApiKeys::new(&["first".to_string(), "second".to_string()])
+            .expect("Keys should be enabled");
+
+        assert!(keys.allows(&headers(header::AUTHORIZATION, "Bearer first")));
+        assert!(keys.allows(&headers(
+            header::HeaderName::from_static(API_KEY_HEADER),
+            "second"
+        )));
+
+        assert!(!keys.allows(&HeaderMap::new()));
+        assert!(!keys.allows(&headers(header::AUTHORIZATION, "Bearer firs")));
+        assert!(!keys.allows(&headers(header::AUTHORIZATION, "Basic first")));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::search::search_result::SearchResult;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, PoisonError},
+};
+
+/// A query against one state of the server: the generation of its index and
+/// the version of the hosted rankings it ran with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub index: String,
+    pub generation: u64,
+    pub rankings: u64,
+    pub ranking: String,
+    /// Safe search the query asked for, `None` leaving it to the ranking
+    pub safe_search: Option<bool>,
+    /// Bits of the recency half-life the query asked for, `None` leaving it
+    /// to the ranking
+    pub half_life_days: Option<u64>,
+    pub query: String,
+    pub limit: usize,
+}
+
+/// What a query answered, all of its response but how long it took.
+#[derive(Debug, Clone)]
+pub struct CachedResults {
+    pub results: Vec<SearchResult>,
+    /// Matching documents
+    pub total: usize,
+    pub partial_results: bool,
+    /// Spelling suggestion for the query
+    pub did_you_mean: Option<String>,
+}
+
+/// Results of recent queries, oldest evicted first.
+///
+/// Keys carry the index generation and rankings version, so a reload or a
+/// config change makes every earlier entry unreachable. Those are dropped as
+/// soon as a query of the new state is cached, like the postings preloaded by
+/// the engine the reload replaced.
+pub struct QueryCache {
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<CacheKey, CachedResults>,
+    order: VecDeque<CacheKey>,
+    /// Latest generation and rankings version cached for each index
+    versions: HashMap<String, (u64, u64)>,
+}
+
+impl QueryCache {
+    /// Returns `None` when `capacity` is 0, meaning nothing is cached.
+    #[must_use]
+    pub fn new(capacity: usize) -> Option<Self> {
+        (capacity > 0).then(|| Self {
+            capacity,
+            state: Mutex::default(),
+        })
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &CacheKey) -> Option<CachedResults> {
+        // A poisoned cache only holds copies of results, keep serving them
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.entries.get(key).cloned()
+    }
+
+    /// Caches `results` unless a newer generation or rankings version of the
+    /// index was cached meanwhile.
+    pub fn insert(&self, key: CacheKey, results: CachedResults) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let version = (key.generation, key.rankings);
+        match state.versions.get(&key.index) {
+            Some(&(generation, rankings)) if generation > version.0 || rankings > version.1 => {
+                return;
+            }
+            Some(&latest) if latest == version => {}
+            _ => {
+                state.entries.retain(|cached, _| cached.index != key.index);
+                state.order.retain(|cached| cached.index != key.index);
+                state.versions.insert(key.index.clone(), version);
+            }
+        }
+
+        if state.entries.contains_key(&key) {
+            return;
+        }
+        while state.order.len() >= self.capacity {
+            if let Some(oldest) = state.order.pop_front() {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.order.push_back(key.clone());
+        state.entries.insert(key, results);
+        drop(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(query: &str, generation: u64, rankings: u64) -> CacheKey {
+        CacheKey {
+            index: "default".to_string(),
+            generation,
+            rankings,
+            ranking: "default".to_string(),
+            safe_search: None,
+            half_life_days: None,
+            query: query.to_string(),
+            limit: 10,
+        }
+    }
+
+    fn cached(cache: &QueryCache, key: &CacheKey) -> Option<usize> {
+        cache.get(key).map(|results| results.total)
+    }
+
+    fn results(total: usize) -> CachedResults {
+        CachedResults {
+            results: Vec::new(),
+            total,
+            partial_results: false,
+            did_you_mean: None,
+        }
+    }
+
+    #[test]
+    fn disabled_without_capacity() {
+        assert!(QueryCache::new(0).is_none());
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let cache = QueryCache::new(2).expect("Cache should be enabled");
+
+        cache.insert(key("rust", 1, 0), results(1));
+        cache.insert(key("cargo", 1, 0), results(2));
+        cache.insert(key("pasta", 1, 0), results(3));
+
+        assert_eq!(cached(&cache, &key("rust", 1, 0)), None);
+        assert_eq!(cached(&cache, &key("cargo", 1, 0)), Some(2));
+        assert_eq!(cached(&cache, &key("pasta", 1, 0)), Some(3));
+    }
+
+    #[test]
+    fn new_generation_invalidates() {
+        let cache = QueryCache::new(10).expect("Cache should be enabled");
+
+        cache.insert(key("rust", 1, 0), results(1));
+        assert_eq!(cached(&cache, &key("rust", 1, 0)), Some(1));
+        assert_eq!(cached(&cache, &key("rust", 2, 0)), None);
+
+        cache.insert(key("cargo", 2, 0), results(2));
+        assert_eq!(cached(&cache, &key("rust", 1, 0)), None);
+
+        // A query that started before the reload finishes after it
+        cache.insert(key("rust", 1, 0), results(1));
+        assert_eq!(cached(&cache, &key("rust", 1, 0)), None);
+
+        cache.insert(key("cargo", 2, 1), results(3));
+        assert_eq!(cached(&cache, &key("cargo", 2, 0)), None);
+        assert_eq!(cached(&cache, &key("cargo", 2, 1)), Some(3));
+    }
+}
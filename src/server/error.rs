@@ -0,0 +1,63 @@
+use crate::error::Error;
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::time::Duration;
+
+pub enum ServerError {
+    UnknownIndex(String),
+    UnknownRanking(String),
+    UnknownTerm(String),
+    MissingReport,
+    Unauthorized,
+    TooManyRequests(Duration),
+    Internal(Error),
+}
+
+impl From<Error> for ServerError {
+    fn from(error: Error) -> Self {
+        Self::Internal(error)
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::UnknownIndex(name) => (StatusCode::NOT_FOUND, format!("Unknown index `{name}`")),
+            Self::UnknownRanking(name) => {
+                (StatusCode::BAD_REQUEST, format!("Unknown ranking `{name}`"))
+            }
+            Self::UnknownTerm(word) => (
+                StatusCode::NOT_FOUND,
+                format!("No document contains `{word}`"),
+            ),
+            Self::MissingReport => (
+                StatusCode::NOT_FOUND,
+                "The index has no build report".to_string(),
+            ),
+            Self::Unauthorized => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    Json(json!({ "error": "Missing or invalid API key" })),
+                )
+                    .into_response();
+            }
+            Self::TooManyRequests(retry_after) => {
+                let seconds = retry_after.as_secs_f64().ceil() as u64;
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, seconds.to_string())],
+                    Json(json!({ "error": "Too many requests" })),
+                )
+                    .into_response();
+            }
+            Self::Internal(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        };
+
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
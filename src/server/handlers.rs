@@ -0,0 +1,555 @@
+use super::{
+    app::{HostedIndex, ReloadRequest, SharedEngine, SharedState, DEFAULT_RANKING},
+    cache::{CacheKey, CachedResults},
+    error::ServerError,
+};
+use crate::{
+    error::{Error, Result},
+    inverted_index::{build_report::BuildReport, term_stats::TermStats},
+    search::{
+        engine::{SearchEngine, SearchOptions, SearchOutcome},
+        ranking::RankingConfig,
+        search_result::SearchResult,
+        trace::QueryTrace,
+    },
+    slow_query_log::SlowQuery,
+};
+use axum::{
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, Response,
+    },
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Instant};
+use tokio::{
+    sync::{mpsc, OwnedSemaphorePermit},
+    task,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+const DEFAULT_LIMIT: usize = 10;
+const INDEX_HTML: &str = include_str!("static/index.html");
+const STREAM_BUFFER: usize = 16;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    q: String,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    /// Named ranking of the server config to rank with
+    #[serde(default = "default_ranking")]
+    ranking: String,
+    /// Overrides whether the ranking leaves out documents flagged at index
+    /// time
+    safe_search: Option<bool>,
+    /// Overrides the days after which the recency of documents halves, 0
+    /// ignoring crawl times
+    half_life_days: Option<f64>,
+    /// Whether to return a trace of how the query ran, which bypasses the
+    /// query cache
+    #[serde(default)]
+    trace: bool,
+}
+
+const fn default_limit() -> usize {
+    DEFAULT_LIMIT
+}
+
+fn default_ranking() -> String {
+    DEFAULT_RANKING.to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestParams {
+    q: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    query: String,
+    ranking: String,
+    total: usize,
+    /// Whether the query hit the engine's limits, so matches may be missing
+    partial_results: bool,
+    elapsed_ms: f64,
+    /// Whether the results came from the query cache
+    cached: bool,
+    /// The query with misspelled words corrected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    did_you_mean: Option<String>,
+    results: Vec<SearchResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<QueryTrace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    generation: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamSummary {
+    ranking: String,
+    total: usize,
+    partial_results: bool,
+    elapsed_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    did_you_mean: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<QueryTrace>,
+}
+
+pub async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Popular past queries starting with `q`, for search-as-you-type.
+pub async fn suggest(
+    State(state): State<SharedState>,
+    Query(params): Query<SuggestParams>,
+) -> Json<Vec<String>> {
+    Json(state.suggest_queries(&params.q, params.limit))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TermStatsResponse {
+    /// Term the word analyzed to
+    term: String,
+    #[serde(flatten)]
+    stats: TermStats,
+}
+
+/// Stats of a word of the default index, for analysis tools.
+pub async fn term_stats(
+    State(state): State<SharedState>,
+    Path(word): Path<String>,
+) -> core::result::Result<Json<TermStatsResponse>, ServerError> {
+    run_term_stats(state.default_index().engine()?, word).await
+}
+
+pub async fn index_term_stats(
+    State(state): State<SharedState>,
+    Path((name, word)): Path<(String, String)>,
+) -> core::result::Result<Json<TermStatsResponse>, ServerError> {
+    run_term_stats(hosted(&state, name)?.engine()?, word).await
+}
+
+async fn run_term_stats(
+    search_engine: SharedEngine,
+    word: String,
+) -> core::result::Result<Json<TermStatsResponse>, ServerError> {
+    let lookup = word.clone();
+    let stats = task::spawn_blocking(move || search_engine.term_stats(&lookup))
+        .await
+        .map_err(Error::task_failed("Term stats"))??;
+
+    stats
+        .map(|(term, stats)| Json(TermStatsResponse { term, stats }))
+        .ok_or(ServerError::UnknownTerm(word))
+}
+
+/// Report of the build of the default index, to audit what is served.
+pub async fn build_report(
+    State(state): State<SharedState>,
+) -> core::result::Result<Json<BuildReport>, ServerError> {
+    run_build_report(&state.default_index().engine()?)
+}
+
+pub async fn index_build_report(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+) -> core::result::Result<Json<BuildReport>, ServerError> {
+    run_build_report(&hosted(&state, name)?.engine()?)
+}
+
+fn run_build_report(
+    search_engine: &SharedEngine,
+) -> core::result::Result<Json<BuildReport>, ServerError> {
+    search_engine
+        .build_report()
+        .cloned()
+        .map(Json)
+        .ok_or(ServerError::MissingReport)
+}
+
+pub async fn list_indexes(State(state): State<SharedState>) -> Json<Vec<String>> {
+    Json(state.indexes().map(|(name, _)| name.to_string()).collect())
+}
+
+/// Ready once every index is loaded and its seek maps agree with the files on
+/// disk, so orchestrators only route queries to instances that can answer.
+pub async fn readyz(State(state): State<SharedState>) -> (StatusCode, String) {
+    let outcome = task::spawn_blocking(move || {
+        state.indexes().try_for_each(|(name, index)| {
+            index
+                .engine()?
+                .verify()
+                .map_err(|e| Error::Generic(format!("Index `{name}`: {e}")))
+        })
+    })
+    .await
+    .map_err(|e| Error::Generic(format!("Readiness check failed: {e}")))
+    .and_then(|outcome| outcome);
+
+    match outcome {
+        Ok(()) => (StatusCode::OK, "ready".to_string()),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+    }
+}
+
+pub async fn search(
+    State(state): State<SharedState>,
+    Query(params): Query<SearchParams>,
+) -> core::result::Result<Json<SearchResponse>, ServerError> {
+    let index = state.default_index_name().to_string();
+    run_search(state, index, params).await
+}
+
+pub async fn index_search(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Query(params): Query<SearchParams>,
+) -> core::result::Result<Json<SearchResponse>, ServerError> {
+    hosted(&state, name.clone())?;
+    run_search(state, name, params).await
+}
+
+pub async fn search_stream(
+    State(state): State<SharedState>,
+    Query(params): Query<SearchParams>,
+) -> core::result::Result<
+    Sse<impl Stream<Item = core::result::Result<Event, Infallible>>>,
+    ServerError,
+> {
+    let ranking = state.ranking(&params.ranking)?;
+    log_query(&state, &params);
+    let slot = state.query_slot().await?;
+    let search_engine = state.default_index().engine();
+    let index = state.default_index_name().to_string();
+    Ok(stream_search(
+        state,
+        index,
+        search_engine,
+        params,
+        ranking,
+        slot,
+    ))
+}
+
+pub async fn index_search_stream(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Query(params): Query<SearchParams>,
+) -> core::result::Result<
+    Sse<impl Stream<Item = core::result::Result<Event, Infallible>>>,
+    ServerError,
+> {
+    let search_engine = hosted(&state, name.clone())?.engine();
+    let ranking = state.ranking(&params.ranking)?;
+    log_query(&state, &params);
+    let slot = state.query_slot().await?;
+    Ok(stream_search(
+        state,
+        name,
+        search_engine,
+        params,
+        ranking,
+        slot,
+    ))
+}
+
+/// Swaps in a new generation of the index. Queries already running finish
+/// against the generation they started on.
+pub async fn reload_index(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    request: Option<Json<ReloadRequest>>,
+) -> core::result::Result<Json<ReloadResponse>, ServerError> {
+    hosted(&state, name.clone())?;
+
+    let generation = task::spawn_blocking(move || {
+        let request = request.map(|Json(request)| request).unwrap_or_default();
+        hosted(&state, name)
+            .map_err(|_| Error::Generic("Index disappeared during reload".to_string()))?
+            .reload(request)
+    })
+    .await
+    .map_err(Error::task_failed("Reload"))??;
+
+    Ok(Json(ReloadResponse { generation }))
+}
+
+/// Rejects clients without a valid API key with `401 Unauthorized`.
+pub async fn require_api_key(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> core::result::Result<Response, ServerError> {
+    if let Some(api_keys) = state.api_keys() {
+        if !api_keys.allows(&headers) {
+            return Err(ServerError::Unauthorized);
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Rejects clients that exceed their query rate with `429 Too Many Requests`.
+pub async fn rate_limit(
+    State(state): State<SharedState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> core::result::Result<Response, ServerError> {
+    if let Some(rate_limiter) = state.rate_limiter() {
+        rate_limiter
+            .check(client.ip())
+            .map_err(ServerError::TooManyRequests)?;
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Answers from the query cache when it holds the query for the current
+/// generation of the index and rankings. Otherwise the search runs in its own
+/// task holding the query slot until it is done, so the cap covers work that
+/// outlives a disconnected client.
+async fn run_search(
+    state: SharedState,
+    index: String,
+    params: SearchParams,
+) -> core::result::Result<Json<SearchResponse>, ServerError> {
+    // Read before the ranking, so a concurrent change can only make the
+    // results newer than their key
+    let rankings = state.rankings_version();
+    let ranking = state.ranking(&params.ranking)?;
+    log_query(&state, &params);
+
+    let start_time = Instant::now();
+    let (search_engine, generation) = hosted(&state, index.clone())?.current()?;
+    let ranking = with_overrides(ranking, &params, &search_engine);
+    let key = CacheKey {
+        index,
+        generation,
+        rankings,
+        ranking: params.ranking.clone(),
+        safe_search: params.safe_search,
+        half_life_days: params.half_life_days.map(f64::to_bits),
+        query: params.q.clone(),
+        limit: params.limit,
+    };
+    let cached = state
+        .query_cache()
+        .filter(|_| !params.trace)
+        .and_then(|cache| cache.get(&key));
+    if let Some(CachedResults {
+        results,
+        total,
+        partial_results,
+        did_you_mean,
+    }) = cached
+    {
+        return Ok(Json(SearchResponse {
+            query: params.q,
+            ranking: params.ranking,
+            total,
+            partial_results,
+            elapsed_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+            cached: true,
+            did_you_mean,
+            results,
+            trace: None,
+        }));
+    }
+
+    let slot = state.query_slot().await?;
+    let response = task::spawn(async move {
+        let _slot = slot;
+        let start_time = Instant::now();
+
+        let did_you_mean = did_you_mean(&search_engine, &params.q).await?;
+        let options = SearchOptions {
+            limit: params.limit,
+            ranking,
+            trace: params.trace || state.logs_slow_queries(),
+        };
+        let (
+            results,
+            SearchOutcome {
+                total,
+                partial_results,
+                trace,
+            },
+        ) = search_engine
+            .search_async(params.q.clone(), options)
+            .await?;
+        let elapsed = start_time.elapsed();
+        state.record_slow_query(
+            elapsed,
+            &SlowQuery {
+                index: Some(&key.index),
+                query: &params.q,
+                elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+                total,
+                trace: trace.as_ref(),
+            },
+        );
+        if let Some(cache) = state.query_cache() {
+            cache.insert(
+                key,
+                CachedResults {
+                    results: results.clone(),
+                    total,
+                    partial_results,
+                    did_you_mean: did_you_mean.clone(),
+                },
+            );
+        }
+
+        Ok::<_, Error>(SearchResponse {
+            query: params.q,
+            ranking: params.ranking,
+            total,
+            partial_results,
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            cached: false,
+            did_you_mean,
+            results,
+            trace: trace.filter(|_| params.trace),
+        })
+    })
+    .await
+    .map_err(Error::task_failed("Search"))??;
+
+    Ok(Json(response))
+}
+
+/// `ranking`, or the engine's own when `None`, with the safe search and
+/// recency half-life the query asks for.
+fn with_overrides(
+    ranking: Option<RankingConfig>,
+    params: &SearchParams,
+    search_engine: &SearchEngine,
+) -> Option<RankingConfig> {
+    if params.safe_search.is_none() && params.half_life_days.is_none() {
+        return ranking;
+    }
+
+    let mut ranking = ranking.unwrap_or_else(|| search_engine.ranking().clone());
+    if let Some(safe_search) = params.safe_search {
+        ranking.safe_search = safe_search;
+    }
+    if let Some(half_life_days) = params.half_life_days {
+        ranking.recency_half_life_days = half_life_days;
+    }
+    Some(ranking)
+}
+
+/// See [`SearchEngine::did_you_mean`], run off the async workers since the
+/// first call of an engine builds its spell checker.
+async fn did_you_mean(search_engine: &SharedEngine, query: &str) -> Result<Option<String>> {
+    let search_engine = Arc::clone(search_engine);
+    let query = query.to_string();
+    task::spawn_blocking(move || search_engine.did_you_mean(&query))
+        .await
+        .map_err(Error::task_failed("Spelling"))
+}
+
+/// Streams results as server-sent events: one `result` event per hit in rank
+/// order, followed by a `done` event carrying the total, or an `error` event.
+fn stream_search(
+    state: SharedState,
+    index: String,
+    search_engine: Result<SharedEngine>,
+    params: SearchParams,
+    ranking: Option<RankingConfig>,
+    slot: OwnedSemaphorePermit,
+) -> Sse<impl Stream<Item = core::result::Result<Event, Infallible>>> {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+    task::spawn_blocking(move || {
+        let _slot = slot;
+        let start_time = Instant::now();
+
+        let outcome = search_engine.and_then(|search_engine| {
+            let ranking = with_overrides(ranking, &params, &search_engine);
+            let ranking = ranking.as_ref().unwrap_or_else(|| search_engine.ranking());
+            let outcome = search_engine.search_streaming_traced(
+                &params.q,
+                ranking,
+                params.limit,
+                params.trace || state.logs_slow_queries(),
+                |result| tx.blocking_send(Ok(json_event("result", &result))).is_ok(),
+            )?;
+            Ok((outcome, search_engine.did_you_mean(&params.q)))
+        });
+
+        let elapsed = start_time.elapsed();
+        let last_event = match outcome {
+            Ok((
+                SearchOutcome {
+                    total,
+                    partial_results,
+                    trace,
+                },
+                did_you_mean,
+            )) => {
+                state.record_slow_query(
+                    elapsed,
+                    &SlowQuery {
+                        index: Some(&index),
+                        query: &params.q,
+                        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+                        total,
+                        trace: trace.as_ref(),
+                    },
+                );
+                json_event(
+                    "done",
+                    &StreamSummary {
+                        ranking: params.ranking,
+                        total,
+                        partial_results,
+                        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+                        did_you_mean,
+                        trace: trace.filter(|_| params.trace),
+                    },
+                )
+            }
+            Err(e) => Event::default().event("error").data(e.to_string()),
+        };
+
+        // The client may already have disconnected, nothing left to notify.
+        let _ = tx.blocking_send(Ok(last_event));
+    });
+
+    Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default())
+}
+
+/// Records the query, and which ranking served it for comparing experiments
+/// offline.
+fn log_query(state: &SharedState, params: &SearchParams) {
+    state.record_query(&params.q, &params.ranking);
+}
+
+fn hosted(state: &SharedState, name: String) -> core::result::Result<&HostedIndex, ServerError> {
+    state.get(&name).ok_or(ServerError::UnknownIndex(name))
+}
+
+fn json_event<T: Serialize>(name: &str, data: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Clients tracked before idle buckets are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client address: each client may send `burst` queries at
+/// once and then `rate` queries per second.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Returns `None` when `rate` is 0, meaning clients are not limited.
+    #[must_use]
+    pub fn new(rate: u32, burst: u32) -> Option<Self> {
+        (rate > 0).then(|| Self {
+            rate: f64::from(rate),
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes a token for `client`, or returns how long until one is available.
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        // A poisoned map only holds token counts, keep limiting with it
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            let full_after = Duration::from_secs_f64(self.burst / self.rate);
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < full_after);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = elapsed.mul_add(self.rate, bucket.tokens).min(self.burst);
+        bucket.updated = now;
+
+        let outcome = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        };
+        drop(buckets);
+
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+    #[test]
+    fn disabled_without_rate() {
+        assert!(RateLimiter::new(0, 10).is_none());
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        let limiter = RateLimiter::new(2, 3).expect("Rate limit should be enabled");
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(CLIENT, start).is_ok());
+        }
+        let retry_after = limiter
+            .check_at(CLIENT, start)
+            .expect_err("Burst should be exhausted");
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        // Other clients have their own bucket
+        assert!(limiter.check_at(OTHER, start).is_ok());
+
+        assert!(limiter
+            .check_at(CLIENT, start + Duration::from_millis(500))
+            .is_ok());
+        assert!(limiter
+            .check_at(CLIENT, start + Duration::from_millis(500))
+            .is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod app;
+mod auth;
+mod cache;
+mod error;
+mod handlers;
+mod limit;
@@ -0,0 +1,133 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, LazyLock,
+};
+
+static REQUESTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+static IDLE: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Whether SIGINT or SIGTERM was received. Long running work polls this
+/// between units of work so it can stop at a consistent point.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Marks the process as waiting for input until the guard is dropped.
+///
+/// Nothing polls for a shutdown request while a read blocks and there is
+/// nothing to save, so SIGINT and SIGTERM end the process right away instead.
+#[must_use]
+pub fn idle() -> Idle {
+    IDLE.store(true, Ordering::Relaxed);
+    Idle(())
+}
+
+/// See [`idle`].
+pub struct Idle(());
+
+impl Drop for Idle {
+    fn drop(&mut self) {
+        IDLE.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Turns the first SIGINT/SIGTERM into a shutdown request and a second one
+/// into an immediate exit, for when the graceful path hangs.
+///
+/// While [`idle`], either ends the process as if no handler was installed.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install_handler() -> crate::error::Result<()> {
+    use signal_hook::{
+        consts::{SIGINT, SIGTERM},
+        flag,
+    };
+
+    for signal in [SIGINT, SIGTERM] {
+        flag::register_conditional_default(signal, Arc::clone(&IDLE))?;
+        flag::register_conditional_shutdown(signal, 1, Arc::clone(&REQUESTED))?;
+        flag::register(signal, Arc::clone(&REQUESTED))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use signal_hook::consts::SIGTERM;
+    use std::{
+        env,
+        io::{self, BufRead, BufReader, Read},
+        os::unix::process::ExitStatusExt,
+        process::{self, Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Set for the child process of `sigterm_ends_idle_read`.
+    const CHILD_ENV: &str = "SEARCH_ENGINE_IDLE_CHILD";
+    const READY: &str = "waiting for input";
+
+    #[test]
+    #[ignore = "run in a child process by sigterm_ends_idle_read"]
+    fn idle_read() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        install_handler().expect("Failed to install signal handler");
+        let _idle = idle();
+        println!("{READY}");
+        let _ = io::stdin().read(&mut [0]);
+
+        // Only reached when the signal didn't end the process
+        process::exit(0);
+    }
+
+    #[test]
+    fn sigterm_ends_idle_read() {
+        let mut child = Command::new(env::current_exe().expect("Failed to find test binary"))
+            .args([
+                "--exact",
+                "shutdown::tests::idle_read",
+                "--ignored",
+                "--nocapture",
+            ])
+            .env(CHILD_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start child process");
+
+        let mut stdout = BufReader::new(child.stdout.take().expect("Child should have stdout"));
+        let mut line = String::new();
+        while !line.contains(READY) {
+            line.clear();
+            let read = stdout
+                .read_line(&mut line)
+                .expect("Failed to read child output");
+            assert!(read > 0, "Child exited before waiting for input");
+        }
+
+        let killed = Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status()
+            .expect("Failed to run kill");
+        assert!(killed.success());
+
+        // Polled rather than waited on, which would close the stdin of the
+        // child and end its read without the signal
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let status = loop {
+            if let Some(status) = child.try_wait().expect("Failed to poll child process") {
+                break status;
+            }
+            if Instant::now() > deadline {
+                let _ = child.kill();
+                panic!("Child kept waiting for input after SIGTERM");
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(status.signal(), Some(SIGTERM));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{config::SlowQueryLogConfig, error::Result, search::trace::QueryTrace};
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Queries that took longer than a threshold, appended one JSON object per
+/// line with their trace, like the slow query log of a database server.
+pub struct SlowQueryLog {
+    threshold: Duration,
+    file: Mutex<File>,
+}
+
+/// A query as the slow query log records it.
+#[derive(Debug, Serialize)]
+pub struct SlowQuery<'a> {
+    /// Hosted index the query ran on, `None` outside the server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<&'a str>,
+    pub query: &'a str,
+    pub elapsed_ms: f64,
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<&'a QueryTrace>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Seconds since the Unix epoch
+    timestamp: u64,
+    #[serde(flatten)]
+    query: &'a SlowQuery<'a>,
+}
+
+impl SlowQueryLog {
+    /// Opens the log of `config` for appending, `None` when its threshold is
+    /// 0.
+    pub fn open(config: &SlowQueryLogConfig) -> Result<Option<Self>> {
+        if config.threshold_ms == 0 {
+            return Ok(None);
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+
+        Ok(Some(Self {
+            threshold: Duration::from_millis(config.threshold_ms),
+            file: Mutex::new(file),
+        }))
+    }
+
+    /// Whether a query that took `elapsed` belongs in the log.
+    #[must_use]
+    pub fn is_slow(&self, elapsed: Duration) -> bool {
+        elapsed >= self.threshold
+    }
+
+    /// Appends `query` to the log.
+    pub fn record(&self, query: &SlowQuery) -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let mut line = serde_json::to_vec(&Entry { timestamp, query })?;
+        line.push(b'\n');
+
+        // Lines are written whole, so a poisoned lock left none half written
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(&line)?;
+        drop(file);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use serde_json::Value;
+    use std::fs;
+
+    #[test]
+    fn records_slow_queries() {
+        let dir = TestDir::new();
+        let path = dir.path("records_slow_queries.log");
+        let config = |threshold_ms| SlowQueryLogConfig {
+            path: path.clone(),
+            threshold_ms,
+        };
+        assert!(SlowQueryLog::open(&config(0))
+            .expect("Failed to open log")
+            .is_none());
+
+        let log = SlowQueryLog::open(&config(100))
+            .expect("Failed to open log")
+            .expect("Missing log");
+        assert!(!log.is_slow(Duration::from_millis(99)));
+        assert!(log.is_slow(Duration::from_millis(100)));
+
+        let trace = QueryTrace {
+            postings_read: 42,
+            ..QueryTrace::default()
+        };
+        log.record(&SlowQuery {
+            index: None,
+            query: "rust",
+            elapsed_ms: 150.0,
+            total: 3,
+            trace: Some(&trace),
+        })
+        .expect("Failed to record");
+
+        let entry: Value = serde_json::from_str(
+            fs::read_to_string(&path)
+                .expect("Failed to read log")
+                .trim_end(),
+        )
+        .expect("Failed to parse entry");
+        assert_eq!(entry["query"], "rust");
+        assert_eq!(entry["total"], 3);
+        assert_eq!(entry["trace"]["postings_read"], 42);
+        assert!(entry["timestamp"].as_u64().is_some_and(|time| time > 0));
+        assert!(entry.get("index").is_none());
+    }
+}
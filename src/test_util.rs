@@ -0,0 +1,48 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::inverted_index::disk_inverted_index::CrawlFile;
+
+/// Directory a test writes its files to, removed with everything in it,
+/// sidecar files included, when dropped.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    #[must_use]
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("search-engine-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).expect("Failed to create test directory");
+        Self(path)
+    }
+
+    /// Path of `name` in the directory.
+    #[must_use]
+    pub fn path(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A page at `https://example.com/{name}` holding `text` in a paragraph.
+#[must_use]
+pub fn page(name: &str, text: &str) -> CrawlFile {
+    CrawlFile {
+        url: format!("https://example.com/{name}"),
+        content: format!("<p>{text}</p>"),
+        encoding: "utf-8".to_string(),
+        crawled_at: None,
+        boost: None,
+        source: None,
+        fields: BTreeMap::new(),
+    }
+}
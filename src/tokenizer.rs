@@ -1,29 +1,72 @@
 use crate::error::{Error, Result};
 use regex::Regex;
-use rust_stemmers::Stemmer;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+
+/// How text is split and normalized. An index must be queried with the
+/// analyzer it was built with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Analyzer {
+    #[default]
+    English,
+    French,
+    German,
+    Italian,
+    Portuguese,
+    Spanish,
+    /// Lowercasing only, no stemming
+    Simple,
+}
+
+impl Analyzer {
+    const fn algorithm(self) -> Option<Algorithm> {
+        match self {
+            Self::English => Some(Algorithm::English),
+            Self::French => Some(Algorithm::French),
+            Self::German => Some(Algorithm::German),
+            Self::Italian => Some(Algorithm::Italian),
+            Self::Portuguese => Some(Algorithm::Portuguese),
+            Self::Spanish => Some(Algorithm::Spanish),
+            Self::Simple => None,
+        }
+    }
+}
 
 pub struct Tokenizer {
-    stemmer: Stemmer,
+    stemmer: Option<Stemmer>,
     regex: Regex,
 }
 
 impl Tokenizer {
     pub fn new() -> Result<Self> {
+        Self::with_analyzer(Analyzer::default())
+    }
+
+    pub fn with_analyzer(analyzer: Analyzer) -> Result<Self> {
         Ok(Self {
-            stemmer: Stemmer::create(rust_stemmers::Algorithm::English),
-            regex: Regex::new(r"\b\w+\b")
-                .map_err(|e| Error::Generic(format!("Failed to compile regex: {e}")))?,
+            stemmer: analyzer.algorithm().map(Stemmer::create),
+            regex: Regex::new(r"\b\w+\b").map_err(Error::TokenizerInit)?,
         })
     }
 
+    #[must_use]
     pub fn tokenize(&self, text: &str) -> Vec<String> {
+        self.words(text)
+            .into_iter()
+            .map(|word| match &self.stemmer {
+                Some(stemmer) => stemmer.stem(&word).to_string(),
+                None => word,
+            })
+            .collect()
+    }
+
+    /// The lowercased words of `text`, before stemming.
+    #[must_use]
+    pub fn words(&self, text: &str) -> Vec<String> {
         self.regex
             .find_iter(text)
-            .map(|token| {
-                self.stemmer
-                    .stem(&token.as_str().to_lowercase())
-                    .to_string()
-            })
+            .map(|word| word.as_str().to_lowercase())
             .collect()
     }
 }
@@ -60,6 +103,14 @@ mod tests {
         assert_eq!(tokens, vec!["i", "am", "a", "test", "sentenc"]);
     }
 
+    #[test]
+    fn test_tokenize_simple_analyzer() {
+        let tokenizer =
+            Tokenizer::with_analyzer(Analyzer::Simple).expect("Failed to create tokenizer");
+        let tokens = tokenizer.tokenize("Running Tests");
+        assert_eq!(tokens, vec!["running", "tests"]);
+    }
+
     #[test]
     fn test_stemmer() {
         let tokenizer = Tokenizer::new().expect("Failed to create tokenizer");
@@ -91,9 +142,10 @@ mod tests {
             "state", "siez", "item", "sensat", "tradit", "refer", "colon", "plot",
         ];
 
+        let stemmer = tokenizer.stemmer.expect("English analyzer should stem");
         let stems: Vec<String> = plurals
             .iter()
-            .map(|word| tokenizer.stemmer.stem(&word.to_lowercase()).to_string())
+            .map(|word| stemmer.stem(&word.to_lowercase()).to_string())
             .collect();
 
         assert_eq!(expected_result, stems);
@@ -0,0 +1,42 @@
+//! Browser bindings over an index downloaded into memory.
+//!
+//! Build with `cargo build --lib --release --target wasm32-unknown-unknown`
+//! and generate the JavaScript glue with `wasm-bindgen --target web`.
+
+use crate::{
+    inverted_index::disk_inverted_index::MemoryInvertedIndex,
+    search::{batch::run_query, engine::SearchEngine},
+};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct WasmSearchEngine {
+    search_engine: SearchEngine<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl WasmSearchEngine {
+    /// Takes the raw contents of the four index files.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        db: Vec<u8>,
+        db_seek: &[u8],
+        url_map: Vec<u8>,
+        url_map_seek: &[u8],
+    ) -> Result<Self, JsError> {
+        Ok(Self {
+            search_engine: SearchEngine::new(MemoryInvertedIndex::from_bytes(
+                db,
+                db_seek,
+                url_map,
+                url_map_seek,
+            )?)?,
+        })
+    }
+
+    /// Returns the top `limit` results for `query` as a JSON string.
+    pub fn search(&self, query: &str, limit: usize) -> Result<String, JsError> {
+        let results = run_query(&self.search_engine, query, limit)?;
+        Ok(serde_json::to_string(&results)?)
+    }
+}